@@ -0,0 +1,17 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+ROOT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
+OUT="${1:-${ROOT_DIR}/web/assets.tar.gz}"
+
+# The web build loads `src/assets/...` and `src/...` data as `assets/...` (see `asset_path` and
+# `data_path`), so the pack stores them under those paths. Rust sources stay out.
+"${ROOT_DIR}/scripts/generate-wasm-indexes.sh" >/dev/null
+tar -czf "${OUT}" \
+  -C "${ROOT_DIR}" \
+  --exclude='*.rs' \
+  --exclude='src/bin' \
+  --transform='s,^src/assets/,assets/,;s,^src/,assets/,' \
+  src
+
+printf 'Asset pack written to %s (%s).\n' "${OUT}" "$(du -h "${OUT}" | cut -f1)"
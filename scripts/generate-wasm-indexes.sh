@@ -38,9 +38,19 @@ generate_index() {
 generate_index "src/structure" "*.json"
 generate_index "src/entity/behaviour" "*.yaml"
 generate_index "src/entity/trait" "*.yaml"
+generate_index "src/entity/ability" "*.yaml"
 generate_index "src/entity/enemy" "*.yaml"
 generate_index "src/entity/friend" "*.yaml"
 generate_index "src/entity/misc" "*.yaml"
 generate_index "src/particle" "*.yaml"
+generate_index "src/item" "*.yaml"
+generate_index "src/hazard" "*.yaml"
+generate_index "src/projectile" "*.yaml"
+generate_index "src/cutscene" "*.yaml"
+generate_index "src/event" "*.yaml"
+generate_index "src/spawner" "*.yaml"
+generate_index "src/crop" "*.yaml"
+generate_index "src/upgrade" "*.yaml"
+generate_index "src/ambient" "*.yaml"
 
 printf 'WASM index manifests generated.\n'
@@ -0,0 +1,340 @@
+//! Abilities entities cast from their behaviour trees.
+//!
+//! Each `src/entity/ability/*.yaml` file is one ability: how long it takes to cast, its cooldown,
+//! how close the target has to be, and what it does when the cast finishes. It can fire
+//! projectiles at the target, damage everything hostile around the caster, summon entities, or
+//! raise one of the caster's stats for a while. The `cast_ability` action names an ability with
+//! its `ability` label and casts it whenever it's ready; the caster stands still while casting.
+//! Casts and cooldowns are drawn as small bars over the caster.
+
+use crate::asset_pack::load_string;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::cull::ViewCull;
+use crate::entity::{
+    DamageEvent, EntityContext, EntityDatabase, EntityInstance, EntityLoadError, EntityRequest, OrphanRule,
+    SummonRequest, Target,
+};
+use crate::entity_pool::EntityPool;
+use crate::faction::PLAYER;
+use crate::helpers::load_wasm_manifest_files;
+
+const BAR_WIDTH: f32 = 14.0;
+const BAR_HEIGHT: f32 = 2.0;
+/// Gap between the top of the caster's hitbox and the bar.
+const BAR_GAP: f32 = 3.0;
+const BAR_BACK: Color = Color::new(0.0, 0.0, 0.0, 0.6);
+const CAST_COLOR: Color = Color::new(1.0, 0.65, 0.2, 1.0);
+const COOLDOWN_COLOR: Color = Color::new(0.55, 0.7, 1.0, 0.8);
+
+#[derive(Clone, Deserialize)]
+pub struct AbilityDef {
+    pub id: String,
+    /// Seconds spent winding up before the effect goes off.
+    #[serde(default)]
+    pub cast_time: f32,
+    /// Seconds after the effect before it can be cast again.
+    #[serde(default = "default_cooldown")]
+    pub cooldown: f32,
+    /// Only cast at targets this close, in world pixels; 0 casts with or without a target.
+    #[serde(default)]
+    pub range: f32,
+    pub effect: AbilityEffect,
+}
+
+fn default_cooldown() -> f32 {
+    3.0
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AbilityEffect {
+    /// Fires `count` projectiles at the target, fanned out over `spread` degrees.
+    Projectile {
+        projectile: String,
+        #[serde(default = "default_count")]
+        count: u32,
+        #[serde(default)]
+        spread: f32,
+    },
+    /// Damages the player and entities hostile to the caster within `radius`.
+    Aoe {
+        radius: f32,
+        damage: f32,
+        #[serde(default)]
+        knockback: f32,
+        #[serde(default)]
+        particle: Option<String>,
+    },
+    /// Spawns `count` children up to `radius` pixels from the caster, keeping at most `max`
+    /// alive (0 for no limit).
+    Summon {
+        entity: String,
+        #[serde(default = "default_count")]
+        count: u32,
+        #[serde(default = "default_summon_radius")]
+        radius: f32,
+        #[serde(default)]
+        max: u32,
+        #[serde(default)]
+        on_parent_death: OrphanRule,
+    },
+    /// Adds `amount` to one of the caster's stats for `duration` seconds.
+    Buff { stat: String, amount: f32, duration: f32 },
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+fn default_summon_radius() -> f32 {
+    24.0
+}
+
+struct Buff {
+    stat: String,
+    amount: f32,
+    remaining: f32,
+}
+
+/// An entity's casts, cooldowns and running buffs.
+#[derive(Default)]
+pub struct AbilityState {
+    /// Seconds until each ability can be cast again, by index in the database.
+    cooldowns: HashMap<usize, f32>,
+    /// The ability being cast and the seconds left until it goes off.
+    casting: Option<(usize, f32)>,
+    buffs: Vec<Buff>,
+    /// Asked for by `cast_ability` this frame; the first one ready is cast once the behaviours
+    /// have run.
+    queued: Vec<String>,
+}
+
+impl AbilityState {
+    /// Asks for `id` to be cast this frame; ignored while it cools down or another cast runs.
+    pub fn queue(&mut self, id: &str) {
+        self.queued.push(id.to_string());
+    }
+
+    pub fn is_casting(&self) -> bool {
+        self.casting.is_some()
+    }
+}
+
+/// Runs casts, cooldowns and buffs for one frame. Call after the entity's behaviours so a cast
+/// can hold it in place.
+pub fn update(entity: &mut EntityInstance, db: &EntityDatabase, ctx: &mut EntityContext, dt: f32) {
+    let mut state = std::mem::take(&mut entity.abilities);
+    state.cooldowns.retain(|_, remaining| {
+        *remaining -= dt;
+        *remaining > 0.0
+    });
+    state.buffs.retain_mut(|buff| {
+        buff.remaining -= dt;
+        if buff.remaining > 0.0 {
+            return true;
+        }
+        add_stat(entity, &buff.stat, -buff.amount);
+        false
+    });
+
+    let queued = std::mem::take(&mut state.queued);
+    if state.casting.is_none()
+        && let Some(index) = queued.iter().filter_map(|id| db.ability_id(id)).find(|&index| {
+            !state.cooldowns.contains_key(&index) && in_range(entity, &db.abilities[index])
+        })
+    {
+        state.casting = Some((index, db.abilities[index].cast_time));
+    }
+    if let Some((index, remaining)) = state.casting.as_mut() {
+        *remaining -= dt;
+        entity.vel = Vec2::ZERO;
+        if *remaining <= 0.0 {
+            let ability = &db.abilities[*index];
+            state.cooldowns.insert(*index, ability.cooldown);
+            state.casting = None;
+            if let Some(buff) = fire(entity, ability, db, ctx) {
+                state.buffs.push(buff);
+            }
+        }
+    }
+    entity.abilities = state;
+}
+
+fn in_range(entity: &EntityInstance, ability: &AbilityDef) -> bool {
+    if ability.range <= 0.0 {
+        return true;
+    }
+    entity
+        .current_target
+        .is_some_and(|target| target.position().distance(entity.pos) <= ability.range)
+}
+
+fn fire(
+    entity: &mut EntityInstance,
+    ability: &AbilityDef,
+    db: &EntityDatabase,
+    ctx: &mut EntityContext,
+) -> Option<Buff> {
+    match &ability.effect {
+        AbilityEffect::Projectile { projectile, count, spread } => {
+            let aim = entity.current_target?.position() - entity.pos;
+            let aim = aim.try_normalize()?;
+            let count = (*count).max(1);
+            let step = if count > 1 { spread.to_radians() / (count - 1) as f32 } else { 0.0 };
+            let first = -step * (count - 1) as f32 * 0.5;
+            for shot in 0..count {
+                entity.requests.push(EntityRequest::SpawnProjectile {
+                    id: projectile.clone(),
+                    pos: entity.pos,
+                    dir: Vec2::from_angle(first + step * shot as f32).rotate(aim),
+                });
+            }
+        }
+        AbilityEffect::Aoe { radius, damage, knockback, particle } => {
+            let faction = db.entities[entity.def].faction_index;
+            let push = |pos: Vec2| (pos - entity.pos).normalize_or_zero() * *knockback;
+            if let Some(player) = ctx.player
+                && !entity.tamed
+                && db.factions.is_hostile(faction, PLAYER)
+                && player.pos.distance(entity.pos) <= *radius
+            {
+                ctx.damage_events.push(DamageEvent {
+                    amount: *damage,
+                    target: Target::Player(player),
+                    knockback: push(player.pos),
+                    source: Some(entity.uid),
+                });
+            }
+            let hits: Vec<DamageEvent> = ctx
+                .neighbours(entity.pos, *radius)
+                .filter(|other| {
+                    other.alive
+                        && other.id != entity.uid
+                        && db.factions.is_hostile(faction, db.entities[other.def].faction_index)
+                })
+                .map(|other| DamageEvent {
+                    amount: *damage,
+                    target: Target::Entity(*other),
+                    knockback: push(other.pos),
+                    source: Some(entity.uid),
+                })
+                .collect();
+            ctx.damage_events.extend(hits);
+            if let Some(particle) = particle {
+                entity.requests.push(EntityRequest::Burst {
+                    id: particle.clone(),
+                    pos: entity.pos,
+                });
+            }
+        }
+        AbilityEffect::Summon { entity: id, count, radius, max, on_parent_death } => {
+            for _ in 0..*count {
+                let offset = Vec2::from_angle(rand::gen_range(0.0, std::f32::consts::TAU))
+                    * rand::gen_range(0.0, radius.max(0.0));
+                entity.requests.push(EntityRequest::Summon(SummonRequest {
+                    id: id.clone(),
+                    pos: entity.pos + offset,
+                    max: *max,
+                    on_parent_death: *on_parent_death,
+                }));
+            }
+        }
+        AbilityEffect::Buff { stat, amount, duration } => {
+            add_stat(entity, stat, *amount);
+            return Some(Buff {
+                stat: stat.clone(),
+                amount: *amount,
+                remaining: *duration,
+            });
+        }
+    }
+    None
+}
+
+/// Drops casts, cooldowns and running buffs, taking the buffs off the stats again; for when the
+/// abilities are reloaded and their indices may have moved.
+pub fn reset(entity: &mut EntityInstance) {
+    let state = std::mem::take(&mut entity.abilities);
+    for buff in state.buffs {
+        add_stat(entity, &buff.stat, -buff.amount);
+    }
+}
+
+/// Adds to a stat, keeping the cached speed in step.
+fn add_stat(entity: &mut EntityInstance, stat: &str, amount: f32) {
+    entity.stats.add(stat, amount);
+    if stat == "speed" {
+        entity.speed = entity.stats.get("speed", entity.speed).max(1.0);
+    }
+}
+
+/// Cast progress, or else the longest cooldown left, over every visible caster, in world space.
+pub fn draw_bars(entities: &EntityPool, db: &EntityDatabase, view: &ViewCull) {
+    for ent in entities.iter() {
+        let state = &ent.instance.abilities;
+        let fill = match state.casting {
+            Some((index, remaining)) => {
+                let cast_time = db.abilities[index].cast_time.max(0.001);
+                Some((1.0 - remaining / cast_time, CAST_COLOR))
+            }
+            None => state
+                .cooldowns
+                .iter()
+                .map(|(&index, &remaining)| remaining / db.abilities[index].cooldown.max(0.001))
+                .max_by(f32::total_cmp)
+                .map(|left| (left, COOLDOWN_COLOR)),
+        };
+        let Some((fill, color)) = fill else {
+            continue;
+        };
+        let hitbox = ent.hitbox(db);
+        if ent.instance.is_corpse() || !view.rect_visible(hitbox) {
+            continue;
+        }
+        let x = hitbox.center().x - BAR_WIDTH * 0.5;
+        let y = hitbox.y - BAR_GAP - BAR_HEIGHT;
+        draw_rectangle(x, y, BAR_WIDTH, BAR_HEIGHT, BAR_BACK);
+        draw_rectangle(x, y, BAR_WIDTH * fill.clamp(0.0, 1.0), BAR_HEIGHT, color);
+    }
+}
+
+pub fn load_abilities(dir: &Path) -> Result<Vec<AbilityDef>, EntityLoadError> {
+    let mut abilities = Vec::new();
+    if !dir.exists() {
+        return Ok(abilities);
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_yaml(&path) {
+            continue;
+        }
+        abilities.push(serde_yaml::from_str(&std::fs::read_to_string(&path)?)?);
+    }
+
+    Ok(abilities)
+}
+
+pub async fn load_abilities_wasm(dir: &str) -> Result<Vec<AbilityDef>, EntityLoadError> {
+    let mut abilities = Vec::new();
+    let files = load_wasm_manifest_files(dir, &["spit_volley.yaml", "screech.yaml"]).await;
+    for file in files {
+        let path = format!("{}/{}", dir, file);
+        let raw_str = load_string(&path)
+            .await
+            .map_err(|err| EntityLoadError::Io(std::io::Error::other(err.to_string())))?;
+        abilities.push(serde_yaml::from_str(&raw_str)?);
+    }
+    Ok(abilities)
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,363 @@
+//! Ambient critters: butterflies, frogs and whatever else makes the map feel lived in.
+//!
+//! Critters are not entities. Each is a position, a heading and a couple of timers moved by its
+//! def's `motion`, with no behaviour tree, collision, loot or saving. With an `ambient` block in
+//! world.yaml, critters from `src/ambient/*.yaml` are scattered just outside the view, weighted by
+//! the biome underneath and filtered by time of day, and dropped as soon as they leave the padded
+//! view. Walking up to one makes it scatter.
+
+use crate::asset_pack::load_string;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::cull::{ViewCull, expand_rect};
+use crate::helpers::{data_path, load_wasm_manifest_files, random_f32};
+use crate::map::TileMap;
+use crate::time::{SpawnTime, WorldTime};
+
+/// Positions tried per spawn before giving up until the next interval.
+const PLACEMENT_ATTEMPTS: u32 = 6;
+/// Seconds a hop lasts.
+const HOP_TIME: f32 = 0.25;
+const HOP_HEIGHT: f32 = 3.0;
+/// Wing beats per second, in radians.
+const FLAP_RATE: f32 = 14.0;
+/// Speed multiplier while scattering from the player.
+const FLEE_BOOST: f32 = 3.0;
+
+#[derive(Debug)]
+pub enum CritterLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for CritterLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CritterLoadError {}
+
+impl From<std::io::Error> for CritterLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for CritterLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AmbientSettings {
+    /// Seconds between spawns.
+    #[serde(default = "default_interval")]
+    pub interval: f32,
+    /// Cap on critters alive at once across every def.
+    #[serde(default = "default_max_alive")]
+    pub max_alive: u32,
+    /// How far past the view critters spawn and live, in world pixels.
+    #[serde(default = "default_margin")]
+    pub margin: f32,
+}
+
+fn default_interval() -> f32 {
+    0.4
+}
+
+fn default_max_alive() -> u32 {
+    16
+}
+
+fn default_margin() -> f32 {
+    48.0
+}
+
+/// How a critter gets around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CritterMotion {
+    /// Drifts on a wandering heading, ignoring walls and water.
+    Flutter,
+    /// Sits, then hops a short way; never onto solid tiles.
+    Hop,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CritterDef {
+    pub id: String,
+    pub motion: CritterMotion,
+    /// Each critter picks one of these.
+    pub colors: Vec<[u8; 3]>,
+    /// Width and height in world pixels.
+    #[serde(default = "default_size")]
+    pub size: [f32; 2],
+    /// Pixels per second while moving.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Spawn weight by biome tag; empty means weight 1 anywhere. Maps without a generator have
+    /// no biomes, so defs that list some never appear there.
+    #[serde(default)]
+    pub biomes: HashMap<String, f32>,
+    #[serde(default)]
+    pub time: SpawnTime,
+    /// Most of this def alive at once.
+    #[serde(default = "default_def_max_alive")]
+    pub max_alive: u32,
+    /// The player coming this close makes it scatter.
+    #[serde(default = "default_flee_distance")]
+    pub flee_distance: f32,
+}
+
+fn default_size() -> [f32; 2] {
+    [3.0, 3.0]
+}
+
+fn default_speed() -> f32 {
+    16.0
+}
+
+fn default_def_max_alive() -> u32 {
+    8
+}
+
+fn default_flee_distance() -> f32 {
+    28.0
+}
+
+impl CritterDef {
+    fn weight_in(&self, biome: Option<&str>) -> f32 {
+        if self.biomes.is_empty() {
+            return 1.0;
+        }
+        biome.and_then(|tag| self.biomes.get(tag)).copied().unwrap_or(0.0).max(0.0)
+    }
+}
+
+struct Critter {
+    def: usize,
+    pos: Vec2,
+    heading: Vec2,
+    color: Color,
+    /// Seconds until the next turn or hop.
+    timer: f32,
+    /// Seconds left in the current hop.
+    hop: f32,
+    /// Wing beat phase.
+    phase: f32,
+}
+
+#[derive(Default)]
+pub struct AmbientLife {
+    settings: Option<AmbientSettings>,
+    defs: Vec<CritterDef>,
+    critters: Vec<Critter>,
+    timer: f32,
+}
+
+impl AmbientLife {
+    /// Loads every def in `dir`; without settings no critter ever appears.
+    pub async fn load_from(
+        dir: impl AsRef<Path>,
+        settings: Option<AmbientSettings>,
+    ) -> Result<Self, CritterLoadError> {
+        let dir = dir.as_ref();
+        let mut defs = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["butterfly.yaml", "frog.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| CritterLoadError::Io(std::io::Error::other(err.to_string())))?;
+                defs.push(serde_yaml::from_str::<CritterDef>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                defs.push(serde_yaml::from_str::<CritterDef>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+        defs.retain(|def| !def.colors.is_empty());
+        defs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(Self {
+            settings,
+            defs,
+            critters: Vec::new(),
+            timer: 0.0,
+        })
+    }
+
+    /// Drops every critter, e.g. after loading a save or changing level.
+    pub fn clear(&mut self) {
+        self.critters.clear();
+        self.timer = 0.0;
+    }
+
+    /// Call every frame with the camera's view in world space.
+    pub fn update(&mut self, dt: f32, view: Rect, map: &TileMap, time: &WorldTime, player_pos: Vec2) {
+        let Some(settings) = self.settings.as_ref() else {
+            return;
+        };
+        let bounds = expand_rect(view, settings.margin.max(0.0));
+        let defs = &self.defs;
+        self.critters
+            .retain(|critter| bounds.contains(critter.pos) && time.allows(defs[critter.def].time));
+        for critter in &mut self.critters {
+            step(critter, &defs[critter.def], dt, map, player_pos);
+        }
+
+        self.timer -= dt;
+        if self.timer > 0.0 {
+            return;
+        }
+        self.timer = settings.interval.max(0.05);
+        if self.critters.len() >= settings.max_alive as usize {
+            return;
+        }
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let pos = vec2(
+                rand::gen_range(bounds.x, bounds.right()),
+                rand::gen_range(bounds.y, bounds.bottom()),
+            );
+            if view.contains(pos) || !open_ground(map, pos) {
+                continue;
+            }
+            if let Some(def) = self.pick(map.biome_at(pos).map(|biome| biome.tag()), time) {
+                let colors = &self.defs[def].colors;
+                let [r, g, b] = colors[rand::gen_range(0, colors.len())];
+                self.critters.push(Critter {
+                    def,
+                    pos,
+                    heading: Vec2::from_angle(rand::gen_range(0.0, std::f32::consts::TAU)),
+                    color: Color::from_rgba(r, g, b, 255),
+                    timer: rand::gen_range(0.2, 1.5),
+                    hop: 0.0,
+                    phase: rand::gen_range(0.0, std::f32::consts::TAU),
+                });
+                return;
+            }
+        }
+    }
+
+    /// Picks a def by its weight in `biome` among those allowed now and under their cap.
+    fn pick(&self, biome: Option<&str>, time: &WorldTime) -> Option<usize> {
+        let weights: Vec<f32> = self
+            .defs
+            .iter()
+            .enumerate()
+            .map(|(index, def)| {
+                let alive = self.critters.iter().filter(|critter| critter.def == index).count();
+                if alive < def.max_alive as usize && time.allows(def.time) {
+                    def.weight_in(biome)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut roll = random_f32() * total;
+        weights.iter().position(|&weight| {
+            roll -= weight;
+            weight > 0.0 && roll <= 0.0
+        })
+    }
+
+    pub fn draw_in_view(&self, view: &ViewCull) {
+        for critter in &self.critters {
+            let def = &self.defs[critter.def];
+            let [w, h] = def.size;
+            let lift = match def.motion {
+                CritterMotion::Flutter => 2.0 + critter.phase.sin() * 1.5,
+                CritterMotion::Hop => (critter.hop / HOP_TIME * std::f32::consts::PI).sin() * HOP_HEIGHT,
+            };
+            let rect = Rect::new(critter.pos.x - w * 0.5, critter.pos.y - h - lift, w, h);
+            let alpha = view.fade_alpha(rect);
+            if alpha <= 0.0 {
+                continue;
+            }
+            let color = Color { a: alpha, ..critter.color };
+            match def.motion {
+                CritterMotion::Flutter => {
+                    let wing = (critter.phase * 2.0).sin().abs().max(0.2) * w * 0.5;
+                    draw_rectangle(critter.pos.x - wing, rect.y, wing, h, color);
+                    draw_rectangle(critter.pos.x, rect.y, wing, h, color);
+                }
+                CritterMotion::Hop => draw_rectangle(rect.x, rect.y, w, h, color),
+            }
+        }
+    }
+}
+
+fn step(critter: &mut Critter, def: &CritterDef, dt: f32, map: &TileMap, player_pos: Vec2) {
+    let away = critter.pos - player_pos;
+    let fleeing = away.length_squared() < def.flee_distance * def.flee_distance;
+    critter.timer -= dt;
+    match def.motion {
+        CritterMotion::Flutter => {
+            critter.phase += FLAP_RATE * dt;
+            if fleeing {
+                critter.heading = away.normalize_or(critter.heading);
+            } else if critter.timer <= 0.0 {
+                let turn = rand::gen_range(-1.2, 1.2);
+                critter.heading = Vec2::from_angle(turn).rotate(critter.heading);
+                critter.timer = rand::gen_range(0.4, 1.2);
+            }
+            let speed = if fleeing { def.speed * FLEE_BOOST } else { def.speed };
+            critter.pos += critter.heading * speed * dt;
+        }
+        CritterMotion::Hop => {
+            if critter.hop > 0.0 {
+                critter.hop -= dt;
+                let next = critter.pos + critter.heading * def.speed * dt;
+                if open_ground(map, next) {
+                    critter.pos = next;
+                } else {
+                    critter.hop = 0.0;
+                }
+            } else if fleeing || critter.timer <= 0.0 {
+                critter.heading = if fleeing {
+                    away.normalize_or(critter.heading)
+                } else {
+                    Vec2::from_angle(rand::gen_range(0.0, std::f32::consts::TAU))
+                };
+                critter.hop = HOP_TIME;
+                critter.timer = rand::gen_range(1.0, 3.0);
+            }
+        }
+    }
+}
+
+/// Whether `pos` is on a loaded tile that isn't solid.
+fn open_ground(map: &TileMap, pos: Vec2) -> bool {
+    if pos.x < 0.0 || pos.y < 0.0 {
+        return false;
+    }
+    let x = (pos.x / map.tile_size()) as usize;
+    let y = (pos.y / map.tile_size()) as usize;
+    map.is_tile_loaded(x, y) && !map.is_solid(x, y)
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
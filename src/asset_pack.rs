@@ -0,0 +1,153 @@
+//! Compressed asset pack for the web build.
+//!
+//! Loading every YAML, JSON, PNG and sound on its own costs the web build hundreds of HTTP
+//! requests. `scripts/build-asset-pack.sh` bundles the data and assets into `assets.tar.gz`
+//! instead, under the same `assets/...` paths `data_path` and `asset_path` give on wasm. The game
+//! mounts it once at startup, and the `load_*` functions here, used in place of macroquad's,
+//! serve files from it and fall back to fetching them one by one. Without a pack, or on native
+//! builds, everything loads from loose files as before.
+
+use macroquad::audio::{load_sound_from_bytes, Sound};
+use macroquad::prelude::*;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::OnceLock;
+
+/// Pack fetched by `mount` on the web build, next to index.html.
+pub const PACK_PATH: &str = "assets.tar.gz";
+const TAR_BLOCK: usize = 512;
+
+static PACK: OnceLock<HashMap<String, Vec<u8>>> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum AssetPackError {
+    Io(std::io::Error),
+    Format(String),
+    /// A pack is already mounted.
+    Mounted,
+}
+
+impl std::fmt::Display for AssetPackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Format(msg) => write!(f, "bad pack: {msg}"),
+            Self::Mounted => write!(f, "an asset pack is already mounted"),
+        }
+    }
+}
+
+impl std::error::Error for AssetPackError {}
+
+impl From<std::io::Error> for AssetPackError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Fetches and unpacks the pack at `path` so later loads are served from memory. Returns the
+/// number of files in it.
+pub async fn mount(path: &str) -> Result<usize, AssetPackError> {
+    let bytes = macroquad::file::load_file(path)
+        .await
+        .map_err(|err| AssetPackError::Io(std::io::Error::other(err.to_string())))?;
+    let files = unpack(&bytes)?;
+    let count = files.len();
+    PACK.set(files).map_err(|_| AssetPackError::Mounted)?;
+    Ok(count)
+}
+
+/// Files of a gzip-compressed tar, by path.
+pub fn unpack(bytes: &[u8]) -> Result<HashMap<String, Vec<u8>>, AssetPackError> {
+    let mut tar = Vec::new();
+    flate2::read::GzDecoder::new(bytes).read_to_end(&mut tar)?;
+    read_tar(&tar)
+}
+
+/// Reads regular files out of a tar, following GNU long names and pax `path` records.
+fn read_tar(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, AssetPackError> {
+    let mut files = HashMap::new();
+    let mut long_name: Option<String> = None;
+    let mut offset = 0;
+    while offset + TAR_BLOCK <= data.len() {
+        let header = &data[offset..offset + TAR_BLOCK];
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let size = parse_octal(&header[124..136])
+            .ok_or_else(|| AssetPackError::Format(format!("bad entry size at byte {offset}")))?;
+        let start = offset + TAR_BLOCK;
+        let body = data
+            .get(start..start + size)
+            .ok_or_else(|| AssetPackError::Format(format!("entry at byte {offset} is cut off")))?;
+        match header[156] {
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| header_name(header));
+                files.insert(normalize(&name).to_string(), body.to_vec());
+            }
+            b'L' => long_name = Some(c_string(body)),
+            b'x' => long_name = pax_path(body).or(long_name),
+            _ => long_name = None,
+        }
+        offset = start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+    }
+    Ok(files)
+}
+
+fn header_name(header: &[u8]) -> String {
+    let name = c_string(&header[0..100]);
+    let prefix = if &header[257..262] == b"ustar" { c_string(&header[345..500]) } else { String::new() };
+    if prefix.is_empty() { name } else { format!("{prefix}/{name}") }
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let text = c_string(field);
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(text, 8).ok()
+}
+
+/// The `path` of a pax header; records are "<len> <key>=<value>\n".
+fn pax_path(body: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, field)| field))
+        .find_map(|field| field.strip_prefix("path=").map(str::to_string))
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// `macroquad::file::load_file` that checks the mounted pack first.
+pub async fn load_file(path: &str) -> Result<Vec<u8>, macroquad::Error> {
+    if let Some(bytes) = PACK.get().and_then(|pack| pack.get(normalize(path))) {
+        return Ok(bytes.clone());
+    }
+    macroquad::file::load_file(path).await
+}
+
+/// `macroquad::file::load_string` that checks the mounted pack first.
+pub async fn load_string(path: &str) -> Result<String, macroquad::Error> {
+    let bytes = load_file(path).await?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// `macroquad::texture::load_texture` that checks the mounted pack first.
+pub async fn load_texture(path: &str) -> Result<Texture2D, macroquad::Error> {
+    let bytes = load_file(path).await?;
+    Ok(Texture2D::from_file_with_format(&bytes, None))
+}
+
+/// `macroquad::audio::load_sound` that checks the mounted pack first.
+pub async fn load_sound(path: &str) -> Result<Sound, macroquad::Error> {
+    let bytes = load_file(path).await?;
+    load_sound_from_bytes(&bytes).await
+}
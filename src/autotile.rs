@@ -0,0 +1,234 @@
+//! Terrain autotiling rules.
+//!
+//! Each terrain picks its tile from which neighbours belong to the same (or a connected)
+//! terrain. `cardinal` terrains use a 4-bit mask of N/E/S/W; `blob` terrains add the diagonals,
+//! counting a corner only when both sides next to it are set, which leaves the usual 47 cases.
+//! Masks without a tile fall back to their cardinal bits and then to the terrain's default tile.
+
+use crate::asset_pack::load_string;
+use serde::Deserialize;
+use std::collections::HashMap;
+use crate::helpers::data_path;
+use crate::map::LayerKind;
+
+pub const MASK_N: u8 = 1;
+pub const MASK_E: u8 = 1 << 1;
+pub const MASK_S: u8 = 1 << 2;
+pub const MASK_W: u8 = 1 << 3;
+pub const MASK_NE: u8 = 1 << 4;
+pub const MASK_SE: u8 = 1 << 5;
+pub const MASK_SW: u8 = 1 << 6;
+pub const MASK_NW: u8 = 1 << 7;
+const MASK_CARDINAL: u8 = MASK_N | MASK_E | MASK_S | MASK_W;
+
+/// Terrain index stored per tile; 0 means no terrain.
+pub type TerrainId = u8;
+pub const NO_TERRAIN: TerrainId = 0;
+
+#[derive(Debug)]
+pub enum AutotileLoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for AutotileLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Json(err) => write!(f, "json error: {err}"),
+            Self::Invalid(err) => write!(f, "invalid autotile rules: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AutotileLoadError {}
+
+impl From<std::io::Error> for AutotileLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for AutotileLoadError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AutotileMode {
+    Cardinal,
+    Blob,
+}
+
+#[derive(Clone)]
+pub struct TerrainRule {
+    pub layer: LayerKind,
+    pub mode: AutotileMode,
+    pub default_tile: u8,
+    pub tiles: HashMap<u8, u8>,
+    /// Other terrains this one blends into as if they were the same terrain.
+    pub connects: Vec<TerrainId>,
+}
+
+impl TerrainRule {
+    pub fn tile_for(&self, mask: u8) -> u8 {
+        self.tiles
+            .get(&mask)
+            .or_else(|| self.tiles.get(&(mask & MASK_CARDINAL)))
+            .copied()
+            .unwrap_or(self.default_tile)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct AutotileRules {
+    terrains: Vec<TerrainRule>,
+    lookup: HashMap<String, TerrainId>,
+}
+
+impl AutotileRules {
+    pub async fn load(path: &str) -> Result<Self, AutotileLoadError> {
+        let raw_str = if cfg!(target_arch = "wasm32") {
+            load_string(&data_path(path))
+                .await
+                .map_err(|err| AutotileLoadError::Io(std::io::Error::other(err.to_string())))?
+        } else {
+            std::fs::read_to_string(path)?
+        };
+        let raw: AutotileFile = serde_json::from_str(&raw_str)?;
+        Self::from_file(raw)
+    }
+
+    fn from_file(raw: AutotileFile) -> Result<Self, AutotileLoadError> {
+        if raw.terrains.len() >= TerrainId::MAX as usize {
+            return Err(AutotileLoadError::Invalid("too many terrains".to_string()));
+        }
+        let lookup: HashMap<String, TerrainId> = raw
+            .terrains
+            .iter()
+            .enumerate()
+            .map(|(index, terrain)| (terrain.id.clone(), index as TerrainId + 1))
+            .collect();
+
+        let mut terrains = Vec::with_capacity(raw.terrains.len());
+        for terrain in raw.terrains {
+            let layer = match terrain.layer.as_deref() {
+                Some("background") | None => LayerKind::Background,
+                Some("foreground") => LayerKind::Foreground,
+                Some("overlay") => LayerKind::Overlay,
+                Some(other) => {
+                    return Err(AutotileLoadError::Invalid(format!(
+                        "terrain '{}' has unknown layer '{other}'",
+                        terrain.id
+                    )));
+                }
+            };
+            let mode = match terrain.mode.as_deref() {
+                Some("cardinal") | None => AutotileMode::Cardinal,
+                Some("blob") => AutotileMode::Blob,
+                Some(other) => {
+                    return Err(AutotileLoadError::Invalid(format!(
+                        "terrain '{}' has unknown mode '{other}'",
+                        terrain.id
+                    )));
+                }
+            };
+            let mut tiles = HashMap::with_capacity(terrain.tiles.len());
+            for (mask, tile) in terrain.tiles {
+                let mask: u8 = mask.parse().map_err(|_| {
+                    AutotileLoadError::Invalid(format!("terrain '{}' has invalid mask '{mask}'", terrain.id))
+                })?;
+                tiles.insert(mask, tile);
+            }
+            let connects = terrain
+                .connects
+                .iter()
+                .filter_map(|other| {
+                    let id = lookup.get(other).copied();
+                    if id.is_none() {
+                        eprintln!("terrain '{}' connects to unknown terrain '{other}'", terrain.id);
+                    }
+                    id
+                })
+                .collect();
+            terrains.push(TerrainRule {
+                layer,
+                mode,
+                default_tile: terrain.default_tile,
+                tiles,
+                connects,
+            });
+        }
+        Ok(Self { terrains, lookup })
+    }
+
+    pub fn terrain_id(&self, id: &str) -> Option<TerrainId> {
+        self.lookup.get(id).copied()
+    }
+
+    pub fn rule(&self, terrain: TerrainId) -> Option<&TerrainRule> {
+        if terrain == NO_TERRAIN {
+            return None;
+        }
+        self.terrains.get(terrain as usize - 1)
+    }
+
+    /// Neighbour mask for a tile of `terrain`; `terrain_at` returns `None` outside the map, which
+    /// counts as connected so terrain runs cleanly off the map edge.
+    pub fn mask(
+        &self,
+        terrain: TerrainId,
+        terrain_at: impl Fn(i32, i32) -> Option<TerrainId>,
+    ) -> u8 {
+        let Some(rule) = self.rule(terrain) else {
+            return 0;
+        };
+        let joins = |dx: i32, dy: i32| {
+            terrain_at(dx, dy).is_none_or(|other| other == terrain || rule.connects.contains(&other))
+        };
+        let mut mask = 0;
+        for (bit, dx, dy) in [(MASK_N, 0, -1), (MASK_E, 1, 0), (MASK_S, 0, 1), (MASK_W, -1, 0)] {
+            if joins(dx, dy) {
+                mask |= bit;
+            }
+        }
+        if rule.mode == AutotileMode::Blob {
+            let corners = [
+                (MASK_NE, MASK_N | MASK_E, 1, -1),
+                (MASK_SE, MASK_S | MASK_E, 1, 1),
+                (MASK_SW, MASK_S | MASK_W, -1, 1),
+                (MASK_NW, MASK_N | MASK_W, -1, -1),
+            ];
+            for (bit, sides, dx, dy) in corners {
+                if mask & sides == sides && joins(dx, dy) {
+                    mask |= bit;
+                }
+            }
+        }
+        mask
+    }
+}
+
+#[derive(Deserialize)]
+struct AutotileFile {
+    terrains: Vec<TerrainFile>,
+}
+
+#[derive(Deserialize)]
+struct TerrainFile {
+    id: String,
+    /// "background" (default), "foreground" or "overlay".
+    #[serde(default)]
+    layer: Option<String>,
+    /// "cardinal" (default) or "blob".
+    #[serde(default)]
+    mode: Option<String>,
+    default_tile: u8,
+    /// Mask (as a decimal string) to tile id.
+    #[serde(default)]
+    tiles: HashMap<String, u8>,
+    #[serde(default)]
+    connects: Vec<String>,
+}
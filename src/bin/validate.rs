@@ -0,0 +1,30 @@
+//! Checks the game's content for broken references without starting the game.
+//!
+//! Run from the game directory: `cargo run --bin validate [ROOT...]`. Without arguments it checks
+//! `src` and every mod under `mods/`. Exits non-zero when anything is wrong.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rustycropbot::mods::{MODS_DIR, ModSet};
+use rustycropbot::validate::validate_content;
+
+fn main() -> ExitCode {
+    let mut roots: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if roots.is_empty() {
+        roots.push(PathBuf::from("src"));
+        roots.extend(ModSet::scan(MODS_DIR).roots().map(PathBuf::from));
+    }
+
+    let issues = validate_content(&roots);
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+    if issues.is_empty() {
+        println!("content ok");
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("{} problem(s) found", issues.len());
+        ExitCode::FAILURE
+    }
+}
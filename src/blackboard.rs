@@ -0,0 +1,65 @@
+//! Per-entity behaviour variables.
+//!
+//! Actions write named values, like `remember_target` storing where the target was last seen, and
+//! conditions read them back through their `var`, so a behaviour tree can act on what happened in
+//! earlier frames. Values are typed; a condition that expects a number fails on a position, the
+//! same as on a variable that was never set.
+
+use macroquad::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlackboardValue {
+    Number(f32),
+    Flag(bool),
+    Position(Vec2),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Blackboard {
+    values: HashMap<String, BlackboardValue>,
+}
+
+impl Blackboard {
+    pub fn get(&self, name: &str) -> Option<BlackboardValue> {
+        self.values.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, value: BlackboardValue) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.values.remove(name);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    pub fn number(&self, name: &str) -> Option<f32> {
+        match self.get(name)? {
+            BlackboardValue::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn flag(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            BlackboardValue::Flag(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn position(&self, name: &str) -> Option<Vec2> {
+        match self.get(name)? {
+            BlackboardValue::Position(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Every variable with its value, for debugging.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, BlackboardValue)> {
+        self.values.iter().map(|(name, &value)| (name.as_str(), value))
+    }
+}
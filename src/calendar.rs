@@ -0,0 +1,245 @@
+//! Scheduled world events defined in `src/event/*.yaml`.
+//!
+//! An event happens on `day` and then every `every` days (only once when `every` is 0). A toast
+//! announces it `announce` days ahead and again when it starts. For that day it can add stock
+//! to every NPC shop, spawn extra entities around the player (held back to day or night like
+//! structure spawns), and change which structures can be used: structures an event `opens` only
+//! work while it runs, and structures it `closes` don't.
+
+use crate::asset_pack::load_string;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+use crate::entity::ShopOffer;
+use crate::helpers::{data_path, load_wasm_manifest_files};
+use crate::map::EntitySpawn;
+use crate::time::SpawnTime;
+use crate::ui::{UiBatch, UiLayer};
+
+/// Event spawns appear this far from the player, in a random direction.
+const SPAWN_DISTANCE: f32 = 240.0;
+const TOAST_TIME: f32 = 5.0;
+const TOAST_FADE: f32 = 1.0;
+
+#[derive(Debug)]
+pub enum CalendarLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for CalendarLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CalendarLoadError {}
+
+impl From<std::io::Error> for CalendarLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for CalendarLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct EventSpawn {
+    pub entity: String,
+    #[serde(default = "default_spawn_count")]
+    pub count: u32,
+    #[serde(default)]
+    pub time: SpawnTime,
+}
+
+fn default_spawn_count() -> u32 {
+    1
+}
+
+fn default_announce() -> u32 {
+    1
+}
+
+#[derive(Clone, Deserialize)]
+pub struct EventDef {
+    pub id: String,
+    pub name: String,
+    /// First day the event happens.
+    pub day: u32,
+    /// Days between repeats; 0 for a one-off.
+    #[serde(default)]
+    pub every: u32,
+    /// Days ahead of the event its announcement shows; 0 announces it only when it starts.
+    #[serde(default = "default_announce")]
+    pub announce: u32,
+    /// Extra stock in every NPC shop.
+    #[serde(default)]
+    pub shop: Vec<ShopOffer>,
+    #[serde(default)]
+    pub spawns: Vec<EventSpawn>,
+    /// Structure ids that can only be used during the event.
+    #[serde(default)]
+    pub opens: Vec<String>,
+    /// Structure ids that can't be used during the event.
+    #[serde(default)]
+    pub closes: Vec<String>,
+}
+
+impl EventDef {
+    pub fn occurs_on(&self, day: u32) -> bool {
+        // A multiple of 0 is only 0, so one-offs happen on `day` alone.
+        day >= self.day && (day - self.day).is_multiple_of(self.every)
+    }
+}
+
+struct Toast {
+    text: String,
+    timer: f32,
+}
+
+pub struct Calendar {
+    events: Vec<EventDef>,
+    /// Last day `update` started; 0 before the first.
+    day: u32,
+    toasts: Vec<Toast>,
+}
+
+impl Calendar {
+    pub fn empty() -> Self {
+        Self {
+            events: Vec::new(),
+            day: 0,
+            toasts: Vec::new(),
+        }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, CalendarLoadError> {
+        let dir = dir.as_ref();
+        let mut events = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["market_day.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| CalendarLoadError::Io(std::io::Error::other(err.to_string())))?;
+                events.push(serde_yaml::from_str::<EventDef>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                events.push(serde_yaml::from_str::<EventDef>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+        events.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(Self {
+            events,
+            day: 0,
+            toasts: Vec::new(),
+        })
+    }
+
+    /// Call every frame. On the first call of a new day, announces upcoming events, starts
+    /// today's and returns the entities they spawn; otherwise returns None.
+    pub fn update(&mut self, day: u32, player_pos: Vec2, dt: f32) -> Option<Vec<EntitySpawn>> {
+        for toast in self.toasts.iter_mut() {
+            toast.timer -= dt;
+        }
+        self.toasts.retain(|toast| toast.timer > 0.0);
+        if day == self.day {
+            return None;
+        }
+        self.day = day;
+
+        let mut spawns = Vec::new();
+        let mut toasts = Vec::new();
+        for event in &self.events {
+            if event.occurs_on(day) {
+                toasts.push(format!("{} today!", event.name));
+                for spawn in &event.spawns {
+                    for _ in 0..spawn.count {
+                        let angle = rand::gen_range(0.0, std::f32::consts::TAU);
+                        spawns.push(EntitySpawn {
+                            entity: spawn.entity.clone(),
+                            pos: player_pos + Vec2::from_angle(angle) * SPAWN_DISTANCE,
+                            time: spawn.time,
+                        });
+                    }
+                }
+            }
+            if event.announce > 0 && event.occurs_on(day + event.announce) {
+                let when = match event.announce {
+                    1 => "tomorrow".to_string(),
+                    days => format!("in {days} days"),
+                };
+                toasts.push(format!("{} {when}", event.name));
+            }
+        }
+        self.toasts.extend(toasts.into_iter().map(|text| Toast {
+            text,
+            timer: TOAST_TIME,
+        }));
+        Some(spawns)
+    }
+
+    /// Treats `day` as already started, so loading a save doesn't replay its events.
+    pub fn skip_to(&mut self, day: u32) {
+        self.day = day;
+        self.toasts.clear();
+    }
+
+    /// Events running today.
+    pub fn active(&self) -> impl Iterator<Item = &EventDef> {
+        self.events.iter().filter(|event| event.occurs_on(self.day))
+    }
+
+    /// Extra shop stock from today's events.
+    pub fn shop_offers(&self) -> Vec<ShopOffer> {
+        self.active().flat_map(|event| event.shop.iter().cloned()).collect()
+    }
+
+    pub fn structure_open(&self, structure_id: &str) -> bool {
+        if self.active().any(|event| event.closes.iter().any(|id| id == structure_id)) {
+            return false;
+        }
+        let opened_by_event = self.events.iter().any(|event| event.opens.iter().any(|id| id == structure_id));
+        !opened_by_event || self.active().any(|event| event.opens.iter().any(|id| id == structure_id))
+    }
+
+    /// Queues the announcement toasts.
+    pub fn draw_ui(&self, ui: &mut UiBatch) {
+        for (index, toast) in self.toasts.iter().enumerate() {
+            let alpha = (toast.timer / TOAST_FADE).min(1.0);
+            let size = measure_text(&toast.text, None, 22, 1.0);
+            let x = (screen_width() - size.width) * 0.5;
+            let y = 70.0 + index as f32 * 30.0;
+            ui.rect(
+                UiLayer::Toast,
+                Rect::new(x - 10.0, y - size.offset_y - 6.0, size.width + 20.0, size.height + 12.0),
+                Color::new(0.0, 0.0, 0.0, 0.6 * alpha),
+            );
+            ui.text(UiLayer::Toast, toast.text.as_str(), vec2(x, y), 22.0, Color::new(1.0, 0.9, 0.5, alpha));
+        }
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,148 @@
+//! Non-lethal takedowns with the capture net.
+//!
+//! With a net in the inventory, C swings it at the nearest capturable creature in front of the
+//! player. The net hurts like any hit, but a creature it brings to 0 hp is knocked out and
+//! packed into a crate item instead of dying. R lets the first crate out next to the player as
+//! a tamed creature: it never goes after the player and stays around where it was released, so
+//! it can be kept in a pen with a trough.
+
+use macroquad::prelude::*;
+use crate::entity::{EntityDatabase, DEF_FLAG_CAPTURABLE};
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::item::{crate_item, crated_entity, Inventory};
+use crate::ui::{UiBatch, UiLayer};
+
+pub const NET_ITEM: &str = "capture_net";
+const SWING_KEY: KeyCode = KeyCode::C;
+const RELEASE_KEY: KeyCode = KeyCode::R;
+/// How far in front of the player the net reaches.
+const NET_RANGE: f32 = 28.0;
+const NET_DAMAGE: f32 = 2.0;
+const SWING_COOLDOWN: f32 = 0.5;
+const RELEASE_DISTANCE: f32 = 20.0;
+const STATUS_TIME: f32 = 2.5;
+
+pub enum CaptureEvent {
+    /// The net hit a creature without knocking it out.
+    Hit { pos: Vec2 },
+    /// A creature went into a crate; the caller removes the entity.
+    Captured { handle: EntityHandle, pos: Vec2 },
+    /// A crate was opened; the caller spawns the entity, tamed.
+    Released { entity: String, pos: Vec2 },
+}
+
+#[derive(Default)]
+pub struct CaptureNet {
+    cooldown: f32,
+    /// Added to `NET_RANGE` and `NET_DAMAGE` by upgrades.
+    extra_range: f32,
+    extra_damage: f32,
+    status: String,
+    status_timer: f32,
+}
+
+impl CaptureNet {
+    pub fn set_upgrades(&mut self, extra_range: f32, extra_damage: f32) {
+        self.extra_range = extra_range.max(0.0);
+        self.extra_damage = extra_damage.max(0.0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.cooldown = (self.cooldown - dt).max(0.0);
+        self.status_timer = (self.status_timer - dt).max(0.0);
+    }
+
+    /// Swings the net or opens a crate. Only call while the player is free to act.
+    pub fn handle_input(
+        &mut self,
+        entities: &mut EntityPool,
+        db: &EntityDatabase,
+        inventory: &mut Inventory,
+        player_pos: Vec2,
+        facing: Vec2,
+    ) -> Option<CaptureEvent> {
+        if is_key_pressed(RELEASE_KEY) {
+            return self.release(db, inventory, player_pos, facing);
+        }
+        if !is_key_pressed(SWING_KEY) || self.cooldown > 0.0 {
+            return None;
+        }
+        if !inventory.has(NET_ITEM) {
+            self.set_status("you need a capture net".to_string());
+            return None;
+        }
+        self.cooldown = SWING_COOLDOWN;
+
+        let range = NET_RANGE + self.extra_range;
+        let reach = player_pos + facing.normalize_or_zero() * range * 0.5;
+        let (handle, target) = entities
+            .iter_mut_with_handles()
+            .filter(|(_, ent)| {
+                !ent.instance.is_corpse() && db.entities[ent.instance.def].has_flag(DEF_FLAG_CAPTURABLE)
+            })
+            .map(|(handle, ent)| {
+                let distance = ent.hitbox(db).center().distance(reach);
+                ((handle, ent), distance)
+            })
+            .filter(|(_, distance)| *distance <= range)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(target, _)| target)?;
+
+        let pos = target.hitbox(db).center();
+        target.instance.hp = (target.instance.hp - NET_DAMAGE - self.extra_damage).max(0.0);
+        if target.instance.hp > 0.0 {
+            return Some(CaptureEvent::Hit { pos });
+        }
+        let def = &db.entities[target.instance.def];
+        inventory.add(&crate_item(&def.id), 1);
+        self.set_status(format!("caught a {}", def.name));
+        Some(CaptureEvent::Captured { handle, pos })
+    }
+
+    fn release(
+        &mut self,
+        db: &EntityDatabase,
+        inventory: &mut Inventory,
+        player_pos: Vec2,
+        facing: Vec2,
+    ) -> Option<CaptureEvent> {
+        let mut crates: Vec<&str> = inventory.counts().keys().filter_map(|id| crated_entity(id)).collect();
+        crates.sort_unstable();
+        let Some(entity) = crates.first().map(|id| id.to_string()) else {
+            self.set_status("no crates to open".to_string());
+            return None;
+        };
+        inventory.remove(&crate_item(&entity), 1);
+        if let Some(def) = db.entity_id(&entity).map(|index| &db.entities[index]) {
+            self.set_status(format!("let out a {}", def.name));
+        }
+        Some(CaptureEvent::Released {
+            entity,
+            pos: player_pos + facing.normalize_or_zero() * RELEASE_DISTANCE,
+        })
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    /// Queues the crate count and the last capture message.
+    pub fn draw_ui(&self, inventory: &Inventory, ui: &mut UiBatch) {
+        let crates: u32 = inventory
+            .counts()
+            .iter()
+            .filter(|(id, _)| crated_entity(id).is_some())
+            .map(|(_, &count)| count)
+            .sum();
+        if crates > 0 {
+            let text = format!("crates: {crates} (R to open)");
+            ui.text(UiLayer::Hud, text, vec2(20.0, screen_height() - 20.0), 18.0, WHITE);
+        }
+        if self.status_timer > 0.0 {
+            let size = measure_text(&self.status, None, 20, 1.0);
+            let pos = vec2((screen_width() - size.width) * 0.5, screen_height() * 0.8);
+            ui.text(UiLayer::Toast, self.status.as_str(), pos, 20.0, WHITE);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! View culling shared by everything drawn in the world.
+//!
+//! The game loop builds one `ViewCull` per frame from the camera's view and a padding. Systems
+//! skip whatever lies entirely outside the padded view, and entities fade out across the padding
+//! instead of popping at the screen edge.
+
+use macroquad::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ViewCull {
+    view: Rect,
+    pad: f32,
+    bounds: Rect,
+}
+
+impl ViewCull {
+    pub fn new(view: Rect, pad: f32) -> Self {
+        let pad = pad.max(1.0);
+        Self {
+            view,
+            pad,
+            bounds: expand_rect(view, pad),
+        }
+    }
+
+    /// The view grown by the padding; nothing outside it is drawn.
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    pub fn point_visible(&self, point: Vec2) -> bool {
+        self.bounds.contains(point)
+    }
+
+    pub fn rect_visible(&self, rect: Rect) -> bool {
+        rect.overlaps(&self.bounds)
+    }
+
+    pub fn circle_visible(&self, center: Vec2, radius: f32) -> bool {
+        circle_overlaps_rect(center, radius.max(0.0), self.bounds)
+    }
+
+    /// 1 while `rect` overlaps the view, falling to 0 as its center moves out across the padding.
+    pub fn fade_alpha(&self, rect: Rect) -> f32 {
+        if rect.overlaps(&self.view) {
+            return 1.0;
+        }
+        if !rect.overlaps(&self.bounds) {
+            return 0.0;
+        }
+        let center = rect.center();
+        let nearest = vec2(
+            center.x.clamp(self.view.x, self.view.right()),
+            center.y.clamp(self.view.y, self.view.bottom()),
+        );
+        (1.0 - center.distance(nearest) / self.pad).clamp(0.0, 1.0)
+    }
+}
+
+pub fn expand_rect(rect: Rect, pad: f32) -> Rect {
+    Rect::new(
+        rect.x - pad,
+        rect.y - pad,
+        rect.w + pad * 2.0,
+        rect.h + pad * 2.0,
+    )
+}
+
+pub fn circle_overlaps_rect(center: Vec2, radius: f32, rect: Rect) -> bool {
+    let nearest = vec2(
+        center.x.clamp(rect.x, rect.x + rect.w),
+        center.y.clamp(rect.y, rect.y + rect.h),
+    );
+    center.distance_squared(nearest) <= radius * radius
+}
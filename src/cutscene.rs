@@ -0,0 +1,372 @@
+//! Scripted sequences defined in `src/cutscene/*.yaml`.
+//!
+//! A cutscene is a list of steps run one after another: camera pans, an entity walking to a
+//! spot, dialogue lines, waits, and particle or sound cues. Positions are offsets from an anchor
+//! given when the cutscene starts (the player for the intro, the entity for an introduction),
+//! so the same script works anywhere in the world. While a cutscene plays the world is frozen
+//! and the player has no control; Escape skips to the end.
+
+use crate::asset_pack::load_string;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::entity::EntityDatabase;
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::helpers::{data_path, load_wasm_manifest_files};
+use crate::particle::ParticleSystem;
+use crate::sound::SoundSystem;
+
+/// Height of the letterbox bars while a cutscene plays, as a fraction of the screen.
+const LETTERBOX: f32 = 0.1;
+const DIALOGUE_HEIGHT: f32 = 90.0;
+const DIALOGUE_MARGIN: f32 = 40.0;
+/// Entities closer than this to their walk target have arrived.
+const ARRIVE_DISTANCE: f32 = 1.0;
+
+#[derive(Debug)]
+pub enum CutsceneLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for CutsceneLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CutsceneLoadError {}
+
+impl From<std::io::Error> for CutsceneLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for CutsceneLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CutsceneStep {
+    /// Does nothing for this many seconds.
+    Wait(f32),
+    /// Pans the camera to the anchor plus `offset`.
+    Camera {
+        #[serde(default)]
+        offset: [f32; 2],
+        #[serde(default = "default_pan_time")]
+        time: f32,
+    },
+    /// Pans the camera back to the player, taking this many seconds.
+    ReturnCamera(f32),
+    /// Walks the entity with this id nearest the anchor to the anchor plus `offset`, and waits
+    /// for it to arrive. The spot becomes the entity's new home.
+    Walk {
+        entity: String,
+        #[serde(default)]
+        offset: [f32; 2],
+        #[serde(default = "default_walk_speed")]
+        speed: f32,
+    },
+    /// Shows a line until E, Enter or a click, or for `time` seconds when set.
+    Say {
+        #[serde(default)]
+        speaker: Option<String>,
+        text: String,
+        #[serde(default)]
+        time: Option<f32>,
+    },
+    Particle {
+        id: String,
+        #[serde(default)]
+        offset: [f32; 2],
+    },
+    Sound(String),
+}
+
+fn default_pan_time() -> f32 {
+    1.0
+}
+
+fn default_walk_speed() -> f32 {
+    40.0
+}
+
+fn default_skippable() -> bool {
+    true
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CutsceneDef {
+    pub id: String,
+    #[serde(default = "default_skippable")]
+    pub skippable: bool,
+    pub steps: Vec<CutsceneStep>,
+}
+
+pub struct CutsceneLibrary {
+    defs: HashMap<String, CutsceneDef>,
+}
+
+impl CutsceneLibrary {
+    pub fn empty() -> Self {
+        Self { defs: HashMap::new() }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, CutsceneLoadError> {
+        let dir = dir.as_ref();
+        let mut defs = HashMap::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["intro.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| CutsceneLoadError::Io(std::io::Error::other(err.to_string())))?;
+                let def: CutsceneDef = serde_yaml::from_str(&raw_str)?;
+                defs.insert(def.id.clone(), def);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                let def: CutsceneDef = serde_yaml::from_str(&std::fs::read_to_string(&path)?)?;
+                defs.insert(def.id.clone(), def);
+            }
+        }
+
+        Ok(Self { defs })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CutsceneDef> {
+        self.defs.get(id)
+    }
+}
+
+/// What a cutscene may touch while it plays.
+pub struct CutsceneContext<'a> {
+    pub entities: &'a mut EntityPool,
+    pub db: &'a EntityDatabase,
+    pub particles: &'a mut ParticleSystem,
+    pub sounds: &'a SoundSystem,
+    pub player_pos: Vec2,
+}
+
+struct Playing {
+    def: CutsceneDef,
+    anchor: Vec2,
+    step: usize,
+    /// Seconds spent on the current step.
+    timer: f32,
+    camera: Vec2,
+    /// Camera position when the current pan started.
+    pan_from: Vec2,
+    /// Uid of the entity the current `walk` step moves.
+    walker: Option<EntityHandle>,
+}
+
+impl Playing {
+    fn at(&self, offset: [f32; 2]) -> Vec2 {
+        self.anchor + Vec2::from(offset)
+    }
+}
+
+/// Plays one cutscene at a time and remembers which ones have been seen.
+#[derive(Default)]
+pub struct CutscenePlayer {
+    playing: Option<Playing>,
+    played: HashSet<String>,
+}
+
+impl CutscenePlayer {
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    pub fn has_played(&self, id: &str) -> bool {
+        self.played.contains(id)
+    }
+
+    /// Ids of every cutscene started so far, sorted, for saves.
+    pub fn played(&self) -> Vec<String> {
+        let mut played: Vec<String> = self.played.iter().cloned().collect();
+        played.sort_unstable();
+        played
+    }
+
+    /// Replaces the seen list from a save and stops any cutscene in progress.
+    pub fn restore(&mut self, played: &[String]) {
+        self.playing = None;
+        self.played = played.iter().cloned().collect();
+    }
+
+    /// Starts `def` with positions relative to `anchor`, replacing any cutscene in progress.
+    pub fn play(&mut self, def: &CutsceneDef, anchor: Vec2, camera: Vec2) {
+        self.played.insert(def.id.clone());
+        self.playing = Some(Playing {
+            def: def.clone(),
+            anchor,
+            step: 0,
+            timer: 0.0,
+            camera,
+            pan_from: camera,
+            walker: None,
+        });
+    }
+
+    /// Where the camera should look while a cutscene plays.
+    pub fn camera_focus(&self) -> Option<Vec2> {
+        self.playing.as_ref().map(|playing| playing.camera)
+    }
+
+    pub fn update(&mut self, dt: f32, ctx: &mut CutsceneContext<'_>) {
+        let Some(playing) = self.playing.as_mut() else {
+            return;
+        };
+        if playing.def.skippable && is_key_pressed(KeyCode::Escape) {
+            skip(playing, ctx);
+            self.playing = None;
+            return;
+        }
+
+        playing.timer += dt;
+        while let Some(step) = playing.def.steps.get(playing.step).cloned() {
+            if !run_step(playing, &step, ctx, dt) {
+                return;
+            }
+            playing.step += 1;
+            playing.timer = 0.0;
+            playing.pan_from = playing.camera;
+            playing.walker = None;
+        }
+        self.playing = None;
+    }
+
+    /// Letterbox bars, the current dialogue line, and the skip hint, in screen space.
+    pub fn draw_ui(&self) {
+        let Some(playing) = self.playing.as_ref() else {
+            return;
+        };
+        let bar = screen_height() * LETTERBOX;
+        draw_rectangle(0.0, 0.0, screen_width(), bar, BLACK);
+        draw_rectangle(0.0, screen_height() - bar, screen_width(), bar, BLACK);
+        if playing.def.skippable {
+            draw_text("Esc: skip", screen_width() - 100.0, bar - 10.0, 18.0, GRAY);
+        }
+
+        if let Some(CutsceneStep::Say { speaker, text, .. }) = playing.def.steps.get(playing.step) {
+            let x = DIALOGUE_MARGIN;
+            let y = screen_height() - bar - DIALOGUE_HEIGHT - 10.0;
+            let w = screen_width() - DIALOGUE_MARGIN * 2.0;
+            draw_rectangle(x, y, w, DIALOGUE_HEIGHT, Color::new(0.0, 0.0, 0.0, 0.8));
+            draw_rectangle_lines(x, y, w, DIALOGUE_HEIGHT, 1.0, WHITE);
+            let mut text_y = y + 28.0;
+            if let Some(speaker) = speaker {
+                draw_text(speaker, x + 14.0, text_y, 22.0, YELLOW);
+                text_y += 26.0;
+            }
+            draw_text(text, x + 14.0, text_y, 20.0, WHITE);
+        }
+    }
+}
+
+/// Advances one step; returns true once it is finished.
+fn run_step(playing: &mut Playing, step: &CutsceneStep, ctx: &mut CutsceneContext<'_>, dt: f32) -> bool {
+    match step {
+        CutsceneStep::Wait(time) => playing.timer >= *time,
+        CutsceneStep::Camera { offset, time } => pan(playing, playing.at(*offset), *time),
+        CutsceneStep::ReturnCamera(time) => pan(playing, ctx.player_pos, *time),
+        CutsceneStep::Walk { entity, offset, speed } => {
+            let target = playing.at(*offset);
+            if playing.walker.is_none() {
+                playing.walker = nearest_entity(ctx, entity, playing.anchor);
+            }
+            let Some(ent) = playing.walker.and_then(|handle| ctx.entities.get_mut(handle)) else {
+                return true;
+            };
+            let to_target = target - ent.instance.pos;
+            let step_len = speed * dt;
+            if to_target.length() <= step_len.max(ARRIVE_DISTANCE) {
+                ent.instance.pos = target;
+                ent.instance.home = target;
+                return true;
+            }
+            ent.instance.pos += to_target.normalize() * step_len;
+            false
+        }
+        CutsceneStep::Say { time, .. } => {
+            let pressed = is_key_pressed(KeyCode::E)
+                || is_key_pressed(KeyCode::Enter)
+                || is_mouse_button_pressed(MouseButton::Left);
+            // Ignore the press that may have ended the previous line.
+            (playing.timer > 0.0 && pressed) || time.is_some_and(|time| playing.timer >= time)
+        }
+        CutsceneStep::Particle { id, offset } => {
+            ctx.particles.burst(id, playing.at(*offset));
+            true
+        }
+        CutsceneStep::Sound(id) => {
+            ctx.sounds.play(id);
+            true
+        }
+    }
+}
+
+/// Moves the camera toward `target` with ease-in-out; true once `time` has passed.
+fn pan(playing: &mut Playing, target: Vec2, time: f32) -> bool {
+    let t = if time > 0.0 { (playing.timer / time).clamp(0.0, 1.0) } else { 1.0 };
+    let eased = t * t * (3.0 - 2.0 * t);
+    playing.camera = playing.pan_from.lerp(target, eased);
+    t >= 1.0
+}
+
+/// Puts walkers from the remaining steps where they would have ended up.
+fn skip(playing: &mut Playing, ctx: &mut CutsceneContext<'_>) {
+    for index in playing.step..playing.def.steps.len() {
+        let CutsceneStep::Walk { entity, offset, .. } = &playing.def.steps[index] else {
+            continue;
+        };
+        let walker = playing
+            .walker
+            .filter(|_| index == playing.step)
+            .or_else(|| nearest_entity(ctx, entity, playing.anchor));
+        let target = playing.at(*offset);
+        if let Some(ent) = walker.and_then(|handle| ctx.entities.get_mut(handle)) {
+            ent.instance.pos = target;
+            ent.instance.home = target;
+        }
+    }
+}
+
+fn nearest_entity(ctx: &CutsceneContext<'_>, id: &str, anchor: Vec2) -> Option<EntityHandle> {
+    ctx.entities
+        .iter_with_handles()
+        .filter(|(_, ent)| !ent.instance.is_corpse() && ctx.db.entities[ent.instance.def].id == id)
+        .min_by(|(_, a), (_, b)| {
+            a.instance
+                .pos
+                .distance_squared(anchor)
+                .total_cmp(&b.instance.pos.distance_squared(anchor))
+        })
+        .map(|(handle, _)| handle)
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
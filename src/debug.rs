@@ -0,0 +1,257 @@
+//! Debug profile: which developer tools a build may use and the keys that toggle them.
+//!
+//! The tools are always compiled in. The `debug` block of world.yaml turns each one on and can
+//! rebind its key; without the block every tool stays off, which is how release builds ship.
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Deserializer};
+use crate::entity::EntityDatabase;
+use crate::entity_pool::EntityPool;
+use crate::map::TileMap;
+use crate::ui::{UiBatch, UiLayer};
+
+const SOLID_COLOR: Color = Color::new(1.0, 0.2, 0.2, 0.35);
+const HITBOX_COLOR: Color = Color::new(0.2, 1.0, 0.4, 0.9);
+const PLAYER_HITBOX_COLOR: Color = Color::new(0.3, 0.7, 1.0, 0.9);
+
+#[derive(Clone, Default, Deserialize)]
+pub struct DebugProfile {
+    /// Frames-per-second counter.
+    #[serde(default)]
+    pub fps: bool,
+    /// Entity overlay showing routes, targets, leashes and ranges.
+    #[serde(default)]
+    pub inspector: bool,
+    /// Panel for tuning entity stats and spawning entities.
+    #[serde(default)]
+    pub entity_editor: bool,
+    /// Outlines of solid tiles and every hitbox.
+    #[serde(default)]
+    pub collision: bool,
+    /// Tile editor that paints the map and exports structures.
+    #[serde(default)]
+    pub editor: bool,
+    #[serde(default)]
+    pub keys: DebugKeys,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct DebugKeys {
+    #[serde(default = "default_fps_key", deserialize_with = "deserialize_key")]
+    pub fps: KeyCode,
+    #[serde(default = "default_inspector_key", deserialize_with = "deserialize_key")]
+    pub inspector: KeyCode,
+    #[serde(default = "default_entity_editor_key", deserialize_with = "deserialize_key")]
+    pub entity_editor: KeyCode,
+    #[serde(default = "default_collision_key", deserialize_with = "deserialize_key")]
+    pub collision: KeyCode,
+    #[serde(default = "default_editor_key", deserialize_with = "deserialize_key")]
+    pub editor: KeyCode,
+}
+
+impl Default for DebugKeys {
+    fn default() -> Self {
+        Self {
+            fps: default_fps_key(),
+            inspector: default_inspector_key(),
+            entity_editor: default_entity_editor_key(),
+            collision: default_collision_key(),
+            editor: default_editor_key(),
+        }
+    }
+}
+
+fn default_fps_key() -> KeyCode {
+    KeyCode::F8
+}
+
+fn default_inspector_key() -> KeyCode {
+    KeyCode::F6
+}
+
+fn default_entity_editor_key() -> KeyCode {
+    KeyCode::F4
+}
+
+fn default_collision_key() -> KeyCode {
+    KeyCode::F7
+}
+
+fn default_editor_key() -> KeyCode {
+    KeyCode::F2
+}
+
+fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    key_from_name(&name).ok_or_else(|| serde::de::Error::custom(format!("unknown key `{name}`")))
+}
+
+/// Function keys, letters, digits and a few others by the name on the key, e.g. `F6`, `K`, `7`
+/// or `Backquote`.
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    const FUNCTION_KEYS: [KeyCode; 12] = [
+        KeyCode::F1,
+        KeyCode::F2,
+        KeyCode::F3,
+        KeyCode::F4,
+        KeyCode::F5,
+        KeyCode::F6,
+        KeyCode::F7,
+        KeyCode::F8,
+        KeyCode::F9,
+        KeyCode::F10,
+        KeyCode::F11,
+        KeyCode::F12,
+    ];
+    const LETTERS: [KeyCode; 26] = [
+        KeyCode::A,
+        KeyCode::B,
+        KeyCode::C,
+        KeyCode::D,
+        KeyCode::E,
+        KeyCode::F,
+        KeyCode::G,
+        KeyCode::H,
+        KeyCode::I,
+        KeyCode::J,
+        KeyCode::K,
+        KeyCode::L,
+        KeyCode::M,
+        KeyCode::N,
+        KeyCode::O,
+        KeyCode::P,
+        KeyCode::Q,
+        KeyCode::R,
+        KeyCode::S,
+        KeyCode::T,
+        KeyCode::U,
+        KeyCode::V,
+        KeyCode::W,
+        KeyCode::X,
+        KeyCode::Y,
+        KeyCode::Z,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        KeyCode::Key0,
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    let name = name.trim();
+    match name.to_ascii_lowercase().as_str() {
+        "backquote" | "grave" | "`" => return Some(KeyCode::GraveAccent),
+        "tab" => return Some(KeyCode::Tab),
+        "insert" => return Some(KeyCode::Insert),
+        "home" => return Some(KeyCode::Home),
+        "end" => return Some(KeyCode::End),
+        "pageup" => return Some(KeyCode::PageUp),
+        "pagedown" => return Some(KeyCode::PageDown),
+        _ => {}
+    }
+    if let Some(number) = name.strip_prefix(['F', 'f'])
+        && let Ok(number) = number.parse::<usize>()
+    {
+        return FUNCTION_KEYS.get(number.checked_sub(1)?).copied();
+    }
+    let mut chars = name.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    if c.is_ascii_alphabetic() {
+        return Some(LETTERS[(c.to_ascii_uppercase() as u8 - b'A') as usize]);
+    }
+    c.to_digit(10).map(|digit| DIGITS[digit as usize])
+}
+
+/// The debug tools that don't belong to a larger panel: the FPS counter and collision outlines.
+#[derive(Default)]
+pub struct DebugTools {
+    profile: DebugProfile,
+    show_fps: bool,
+    show_collision: bool,
+}
+
+impl DebugTools {
+    /// The FPS counter starts shown when the profile allows it; collision outlines start hidden.
+    pub fn new(profile: DebugProfile) -> Self {
+        Self {
+            show_fps: profile.fps,
+            show_collision: false,
+            profile,
+        }
+    }
+
+    pub fn update(&mut self) {
+        if self.profile.fps && is_key_pressed(self.profile.keys.fps) {
+            self.show_fps = !self.show_fps;
+        }
+        if self.profile.collision && is_key_pressed(self.profile.keys.collision) {
+            self.show_collision = !self.show_collision;
+        }
+    }
+
+    pub fn fps_visible(&self) -> bool {
+        self.show_fps
+    }
+
+    /// Queues the solid tiles and the hitboxes of entities and the player that `camera` shows, at
+    /// their screen positions under it.
+    pub fn draw_collision(
+        &self,
+        map: &TileMap,
+        camera: &Camera2D,
+        entities: &EntityPool,
+        db: &EntityDatabase,
+        player_hitbox: Rect,
+        ui: &mut UiBatch,
+    ) {
+        if !self.show_collision {
+            return;
+        }
+        let view = screen_rect_to_world(camera);
+        let size = map.tile_size();
+        let first_x = (view.x / size).floor().max(0.0) as usize;
+        let first_y = (view.y / size).floor().max(0.0) as usize;
+        let last_x = (((view.x + view.w) / size).ceil().max(0.0) as usize).min(map.width());
+        let last_y = (((view.y + view.h) / size).ceil().max(0.0) as usize).min(map.height());
+        for y in first_y..last_y {
+            for x in first_x..last_x {
+                if map.is_solid(x, y) {
+                    let tile = Rect::new(x as f32 * size, y as f32 * size, size, size);
+                    ui.rect(UiLayer::World, world_rect_to_screen(camera, tile), SOLID_COLOR);
+                }
+            }
+        }
+        for ent in entities.iter() {
+            if ent.instance.is_corpse() {
+                continue;
+            }
+            let hitbox = ent.hitbox(db);
+            if hitbox.overlaps(&view) {
+                ui.rect_lines(UiLayer::World, world_rect_to_screen(camera, hitbox), 1.0, HITBOX_COLOR);
+            }
+        }
+        ui.rect_lines(UiLayer::World, world_rect_to_screen(camera, player_hitbox), 1.0, PLAYER_HITBOX_COLOR);
+    }
+}
+
+/// The world area `camera` shows across the whole screen.
+fn screen_rect_to_world(camera: &Camera2D) -> Rect {
+    let a = camera.screen_to_world(Vec2::ZERO);
+    let b = camera.screen_to_world(vec2(screen_width(), screen_height()));
+    Rect::new(a.x.min(b.x), a.y.min(b.y), (a.x - b.x).abs(), (a.y - b.y).abs())
+}
+
+/// Where a world-space rect lands on screen under `camera`.
+fn world_rect_to_screen(camera: &Camera2D, rect: Rect) -> Rect {
+    let a = camera.world_to_screen(rect.point());
+    let b = camera.world_to_screen(rect.point() + rect.size());
+    Rect::new(a.x.min(b.x), a.y.min(b.y), (a.x - b.x).abs(), (a.y - b.y).abs())
+}
@@ -0,0 +1,170 @@
+//! Dynamic difficulty.
+//!
+//! With a `director` block in world.yaml, the director watches how the last `window` seconds
+//! went: damage the player took, player deaths and enemies killed. A player taking little damage
+//! while killing quickly pushes its intensity up; heavy damage or a death pulls it down. The
+//! intensity, kept between -1 and 1, scales how many structure and calendar spawns go ahead and
+//! the chance that a spawn comes in as an elite, one tier above its spawn position. Without the
+//! block the director stays neutral.
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+use crate::helpers::random_f32;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DirectorSettings {
+    /// Seconds of history the director judges.
+    #[serde(default = "default_window")]
+    pub window: f32,
+    /// Damage per minute taken that counts as fair; more eases off.
+    #[serde(default = "default_target_damage")]
+    pub target_damage_per_minute: f32,
+    /// Kills per minute that count as fair; more ramps up.
+    #[serde(default = "default_target_kills")]
+    pub target_kills_per_minute: f32,
+    /// Intensity change per second at full pressure.
+    #[serde(default = "default_adjust_rate")]
+    pub adjust_rate: f32,
+    /// Spawn multiplier at the lowest and highest intensity.
+    #[serde(default = "default_min_spawn_scale")]
+    pub min_spawn_scale: f32,
+    #[serde(default = "default_max_spawn_scale")]
+    pub max_spawn_scale: f32,
+    /// Elite chance at the highest intensity; none at or below neutral.
+    #[serde(default = "default_max_elite_chance")]
+    pub max_elite_chance: f32,
+}
+
+#[derive(Clone, Copy)]
+enum Event {
+    Damage(f32),
+    Death,
+    Kill,
+}
+
+#[derive(Default)]
+pub struct Director {
+    settings: Option<DirectorSettings>,
+    /// Events with their age in seconds, oldest first.
+    events: VecDeque<(f32, Event)>,
+    intensity: f32,
+}
+
+impl Director {
+    pub fn new(settings: Option<DirectorSettings>) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    /// -1 (easiest) to 1 (hardest); 0 is neutral.
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn record_damage(&mut self, amount: f32) {
+        if amount > 0.0 {
+            self.record(Event::Damage(amount));
+        }
+    }
+
+    pub fn record_death(&mut self) {
+        self.record(Event::Death);
+    }
+
+    pub fn record_kill(&mut self) {
+        self.record(Event::Kill);
+    }
+
+    fn record(&mut self, event: Event) {
+        if self.settings.is_some() {
+            self.events.push_back((0.0, event));
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let Some(settings) = self.settings.as_ref() else {
+            return;
+        };
+        let window = settings.window.max(1.0);
+        for (age, _) in &mut self.events {
+            *age += dt;
+        }
+        while self.events.front().is_some_and(|&(age, _)| age > window) {
+            self.events.pop_front();
+        }
+
+        let per_minute = 60.0 / window;
+        let mut damage = 0.0;
+        let mut kills = 0.0;
+        let mut died = false;
+        for &(_, event) in &self.events {
+            match event {
+                Event::Damage(amount) => damage += amount,
+                Event::Kill => kills += 1.0,
+                Event::Death => died = true,
+            }
+        }
+        let hurt = damage * per_minute / settings.target_damage_per_minute.max(0.001);
+        let pace = kills * per_minute / settings.target_kills_per_minute.max(0.001);
+        // Above 0 the player is coping; a recent death is the strongest sign they aren't.
+        let pressure = if died { -1.0 } else { ((pace - hurt) * 0.5).clamp(-1.0, 1.0) };
+        self.intensity = (self.intensity + pressure * settings.adjust_rate * dt).clamp(-1.0, 1.0);
+    }
+
+    /// How much spawns are scaled at the current intensity.
+    pub fn spawn_scale(&self) -> f32 {
+        let Some(settings) = self.settings.as_ref() else {
+            return 1.0;
+        };
+        if self.intensity >= 0.0 {
+            1.0 + (settings.max_spawn_scale - 1.0).max(0.0) * self.intensity
+        } else {
+            1.0 - (1.0 - settings.min_spawn_scale).clamp(0.0, 1.0) * -self.intensity
+        }
+    }
+
+    /// Copies of one queued spawn to make: 0 to skip it, more than 1 to add extras.
+    pub fn roll_spawn_count(&self) -> u32 {
+        let scale = self.spawn_scale();
+        let whole = scale.floor();
+        whole as u32 + u32::from(random_f32() < scale - whole)
+    }
+
+    /// Whether a new spawn should be an elite.
+    pub fn roll_elite(&self) -> bool {
+        let Some(settings) = self.settings.as_ref() else {
+            return false;
+        };
+        random_f32() < settings.max_elite_chance.clamp(0.0, 1.0) * self.intensity.max(0.0)
+    }
+}
+
+fn default_window() -> f32 {
+    90.0
+}
+
+fn default_target_damage() -> f32 {
+    10.0
+}
+
+fn default_target_kills() -> f32 {
+    3.0
+}
+
+fn default_adjust_rate() -> f32 {
+    0.02
+}
+
+fn default_min_spawn_scale() -> f32 {
+    0.6
+}
+
+fn default_max_spawn_scale() -> f32 {
+    1.5
+}
+
+fn default_max_elite_chance() -> f32 {
+    0.25
+}
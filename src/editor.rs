@@ -0,0 +1,403 @@
+//! In-game tile editor.
+//!
+//! Its key (F2 unless the debug profile rebinds it) toggles it when the debug profile allows the
+//! editor. Left mouse paints the selected tile onto the selected layer, right mouse
+//! erases, the scroll wheel cycles the tile (hold Shift to jump a tileset row) and Tab cycles
+//! the layer. The export button writes every tile touched since the editor was opened out as a
+//! structure JSON.
+//!
+//! When `creative` is set in world.yaml, F3 toggles creative mode: the editor opens with B
+//! cycling the brush between tiles, structures and entities, and the player takes no damage,
+//! walks through walls and never runs out of items. With the structure brush, right mouse
+//! removes the structure under the cursor.
+
+use macroquad::prelude::*;
+use std::path::Path;
+use crate::map::{LayerKind, StructureDef, TileMap, TileSet};
+
+pub const CREATIVE_KEY: KeyCode = KeyCode::F3;
+const BRUSH_KEY: KeyCode = KeyCode::B;
+const EXPORT_PATH: &str = "exports/editor_structure.json";
+const EXPORT_ID: &str = "editor_structure";
+const ROW_STEP: i32 = 16;
+const STATUS_TIME: f32 = 3.0;
+
+const PANEL_POS: Vec2 = vec2(20.0, 60.0);
+const PANEL_SIZE: Vec2 = vec2(220.0, 132.0);
+const PREVIEW_SIZE: f32 = 48.0;
+const BUTTON_SIZE: Vec2 = vec2(96.0, 28.0);
+
+/// Inclusive tile bounds of everything painted or erased.
+#[derive(Clone, Copy)]
+struct EditBounds {
+    min_x: usize,
+    min_y: usize,
+    max_x: usize,
+    max_y: usize,
+}
+
+impl EditBounds {
+    fn include(bounds: Option<Self>, x: usize, y: usize) -> Self {
+        match bounds {
+            Some(b) => Self {
+                min_x: b.min_x.min(x),
+                min_y: b.min_y.min(y),
+                max_x: b.max_x.max(x),
+                max_y: b.max_y.max(y),
+            },
+            None => Self {
+                min_x: x,
+                min_y: y,
+                max_x: x,
+                max_y: y,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Brush {
+    Tile,
+    Structure,
+    Entity,
+}
+
+/// What creative mode can place besides tiles. Only set when world.yaml allows creative mode.
+struct CreativePalette {
+    structures: Vec<StructureDef>,
+    entities: Vec<String>,
+    structure: usize,
+    entity: usize,
+}
+
+pub struct MapEditor {
+    /// Whether the debug profile allows the tile editor; creative mode doesn't need it.
+    enabled: bool,
+    key: KeyCode,
+    active: bool,
+    layer: LayerKind,
+    tile: u8,
+    tile_count: usize,
+    brush: Brush,
+    palette: Option<CreativePalette>,
+    creative: bool,
+    /// Entities placed with the entity brush, waiting for the game loop to spawn them.
+    pending_spawns: Vec<(String, Vec2)>,
+    edited: Option<EditBounds>,
+    status: String,
+    status_timer: f32,
+}
+
+impl MapEditor {
+    pub fn new(tile_count: usize, enabled: bool, key: KeyCode) -> Self {
+        Self {
+            enabled,
+            key,
+            active: false,
+            layer: LayerKind::Background,
+            tile: 0,
+            tile_count: tile_count.min(u8::MAX as usize),
+            brush: Brush::Tile,
+            palette: None,
+            creative: false,
+            pending_spawns: Vec::new(),
+            edited: None,
+            status: String::new(),
+            status_timer: 0.0,
+        }
+    }
+
+    /// Allows creative mode, with every structure and entity available to place.
+    pub fn with_creative(mut self, structures: Vec<StructureDef>, entities: Vec<String>) -> Self {
+        self.palette = Some(CreativePalette {
+            structures,
+            entities,
+            structure: 0,
+            entity: 0,
+        });
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn is_creative(&self) -> bool {
+        self.creative
+    }
+
+    /// Takes the entities placed since the last call, as entity id and world position.
+    pub fn take_entity_spawns(&mut self) -> Vec<(String, Vec2)> {
+        std::mem::take(&mut self.pending_spawns)
+    }
+
+    pub fn update(&mut self, map: &mut TileMap, mouse_world: Vec2, dt: f32) {
+        self.status_timer = (self.status_timer - dt).max(0.0);
+        if is_key_pressed(CREATIVE_KEY) && self.palette.is_some() {
+            self.creative = !self.creative;
+            self.active = self.creative;
+            self.brush = Brush::Tile;
+            self.edited = None;
+            self.set_status(format!("creative mode {}", if self.creative { "on" } else { "off" }));
+        } else if self.enabled && is_key_pressed(self.key) && !self.creative {
+            self.active = !self.active;
+            self.edited = None;
+        }
+        if !self.active {
+            return;
+        }
+        if self.creative && is_key_pressed(BRUSH_KEY) {
+            self.brush = match self.brush {
+                Brush::Tile => Brush::Structure,
+                Brush::Structure => Brush::Entity,
+                Brush::Entity => Brush::Tile,
+            };
+        }
+        if self.brush != Brush::Tile {
+            self.update_creative_brush(map, mouse_world);
+            return;
+        }
+
+        if is_key_pressed(KeyCode::Tab) {
+            self.layer = match self.layer {
+                LayerKind::Background => LayerKind::Foreground,
+                LayerKind::Foreground => LayerKind::Overlay,
+                LayerKind::Overlay => LayerKind::Background,
+            };
+        }
+        let scroll = mouse_wheel().1;
+        if scroll != 0.0 && self.tile_count > 0 {
+            let step = if is_key_down(KeyCode::LeftShift) { ROW_STEP } else { 1 };
+            let delta = if scroll > 0.0 { -step } else { step };
+            self.tile = (self.tile as i32 + delta).rem_euclid(self.tile_count as i32) as u8;
+        }
+
+        let (mx, my) = mouse_position();
+        if point_in_ui(vec2(mx, my)) {
+            if is_mouse_button_pressed(MouseButton::Left) && export_button_rect().contains(vec2(mx, my)) {
+                self.export(map);
+            }
+            return;
+        }
+
+        let Some(grid) = map.grid_index(mouse_world) else {
+            return;
+        };
+        let (x, y) = (grid.x as usize, grid.y as usize);
+        let changed = if is_mouse_button_down(MouseButton::Left) {
+            map.paint_tile(self.layer, x, y, self.tile)
+        } else if is_mouse_button_down(MouseButton::Right) {
+            map.erase_tile(self.layer, x, y)
+        } else {
+            false
+        };
+        if changed {
+            self.edited = Some(EditBounds::include(self.edited, x, y));
+        }
+    }
+
+    /// Structure and entity brushes: the wheel picks what to place and a click places one. A
+    /// right click with the structure brush removes a structure instead.
+    fn update_creative_brush(&mut self, map: &mut TileMap, mouse_world: Vec2) {
+        let Some(palette) = self.palette.as_mut() else {
+            return;
+        };
+        let (selected, count) = match self.brush {
+            Brush::Structure => (&mut palette.structure, palette.structures.len()),
+            _ => (&mut palette.entity, palette.entities.len()),
+        };
+        let scroll = mouse_wheel().1;
+        if scroll != 0.0 && count > 0 {
+            let delta = if scroll > 0.0 { -1 } else { 1 };
+            *selected = (*selected as i32 + delta).rem_euclid(count as i32) as usize;
+        }
+        let removing = self.brush == Brush::Structure && is_mouse_button_pressed(MouseButton::Right);
+        if !(is_mouse_button_pressed(MouseButton::Left) || removing) || point_in_ui(mouse_position().into()) {
+            return;
+        }
+        if removing {
+            if let Some(grid) = map.grid_index(mouse_world)
+                && !map.remove_structure_at(grid.x as usize, grid.y as usize)
+            {
+                self.set_status("no structure here".to_string());
+            }
+            return;
+        }
+        match self.brush {
+            Brush::Structure => {
+                let Some(def) = palette.structures.get(palette.structure) else {
+                    return;
+                };
+                let Some(grid) = map.grid_index(mouse_world) else {
+                    return;
+                };
+                if !map.place_structure(def, grid.x as usize, grid.y as usize) {
+                    let status = format!("{} doesn't fit here", def.id);
+                    self.set_status(status);
+                }
+            }
+            _ => {
+                if let Some(id) = palette.entities.get(palette.entity) {
+                    self.pending_spawns.push((id.clone(), mouse_world));
+                }
+            }
+        }
+    }
+
+    /// Name of what the current brush places.
+    fn brush_label(&self) -> String {
+        let palette = self.palette.as_ref();
+        match self.brush {
+            Brush::Tile => format!("tile {}", self.tile),
+            Brush::Structure => palette
+                .and_then(|p| p.structures.get(p.structure))
+                .map_or_else(|| "no structures".to_string(), |def| def.id.clone()),
+            Brush::Entity => palette
+                .and_then(|p| p.entities.get(p.entity))
+                .cloned()
+                .unwrap_or_else(|| "no entities".to_string()),
+        }
+    }
+
+    fn export(&mut self, map: &TileMap) {
+        let Some(bounds) = self.edited else {
+            self.set_status("nothing edited yet".to_string());
+            return;
+        };
+        let result = map
+            .export_structure_json(
+                EXPORT_ID,
+                bounds.min_x,
+                bounds.min_y,
+                bounds.max_x - bounds.min_x + 1,
+                bounds.max_y - bounds.min_y + 1,
+            )
+            .map_err(|err| err.to_string())
+            .and_then(|json| write_export(Path::new(EXPORT_PATH), &json));
+        match result {
+            Ok(()) => self.set_status(format!("exported to {EXPORT_PATH}")),
+            Err(err) => self.set_status(format!("export failed: {err}")),
+        }
+    }
+
+    fn set_status(&mut self, status: String) {
+        eprintln!("editor: {status}");
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    /// Highlights the edited area and previews the brush under the cursor. Call with the world
+    /// camera set.
+    pub fn draw_world(&self, map: &TileMap, tileset: &TileSet, mouse_world: Vec2) {
+        if !self.active {
+            return;
+        }
+        if let Some(bounds) = self.edited {
+            let min = map.tile_bounds(bounds.min_x, bounds.min_y);
+            let max = map.tile_bounds(bounds.max_x, bounds.max_y);
+            draw_rectangle_lines(min.x, min.y, max.right() - min.x, max.bottom() - min.y, 1.0, SKYBLUE);
+        }
+        let Some(grid) = map.grid_index(mouse_world) else {
+            return;
+        };
+        let area = map.tile_bounds(grid.x as usize, grid.y as usize);
+        match self.brush {
+            Brush::Tile => {
+                draw_tile(tileset, self.tile, area, Color::new(1.0, 1.0, 1.0, 0.6));
+                draw_rectangle_lines(area.x, area.y, area.w, area.h, 1.0, YELLOW);
+            }
+            Brush::Structure => {
+                let size = self
+                    .palette
+                    .as_ref()
+                    .and_then(|p| p.structures.get(p.structure))
+                    .map_or((1, 1), |def| def.structure.size());
+                draw_rectangle_lines(area.x, area.y, area.w * size.0 as f32, area.h * size.1 as f32, 1.0, YELLOW);
+            }
+            Brush::Entity => draw_circle_lines(mouse_world.x, mouse_world.y, area.w * 0.5, 1.0, YELLOW),
+        }
+    }
+
+    /// Draws the tool panel in screen space.
+    pub fn draw_ui(&self, tileset: &TileSet) {
+        if !self.active {
+            return;
+        }
+        draw_rectangle(PANEL_POS.x, PANEL_POS.y, PANEL_SIZE.x, PANEL_SIZE.y, Color::new(0.0, 0.0, 0.0, 0.7));
+        let preview = Rect::new(PANEL_POS.x + 8.0, PANEL_POS.y + 8.0, PREVIEW_SIZE, PREVIEW_SIZE);
+        draw_rectangle(preview.x, preview.y, preview.w, preview.h, DARKGRAY);
+        if self.brush == Brush::Tile {
+            draw_tile(tileset, self.tile, preview, WHITE);
+        }
+
+        let text_x = preview.right() + 10.0;
+        let layer = match (self.brush, self.layer) {
+            (Brush::Structure, _) => "structure",
+            (Brush::Entity, _) => "entity",
+            (Brush::Tile, LayerKind::Background) => "background",
+            (Brush::Tile, LayerKind::Foreground) => "foreground",
+            (Brush::Tile, LayerKind::Overlay) => "overlay",
+        };
+        draw_text(&self.brush_label(), text_x, PANEL_POS.y + 24.0, 20.0, WHITE);
+        draw_text(layer, text_x, PANEL_POS.y + 44.0, 20.0, WHITE);
+        let hint = if self.creative {
+            "b: brush  tab: layer  wheel: pick"
+        } else {
+            "tab: layer  wheel: tile"
+        };
+        draw_text(hint, PANEL_POS.x + 8.0, PANEL_POS.y + 76.0, 16.0, LIGHTGRAY);
+        if self.creative {
+            draw_text("creative", PANEL_POS.x + PANEL_SIZE.x - 64.0, PANEL_POS.y + 120.0, 16.0, GOLD);
+        }
+
+        let button = export_button_rect();
+        let hovered = button.contains(mouse_position().into());
+        draw_rectangle(button.x, button.y, button.w, button.h, if hovered { GRAY } else { DARKGRAY });
+        draw_rectangle_lines(button.x, button.y, button.w, button.h, 1.0, WHITE);
+        draw_text("Export", button.x + 18.0, button.y + 20.0, 20.0, WHITE);
+
+        if self.status_timer > 0.0 {
+            draw_text(&self.status, PANEL_POS.x, PANEL_POS.y + PANEL_SIZE.y + 20.0, 18.0, WHITE);
+        }
+    }
+}
+
+fn export_button_rect() -> Rect {
+    Rect::new(
+        PANEL_POS.x + 8.0,
+        PANEL_POS.y + PANEL_SIZE.y - BUTTON_SIZE.y - 8.0,
+        BUTTON_SIZE.x,
+        BUTTON_SIZE.y,
+    )
+}
+
+fn point_in_ui(point: Vec2) -> bool {
+    Rect::new(PANEL_POS.x, PANEL_POS.y, PANEL_SIZE.x, PANEL_SIZE.y).contains(point)
+}
+
+fn draw_tile(tileset: &TileSet, id: u8, area: Rect, color: Color) {
+    let Some(source) = tileset.get(id) else {
+        return;
+    };
+    draw_texture_ex(
+        tileset.texture(),
+        area.x,
+        area.y,
+        color,
+        DrawTextureParams {
+            dest_size: Some(area.size()),
+            source: Some(source),
+            ..Default::default()
+        },
+    );
+}
+
+fn write_export(path: &Path, json: &str) -> Result<(), String> {
+    if cfg!(target_arch = "wasm32") {
+        return Err("exporting is not supported on this platform".to_string());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    std::fs::write(path, json).map_err(|err| err.to_string())
+}
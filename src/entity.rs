@@ -1,6 +1,8 @@
 use macroquad::prelude::*;
-use macroquad::file::load_string;
+use crate::asset_pack::{load_string, load_texture};
 use crate::helpers::{asset_path, data_path, load_wasm_manifest_files};
+use crate::mods;
+use crate::spatial::SpatialGrid;
 use serde::Deserialize;
 use serde_yaml::Value as YamlValue;
 use std::collections::HashMap;
@@ -9,6 +11,11 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::r#trait::*;
 use crate::particle::ParticleEmitter;
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::ability::{self, AbilityDef, AbilityState, load_abilities, load_abilities_wasm};
+use crate::faction::{FactionFile, FactionTable, PLAYER};
+use crate::blackboard::Blackboard;
+use crate::pet::{PET_ACTION, TameInfo};
 
 pub type MovementFn = fn(
     entity: &mut EntityInstance,
@@ -20,6 +27,9 @@ pub type MovementFn = fn(
 
 pub type MovementParams = HashMap<String, f32>;
 
+/// String-valued action settings (template ids, tags) that can't live in `MovementParams`.
+pub type ActionLabels = HashMap<String, String>;
+
 #[derive(Debug)]
 pub enum EntityLoadError {
     Io(std::io::Error),
@@ -53,6 +63,29 @@ impl From<serde_yaml::Error> for EntityLoadError {
     }
 }
 
+/// A problem with one entity definition. The loaders leave out what they can't resolve and carry
+/// on; `EntityDatabase::validate` reports those together with what loaded but won't behave.
+#[derive(Clone, Debug)]
+pub struct DefIssue {
+    pub entity: String,
+    pub message: String,
+}
+
+impl DefIssue {
+    fn new(entity: &str, message: String) -> Self {
+        Self {
+            entity: entity.to_string(),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for DefIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "entity '{}': {}", self.entity, self.message)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum EntityKind {
@@ -61,16 +94,97 @@ pub enum EntityKind {
     Misc,
 }
 
-pub const DEF_FLAG_TARGET_PLAYER: u16 = 1 << 0;
-pub const DEF_FLAG_TARGET_NEAREST_ENTITY: u16 = 1 << 1;
-pub const DEF_FLAG_TARGET_NEAREST_ENEMY: u16 = 1 << 2;
-pub const DEF_FLAG_TARGET_NEAREST_FRIEND: u16 = 1 << 3;
-pub const DEF_FLAG_TARGET_NEAREST_MISC: u16 = 1 << 4;
-pub const DEF_FLAG_NO_ENTITY_COLLISION: u16 = 1 << 5;
-pub const DEF_FLAG_NO_ENEMY_COLLISION: u16 = 1 << 6;
-pub const DEF_FLAG_NO_FRIEND_COLLISION: u16 = 1 << 7;
-pub const DEF_FLAG_NO_MISC_COLLISION: u16 = 1 << 8;
-pub const DEF_FLAG_NO_PLAYER_COLLISION: u16 = 1 << 9;
+/// What an entity is to collision. Each definition sits on one layer (`layer:` in its YAML,
+/// defaulting to its kind) and is pushed by the layers listed in `collides_with`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionLayer {
+    Player,
+    Enemy,
+    Friend,
+    Misc,
+    /// Solid map tiles.
+    Terrain,
+}
+
+impl CollisionLayer {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl From<EntityKind> for CollisionLayer {
+    fn from(kind: EntityKind) -> Self {
+        match kind {
+            EntityKind::Enemy => Self::Enemy,
+            EntityKind::Friend => Self::Friend,
+            EntityKind::Misc => Self::Misc,
+        }
+    }
+}
+
+/// A set of collision layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionMask(u8);
+
+impl CollisionMask {
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self(0b1_1111);
+    /// Every layer an entity can be on.
+    const ENTITIES: Self = Self(0b0_1111);
+
+    pub fn contains(self, layer: CollisionLayer) -> bool {
+        self.0 & layer.bit() != 0
+    }
+
+    pub fn with(self, layer: CollisionLayer) -> Self {
+        Self(self.0 | layer.bit())
+    }
+
+    pub fn without(self, layer: CollisionLayer) -> Self {
+        Self(self.0 & !layer.bit())
+    }
+}
+
+impl FromIterator<CollisionLayer> for CollisionMask {
+    fn from_iter<I: IntoIterator<Item = CollisionLayer>>(layers: I) -> Self {
+        layers.into_iter().fold(Self::NONE, Self::with)
+    }
+}
+
+pub const DEF_FLAG_CANT_SWIM: u16 = 1 << 10;
+/// Knocked out instead of killed by the capture net, and crated.
+pub const DEF_FLAG_CAPTURABLE: u16 = 1 << 11;
+/// Only acquires targets it can see, and loses them after a while out of sight.
+pub const DEF_FLAG_REQUIRES_LOS: u16 = 1 << 12;
+
+/// Damage per second taken by `cant_swim` entities while in liquid.
+const DROWN_DAMAGE_PER_SECOND: f32 = 2.0;
+
+const CORPSE_TINT: Color = Color::new(0.45, 0.45, 0.45, 1.0);
+/// Distance from home at which a returning entity counts as back and may aggro again.
+const LEASH_HOME_RADIUS: f32 = 6.0;
+/// Seconds a `requires_los` entity keeps chasing a target it can't see, unless its `los_memory`
+/// stat says otherwise.
+const LOS_MEMORY: f32 = 2.0;
+/// Damage and speed multiplier for children whose parent died with `on_parent_death: enrage`.
+const ENRAGE_SCALE: f32 = 1.5;
+/// Action key naming the animation clip to play while that action is active.
+const ANIM_LABEL: &str = "anim";
+const ANIM_IDLE: &str = "idle";
+const ANIM_WALK: &str = "walk";
+const ANIM_WALK_SPEED: f32 = 4.0;
+const ANIM_LAUNCHED: &str = "launched";
+const ANIM_DASH: &str = "dash";
+const ANIM_HURT: &str = "hurt";
+const ANIM_WORK: &str = "work";
+const ANIM_DIE: &str = "die";
+/// Shown once `die` has played through, for sprites that have it.
+const ANIM_CORPSE: &str = "corpse";
+/// Clips the idle micro-behaviours play unless their action names another `anim`.
+const IDLE_CLIPS: [(&str, &str); 3] = [("look_around", "look"), ("peck", "peck"), ("hop", "hop")];
+/// Seconds the hurt clip plays after taking damage.
+const HURT_ANIM_TIME: f32 = 0.25;
 
 impl EntityKind {
     fn from_dir(name: &str) -> Option<Self> {
@@ -81,6 +195,15 @@ impl EntityKind {
             _ => None,
         }
     }
+
+    /// Also the faction of entities that don't name one.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Enemy => "enemy",
+            Self::Friend => "friend",
+            Self::Misc => "misc",
+        }
+    }
 }
 
 #[derive(Default, Clone)]
@@ -99,9 +222,26 @@ impl StatBlock {
         }
     }
 
+    pub fn scale(&mut self, key: &str, factor: f32) {
+        if let Some(value) = self.values.get_mut(key) {
+            *value *= factor;
+        }
+    }
+
     pub fn get(&self, key: &str, default: f32) -> f32 {
         self.values.get(key).copied().unwrap_or(default)
     }
+
+    pub fn set(&mut self, key: &str, value: f32) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    /// Stat names in alphabetical order.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.values.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
 }
 
 #[derive(Clone)]
@@ -123,7 +263,54 @@ pub struct BehaviorDef {
 pub enum BehaviorNode {
     Selector { children: Vec<BehaviorNode> },
     Sequence { children: Vec<BehaviorNode> },
-    Condition { name: String, value: Option<f32> },
+    /// Runs one child picked at random by `weights` (1 for children without one), picking again
+    /// every `interval` seconds or as soon as the picked child fails.
+    RandomSelector {
+        children: Vec<BehaviorNode>,
+        #[serde(default)]
+        weights: Vec<f32>,
+        #[serde(default = "default_random_interval")]
+        interval: f32,
+        #[serde(skip)]
+        slot: usize,
+    },
+    Condition {
+        name: String,
+        value: Option<f32>,
+        /// Blackboard variable the `var_*` conditions read.
+        #[serde(default)]
+        var: Option<String>,
+        /// Structure tag `structure_in_range` looks for.
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// Succeeds when the child fails and the other way round; never passes actions up.
+    Inverter { child: Box<BehaviorNode> },
+    /// Fails for `seconds` after the child stops succeeding, e.g. once a dash is over.
+    Cooldown {
+        seconds: f32,
+        child: Box<BehaviorNode>,
+        #[serde(skip)]
+        slot: usize,
+    },
+    /// Lets the child succeed for at most `seconds` in a row; then fails until the child fails
+    /// or goes unvisited.
+    Timeout {
+        seconds: f32,
+        child: Box<BehaviorNode>,
+        #[serde(skip)]
+        slot: usize,
+    },
+    /// Passes to the child with probability `value`, rolled again every `interval` seconds and
+    /// whenever the node is reached after being skipped.
+    Chance {
+        value: f32,
+        #[serde(default = "default_chance_interval")]
+        interval: f32,
+        child: Box<BehaviorNode>,
+        #[serde(skip)]
+        slot: usize,
+    },
     Action {
         name: String,
         #[serde(default)]
@@ -139,6 +326,73 @@ pub enum BehaviorNode {
 pub struct TextureInfo {
     pub texture: Texture2D,
     pub draw: DrawParams,
+    /// Optional per-clip textures; clips without one fall back to `texture`.
+    pub clips: HashMap<String, Texture2D>,
+    /// Frame grid when `texture` is a sprite sheet; `animations` pick frames from it.
+    pub sheet: Option<SpriteSheet>,
+    pub animations: HashMap<String, Animation>,
+    /// Each image file above with its texture, for hot reload.
+    pub files: Vec<(String, Texture2D)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteSheet {
+    pub frame_size: Vec2,
+    pub columns: u32,
+}
+
+impl SpriteSheet {
+    /// Source rect of a frame, counting row by row from the top left.
+    pub fn frame_rect(&self, frame: u32) -> Rect {
+        let columns = self.columns.max(1);
+        Rect::new(
+            (frame % columns) as f32 * self.frame_size.x,
+            (frame / columns) as f32 * self.frame_size.y,
+            self.frame_size.x,
+            self.frame_size.y,
+        )
+    }
+}
+
+/// A named clip of sprite sheet frames.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub frames: Vec<u32>,
+    pub frame_time: f32,
+    /// Clips that don't loop, like `die`, hold their last frame.
+    pub looped: bool,
+}
+
+impl Animation {
+    fn frame_at(&self, elapsed: f32) -> u32 {
+        let step = (elapsed / self.frame_time.max(0.001)) as usize;
+        let last = self.frames.len().saturating_sub(1);
+        let index = if self.looped { step % self.frames.len().max(1) } else { step.min(last) };
+        self.frames.get(index).copied().unwrap_or(0)
+    }
+}
+
+impl TextureInfo {
+    /// Size drawn when `dest_size` isn't set: one frame for sprite sheets, else the texture.
+    pub fn size(&self) -> Vec2 {
+        self.draw
+            .dest_size
+            .or(self.sheet.map(|sheet| sheet.frame_size))
+            .unwrap_or_else(|| self.texture.size())
+    }
+
+    /// Whether `clip` has its own frames or texture rather than falling back to idle.
+    pub fn has_clip(&self, clip: &str) -> bool {
+        self.animations.contains_key(clip) || self.clips.contains_key(clip)
+    }
+
+    /// Seconds a sheet clip takes to play through once; 0 for single textures.
+    pub fn clip_length(&self, clip: &str) -> f32 {
+        self.animations
+            .get(clip)
+            .map(|animation| animation.frames.len() as f32 * animation.frame_time)
+            .unwrap_or(0.0)
+    }
 }
 
 #[derive(Clone)]
@@ -195,10 +449,9 @@ impl Entity {
     }
 
     pub fn clamp_to_map(&mut self, map: &crate::map::TileMap, db: &EntityDatabase) {
-        let bounds = map.get_border_hitbox();
         let def = &db.entities[self.instance.def];
-        self.instance.pos =
-            crate::helpers::clamp_hitbox_to_rect(def.hitbox, self.instance.pos, bounds);
+        // Only the player travels between levels; other actors treat transitions as walls.
+        self.instance.pos = map.apply_borders(def.hitbox, self.instance.pos).0;
     }
 }
 
@@ -214,8 +467,271 @@ pub struct EntityDef {
     pub behavior_tree: Option<BehaviorNode>,
     pub base_stats: StatBlock,
     pub speed: f32,
+    pub collision_layer: CollisionLayer,
+    pub collides_with: CollisionMask,
+    /// `collides_with` includes terrain.
     pub collides: bool,
     pub flags: u16,
+    /// Faction name from the YAML, or the kind's name.
+    pub faction: String,
+    /// Index of `faction` in `EntityDatabase::factions`.
+    pub faction_index: usize,
+    pub death: DeathInfo,
+    pub leash: Option<LeashInfo>,
+    pub aggro: Option<AggroInfo>,
+    pub knockback: KnockbackInfo,
+    pub hunger: Option<HungerInfo>,
+    /// How the player tames it, if it can be tamed.
+    pub tame: Option<TameInfo>,
+    pub patrol: Option<PatrolRoute>,
+    pub npc: Option<NpcInfo>,
+    /// Cutscene played the first time one of these comes into view.
+    pub intro_cutscene: Option<String>,
+    /// Idle sounds played now and then while the entity is near the camera.
+    pub vocals: Vec<VocalInfo>,
+    /// File the definition was loaded from, for tools that write it back out.
+    pub source: String,
+}
+
+/// An idle sound: every `interval` seconds the entity plays `sound` with probability `chance`.
+#[derive(Clone, Deserialize)]
+pub struct VocalInfo {
+    pub sound: String,
+    pub interval: f32,
+    #[serde(default = "default_vocal_chance")]
+    pub chance: f32,
+}
+
+fn default_vocal_chance() -> f32 {
+    1.0
+}
+
+#[derive(Clone)]
+pub struct DeathInfo {
+    /// Seconds the corpse stays before it is removed.
+    pub corpse_time: f32,
+    /// Seconds at the end of `corpse_time` over which the corpse fades out.
+    pub fade_time: f32,
+    pub particle: Option<String>,
+    pub sound: Option<String>,
+    pub drops: Vec<LootDrop>,
+}
+
+/// An item an entity may leave on the ground where it dies.
+#[derive(Clone, Deserialize)]
+pub struct LootDrop {
+    pub item: String,
+    /// Chance (0..1) that anything drops.
+    #[serde(default = "default_drop_chance")]
+    pub chance: f32,
+    /// How many drop, picked evenly from `min` to `max` inclusive.
+    #[serde(default = "default_drop_count")]
+    pub min: u32,
+    #[serde(default = "default_drop_count")]
+    pub max: u32,
+}
+
+impl LootDrop {
+    /// How many drop this time; often 0 for drops with a low chance.
+    pub fn roll(&self) -> u32 {
+        if rand::gen_range(0.0, 1.0) >= self.chance {
+            return 0;
+        }
+        rand::gen_range(self.min, self.max.max(self.min) + 1)
+    }
+}
+
+/// How close a target has to come before the entity goes after it, and how far it may get away
+/// before the entity loses interest. Both are in world pixels from the entity.
+#[derive(Clone, Copy)]
+pub struct AggroInfo {
+    pub acquire: f32,
+    /// At least `acquire`, so a target at the edge doesn't flicker in and out.
+    pub lose: f32,
+}
+
+/// How far an entity may chase from its home before giving up and walking back.
+#[derive(Clone, Copy)]
+pub struct LeashInfo {
+    pub radius: f32,
+    /// HP regenerated per second while returning home.
+    pub regen: f32,
+    pub return_speed: f32,
+}
+
+/// Food an animal needs to stay fed. Food drains over time and is topped up by eating from
+/// troughs; behaviour trees check it with the `hungry` and `fed` conditions.
+#[derive(Clone, Copy)]
+pub struct HungerInfo {
+    pub max: f32,
+    /// Food lost per second.
+    pub decay: f32,
+    /// Fraction of `max` (0..1) at or above which the entity counts as fed.
+    pub fed_at: f32,
+    /// Food taken from a trough per bite.
+    pub bite: f32,
+}
+
+/// Fixed route walked by the `patrol` action.
+#[derive(Clone)]
+pub struct PatrolRoute {
+    /// Offsets from the entity's spawn point, visited in order.
+    pub waypoints: Vec<Vec2>,
+    pub mode: PatrolMode,
+    /// Seconds spent standing at each waypoint.
+    pub wait: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatrolMode {
+    /// Heads back to the first waypoint after the last.
+    Loop,
+    /// Walks the route backwards after reaching either end.
+    PingPong,
+}
+
+impl PatrolMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "loop" => Some(Self::Loop),
+            "ping_pong" => Some(Self::PingPong),
+            _ => None,
+        }
+    }
+}
+
+/// Progress of an entity along its `patrol` route.
+#[derive(Clone)]
+pub struct Patrol {
+    pub route: PatrolRoute,
+    /// Index of the waypoint being walked to.
+    pub next: usize,
+    /// Walking the route in order; only false on the way back of a ping-pong.
+    pub forward: bool,
+}
+
+impl Patrol {
+    fn new(route: PatrolRoute) -> Self {
+        Self {
+            route,
+            next: 0,
+            forward: true,
+        }
+    }
+
+    /// Moves on to the waypoint after `next`.
+    pub fn advance(&mut self, mode: PatrolMode) {
+        let len = self.route.waypoints.len();
+        if len < 2 {
+            return;
+        }
+        match mode {
+            PatrolMode::Loop => self.next = (self.next + 1) % len,
+            PatrolMode::PingPong => {
+                if self.forward && self.next + 1 >= len {
+                    self.forward = false;
+                } else if !self.forward && self.next == 0 {
+                    self.forward = true;
+                }
+                self.next = if self.forward { self.next + 1 } else { self.next - 1 };
+            }
+        }
+    }
+}
+
+/// Gifts, dialogue and shop of a named NPC. Relationship points are kept per `EntityDef::name`.
+#[derive(Clone)]
+pub struct NpcInfo {
+    /// Relationship points per item; items not listed are refused as gifts.
+    pub gifts: HashMap<String, u32>,
+    /// Sorted by `level`.
+    pub dialogue: Vec<DialogueLine>,
+    pub shop: Vec<ShopOffer>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct DialogueLine {
+    /// Relationship level the line needs.
+    #[serde(default)]
+    pub level: usize,
+    pub text: String,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ShopOffer {
+    pub item: String,
+    /// Coins before the relationship discount.
+    pub price: u32,
+}
+
+/// Current food of an entity with a `hunger` block.
+#[derive(Clone, Copy)]
+pub struct Hunger {
+    pub food: f32,
+    pub info: HungerInfo,
+}
+
+impl Hunger {
+    fn new(info: HungerInfo) -> Self {
+        Self {
+            food: info.max,
+            info,
+        }
+    }
+
+    /// Fed when food is at or above `fraction` of max, or the def's `fed_at` without one.
+    pub fn is_fed(&self, fraction: Option<f32>) -> bool {
+        self.food >= fraction.unwrap_or(self.info.fed_at).clamp(0.0, 1.0) * self.info.max
+    }
+
+    /// Food the entity can still take before it is full.
+    pub fn appetite(&self) -> f32 {
+        (self.info.max - self.food).max(0.0)
+    }
+
+    pub fn eat(&mut self, amount: f32) {
+        self.food = (self.food + amount.max(0.0)).min(self.info.max);
+    }
+}
+
+/// How an entity takes knockback and when a hit is heavy enough to launch it.
+#[derive(Clone, Copy)]
+pub struct KnockbackInfo {
+    /// Fraction of incoming knockback ignored (0..1).
+    pub resistance: f32,
+    /// Knockback speed at or above which the entity is launched instead of just pushed.
+    pub launch_threshold: f32,
+    pub launch_time: f32,
+    /// Peak height of the launch arc in pixels; only affects drawing.
+    pub launch_height: f32,
+    /// Damage for hitting a wall while launched at exactly the threshold speed; scales with speed.
+    pub wall_damage: f32,
+    /// How quickly grounded knockback dies off, per second.
+    pub friction: f32,
+}
+
+impl Default for KnockbackInfo {
+    fn default() -> Self {
+        Self {
+            resistance: 0.0,
+            launch_threshold: 260.0,
+            launch_time: 0.45,
+            launch_height: 10.0,
+            wall_damage: 1.0,
+            friction: 10.0,
+        }
+    }
+}
+
+/// Airborne state after a heavy hit: no AI and no ground friction until the entity lands.
+#[derive(Clone, Copy)]
+pub struct LaunchState {
+    pub remaining: f32,
+    pub duration: f32,
+    pub height: f32,
+    /// Wall damage is dealt once per launch.
+    pub hit_wall: bool,
 }
 
 impl EntityDef {
@@ -228,20 +744,50 @@ impl EntityDef {
     }
 
     pub fn draw_with_alpha(&self, pos: Vec2, alpha: f32) {
-        let tex = &self.texture.texture;
-        let draw = &self.texture.draw;
+        self.draw_tinted(pos, None, alpha, WHITE, false);
+    }
+
+    /// Draws the frame `anim` is on. Sprite sheets fall back to the idle clip, then the first
+    /// frame, for clips they don't define; otherwise a clip picks its own texture if it has one.
+    /// `flip_x` mirrors the sprite on top of the def's own flip.
+    pub fn draw_tinted(&self, pos: Vec2, anim: Option<&AnimState>, alpha: f32, tint: Color, flip_x: bool) {
+        let info = &self.texture;
+        let (tex, source) = match info.sheet {
+            Some(sheet) => {
+                let frame = anim
+                    .and_then(|anim| {
+                        let clip = info.animations.get(&anim.clip).or(info.animations.get(ANIM_IDLE))?;
+                        Some(clip.frame_at(anim.elapsed))
+                    })
+                    .unwrap_or(0);
+                (&info.texture, Some(sheet.frame_rect(frame)))
+            }
+            None => {
+                let tex = anim
+                    .and_then(|anim| info.clips.get(&anim.clip))
+                    .unwrap_or(&info.texture);
+                (tex, None)
+            }
+        };
+        let draw = &info.draw;
 
-        let dest = draw.dest_size.or_else(|| Some(vec2(tex.width(), tex.height())));
+        let dest = draw
+            .dest_size
+            .or(source.map(|source| source.size()))
+            .or_else(|| Some(vec2(tex.width(), tex.height())));
         let params = DrawTextureParams {
+            source,
             dest_size: dest,
             rotation: draw.rotation,
-            flip_x: draw.flip_x,
+            flip_x: draw.flip_x != flip_x,
             flip_y: draw.flip_y,
             pivot: draw.pivot,
-            ..Default::default()
         };
         let mut color = draw.color;
-        color.a *= alpha.clamp(0.0, 1.0);
+        color.r *= tint.r;
+        color.g *= tint.g;
+        color.b *= tint.b;
+        color.a *= alpha.clamp(0.0, 1.0) * tint.a;
 
         draw_texture_ex(
             tex,
@@ -262,27 +808,73 @@ impl EntityDef {
     }
 }
 
+/// Drawing tweaks idle actions make without needing art: a lift off the ground and a mirrored
+/// sprite. Cleared every frame before behaviours run.
+#[derive(Clone, Copy, Default)]
+pub struct Pose {
+    pub lift: f32,
+    pub flip_x: bool,
+}
+
+/// Animation clip an entity is currently showing, and for how long it has been showing it.
+pub struct AnimState {
+    pub clip: String,
+    pub elapsed: f32,
+}
+
+impl AnimState {
+    fn new(clip: &str) -> Self {
+        Self {
+            clip: clip.to_string(),
+            elapsed: 0.0,
+        }
+    }
+
+    /// Switches to `clip`, restarting playback only when it actually changes.
+    pub fn play(&mut self, clip: &str, dt: f32) {
+        if self.clip == clip {
+            self.elapsed += dt;
+        } else {
+            self.clip.clear();
+            self.clip.push_str(clip);
+            self.elapsed = 0.0;
+        }
+    }
+}
+
 pub struct BehaviorRuntime {
     pub name: String,
     pub func: MovementFn,
     pub params: MovementParams,
+    pub labels: ActionLabels,
     pub timer: f32,
     pub dir: Vec2,
     pub cooldown: f32,
+    /// Remaining waypoints for movement functions that plan a route.
+    pub path: Vec<Vec2>,
+    /// Seconds of work done by actions that take a while, like `harvest_structure`.
+    pub progress: f32,
 }
 
 #[derive(Clone, Copy)]
 pub struct PlayerTarget {
     pub pos: Vec2,
     pub hitbox: Rect,
+    /// Dash i-frames are active; attacks that honour them should pass through.
+    pub dashing: bool,
+    pub parrying: bool,
 }
 
 #[derive(Clone, Copy)]
 pub struct EntityTarget {
     pub id: u64,
+    /// Where the entity lives in the pool, for applying effects back to it.
+    pub handle: EntityHandle,
     pub def: usize,
     pub kind: EntityKind,
+    pub layer: CollisionLayer,
     pub pos: Vec2,
+    pub vel: Vec2,
     pub hitbox: Rect,
     pub alive: bool,
 }
@@ -315,6 +907,70 @@ impl Target {
 pub struct DamageEvent {
     pub amount: f32,
     pub target: Target,
+    /// Velocity impulse for the target; entities may be launched by heavy hits.
+    pub knockback: Vec2,
+    /// Uid of the entity that dealt it, if an entity did.
+    pub source: Option<u64>,
+}
+
+/// Side effects a movement function wants applied outside the entity update.
+pub enum EntityRequest {
+    SpawnHazard { id: String, pos: Vec2 },
+    SpawnProjectile { id: String, pos: Vec2, dir: Vec2 },
+    /// A launched entity slammed into a wall and took bonus damage.
+    LaunchImpact { pos: Vec2 },
+    /// A launched entity touched down again.
+    Landed { pos: Vec2 },
+    DropItem { id: String, pos: Vec2 },
+    /// Take up to `amount` feed from the trough at `pos`.
+    Eat { pos: Vec2, amount: f32 },
+    /// Play a particle burst.
+    Burst { id: String, pos: Vec2 },
+    /// Spawn a child of the requesting entity.
+    Summon(SummonRequest),
+    /// Remove the structure covering this tile, e.g. a tree that's been chopped down.
+    Harvest { tile: (usize, usize) },
+    /// Pollinate the crop on this tile so it grows faster.
+    Pollinate { tile: (usize, usize) },
+}
+
+pub struct SummonRequest {
+    pub id: String,
+    pub pos: Vec2,
+    /// Most children of the summoner alive at once; 0 for no limit.
+    pub max: u32,
+    pub on_parent_death: OrphanRule,
+}
+
+/// What a summoned child does when its parent dies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanRule {
+    /// Carries on as if nothing happened.
+    Stay,
+    /// Dies with its parent, without drops.
+    #[default]
+    Despawn,
+    /// Hits harder and moves faster for the rest of its life.
+    Enrage,
+}
+
+impl OrphanRule {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stay" => Some(Self::Stay),
+            "despawn" => Some(Self::Despawn),
+            "enrage" => Some(Self::Enrage),
+            _ => None,
+        }
+    }
+}
+
+/// The entity that summoned this one.
+#[derive(Clone, Copy, Debug)]
+pub struct ParentLink {
+    pub uid: u64,
+    pub on_death: OrphanRule,
 }
 
 pub struct EntityInstance {
@@ -330,8 +986,49 @@ pub struct EntityInstance {
     pub collision_scratch: Vec<Rect>,
     pub dynamic_collision_scratch: Vec<Rect>,
     pub current_target: Option<Target>,
+    /// Seconds the current target has been out of sight, for `requires_los` entities.
+    pub unseen_time: f32,
+    pub abilities: AbilityState,
+    /// Set on entities summoned by another.
+    pub parent: Option<ParentLink>,
+    /// Set by actions while the entity works on something in place; plays the `work` clip.
+    pub working: bool,
     pub contact_cooldown: f32,
     pub dash_trail: Option<ParticleEmitter>,
+    /// Set while a ground entity stands in a liquid tile.
+    pub swimming: bool,
+    pub ripple_trail: Option<ParticleEmitter>,
+    pub corpse_timer: Option<f32>,
+    pub requests: Vec<EntityRequest>,
+    /// Spawn position the entity is leashed to.
+    pub home: Vec2,
+    /// Set while a leashed entity has disengaged and is heading home.
+    pub returning_home: bool,
+    pub anim: AnimState,
+    /// Seconds left of the hurt clip.
+    pub hurt_timer: f32,
+    /// World tier the entity was scaled for when it spawned.
+    pub tier: u32,
+    /// Velocity added on top of movement; decays by the def's friction unless launched.
+    pub knockback: Vec2,
+    pub launch: Option<LaunchState>,
+    /// Offset from `pos` to the hitbox center, so movement can steer the body rather than the
+    /// sprite origin.
+    pub center_offset: Vec2,
+    pub hunger: Option<Hunger>,
+    pub patrol: Option<Patrol>,
+    /// Released from a crate by the player, or a pet; never targets the player.
+    pub tamed: bool,
+    /// Follows the player around instead of running its behaviour tree.
+    pub pet: bool,
+    /// Too far from the camera to simulate this frame; skips behaviours and collision.
+    pub asleep: bool,
+    /// Seconds until each of the def's vocals next rolls its chance.
+    pub vocal_timers: Vec<f32>,
+    pub decorators: DecoratorMemory,
+    /// Variables behaviour actions and conditions share between frames.
+    pub blackboard: Blackboard,
+    pub pose: Pose,
 }
 
 impl EntityInstance {
@@ -344,24 +1041,59 @@ impl EntityInstance {
         registry: &MovementRegistry,
     ) {
         self.vel = Vec2::ZERO;
-        self.current_target = ctx.resolve_target(db, self);
+        self.pose = Pose::default();
         if self.contact_cooldown > 0.0 {
             self.contact_cooldown = (self.contact_cooldown - dt).max(0.0);
         }
 
         let def = &db.entities[self.def];
-        let mut desired_actions = def
-            .behavior_tree
-            .as_ref()
-            .map(|tree| select_actions(tree, self, ctx))
-            .unwrap_or_default()
-            .into_iter()
-            .filter(|a| registry.has(&a.name))
-            .collect::<Vec<_>>();
+        if self.launch.is_some() {
+            self.update_launch(def, map, dt);
+            self.update_animation(dt);
+            return;
+        }
+        if !self.pet {
+            self.update_leash(def.leash, dt);
+        }
+        self.tick_hunger(dt);
+        let return_speed = def.leash.map(|leash| self.speed * leash.return_speed);
+        let mut desired_actions = if let Some(speed) = return_speed.filter(|_| self.returning_home) {
+            self.current_target = None;
+            let mut params = MovementParams::new();
+            params.insert("speed".to_string(), speed);
+            vec![SelectedAction {
+                name: "return_home".to_string(),
+                params,
+                labels: ActionLabels::new(),
+            }]
+        } else if self.pet {
+            self.current_target = ctx.resolve_target(db, self);
+            vec![SelectedAction {
+                name: PET_ACTION.to_string(),
+                params: MovementParams::new(),
+                labels: ActionLabels::new(),
+            }]
+        } else {
+            let target = ctx.resolve_target(db, self);
+            self.current_target = self.check_sight(def, target, map, dt);
+            let mut memory = std::mem::take(&mut self.decorators);
+            memory.begin(dt);
+            let actions = def
+                .behavior_tree
+                .as_ref()
+                .map(|tree| select_actions(tree, self, ctx, &mut memory))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|a| registry.has(&a.name))
+                .collect::<Vec<_>>();
+            self.decorators = memory;
+            actions
+        };
         if desired_actions.is_empty() {
             desired_actions.push(SelectedAction {
                 name: "idle".to_string(),
                 params: MovementParams::new(),
+                labels: ActionLabels::new(),
             });
         }
 
@@ -370,7 +1102,11 @@ impl EntityInstance {
         for desired in desired_actions {
             if let Some(index) = existing
                 .iter()
-                .position(|b| b.name == desired.name && b.params == desired.params)
+                .position(|b| {
+                    b.name == desired.name
+                        && b.params == desired.params
+                        && b.labels == desired.labels
+                })
             {
                 synced.push(existing.remove(index));
             } else {
@@ -378,14 +1114,18 @@ impl EntityInstance {
                     name: desired.name.clone(),
                     func: registry.resolve(&desired.name),
                     params: desired.params.clone(),
+                    labels: desired.labels.clone(),
                     timer: 0.0,
                     dir: Vec2::ZERO,
                     cooldown: 0.0,
+                    path: Vec::new(),
+                    progress: 0.0,
                 });
             }
         }
         self.behaviors = synced;
 
+        self.working = false;
         let mut behaviors = std::mem::take(&mut self.behaviors);
         for behavior in behaviors.iter_mut() {
             let func = behavior.func;
@@ -394,10 +1134,22 @@ impl EntityInstance {
             behavior.params = params;
         }
         self.behaviors = behaviors;
+        ability::update(self, db, ctx, dt);
+
+        // Only ground entities feel the tile under them; in liquid they swim and can't dash, and
+        // sticky ground stops dashes too.
+        let ground = def.collides.then(|| map.tile_properties_at(self.pos));
+        self.swimming = ground.is_some_and(|ground| ground.liquid);
+        let can_dash = !self.swimming && ground.is_none_or(|ground| ground.material.allows_dash());
 
         let mut max_speed = self.speed.max(1.0);
+        if self.returning_home
+            && let Some(speed) = return_speed
+        {
+            max_speed = max_speed.max(speed);
+        }
         for behavior in self.behaviors.iter() {
-            if behavior.name != "dash_at_target" || behavior.timer <= 0.0 {
+            if !can_dash || behavior.name != "dash_at_target" || behavior.timer <= 0.0 {
                 continue;
             }
                 let dash_speed = behavior
@@ -412,10 +1164,23 @@ impl EntityInstance {
             self.vel = self.vel / speed * max_speed;
         }
 
-        let def = &db.entities[self.def];
+        if let Some(ground) = ground {
+            self.vel *= (1.0 - ground.friction) * map.slow_factor(def.hitbox.offset(self.pos));
+            let mut damage = ground.damage_per_second;
+            if self.swimming {
+                self.vel *= crate::map::SWIM_SPEED_SCALE;
+                if def.has_flag(DEF_FLAG_CANT_SWIM) {
+                    damage += DROWN_DAMAGE_PER_SECOND;
+                }
+            }
+            self.apply_damage(damage * dt);
+        }
+        self.vel += self.knockback;
+        let friction = def.knockback.friction * ground.map_or(1.0, |ground| ground.material.knockback_friction());
+        self.knockback *= (1.0 - friction * dt).clamp(0.0, 1.0);
         self.dynamic_collision_scratch.clear();
         collect_dynamic_collision_hitboxes(
-            def.flags,
+            def.collides_with,
             self.uid,
             self.current_target,
             ctx,
@@ -470,28 +1235,241 @@ impl EntityInstance {
                 pos = resolved;
                 vel.y = vy;
             }
+            if def.collides
+                && vel.y < 0.0
+                && let Some(grid) = map.grid_index(hitbox_center_world(pos, def.hitbox))
+            {
+                let radius = collision_radius(map, vel, dt);
+                map.fill_one_way_around_grid(grid, radius, &mut self.collision_scratch);
+                let (resolved, vy) =
+                    crate::helpers::resolve_one_way(def.hitbox, self.pos.y, pos, vel.y, &mut self.collision_scratch);
+                pos = resolved;
+                vel.y = vy;
+            }
 
+            if def.collides {
+                self.bounce_off_walls(map, def.world_hitbox(pos), self.vel, vel);
+            }
             self.pos = pos;
             self.vel = vel;
         } else {
             self.pos += self.vel * dt;
         }
 
+        self.update_animation(dt);
         self.apply_contact_damage(ctx, db);
     }
 
+    /// Picks the clip to show: launched, then hurt right after a hit, then whatever the
+    /// highest-priority active behavior hints, then dash, walk or idle from how it's moving.
+    fn update_animation(&mut self, dt: f32) {
+        self.hurt_timer = (self.hurt_timer - dt).max(0.0);
+        if self.launch.is_some() {
+            self.anim.play(ANIM_LAUNCHED, dt);
+            return;
+        }
+        if self.hurt_timer > 0.0 {
+            self.anim.play(ANIM_HURT, dt);
+            return;
+        }
+        let hinted = self
+            .behaviors
+            .iter()
+            .find_map(|behavior| {
+                behavior.labels.get(ANIM_LABEL).map(String::as_str).or_else(|| {
+                    IDLE_CLIPS
+                        .iter()
+                        .find(|(action, _)| *action == behavior.name)
+                        .map(|(_, clip)| *clip)
+                })
+            });
+        let clip = match hinted {
+            Some(clip) => clip,
+            None if self.working => ANIM_WORK,
+            None if self.is_dashing() => ANIM_DASH,
+            None if self.vel.length_squared() > ANIM_WALK_SPEED * ANIM_WALK_SPEED => ANIM_WALK,
+            None => ANIM_IDLE,
+        };
+        self.anim.play(clip, dt);
+    }
+
     pub fn draw(&self, db: &EntityDatabase) {
         db.entities[self.def].draw(self.pos);
     }
 
     pub fn draw_with_alpha(&self, db: &EntityDatabase, alpha: f32) {
-        db.entities[self.def].draw_with_alpha(self.pos, alpha);
+        let def = &db.entities[self.def];
+        match self.corpse_timer {
+            Some(timer) => {
+                let fade = (timer / def.death.fade_time.max(0.001)).clamp(0.0, 1.0);
+                def.draw_tinted(self.pos, Some(&self.anim), alpha * fade, CORPSE_TINT, false);
+            }
+            None => {
+                let pos = self.pos - vec2(0.0, self.launch_lift() + self.pose.lift);
+                def.draw_tinted(pos, Some(&self.anim), alpha, WHITE, self.pose.flip_x);
+            }
+        }
     }
 
     pub fn hitbox(&self, db: &EntityDatabase) -> Rect {
         db.entities[self.def].world_hitbox(self.pos)
     }
 
+    /// Height of the launch arc this frame; 0 while grounded.
+    fn launch_lift(&self) -> f32 {
+        let Some(launch) = self.launch else {
+            return 0.0;
+        };
+        let t = 1.0 - launch.remaining / launch.duration.max(0.001);
+        (t.clamp(0.0, 1.0) * std::f32::consts::PI).sin() * launch.height
+    }
+
+    /// Knockback stops along the axes `moving` was blocked on. Bouncy walls throw it back instead,
+    /// and turn dashes around.
+    fn bounce_off_walls(&mut self, map: &crate::map::TileMap, hitbox: Rect, moving: Vec2, resolved: Vec2) {
+        let mut flip = Vec2::ONE;
+        if moving.x != 0.0 && resolved.x == 0.0 {
+            let restitution = map.wall_material(hitbox, vec2(moving.x, 0.0)).restitution();
+            self.knockback.x *= -restitution;
+            if restitution > 0.0 {
+                flip.x = -1.0;
+            }
+        }
+        if moving.y != 0.0 && resolved.y == 0.0 {
+            let restitution = map.wall_material(hitbox, vec2(0.0, moving.y)).restitution();
+            self.knockback.y *= -restitution;
+            if restitution > 0.0 {
+                flip.y = -1.0;
+            }
+        }
+        if flip != Vec2::ONE {
+            for behavior in self.behaviors.iter_mut() {
+                if behavior.name == "dash_at_target" && behavior.timer > 0.0 {
+                    behavior.dir *= flip;
+                }
+            }
+        }
+    }
+
+    /// Flies along the knockback velocity without friction, taking damage once if it hits a wall.
+    /// Bouncy walls throw it back unhurt.
+    fn update_launch(&mut self, def: &EntityDef, map: &crate::map::TileMap, dt: f32) {
+        let Some(mut launch) = self.launch else {
+            return;
+        };
+        let before = self.knockback;
+        let mut pos = self.pos;
+        let mut vel = before;
+        if def.collides {
+            pos.x += vel.x * dt;
+            (pos, vel.x) = self.resolve_map_axis(def.hitbox, map, pos, vel, dt, crate::helpers::Axis::X);
+            pos.y += vel.y * dt;
+            (pos, vel.y) = self.resolve_map_axis(def.hitbox, map, pos, vel, dt, crate::helpers::Axis::Y);
+        } else {
+            pos += vel * dt;
+        }
+        if def.collides {
+            let hitbox = def.world_hitbox(pos);
+            if before.x != 0.0 && vel.x == 0.0 {
+                vel.x = -before.x * map.wall_material(hitbox, vec2(before.x, 0.0)).restitution();
+            }
+            if before.y != 0.0 && vel.y == 0.0 {
+                vel.y = -before.y * map.wall_material(hitbox, vec2(0.0, before.y)).restitution();
+            }
+        }
+        self.pos = pos;
+        self.vel = vel;
+        self.knockback = vel;
+
+        let stopped = (before.x != 0.0 && vel.x == 0.0) || (before.y != 0.0 && vel.y == 0.0);
+        if stopped && !launch.hit_wall {
+            launch.hit_wall = true;
+            let force = before.length() / def.knockback.launch_threshold.max(1.0);
+            self.apply_damage(def.knockback.wall_damage * force);
+            self.requests.push(EntityRequest::LaunchImpact { pos: self.pos });
+        }
+
+        launch.remaining -= dt;
+        if launch.remaining > 0.0 {
+            self.launch = Some(launch);
+            return;
+        }
+        self.launch = None;
+        self.knockback = Vec2::ZERO;
+        self.vel = Vec2::ZERO;
+        self.requests.push(EntityRequest::Landed {
+            pos: def.world_hitbox(self.pos).center(),
+        });
+    }
+
+    fn resolve_map_axis(
+        &mut self,
+        hitbox: Rect,
+        map: &crate::map::TileMap,
+        pos: Vec2,
+        vel: Vec2,
+        dt: f32,
+        axis: crate::helpers::Axis,
+    ) -> (Vec2, f32) {
+        let vel_axis = match axis {
+            crate::helpers::Axis::X => vel.x,
+            crate::helpers::Axis::Y => vel.y,
+        };
+        self.collision_scratch.clear();
+        if let Some(grid) = map.grid_index(hitbox_center_world(pos, hitbox)) {
+            let radius = collision_radius(map, vel, dt);
+            map.fill_hitboxes_around_grid(grid, radius, &mut self.collision_scratch);
+        }
+        crate::helpers::resolve_collisions_axis(hitbox, pos, vel_axis, &self.collision_scratch, axis)
+    }
+
+    /// Disengages once the entity strays past its leash and re-arms it after it gets back home.
+    fn update_leash(&mut self, leash: Option<LeashInfo>, dt: f32) {
+        let Some(leash) = leash else {
+            return;
+        };
+        let dist = self.pos.distance(self.home);
+        if !self.returning_home {
+            if dist > leash.radius {
+                self.returning_home = true;
+            }
+            return;
+        }
+        self.hp = (self.hp + leash.regen * dt).min(self.max_hp);
+        if dist <= LEASH_HOME_RADIUS {
+            self.returning_home = false;
+        }
+    }
+
+    /// For `requires_los` entities: a new target has to be in plain sight, and the one being
+    /// chased is dropped once it has been out of sight for `los_memory` seconds.
+    fn check_sight(
+        &mut self,
+        def: &EntityDef,
+        target: Option<Target>,
+        map: &crate::map::TileMap,
+        dt: f32,
+    ) -> Option<Target> {
+        if !def.has_flag(DEF_FLAG_REQUIRES_LOS) {
+            return target;
+        }
+        let target = target?;
+        if map.line_of_sight(self.pos, target.position()) {
+            self.unseen_time = 0.0;
+            return Some(target);
+        }
+        let chasing = match (self.current_target, target) {
+            (Some(Target::Player(_)), Target::Player(_)) => true,
+            (Some(Target::Entity(current)), Target::Entity(next)) => current.id == next.id,
+            _ => false,
+        };
+        if !chasing {
+            return None;
+        }
+        self.unseen_time += dt;
+        (self.unseen_time < self.stats.get("los_memory", LOS_MEMORY)).then_some(target)
+    }
+
     pub fn is_dashing(&self) -> bool {
         self.behaviors
             .first()
@@ -502,6 +1480,15 @@ impl EntityInstance {
             .unwrap_or(false)
     }
 
+    /// The `dash_knockback` param of the dash under way, which replaces the `knockback` stat for
+    /// hits landed while dashing.
+    fn dash_knockback(&self) -> Option<f32> {
+        if !self.is_dashing() {
+            return None;
+        }
+        self.behaviors.first()?.params.get("dash_knockback").copied()
+    }
+
     fn apply_contact_damage(&mut self, ctx: &mut EntityContext, db: &EntityDatabase) {
         let damage = self.stats.get("damage", 0.0);
         if damage <= 0.0 || self.contact_cooldown > 0.0 {
@@ -510,18 +1497,12 @@ impl EntityInstance {
         let Some(target) = self.current_target else {
             return;
         };
-        let def_flags = db.entities[self.def].flags;
-        let target_any = (def_flags & DEF_FLAG_TARGET_NEAREST_ENTITY) != 0;
-        let target_enemy = (def_flags & DEF_FLAG_TARGET_NEAREST_ENEMY) != 0;
-        let target_friend = (def_flags & DEF_FLAG_TARGET_NEAREST_FRIEND) != 0;
-        let target_misc = (def_flags & DEF_FLAG_TARGET_NEAREST_MISC) != 0;
-        let has_specific_target_flags = target_enemy || target_friend || target_misc;
-        let target_player = (def_flags & DEF_FLAG_TARGET_PLAYER) != 0;
+        let faction = db.entities[self.def].faction_index;
 
         let target_hitbox = match target {
             Target::Position(_) => return,
             Target::Player(_) => {
-                if !target_player {
+                if !db.factions.is_hostile(faction, PLAYER) || self.tamed {
                     return;
                 }
                 let Some(player) = ctx.player else {
@@ -537,30 +1518,8 @@ impl EntityInstance {
                 else {
                     return;
                 };
-                let kind_ok = match target_live.kind {
-                    EntityKind::Enemy => {
-                        if has_specific_target_flags {
-                            target_enemy
-                        } else {
-                            target_any || target_enemy
-                        }
-                    }
-                    EntityKind::Friend => {
-                        if has_specific_target_flags {
-                            target_friend
-                        } else {
-                            target_any || target_friend
-                        }
-                    }
-                    EntityKind::Misc => {
-                        if has_specific_target_flags {
-                            target_misc
-                        } else {
-                            target_any || target_misc
-                        }
-                    }
-                };
-                if !kind_ok {
+                // Pets bite whatever they were sent after.
+                if !self.pet && !db.factions.is_hostile(faction, db.entities[target_live.def].faction_index) {
                     return;
                 }
                 target_live.hitbox
@@ -569,7 +1528,14 @@ impl EntityInstance {
 
         let hb = db.entities[self.def].world_hitbox(self.pos);
         if hb.overlaps(&target_hitbox) {
-            ctx.damage_events.push(DamageEvent { amount: damage, target });
+            let strength = self.dash_knockback().unwrap_or_else(|| self.stats.get("knockback", 0.0));
+            let knockback = (target_hitbox.center() - hb.center()).normalize_or_zero() * strength;
+            ctx.damage_events.push(DamageEvent {
+                amount: damage,
+                target,
+                knockback,
+                source: Some(self.uid),
+            });
             self.contact_cooldown = 0.3;
         }
     }
@@ -587,10 +1553,32 @@ impl MovementRegistry {
         };
         registry.register("idle", movement_idle);
         registry.register("wander", movement_wander);
+        registry.register("wander_home", movement_wander_home);
         registry.register("seek", movement_seek);
         registry.register("flee", movement_flee);
         registry.register("dash_at_target", movement_dash_at_target);
         registry.register("virabird_ai", movement_virabird_ai);
+        registry.register("drop_hazard", movement_drop_hazard);
+        registry.register("produce_item", movement_produce_item);
+        registry.register("seek_feed", movement_seek_feed);
+        registry.register("shoot_projectile", movement_shoot_projectile);
+        registry.register("return_home", movement_return_home);
+        registry.register("path_to_target", movement_path_to_target);
+        registry.register("set_var", movement_set_var);
+        registry.register("set_flag", movement_set_flag);
+        registry.register("clear_var", movement_clear_var);
+        registry.register("remember_target", movement_remember_target);
+        registry.register("investigate", movement_investigate);
+        registry.register("look_around", movement_look_around);
+        registry.register("peck", movement_peck);
+        registry.register("hop", movement_hop);
+        registry.register("patrol", movement_patrol);
+        registry.register("flock", movement_flock);
+        registry.register("cast_ability", movement_cast_ability);
+        registry.register("summon", movement_summon);
+        registry.register("harvest_structure", movement_harvest_structure);
+        registry.register("pollinate", movement_pollinate);
+        registry.register(PET_ACTION, movement_follow_owner);
         registry
     }
 
@@ -610,63 +1598,101 @@ impl MovementRegistry {
     }
 }
 
-pub struct EntityContext {
+pub struct EntityContext<'a> {
+    pub map: &'a crate::map::TileMap,
     pub player: Option<PlayerTarget>,
     pub target: Option<Target>,
     pub entities: Vec<EntityTarget>,
-    pub target_cache: HashMap<(u64, u8), Option<EntityTarget>>,
+    /// `entities` bucketed by position, for neighbour queries.
+    pub grid: SpatialGrid,
+    pub target_cache: HashMap<u64, Option<EntityTarget>>,
     pub view_height: f32,
     pub damage_events: Vec<DamageEvent>,
+    /// From the world time, for the `night` and `day` conditions.
+    pub night: bool,
+    /// Uid of the entity that last hurt the player, for pets to go after.
+    pub player_attacker: Option<u64>,
+    /// Tiles of crops that can still be pollinated today, for bees.
+    pub crops: Vec<(usize, usize)>,
 }
 
-impl EntityContext {
+impl EntityContext<'_> {
+    /// Entities whose position is within `radius` of `pos`.
+    pub fn neighbours(&self, pos: Vec2, radius: f32) -> impl Iterator<Item = &EntityTarget> {
+        self.grid
+            .candidates(pos, radius)
+            .filter_map(|index| self.entities.get(index))
+            .filter(move |other| other.pos.distance_squared(pos) <= radius * radius)
+    }
+
+    /// The nearest of `crops` within `range` of `pos`, with the center of its tile.
+    pub fn nearest_crop(&self, pos: Vec2, range: f32) -> Option<((usize, usize), Vec2)> {
+        let tile_size = self.map.tile_size();
+        self.crops
+            .iter()
+            .map(|&(x, y)| ((x, y), vec2((x as f32 + 0.5) * tile_size, (y as f32 + 0.5) * tile_size)))
+            .filter(|(_, center)| center.distance_squared(pos) <= range * range)
+            .min_by(|a, b| a.1.distance_squared(pos).total_cmp(&b.1.distance_squared(pos)))
+    }
+
+    /// The nearer of the player and the nearest entity `entity` may go after, counting only
+    /// factions its own is hostile to, inside its aggro ranges and leash.
     fn resolve_target(&mut self, db: &EntityDatabase, entity: &EntityInstance) -> Option<Target> {
         if let Some(target) = self.target {
             return Some(target);
         }
-        let def_flags = db.entities[entity.def].flags;
-        let target_player = (def_flags & DEF_FLAG_TARGET_PLAYER) != 0;
-        if target_player {
-            return self.player.map(Target::Player);
-        }
-
-        let target_any = (def_flags & DEF_FLAG_TARGET_NEAREST_ENTITY) != 0;
-        let target_enemy = (def_flags & DEF_FLAG_TARGET_NEAREST_ENEMY) != 0;
-        let target_friend = (def_flags & DEF_FLAG_TARGET_NEAREST_FRIEND) != 0;
-        let target_misc = (def_flags & DEF_FLAG_TARGET_NEAREST_MISC) != 0;
-        let has_specific_target_flags = target_enemy || target_friend || target_misc;
-        let is_kind_targetable = |kind: EntityKind| match kind {
-            EntityKind::Enemy => {
-                if has_specific_target_flags {
-                    target_enemy
-                } else {
-                    target_any || target_enemy
-                }
-            }
-            EntityKind::Friend => {
-                if has_specific_target_flags {
-                    target_friend
-                } else {
-                    target_any || target_friend
-                }
-            }
-            EntityKind::Misc => {
-                if has_specific_target_flags {
-                    target_misc
-                } else {
-                    target_any || target_misc
-                }
+        if entity.pet {
+            let attacker = self.player_attacker?;
+            return self
+                .entities
+                .iter()
+                .find(|other| other.id == attacker && other.alive)
+                .copied()
+                .map(Target::Entity);
+        }
+        let def = &db.entities[entity.def];
+        let faction = def.faction_index;
+        let hunts_player = db.factions.is_hostile(faction, PLAYER);
+        // Tamed entities are on the player's side and leave everything alone.
+        if hunts_player && entity.tamed {
+            return None;
+        }
+        let chasing_player = matches!(entity.current_target, Some(Target::Player(_)));
+        let player = self
+            .player
+            .filter(|player| hunts_player && within_aggro(entity, def, player.pos, chasing_player));
+        let other = if db.factions.hunts_entities(faction) {
+            self.nearest_hostile(db, entity)
+        } else {
+            None
+        };
+        match (player, other) {
+            (Some(player), Some(other))
+                if entity.pos.distance_squared(other.pos) < entity.pos.distance_squared(player.pos) =>
+            {
+                Some(Target::Entity(other))
             }
+            (Some(player), _) => Some(Target::Player(player)),
+            (None, other) => other.map(Target::Entity),
+        }
+    }
+
+    /// The nearest living entity of a faction hostile to `entity`'s that it may go after.
+    fn nearest_hostile(&mut self, db: &EntityDatabase, entity: &EntityInstance) -> Option<EntityTarget> {
+        let def = &db.entities[entity.def];
+        let chasing_id = match entity.current_target {
+            Some(Target::Entity(target)) => Some(target.id),
+            _ => None,
+        };
+        // Without ranges a target only changes when it dies, so the cache can be trusted; with
+        // them, targets come into and go out of range every frame.
+        let ranged = def.aggro.is_some() || def.leash.is_some();
+        let is_hostile = |candidate: &EntityTarget| {
+            db.factions
+                .is_hostile(def.faction_index, db.entities[candidate.def].faction_index)
         };
-        let mask = (target_any as u8)
-            | ((target_enemy as u8) << 1)
-            | ((target_friend as u8) << 2)
-            | ((target_misc as u8) << 3);
 
-        if mask == 0 {
-            return None;
-        }
-        if let Some(cached) = self.target_cache.get(&(entity.uid, mask)).copied() {
+        if let Some(cached) = self.target_cache.get(&entity.uid).copied() {
             if let Some(cached_target) = cached {
                 let current_target = self
                     .entities
@@ -674,27 +1700,24 @@ impl EntityContext {
                     .find(|candidate| {
                         candidate.id == cached_target.id
                             && candidate.alive
-                            && is_kind_targetable(candidate.kind)
+                            && is_hostile(candidate)
+                            && within_aggro(entity, def, candidate.pos, chasing_id == Some(candidate.id))
                     })
                     .copied();
-                if let Some(target) = current_target {
-                    return Some(Target::Entity(target));
+                if current_target.is_some() {
+                    return current_target;
                 }
-            } else {
+            } else if !ranged {
                 return None;
             }
         }
 
         let mut best: Option<(f32, EntityTarget)> = None;
         for candidate in &self.entities {
-            if candidate.id == entity.uid {
+            if candidate.id == entity.uid || !candidate.alive {
                 continue;
             }
-            if !candidate.alive {
-                continue;
-            }
-            let kind_ok = is_kind_targetable(candidate.kind);
-            if !kind_ok {
+            if !is_hostile(candidate) || !within_aggro(entity, def, candidate.pos, chasing_id == Some(candidate.id)) {
                 continue;
             }
             let dist_sq = entity.pos.distance_squared(candidate.pos);
@@ -704,37 +1727,72 @@ impl EntityContext {
             }
         }
         let resolved = best.map(|(_, target)| target);
-        self.target_cache.insert((entity.uid, mask), resolved);
-        resolved.map(Target::Entity)
+        self.target_cache.insert(entity.uid, resolved);
+        resolved
+    }
+}
+
+fn link_factions(entities: &mut [EntityDef], factions: &mut FactionTable) {
+    for def in entities {
+        def.faction_index = factions.index(&def.faction);
     }
 }
 
+/// Whether `entity` may go after a target at `pos`: inside its leash around home, and within its
+/// acquire range, or its lose range when it is `chasing` that target already.
+fn within_aggro(entity: &EntityInstance, def: &EntityDef, pos: Vec2, chasing: bool) -> bool {
+    if let Some(leash) = def.leash
+        && entity.home.distance(pos) > leash.radius
+    {
+        return false;
+    }
+    let Some(aggro) = def.aggro else {
+        return true;
+    };
+    let range = if chasing { aggro.lose } else { aggro.acquire };
+    entity.pos.distance(pos) <= range
+}
+
 pub struct EntityDatabase {
     pub traits: Vec<TraitDef>,
     pub behaviors: Vec<BehaviorDef>,
     pub entities: Vec<EntityDef>,
+    pub abilities: Vec<AbilityDef>,
+    /// Factions of `entities` and who is hostile to whom.
+    pub factions: FactionTable,
     trait_lookup: HashMap<String, usize>,
     behavior_lookup: HashMap<String, usize>,
     entity_lookup: HashMap<String, usize>,
+    ability_lookup: HashMap<String, usize>,
+    /// Problems the loaders worked around, kept for `validate`.
+    load_issues: Vec<DefIssue>,
 }
 
 impl EntityDatabase {
     pub async fn load_from(root: impl AsRef<Path>) -> Result<Self, EntityLoadError> {
         let root_path = root.as_ref().to_path_buf();
-        let (behaviors, traits) = if cfg!(target_arch = "wasm32") {
+        let (behaviors, traits, abilities) = if cfg!(target_arch = "wasm32") {
             let root = data_path(&root_path.to_string_lossy());
             let behaviors = load_behaviors_wasm(&format!("{}/behaviour", root)).await?;
             let traits = load_traits_wasm(&format!("{}/trait", root)).await?;
-            (behaviors, traits)
+            let abilities = load_abilities_wasm(&format!("{}/ability", root)).await?;
+            (behaviors, traits, abilities)
         } else {
             let behavior_dir = root_path.join("behaviour");
             let trait_dir = root_path.join("trait");
-            (load_behaviors(&behavior_dir)?, load_traits(&trait_dir)?)
+            let ability_dir = root_path.join("ability");
+            (load_behaviors(&behavior_dir)?, load_traits(&trait_dir)?, load_abilities(&ability_dir)?)
         };
+        let ability_lookup = abilities
+            .iter()
+            .enumerate()
+            .map(|(index, ability)| (ability.id.clone(), index))
+            .collect();
         let (trait_lookup, behavior_lookup) = build_lookups(&traits, &behaviors);
 
         let mut entities = Vec::new();
         let mut entity_lookup = HashMap::new();
+        let mut load_issues = Vec::new();
         if cfg!(target_arch = "wasm32") {
             let root = data_path(&root_path.to_string_lossy());
             load_entities_from_dir_wasm(
@@ -746,6 +1804,7 @@ impl EntityDatabase {
                 &behaviors,
                 &mut entities,
                 &mut entity_lookup,
+                &mut load_issues,
             )
             .await?;
             load_entities_from_dir_wasm(
@@ -757,6 +1816,7 @@ impl EntityDatabase {
                 &behaviors,
                 &mut entities,
                 &mut entity_lookup,
+                &mut load_issues,
             )
             .await?;
             load_entities_from_dir_wasm(
@@ -768,6 +1828,7 @@ impl EntityDatabase {
                 &behaviors,
                 &mut entities,
                 &mut entity_lookup,
+                &mut load_issues,
             )
             .await?;
         } else {
@@ -783,6 +1844,7 @@ impl EntityDatabase {
                 &behaviors,
                 &mut entities,
                 &mut entity_lookup,
+                &mut load_issues,
             )
             .await?;
             load_entities_from_dir(
@@ -794,6 +1856,7 @@ impl EntityDatabase {
                 &behaviors,
                 &mut entities,
                 &mut entity_lookup,
+                &mut load_issues,
             )
             .await?;
             load_entities_from_dir(
@@ -805,35 +1868,187 @@ impl EntityDatabase {
                 &behaviors,
                 &mut entities,
                 &mut entity_lookup,
+                &mut load_issues,
             )
             .await?;
         }
 
+        let mut factions = FactionTable::default();
+        factions.merge(FactionFile::load(&root_path.join("factions.yaml").to_string_lossy()).await?);
+        link_factions(&mut entities, &mut factions);
+
         Ok(Self {
             traits,
             behaviors,
             entities,
+            abilities,
+            factions,
             trait_lookup,
             behavior_lookup,
             entity_lookup,
+            ability_lookup,
+            load_issues,
         })
     }
 
+    /// Merges a mod's `root` (laid out like `src/entity`) over the database. Definitions replace
+    /// loaded ones with the same id; `merged` sees the kind ("trait", "behavior", "ability" or
+    /// "entity"), id and whether it replaced one. Mod entities can use built-in traits, behaviors
+    /// and abilities, and a mod's `factions.yaml` adds to the loaded hostilities.
+    pub async fn load_mod(
+        &mut self,
+        root: &Path,
+        mut merged: impl FnMut(&'static str, &str, bool),
+    ) -> Result<(), EntityLoadError> {
+        for def in read_traits(&root.join("trait"))? {
+            let id = def.id.clone();
+            let replaced = mods::upsert(&mut self.traits, &mut self.trait_lookup, id.clone(), def);
+            merged("trait", &id, replaced);
+        }
+        for def in load_behaviors(&root.join("behaviour"))? {
+            let id = def.id.clone();
+            let replaced = mods::upsert(&mut self.behaviors, &mut self.behavior_lookup, id.clone(), def);
+            merged("behavior", &id, replaced);
+        }
+        for def in load_abilities(&root.join("ability"))? {
+            let id = def.id.clone();
+            let replaced = mods::upsert(&mut self.abilities, &mut self.ability_lookup, id.clone(), def);
+            merged("ability", &id, replaced);
+        }
+
+        let mut entities = Vec::new();
+        let mut entity_lookup = HashMap::new();
+        for (dir, kind) in [
+            ("enemy", EntityKind::Enemy),
+            ("friend", EntityKind::Friend),
+            ("misc", EntityKind::Misc),
+        ] {
+            load_entities_from_dir(
+                &root.join(dir),
+                kind,
+                &self.trait_lookup,
+                &self.behavior_lookup,
+                &self.traits,
+                &self.behaviors,
+                &mut entities,
+                &mut entity_lookup,
+                &mut self.load_issues,
+            )
+            .await?;
+        }
+        for def in entities {
+            let id = def.id.clone();
+            let replaced = mods::upsert(&mut self.entities, &mut self.entity_lookup, id.clone(), def);
+            merged("entity", &id, replaced);
+        }
+        self.factions.merge(FactionFile::read(&root.join("factions.yaml"))?);
+        link_factions(&mut self.entities, &mut self.factions);
+        Ok(())
+    }
+
     pub fn entity_id(&self, id: &str) -> Option<usize> {
         self.entity_lookup.get(id).copied()
     }
 
+    pub fn ability_id(&self, id: &str) -> Option<usize> {
+        self.ability_lookup.get(id).copied()
+    }
+
+    pub fn entity_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.entity_lookup.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        ids
+    }
+
     pub fn empty() -> Self {
         Self {
             traits: Vec::new(),
             behaviors: Vec::new(),
             entities: Vec::new(),
+            abilities: Vec::new(),
+            factions: FactionTable::default(),
             trait_lookup: HashMap::new(),
             behavior_lookup: HashMap::new(),
             entity_lookup: HashMap::new(),
+            ability_lookup: HashMap::new(),
+            load_issues: Vec::new(),
         }
     }
 
+    pub fn def(&self, id: &str) -> Option<&EntityDef> {
+        self.entity_lookup.get(id).map(|&index| &self.entities[index])
+    }
+
+    /// Replaces every definition with `fresh`, e.g. after the YAML changed on disk, and moves
+    /// live instances onto the definition with the same id. Instances whose definition is gone
+    /// are removed; returns how many.
+    pub fn replace(&mut self, fresh: EntityDatabase, entities: &mut EntityPool) -> usize {
+        let mut gone = Vec::new();
+        for (handle, ent) in entities.iter_mut_with_handles() {
+            let Some(index) = fresh.entity_id(&self.entities[ent.instance.def].id) else {
+                gone.push(handle);
+                continue;
+            };
+            let before = self.spawn_stats(ent.instance.def);
+            let after = fresh.spawn_stats(index);
+            ability::reset(&mut ent.instance);
+            ent.instance.rebind(index, &fresh.entities[index], &before, &after);
+        }
+        *self = fresh;
+        for &handle in &gone {
+            entities.remove(handle);
+        }
+        gone.len()
+    }
+
+    /// Every problem with the loaded definitions: what the loaders left out, behavior actions the
+    /// registry doesn't know, hitboxes without area, and trait flags the rest of the definition
+    /// cancels out.
+    pub fn validate(&self, registry: &MovementRegistry) -> Vec<DefIssue> {
+        let mut issues = self.load_issues.clone();
+        for def in &self.entities {
+            let mut issue = |message: String| issues.push(DefIssue::new(&def.id, message));
+            for action in def.behavior_tree.iter().flat_map(BehaviorNode::actions) {
+                if !registry.has(action) {
+                    issue(format!("behavior action '{action}' isn't registered; it will idle"));
+                }
+            }
+            if def.hitbox.w <= 0.0 || def.hitbox.h <= 0.0 {
+                issue(format!("hitbox is {}x{}; it needs a positive width and height", def.hitbox.w, def.hitbox.h));
+            }
+            for trait_def in def.traits.iter().map(|&index| &self.traits[index]) {
+                for flag in &trait_def.flags {
+                    let ignored = match flag.as_str() {
+                        "no_map_collision" => def.collides,
+                        "no_entity_collision" => def.collides_with.0 & CollisionMask::ENTITIES.0 != 0,
+                        "no_player_collision" => def.collides_with.contains(CollisionLayer::Player),
+                        "no_enemy_collision" => def.collides_with.contains(CollisionLayer::Enemy),
+                        "no_friend_collision" => def.collides_with.contains(CollisionLayer::Friend),
+                        "no_misc_collision" => def.collides_with.contains(CollisionLayer::Misc),
+                        _ => false,
+                    };
+                    if ignored {
+                        issue(format!("trait '{}' sets {flag}, but collides_with overrides it", trait_def.id));
+                    }
+                }
+            }
+            if def.has_flag(DEF_FLAG_CANT_SWIM) && !def.collides {
+                issue("cant_swim does nothing without terrain collision".to_string());
+            }
+        }
+        issues
+    }
+
+    /// The stats an instance of entity `index` spawns with: its own plus its traits'.
+    fn spawn_stats(&self, index: usize) -> StatBlock {
+        let def = &self.entities[index];
+        let mut stats = def.base_stats.clone();
+        for &trait_idx in &def.traits {
+            stats.merge(&self.traits[trait_idx].stats);
+        }
+        stats
+    }
+
     pub fn spawn(
         &self,
         id: &str,
@@ -843,10 +2058,7 @@ impl EntityDatabase {
         let index = self.entity_lookup.get(id).copied()?;
         let def = &self.entities[index];
 
-        let mut stats = def.base_stats.clone();
-        for &trait_idx in &def.traits {
-            stats.merge(&self.traits[trait_idx].stats);
-        }
+        let stats = self.spawn_stats(index);
         let max_hp = stats.get("hp", 1.0).max(1.0);
 
         let mut behaviors = Vec::new();
@@ -864,9 +2076,12 @@ impl EntityDatabase {
             name: action.to_string(),
             func: registry.resolve(action),
             params: MovementParams::new(),
+            labels: ActionLabels::new(),
             timer: 0.0,
             dir: Vec2::ZERO,
             cooldown: 0.0,
+            path: Vec::new(),
+            progress: 0.0,
         });
 
         Some(EntityInstance {
@@ -882,8 +2097,38 @@ impl EntityDatabase {
             collision_scratch: Vec::with_capacity(25),
             dynamic_collision_scratch: Vec::with_capacity(25),
             current_target: None,
+            unseen_time: 0.0,
+            abilities: AbilityState::default(),
+            parent: None,
+            working: false,
             contact_cooldown: 0.0,
             dash_trail: None,
+            swimming: false,
+            ripple_trail: None,
+            corpse_timer: None,
+            requests: Vec::new(),
+            home: pos,
+            returning_home: false,
+            anim: AnimState::new(ANIM_IDLE),
+            hurt_timer: 0.0,
+            tier: 0,
+            knockback: Vec2::ZERO,
+            launch: None,
+            center_offset: def.hitbox.center(),
+            hunger: def.hunger.map(Hunger::new),
+            patrol: def.patrol.clone().map(Patrol::new),
+            tamed: false,
+            pet: false,
+            asleep: false,
+            // Start somewhere in the interval so a group doesn't call in unison.
+            vocal_timers: def
+                .vocals
+                .iter()
+                .map(|vocal| rand::gen_range(0.0, vocal.interval.max(0.0)))
+                .collect(),
+            decorators: DecoratorMemory::default(),
+            blackboard: Blackboard::default(),
+            pose: Pose::default(),
         })
     }
 }
@@ -894,6 +2139,161 @@ impl EntityInstance {
             return;
         }
         self.hp = (self.hp - amount).max(0.0);
+        self.hurt_timer = HURT_ANIM_TIME;
+    }
+
+    /// Multiplies each listed stat by `1 + per_tier * tier` and refreshes hp and speed.
+    pub fn apply_tier(&mut self, tier: u32, per_tier: &HashMap<String, f32>) {
+        self.tier = tier;
+        for (key, step) in per_tier {
+            self.stats.scale(key, 1.0 + step * tier as f32);
+        }
+        self.max_hp = self.stats.get("hp", self.max_hp).max(1.0);
+        self.hp = self.max_hp;
+        self.speed = self.stats.get("speed", self.speed).max(1.0);
+    }
+
+    /// Moves the instance onto a reloaded definition. Each stat keeps its ratio to what it
+    /// spawned with, so tiers carry over, and hp keeps its share of max hp. Decorator memory and
+    /// the patrol start over.
+    pub fn rebind(&mut self, index: usize, def: &EntityDef, before: &StatBlock, after: &StatBlock) {
+        self.def = index;
+        let mut keys = before.keys();
+        keys.extend(after.keys());
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let old = before.get(key, 0.0);
+            let new = after.get(key, 0.0);
+            let current = self.stats.get(key, old);
+            let value = if old != 0.0 { current * new / old } else { current + new };
+            self.stats.set(key, value);
+        }
+        let share = self.hp / self.max_hp.max(1.0);
+        self.max_hp = self.stats.get("hp", 1.0).max(1.0);
+        self.hp = share * self.max_hp;
+        self.speed = self.stats.get("speed", def.speed).max(1.0);
+        self.center_offset = def.hitbox.center();
+        self.hunger = match (self.hunger, def.hunger) {
+            (Some(hunger), Some(info)) => Some(Hunger {
+                food: hunger.food.min(info.max),
+                info,
+            }),
+            (_, info) => info.map(Hunger::new),
+        };
+        self.patrol = def.patrol.clone().map(Patrol::new);
+        self.vocal_timers.resize(def.vocals.len(), 0.0);
+        self.decorators = DecoratorMemory::default();
+    }
+
+    /// The cheap tick for entities far from the camera: they stand still and only get hungrier.
+    pub fn sleep(&mut self, dt: f32) {
+        self.vel = Vec2::ZERO;
+        self.tick_hunger(dt);
+    }
+
+    fn tick_hunger(&mut self, dt: f32) {
+        if let Some(hunger) = self.hunger.as_mut() {
+            hunger.food = (hunger.food - hunger.info.decay * dt).max(0.0);
+        }
+    }
+
+    /// Turns the entity into the player's pet; see `crate::pet`.
+    pub fn make_pet(&mut self) {
+        self.pet = true;
+        self.tamed = true;
+        self.returning_home = false;
+        self.current_target = None;
+        self.parent = None;
+    }
+
+    /// Called when the entity's parent dies; returns true if the child should die as well.
+    pub fn orphan(&mut self) -> bool {
+        let Some(parent) = self.parent.take() else {
+            return false;
+        };
+        match parent.on_death {
+            OrphanRule::Stay => false,
+            OrphanRule::Despawn => true,
+            OrphanRule::Enrage => {
+                self.stats.scale("damage", ENRAGE_SCALE);
+                self.stats.scale("speed", ENRAGE_SCALE);
+                self.speed = self.stats.get("speed", self.speed * ENRAGE_SCALE).max(1.0);
+                false
+            }
+        }
+    }
+
+    /// Pushes the entity; knockback at or above the def's threshold launches it into the air.
+    pub fn apply_knockback(&mut self, impulse: Vec2, info: &KnockbackInfo) {
+        if self.is_corpse() {
+            return;
+        }
+        let impulse = impulse * (1.0 - info.resistance).clamp(0.0, 1.0);
+        if impulse.length_squared() <= 0.0 {
+            return;
+        }
+        self.knockback += impulse;
+        if self.launch.is_none() && self.knockback.length() >= info.launch_threshold {
+            self.launch = Some(LaunchState {
+                remaining: info.launch_time,
+                duration: info.launch_time,
+                height: info.launch_height,
+                hit_wall: false,
+            });
+            self.current_target = None;
+        }
+    }
+
+    /// Counts down the vocal timers and returns the sound to play this frame, if any.
+    pub fn tick_vocals<'a>(&mut self, def: &'a EntityDef, dt: f32) -> Option<&'a str> {
+        let mut sound = None;
+        for (vocal, timer) in def.vocals.iter().zip(self.vocal_timers.iter_mut()) {
+            *timer -= dt;
+            if *timer > 0.0 {
+                continue;
+            }
+            *timer += vocal.interval.max(0.1);
+            if sound.is_none() && rand::gen_range(0.0, 1.0) < vocal.chance {
+                sound = Some(vocal.sound.as_str());
+            }
+        }
+        sound
+    }
+
+    pub fn is_corpse(&self) -> bool {
+        self.corpse_timer.is_some()
+    }
+
+    /// Switches a dead entity into its corpse stage: no behaviors, no movement, fading out.
+    pub fn begin_corpse(&mut self, duration: f32) {
+        self.corpse_timer = Some(duration.max(0.0));
+        self.behaviors.clear();
+        self.vel = Vec2::ZERO;
+        self.knockback = Vec2::ZERO;
+        self.launch = None;
+        self.current_target = None;
+        self.anim.play(ANIM_DIE, 0.0);
+    }
+
+    /// Advances the corpse stage: the `die` clip, then the `corpse` clip if the sprite has one.
+    /// Returns false once the entity should be removed.
+    pub fn tick_corpse(&mut self, db: &EntityDatabase, dt: f32) -> bool {
+        let Some(timer) = self.corpse_timer.as_mut() else {
+            return true;
+        };
+        *timer -= dt;
+        let def = &db.entities[self.def];
+        let texture = &def.texture;
+        let dead_for = def.death.corpse_time - *timer;
+        let clip = if texture.has_clip(ANIM_CORPSE) && dead_for >= texture.clip_length(ANIM_DIE) {
+            ANIM_CORPSE
+        } else {
+            ANIM_DIE
+        };
+        let alive = *timer > 0.0;
+        self.anim.play(clip, dt);
+        alive
     }
 }
 
@@ -917,21 +2317,13 @@ fn hitbox_center_world(pos: Vec2, hitbox: Rect) -> Vec2 {
 }
 
 fn collect_dynamic_collision_hitboxes(
-    entity_flags: u16,
+    collides_with: CollisionMask,
     entity_uid: u64,
     current_target: Option<Target>,
     ctx: &EntityContext,
     out: &mut Vec<Rect>,
 ) {
     out.clear();
-    if (entity_flags & DEF_FLAG_NO_ENTITY_COLLISION) != 0 {
-        return;
-    }
-
-    let no_enemy_collision = (entity_flags & DEF_FLAG_NO_ENEMY_COLLISION) != 0;
-    let no_friend_collision = (entity_flags & DEF_FLAG_NO_FRIEND_COLLISION) != 0;
-    let no_misc_collision = (entity_flags & DEF_FLAG_NO_MISC_COLLISION) != 0;
-    let no_player_collision = (entity_flags & DEF_FLAG_NO_PLAYER_COLLISION) != 0;
     let target_entity_id = match current_target {
         Some(Target::Entity(target)) => Some(target.id),
         _ => None,
@@ -940,25 +2332,19 @@ fn collect_dynamic_collision_hitboxes(
 
     out.reserve(ctx.entities.len() + 1);
 
-    if !no_player_collision && !target_is_player {
+    if collides_with.contains(CollisionLayer::Player) && !target_is_player {
         if let Some(player) = ctx.player {
             out.push(player.hitbox);
         }
     }
 
     for other in &ctx.entities {
-        if other.id == entity_uid {
+        if other.id == entity_uid || !other.alive {
             continue;
         }
-        if target_entity_id == Some(other.id) {
+        if target_entity_id == Some(other.id) || !collides_with.contains(other.layer) {
             continue;
         }
-        match other.kind {
-            EntityKind::Enemy if no_enemy_collision => continue,
-            EntityKind::Friend if no_friend_collision => continue,
-            EntityKind::Misc if no_misc_collision => continue,
-            _ => {}
-        }
         out.push(other.hitbox);
     }
 }
@@ -966,6 +2352,7 @@ fn collect_dynamic_collision_hitboxes(
 struct SelectedAction {
     name: String,
     params: MovementParams,
+    labels: ActionLabels,
 }
 
 fn action_params(params: &MovementParams, extra: &HashMap<String, YamlValue>) -> MovementParams {
@@ -982,10 +2369,18 @@ fn action_params(params: &MovementParams, extra: &HashMap<String, YamlValue>) ->
     merged
 }
 
+fn action_labels(extra: &HashMap<String, YamlValue>) -> ActionLabels {
+    extra
+        .iter()
+        .filter_map(|(key, value)| value.as_str().map(|v| (key.clone(), v.to_string())))
+        .collect()
+}
+
 fn eval_behavior(
     node: &BehaviorNode,
     entity: &EntityInstance,
     ctx: &EntityContext,
+    memory: &mut DecoratorMemory,
 ) -> (Option<SelectedAction>, Vec<SelectedAction>, bool) {
     match node {
         BehaviorNode::Action {
@@ -997,22 +2392,30 @@ fn eval_behavior(
             let action = SelectedAction {
                 name: name.clone(),
                 params: action_params(params, extra),
+                labels: action_labels(extra),
             };
             let mut multi = Vec::new();
             if *multiple {
                 multi.push(SelectedAction {
                     name: action.name.clone(),
                     params: action.params.clone(),
+                    labels: action.labels.clone(),
                 });
             }
             (Some(action), multi, true)
         }
-        BehaviorNode::Condition { name, value } => (None, Vec::new(), eval_condition(name, *value, entity, ctx)),
+        BehaviorNode::Condition { name, value, var, tag } => {
+            let labels = ConditionLabels {
+                var: var.as_deref(),
+                tag: tag.as_deref(),
+            };
+            (None, Vec::new(), eval_condition(name, *value, labels, entity, ctx))
+        }
         BehaviorNode::Sequence { children } => {
             let mut action = None;
             let mut multiple_actions = Vec::new();
             for child in children {
-                let (child_action, child_multiple, ok) = eval_behavior(child, entity, ctx);
+                let (child_action, child_multiple, ok) = eval_behavior(child, entity, ctx, memory);
                 if !ok {
                     return (None, Vec::new(), false);
                 }
@@ -1021,23 +2424,100 @@ fn eval_behavior(
                 }
                 multiple_actions.extend(child_multiple);
             }
-            (action, multiple_actions, true)
+            (action, multiple_actions, true)
+        }
+        BehaviorNode::Selector { children } => {
+            let mut primary: Option<SelectedAction> = None;
+            let mut multiple_actions = Vec::new();
+            let mut any_ok = false;
+            for child in children {
+                let (child_action, child_multiple, ok) = eval_behavior(child, entity, ctx, memory);
+                if ok {
+                    any_ok = true;
+                    if primary.is_none() {
+                        primary = child_action;
+                    }
+                    multiple_actions.extend(child_multiple);
+                }
+            }
+            (primary, multiple_actions, any_ok)
+        }
+        BehaviorNode::RandomSelector {
+            children,
+            weights,
+            interval,
+            slot,
+        } => {
+            if children.is_empty() {
+                return (None, Vec::new(), false);
+            }
+            let (mut state, continued) = memory.visit(*slot);
+            if !continued || memory.clock >= state.at || state.choice >= children.len() {
+                state.choice = pick_weighted(children.len(), weights);
+                state.at = memory.clock + interval.max(0.0);
+            }
+            let result = eval_behavior(&children[state.choice], entity, ctx, memory);
+            if !result.2 {
+                state.at = memory.clock;
+            }
+            memory.store(*slot, state);
+            result
+        }
+        BehaviorNode::Inverter { child } => {
+            let (_, _, ok) = eval_behavior(child, entity, ctx, memory);
+            (None, Vec::new(), !ok)
+        }
+        BehaviorNode::Cooldown { seconds, child, slot } => {
+            let (mut state, continued) = memory.visit(*slot);
+            if memory.clock < state.at {
+                return (None, Vec::new(), false);
+            }
+            let result = eval_behavior(child, entity, ctx, memory);
+            // The cooldown starts when the child stops succeeding, or stops being reached.
+            if state.active && (!result.2 || !continued) {
+                state.at = memory.clock + seconds;
+                state.active = false;
+                memory.store(*slot, state);
+                return (None, Vec::new(), false);
+            }
+            state.active = result.2;
+            memory.store(*slot, state);
+            result
+        }
+        BehaviorNode::Timeout { seconds, child, slot } => {
+            let (mut state, continued) = memory.visit(*slot);
+            let result = eval_behavior(child, entity, ctx, memory);
+            if !result.2 {
+                state.active = false;
+                memory.store(*slot, state);
+                return result;
+            }
+            if !state.active || !continued {
+                state.active = true;
+                state.at = memory.clock;
+            }
+            memory.store(*slot, state);
+            if memory.clock - state.at > *seconds {
+                return (None, Vec::new(), false);
+            }
+            result
         }
-        BehaviorNode::Selector { children } => {
-            let mut primary: Option<SelectedAction> = None;
-            let mut multiple_actions = Vec::new();
-            let mut any_ok = false;
-            for child in children {
-                let (child_action, child_multiple, ok) = eval_behavior(child, entity, ctx);
-                if ok {
-                    any_ok = true;
-                    if primary.is_none() {
-                        primary = child_action;
-                    }
-                    multiple_actions.extend(child_multiple);
-                }
+        BehaviorNode::Chance {
+            value,
+            interval,
+            child,
+            slot,
+        } => {
+            let (mut state, continued) = memory.visit(*slot);
+            if !continued || memory.clock >= state.at {
+                state.active = rand::gen_range(0.0, 1.0) < *value;
+                state.at = memory.clock + interval.max(0.0);
             }
-            (primary, multiple_actions, any_ok)
+            memory.store(*slot, state);
+            if !state.active {
+                return (None, Vec::new(), false);
+            }
+            eval_behavior(child, entity, ctx, memory)
         }
     }
 }
@@ -1046,8 +2526,9 @@ fn select_actions(
     node: &BehaviorNode,
     entity: &EntityInstance,
     ctx: &EntityContext,
+    memory: &mut DecoratorMemory,
 ) -> Vec<SelectedAction> {
-    let (primary, multiple, ok) = eval_behavior(node, entity, ctx);
+    let (primary, multiple, ok) = eval_behavior(node, entity, ctx, memory);
     if !ok {
         return Vec::new();
     }
@@ -1059,7 +2540,11 @@ fn select_actions(
     for action in multiple {
         let duplicate = out
             .iter()
-            .any(|existing| existing.name == action.name && existing.params == action.params);
+            .any(|existing| {
+                existing.name == action.name
+                    && existing.params == action.params
+                    && existing.labels == action.labels
+            });
         if !duplicate {
             out.push(action);
         }
@@ -1067,7 +2552,168 @@ fn select_actions(
     out
 }
 
-fn eval_condition(name: &str, value: Option<f32>, entity: &EntityInstance, ctx: &EntityContext) -> bool {
+fn default_chance_interval() -> f32 {
+    1.0
+}
+
+fn default_random_interval() -> f32 {
+    3.0
+}
+
+/// Index of a child picked at random, each weighted by `weights` (1 when missing).
+fn pick_weighted(count: usize, weights: &[f32]) -> usize {
+    let weight = |index: usize| weights.get(index).copied().unwrap_or(1.0).max(0.0);
+    let total: f32 = (0..count).map(weight).sum();
+    if total <= 0.0 {
+        return 0;
+    }
+    let mut roll = rand::gen_range(0.0, total);
+    for index in 0..count {
+        roll -= weight(index);
+        if roll < 0.0 {
+            return index;
+        }
+    }
+    count - 1
+}
+
+/// Per-entity state of the stateful decorators in its behaviour tree, by slot.
+#[derive(Clone, Default)]
+pub struct DecoratorMemory {
+    /// Seconds of behaviour evaluated so far.
+    clock: f32,
+    /// Evaluations so far, to tell decorators that were skipped.
+    frame: u32,
+    slots: Vec<DecoratorState>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct DecoratorState {
+    /// Cooldown end, timeout start or next roll of a chance or random selector, on the memory's
+    /// clock.
+    at: f32,
+    /// Child a random selector picked.
+    choice: usize,
+    /// Cooldown: the child was succeeding. Timeout: it is timing the child. Chance: the roll
+    /// passed.
+    active: bool,
+    /// Frame of the last visit.
+    seen: u32,
+}
+
+impl DecoratorMemory {
+    fn begin(&mut self, dt: f32) {
+        self.clock += dt;
+        self.frame += 1;
+    }
+
+    /// The slot's state and whether it was also visited the frame before.
+    fn visit(&mut self, slot: usize) -> (DecoratorState, bool) {
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, DecoratorState::default());
+        }
+        let state = &mut self.slots[slot];
+        let continued = state.seen != 0 && state.seen + 1 == self.frame;
+        state.seen = self.frame;
+        (*state, continued)
+    }
+
+    fn store(&mut self, slot: usize, state: DecoratorState) {
+        self.slots[slot] = state;
+    }
+}
+
+/// Conditions that check a distance, as a fraction of the view height (1 when left out).
+pub const RANGE_CONDITIONS: [&str; 5] = [
+    "target_in_range",
+    "feed_in_range",
+    "var_in_range",
+    "structure_in_range",
+    "crop_in_range",
+];
+
+impl BehaviorNode {
+    /// The names of every action in the tree.
+    pub fn actions(&self) -> Vec<&str> {
+        match self {
+            Self::Selector { children }
+            | Self::Sequence { children }
+            | Self::RandomSelector { children, .. } => children.iter().flat_map(BehaviorNode::actions).collect(),
+            Self::Inverter { child }
+            | Self::Cooldown { child, .. }
+            | Self::Timeout { child, .. }
+            | Self::Chance { child, .. } => child.actions(),
+            Self::Action { name, .. } => vec![name.as_str()],
+            Self::Condition { .. } => Vec::new(),
+        }
+    }
+
+    /// The range conditions anywhere in the tree, with their value.
+    pub fn ranges(&self) -> Vec<(&str, f32)> {
+        match self {
+            Self::Selector { children }
+            | Self::Sequence { children }
+            | Self::RandomSelector { children, .. } => children.iter().flat_map(BehaviorNode::ranges).collect(),
+            Self::Condition { name, value, .. } if RANGE_CONDITIONS.contains(&name.as_str()) => {
+                vec![(name.as_str(), value.unwrap_or(1.0).max(0.0))]
+            }
+            Self::Inverter { child }
+            | Self::Cooldown { child, .. }
+            | Self::Timeout { child, .. }
+            | Self::Chance { child, .. } => child.ranges(),
+            Self::Condition { .. } | Self::Action { .. } => Vec::new(),
+        }
+    }
+
+    /// Numbers the stateful decorators so each entity can keep their state in a
+    /// `DecoratorMemory`.
+    fn with_slots(mut self) -> Self {
+        self.assign_slots(&mut 0);
+        self
+    }
+
+    fn assign_slots(&mut self, next: &mut usize) {
+        match self {
+            Self::Selector { children } | Self::Sequence { children } => {
+                for child in children {
+                    child.assign_slots(next);
+                }
+            }
+            Self::RandomSelector { children, slot, .. } => {
+                *slot = *next;
+                *next += 1;
+                for child in children {
+                    child.assign_slots(next);
+                }
+            }
+            Self::Cooldown { child, slot, .. }
+            | Self::Timeout { child, slot, .. }
+            | Self::Chance { child, slot, .. } => {
+                *slot = *next;
+                *next += 1;
+                child.assign_slots(next);
+            }
+            Self::Inverter { child } => child.assign_slots(next),
+            Self::Condition { .. } | Self::Action { .. } => {}
+        }
+    }
+}
+
+/// The string keys a condition node can carry.
+struct ConditionLabels<'a> {
+    var: Option<&'a str>,
+    tag: Option<&'a str>,
+}
+
+fn eval_condition(
+    name: &str,
+    value: Option<f32>,
+    labels: ConditionLabels,
+    entity: &EntityInstance,
+    ctx: &EntityContext,
+) -> bool {
+    let blackboard = &entity.blackboard;
+    let var = labels.var;
     match name {
         "target_in_range" => {
             let Some(target) = entity.current_target.as_ref().map(Target::position) else {
@@ -1076,6 +2722,41 @@ fn eval_condition(name: &str, value: Option<f32>, entity: &EntityInstance, ctx:
             let range = value.unwrap_or(1.0).max(0.0) * ctx.view_height.max(1.0);
             entity.pos.distance(target) <= range
         }
+        "night" => ctx.night,
+        "day" => !ctx.night,
+        // Entities without hunger never get hungry, so they always count as fed.
+        "hungry" => entity.hunger.is_some_and(|hunger| !hunger.is_fed(value)),
+        "fed" => entity.hunger.is_none_or(|hunger| hunger.is_fed(value)),
+        "feed_in_range" => {
+            let range = value.unwrap_or(1.0).max(0.0) * ctx.view_height.max(1.0);
+            ctx.map.nearest_feed(entity.pos + entity.center_offset, range).is_some()
+        }
+        "structure_in_range" => {
+            let Some(tag) = labels.tag else {
+                return false;
+            };
+            let range = value.unwrap_or(1.0).max(0.0) * ctx.view_height.max(1.0);
+            ctx.map.nearest_structure(entity.pos + entity.center_offset, tag, range).is_some()
+        }
+        "crop_in_range" => {
+            let range = value.unwrap_or(1.0).max(0.0) * ctx.view_height.max(1.0);
+            ctx.nearest_crop(entity.pos + entity.center_offset, range).is_some()
+        }
+        "var_set" => var.is_some_and(|var| blackboard.contains(var)),
+        "var_true" => var.and_then(|var| blackboard.flag(var)).unwrap_or(false),
+        "var_greater_than" => var
+            .and_then(|var| blackboard.number(var))
+            .is_some_and(|number| number > value.unwrap_or(0.0)),
+        "var_less_than" => var
+            .and_then(|var| blackboard.number(var))
+            .is_some_and(|number| number < value.unwrap_or(0.0)),
+        "var_in_range" => {
+            let Some(pos) = var.and_then(|var| blackboard.position(var)) else {
+                return false;
+            };
+            let range = value.unwrap_or(1.0).max(0.0) * ctx.view_height.max(1.0);
+            entity.pos.distance(pos) <= range
+        }
         _ => false,
     }
 }
@@ -1092,7 +2773,9 @@ fn first_action_with_registry<'a>(
                 None
             }
         }
-        BehaviorNode::Selector { children } | BehaviorNode::Sequence { children } => {
+        BehaviorNode::Selector { children }
+        | BehaviorNode::Sequence { children }
+        | BehaviorNode::RandomSelector { children, .. } => {
             for child in children {
                 if let Some(name) = first_action_with_registry(child, registry) {
                     return Some(name);
@@ -1100,6 +2783,10 @@ fn first_action_with_registry<'a>(
             }
             None
         }
+        BehaviorNode::Inverter { .. } => None,
+        BehaviorNode::Cooldown { child, .. }
+        | BehaviorNode::Timeout { child, .. }
+        | BehaviorNode::Chance { child, .. } => first_action_with_registry(child, registry),
         BehaviorNode::Condition { .. } => None,
     }
 }
@@ -1130,38 +2817,48 @@ fn trait_indices_have_flag(trait_indices: &[usize], traits: &[TraitDef], flag: &
     })
 }
 
-fn entity_flags_from_trait_indices(trait_indices: &[usize], traits: &[TraitDef]) -> u16 {
-    let mut flags = 0u16;
-
-    if trait_indices_have_flag(trait_indices, traits, "target_player") {
-        flags |= DEF_FLAG_TARGET_PLAYER;
-    }
-    if trait_indices_have_flag(trait_indices, traits, "target_nearest_entity") {
-        flags |= DEF_FLAG_TARGET_NEAREST_ENTITY;
-    }
-    if trait_indices_have_flag(trait_indices, traits, "target_nearest_enemy") {
-        flags |= DEF_FLAG_TARGET_NEAREST_ENEMY;
-    }
-    if trait_indices_have_flag(trait_indices, traits, "target_nearest_friend") {
-        flags |= DEF_FLAG_TARGET_NEAREST_FRIEND;
+/// An explicit `collides_with` list wins. Otherwise the entity collides with everything except
+/// what its `no_*_collision` traits and `collides: false` rule out.
+fn collision_mask(
+    collides_with: Option<Vec<CollisionLayer>>,
+    collides: Option<bool>,
+    trait_indices: &[usize],
+    traits: &[TraitDef],
+) -> CollisionMask {
+    if let Some(layers) = collides_with {
+        return layers.into_iter().collect();
     }
-    if trait_indices_have_flag(trait_indices, traits, "target_nearest_misc") {
-        flags |= DEF_FLAG_TARGET_NEAREST_MISC;
+    let mut mask = CollisionMask::ALL;
+    if collides == Some(false) || trait_indices_have_flag(trait_indices, traits, "no_map_collision") {
+        mask = mask.without(CollisionLayer::Terrain);
     }
     if trait_indices_have_flag(trait_indices, traits, "no_entity_collision") {
-        flags |= DEF_FLAG_NO_ENTITY_COLLISION;
-    }
-    if trait_indices_have_flag(trait_indices, traits, "no_enemy_collision") {
-        flags |= DEF_FLAG_NO_ENEMY_COLLISION;
+        mask = CollisionMask(mask.0 & !CollisionMask::ENTITIES.0);
+    }
+    for (flag, layer) in [
+        ("no_player_collision", CollisionLayer::Player),
+        ("no_enemy_collision", CollisionLayer::Enemy),
+        ("no_friend_collision", CollisionLayer::Friend),
+        ("no_misc_collision", CollisionLayer::Misc),
+    ] {
+        if trait_indices_have_flag(trait_indices, traits, flag) {
+            mask = mask.without(layer);
+        }
     }
-    if trait_indices_have_flag(trait_indices, traits, "no_friend_collision") {
-        flags |= DEF_FLAG_NO_FRIEND_COLLISION;
+    mask
+}
+
+fn entity_flags_from_trait_indices(trait_indices: &[usize], traits: &[TraitDef]) -> u16 {
+    let mut flags = 0u16;
+
+    if trait_indices_have_flag(trait_indices, traits, "cant_swim") {
+        flags |= DEF_FLAG_CANT_SWIM;
     }
-    if trait_indices_have_flag(trait_indices, traits, "no_misc_collision") {
-        flags |= DEF_FLAG_NO_MISC_COLLISION;
+    if trait_indices_have_flag(trait_indices, traits, "capturable") {
+        flags |= DEF_FLAG_CAPTURABLE;
     }
-    if trait_indices_have_flag(trait_indices, traits, "no_player_collision") {
-        flags |= DEF_FLAG_NO_PLAYER_COLLISION;
+    if trait_indices_have_flag(trait_indices, traits, "requires_los") {
+        flags |= DEF_FLAG_REQUIRES_LOS;
     }
 
     flags
@@ -1190,9 +2887,14 @@ fn load_behaviors(dir: &Path) -> Result<Vec<BehaviorDef>, EntityLoadError> {
 }
 
 fn load_traits(dir: &Path) -> Result<Vec<TraitDef>, EntityLoadError> {
+    let mut traits = read_traits(dir)?;
+    append_builtin_traits(&mut traits);
+    Ok(traits)
+}
+
+fn read_traits(dir: &Path) -> Result<Vec<TraitDef>, EntityLoadError> {
     let mut traits = Vec::new();
     if !dir.exists() {
-        append_builtin_traits(&mut traits);
         return Ok(traits);
     }
 
@@ -1215,7 +2917,6 @@ fn load_traits(dir: &Path) -> Result<Vec<TraitDef>, EntityLoadError> {
         });
     }
 
-    append_builtin_traits(&mut traits);
     Ok(traits)
 }
 
@@ -1269,9 +2970,10 @@ async fn load_entities_from_dir_wasm(
     behaviors: &[BehaviorDef],
     entities: &mut Vec<EntityDef>,
     entity_lookup: &mut HashMap<String, usize>,
+    issues: &mut Vec<DefIssue>,
 ) -> Result<(), EntityLoadError> {
     let files = if dir.ends_with("/enemy") {
-        load_wasm_manifest_files(dir, &["virat.yaml", "virabird.yaml"]).await
+        load_wasm_manifest_files(dir, &["virat.yaml", "virat_queen.yaml", "virabird.yaml"]).await
     } else {
         load_wasm_manifest_files(dir, &[]).await
     };
@@ -1300,11 +3002,10 @@ async fn load_entities_from_dir_wasm(
 
         let mut trait_indices = Vec::with_capacity(raw.traits.len());
         for id in raw.traits {
-            let idx = trait_lookup
-                .get(&id)
-                .copied()
-                .ok_or_else(|| EntityLoadError::MissingDefinition(format!("trait {id}")))?;
-            trait_indices.push(idx);
+            match trait_lookup.get(&id) {
+                Some(&idx) => trait_indices.push(idx),
+                None => issues.push(DefIssue::new(&raw.id, format!("unknown trait '{id}'; left out"))),
+            }
         }
 
         let mut tags = raw.trait_tags;
@@ -1318,19 +3019,28 @@ async fn load_entities_from_dir_wasm(
         let behavior_tree = if let Some(behavior) = raw.behavior {
             Some(behavior)
         } else if let Some(id) = raw.behavior_id {
-            let idx = behavior_lookup
-                .get(&id)
-                .copied()
-                .ok_or_else(|| EntityLoadError::MissingDefinition(format!("behavior {id}")))?;
-            Some(behaviors[idx].tree.clone())
+            let tree = behavior_lookup.get(&id).map(|&idx| behaviors[idx].tree.clone());
+            if tree.is_none() {
+                issues.push(DefIssue::new(&raw.id, format!("unknown behavior '{id}'; it will idle")));
+            }
+            tree
         } else {
             None
-        };
+        }
+        .map(BehaviorNode::with_slots);
 
-        let tex = load_texture(&asset_path(&raw.visuals.sprite))
-            .await
-            .map_err(|err| EntityLoadError::Texture(err.to_string()))?;
+        let tex = match load_texture(&asset_path(&raw.visuals.sprite)).await {
+            Ok(tex) => tex,
+            Err(err) => {
+                let message = format!("sprite '{}' didn't load ({err}); entity left out", raw.visuals.sprite);
+                issues.push(DefIssue::new(&raw.id, message));
+                continue;
+            }
+        };
         tex.set_filter(FilterMode::Nearest);
+        let clips = load_clip_textures(&raw.id, &raw.visuals.clips, issues).await;
+        let (sheet, animations) = load_animations(&raw.id, &raw.visuals, &tex, issues);
+        let files = texture_files(&raw.visuals, &tex, &clips);
 
         let draw_params = raw.visuals.draw_params.unwrap_or_default();
         let color = Color::from_rgba(
@@ -1352,14 +3062,22 @@ async fn load_entities_from_dir_wasm(
             raw.hitbox.h,
         );
 
+        let collision_layer = raw.layer.unwrap_or(kind.into());
+        let collides_with = collision_mask(raw.collides_with, raw.collides, &trait_indices, traits);
+        let collides = collides_with.contains(CollisionLayer::Terrain);
         let mut base_stats = StatBlock::default();
         for (key, value) in raw.stats {
             base_stats.add(&key, value);
         }
 
-        let collides = raw.collides.unwrap_or(true)
-            && !trait_indices_have_flag(&trait_indices, traits, "no_map_collision");
         let flags = entity_flags_from_trait_indices(&trait_indices, traits);
+        let death = raw.death.map(DeathFile::into_info).unwrap_or_else(DeathInfo::default_info);
+        let leash = raw.leash.map(LeashFile::into_info);
+        let aggro = raw.aggro.map(AggroFile::into_info);
+        let knockback = raw.knockback.map(KnockbackFile::into_info).unwrap_or_default();
+        let hunger = raw.hunger.map(HungerFile::into_info);
+        let patrol = raw.patrol.and_then(PatrolFile::into_info);
+        let npc = raw.npc.map(NpcFile::into_info);
 
         let def = EntityDef {
             id: raw.id.clone(),
@@ -1376,6 +3094,10 @@ async fn load_entities_from_dir_wasm(
                     color,
                     offset: vec2(draw_params.offset[0], draw_params.offset[1]),
                 },
+                clips,
+                sheet,
+                animations,
+                files,
             },
             hitbox,
             traits: trait_indices,
@@ -1383,8 +3105,23 @@ async fn load_entities_from_dir_wasm(
             behavior_tree,
             base_stats,
             speed: raw.speed,
+            collision_layer,
+            collides_with,
             collides,
             flags,
+            faction: raw.faction.unwrap_or_else(|| kind.name().to_string()),
+            faction_index: PLAYER,
+            death,
+            leash,
+            aggro,
+            knockback,
+            hunger,
+            tame: raw.tame,
+            patrol,
+            npc,
+            intro_cutscene: raw.intro_cutscene,
+            vocals: raw.vocals,
+            source: path.clone(),
         };
 
         let index = entities.len();
@@ -1404,6 +3141,7 @@ async fn load_entities_from_dir(
     behaviors: &[BehaviorDef],
     entities: &mut Vec<EntityDef>,
     entity_lookup: &mut HashMap<String, usize>,
+    issues: &mut Vec<DefIssue>,
 ) -> Result<(), EntityLoadError> {
     if !dir.exists() {
         return Ok(());
@@ -1434,11 +3172,10 @@ async fn load_entities_from_dir(
 
         let mut trait_indices = Vec::with_capacity(raw.traits.len());
         for id in raw.traits {
-            let idx = trait_lookup
-                .get(&id)
-                .copied()
-                .ok_or_else(|| EntityLoadError::MissingDefinition(format!("trait {id}")))?;
-            trait_indices.push(idx);
+            match trait_lookup.get(&id) {
+                Some(&idx) => trait_indices.push(idx),
+                None => issues.push(DefIssue::new(&raw.id, format!("unknown trait '{id}'; left out"))),
+            }
         }
 
         let mut tags = raw.trait_tags;
@@ -1452,19 +3189,28 @@ async fn load_entities_from_dir(
         let behavior_tree = if let Some(behavior) = raw.behavior {
             Some(behavior)
         } else if let Some(id) = raw.behavior_id {
-            let idx = behavior_lookup
-                .get(&id)
-                .copied()
-                .ok_or_else(|| EntityLoadError::MissingDefinition(format!("behavior {id}")))?;
-            Some(behaviors[idx].tree.clone())
+            let tree = behavior_lookup.get(&id).map(|&idx| behaviors[idx].tree.clone());
+            if tree.is_none() {
+                issues.push(DefIssue::new(&raw.id, format!("unknown behavior '{id}'; it will idle")));
+            }
+            tree
         } else {
             None
-        };
+        }
+        .map(BehaviorNode::with_slots);
 
-        let tex = load_texture(&asset_path(&raw.visuals.sprite))
-            .await
-            .map_err(|err| EntityLoadError::Texture(err.to_string()))?;
+        let tex = match load_texture(&asset_path(&raw.visuals.sprite)).await {
+            Ok(tex) => tex,
+            Err(err) => {
+                let message = format!("sprite '{}' didn't load ({err}); entity left out", raw.visuals.sprite);
+                issues.push(DefIssue::new(&raw.id, message));
+                continue;
+            }
+        };
         tex.set_filter(FilterMode::Nearest);
+        let clips = load_clip_textures(&raw.id, &raw.visuals.clips, issues).await;
+        let (sheet, animations) = load_animations(&raw.id, &raw.visuals, &tex, issues);
+        let files = texture_files(&raw.visuals, &tex, &clips);
 
         let draw_params = raw.visuals.draw_params.unwrap_or_default();
         let color = Color::from_rgba(
@@ -1487,14 +3233,22 @@ async fn load_entities_from_dir(
             raw.hitbox.h,
         );
 
+        let collision_layer = raw.layer.unwrap_or(kind.into());
+        let collides_with = collision_mask(raw.collides_with, raw.collides, &trait_indices, traits);
+        let collides = collides_with.contains(CollisionLayer::Terrain);
         let mut base_stats = StatBlock::default();
         for (key, value) in raw.stats {
             base_stats.add(&key, value);
         }
 
-        let collides = raw.collides.unwrap_or(true)
-            && !trait_indices_have_flag(&trait_indices, traits, "no_map_collision");
         let flags = entity_flags_from_trait_indices(&trait_indices, traits);
+        let death = raw.death.map(DeathFile::into_info).unwrap_or_else(DeathInfo::default_info);
+        let leash = raw.leash.map(LeashFile::into_info);
+        let aggro = raw.aggro.map(AggroFile::into_info);
+        let knockback = raw.knockback.map(KnockbackFile::into_info).unwrap_or_default();
+        let hunger = raw.hunger.map(HungerFile::into_info);
+        let patrol = raw.patrol.and_then(PatrolFile::into_info);
+        let npc = raw.npc.map(NpcFile::into_info);
 
         let def = EntityDef {
             id: raw.id.clone(),
@@ -1511,6 +3265,10 @@ async fn load_entities_from_dir(
                     color,
                     offset: vec2(draw_params.offset[0], draw_params.offset[1]),
                 },
+                clips,
+                sheet,
+                animations,
+                files,
             },
             hitbox,
             traits: trait_indices,
@@ -1518,8 +3276,23 @@ async fn load_entities_from_dir(
             behavior_tree,
             base_stats,
             speed: raw.speed,
+            collision_layer,
+            collides_with,
             collides,
             flags,
+            faction: raw.faction.unwrap_or_else(|| kind.name().to_string()),
+            faction_index: PLAYER,
+            death,
+            leash,
+            aggro,
+            knockback,
+            hunger,
+            tame: raw.tame,
+            patrol,
+            npc,
+            intro_cutscene: raw.intro_cutscene,
+            vocals: raw.vocals,
+            source: path.to_string_lossy().into_owned(),
         };
 
         let index = entities.len();
@@ -1574,11 +3347,153 @@ struct EntityFile {
     speed: f32,
     kind: Option<EntityKind>,
     #[serde(default)]
+    faction: Option<String>,
+    #[serde(default)]
     collides: Option<bool>,
     #[serde(default)]
+    layer: Option<CollisionLayer>,
+    #[serde(default)]
+    collides_with: Option<Vec<CollisionLayer>>,
+    #[serde(default)]
     behavior: Option<BehaviorNode>,
     #[serde(default)]
     behavior_id: Option<String>,
+    #[serde(default)]
+    death: Option<DeathFile>,
+    #[serde(default)]
+    leash: Option<LeashFile>,
+    #[serde(default)]
+    aggro: Option<AggroFile>,
+    #[serde(default)]
+    knockback: Option<KnockbackFile>,
+    #[serde(default)]
+    hunger: Option<HungerFile>,
+    #[serde(default)]
+    tame: Option<TameInfo>,
+    #[serde(default)]
+    patrol: Option<PatrolFile>,
+    #[serde(default)]
+    npc: Option<NpcFile>,
+    #[serde(default)]
+    intro_cutscene: Option<String>,
+    #[serde(default)]
+    vocals: Vec<VocalInfo>,
+}
+
+#[derive(Deserialize)]
+struct DeathFile {
+    #[serde(default = "default_corpse_time")]
+    corpse_time: f32,
+    /// Defaults to the whole of `corpse_time`.
+    #[serde(default)]
+    fade_time: Option<f32>,
+    #[serde(default)]
+    particle: Option<String>,
+    #[serde(default)]
+    sound: Option<String>,
+    #[serde(default)]
+    drops: Vec<LootDrop>,
+    /// Older files list item ids here, each always dropping once.
+    #[serde(default)]
+    loot: Vec<String>,
+}
+
+impl DeathFile {
+    fn into_info(self) -> DeathInfo {
+        let legacy = self.loot.into_iter().map(|item| LootDrop {
+            item,
+            chance: default_drop_chance(),
+            min: default_drop_count(),
+            max: default_drop_count(),
+        });
+        let corpse_time = self.corpse_time.max(0.0);
+        DeathInfo {
+            corpse_time,
+            fade_time: self.fade_time.map_or(corpse_time, |fade| fade.clamp(0.0, corpse_time)),
+            particle: self.particle,
+            sound: self.sound,
+            drops: self.drops.into_iter().chain(legacy).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LeashFile {
+    radius: f32,
+    #[serde(default = "default_leash_regen")]
+    regen: f32,
+    #[serde(default = "default_leash_return_speed")]
+    return_speed: f32,
+}
+
+impl LeashFile {
+    fn into_info(self) -> LeashInfo {
+        LeashInfo {
+            radius: self.radius.max(LEASH_HOME_RADIUS),
+            regen: self.regen.max(0.0),
+            return_speed: self.return_speed.max(0.1),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AggroFile {
+    acquire: f32,
+    /// Half again `acquire` when left out.
+    #[serde(default)]
+    lose: Option<f32>,
+}
+
+impl AggroFile {
+    fn into_info(self) -> AggroInfo {
+        let acquire = self.acquire.max(0.0);
+        AggroInfo {
+            acquire,
+            lose: self.lose.unwrap_or(acquire * 1.5).max(acquire),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct KnockbackFile {
+    #[serde(default)]
+    resistance: Option<f32>,
+    #[serde(default)]
+    launch_threshold: Option<f32>,
+    #[serde(default)]
+    launch_time: Option<f32>,
+    #[serde(default)]
+    launch_height: Option<f32>,
+    #[serde(default)]
+    wall_damage: Option<f32>,
+    #[serde(default)]
+    friction: Option<f32>,
+}
+
+impl KnockbackFile {
+    fn into_info(self) -> KnockbackInfo {
+        let base = KnockbackInfo::default();
+        KnockbackInfo {
+            resistance: self.resistance.unwrap_or(base.resistance).clamp(0.0, 1.0),
+            launch_threshold: self.launch_threshold.unwrap_or(base.launch_threshold).max(1.0),
+            launch_time: self.launch_time.unwrap_or(base.launch_time).max(0.0),
+            launch_height: self.launch_height.unwrap_or(base.launch_height).max(0.0),
+            wall_damage: self.wall_damage.unwrap_or(base.wall_damage).max(0.0),
+            friction: self.friction.unwrap_or(base.friction).max(0.0),
+        }
+    }
+}
+
+impl DeathInfo {
+    fn default_info() -> Self {
+        Self {
+            corpse_time: default_corpse_time(),
+            fade_time: default_corpse_time(),
+            particle: Some("death_puff".to_string()),
+            sound: None,
+            drops: Vec::new(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -1586,6 +3501,125 @@ struct VisualsFile {
     sprite: String,
     #[serde(default)]
     draw_params: Option<DrawParamsFile>,
+    /// Clip name -> sprite path, selected via the `anim` hint on behavior actions.
+    #[serde(default)]
+    clips: HashMap<String, String>,
+    /// Makes `sprite` a sheet of equal frames for `animations`.
+    #[serde(default)]
+    sheet: Option<SheetFile>,
+    #[serde(default)]
+    animations: HashMap<String, AnimationFile>,
+}
+
+#[derive(Deserialize)]
+struct SheetFile {
+    frame_width: f32,
+    frame_height: f32,
+}
+
+#[derive(Deserialize)]
+struct AnimationFile {
+    frames: Vec<u32>,
+    #[serde(default = "default_frame_time")]
+    frame_time: f32,
+    #[serde(default = "default_looped")]
+    looped: bool,
+}
+
+fn default_frame_time() -> f32 {
+    0.15
+}
+
+fn default_looped() -> bool {
+    true
+}
+
+/// Reads the frame grid and clips, rejecting clips that point past the last frame.
+/// The sheet and its animations; a sheet without a frame size is dropped and broken animations
+/// are left out, each noted in `issues`.
+fn load_animations(
+    id: &str,
+    visuals: &VisualsFile,
+    texture: &Texture2D,
+    issues: &mut Vec<DefIssue>,
+) -> (Option<SpriteSheet>, HashMap<String, Animation>) {
+    let Some(sheet) = visuals.sheet.as_ref() else {
+        return (None, HashMap::new());
+    };
+    if sheet.frame_width <= 0.0 || sheet.frame_height <= 0.0 {
+        let message = format!("sheet '{}' needs a frame size; drawn as one image", visuals.sprite);
+        issues.push(DefIssue::new(id, message));
+        return (None, HashMap::new());
+    }
+    let columns = (texture.width() / sheet.frame_width).floor().max(1.0) as u32;
+    let rows = (texture.height() / sheet.frame_height).floor().max(1.0) as u32;
+    let mut animations = HashMap::with_capacity(visuals.animations.len());
+    for (name, clip) in &visuals.animations {
+        if clip.frames.is_empty() {
+            issues.push(DefIssue::new(id, format!("animation '{name}' has no frames; left out")));
+            continue;
+        }
+        if let Some(&frame) = clip.frames.iter().find(|&&frame| frame >= columns * rows) {
+            let message = format!(
+                "animation '{name}' uses frame {frame} but sheet '{}' has {}; left out",
+                visuals.sprite,
+                columns * rows
+            );
+            issues.push(DefIssue::new(id, message));
+            continue;
+        }
+        animations.insert(
+            name.clone(),
+            Animation {
+                frames: clip.frames.clone(),
+                frame_time: clip.frame_time.max(0.001),
+                looped: clip.looped,
+            },
+        );
+    }
+    let sheet = SpriteSheet {
+        frame_size: vec2(sheet.frame_width, sheet.frame_height),
+        columns,
+    };
+    (Some(sheet), animations)
+}
+
+/// The sprite and clip files of `visuals` with the textures loaded from them.
+fn texture_files(
+    visuals: &VisualsFile,
+    sprite: &Texture2D,
+    clips: &HashMap<String, Texture2D>,
+) -> Vec<(String, Texture2D)> {
+    let mut files = vec![(asset_path(&visuals.sprite), sprite.clone())];
+    for (clip, path) in &visuals.clips {
+        if let Some(texture) = clips.get(clip) {
+            files.push((asset_path(path), texture.clone()));
+        }
+    }
+    files
+}
+
+/// Per-clip textures; a clip whose file doesn't load is noted in `issues` and falls back to the
+/// entity's sprite.
+async fn load_clip_textures(
+    id: &str,
+    clips: &HashMap<String, String>,
+    issues: &mut Vec<DefIssue>,
+) -> HashMap<String, Texture2D> {
+    let mut out = HashMap::with_capacity(clips.len());
+    for (clip, sprite) in clips {
+        let tex = match load_texture(&asset_path(sprite)).await {
+            Ok(tex) => tex,
+            Err(err) => {
+                let message = format!("clip '{clip}' sprite '{sprite}' didn't load ({err}); using the main sprite");
+                issues.push(DefIssue::new(id, message));
+                continue;
+            }
+        };
+        tex.set_filter(FilterMode::Nearest);
+        out.insert(clip.clone(), tex);
+    }
+    out
 }
 
 #[derive(Default, Deserialize)]
@@ -1625,3 +3659,109 @@ fn default_color() -> [u8; 4] {
 fn default_speed() -> f32 {
     80.0
 }
+
+#[derive(Deserialize)]
+struct HungerFile {
+    #[serde(default = "default_hunger_max")]
+    max: f32,
+    #[serde(default = "default_hunger_decay")]
+    decay: f32,
+    #[serde(default = "default_hunger_fed_at")]
+    fed_at: f32,
+    #[serde(default = "default_hunger_bite")]
+    bite: f32,
+}
+
+#[derive(Deserialize)]
+struct PatrolFile {
+    waypoints: Vec<[f32; 2]>,
+    #[serde(default = "default_patrol_mode")]
+    mode: PatrolMode,
+    #[serde(default)]
+    wait: f32,
+}
+
+impl PatrolFile {
+    fn into_info(self) -> Option<PatrolRoute> {
+        if self.waypoints.is_empty() {
+            return None;
+        }
+        Some(PatrolRoute {
+            waypoints: self.waypoints.into_iter().map(Vec2::from).collect(),
+            mode: self.mode,
+            wait: self.wait.max(0.0),
+        })
+    }
+}
+
+impl HungerFile {
+    fn into_info(self) -> HungerInfo {
+        HungerInfo {
+            max: self.max.max(1.0),
+            decay: self.decay.max(0.0),
+            fed_at: self.fed_at.clamp(0.0, 1.0),
+            bite: self.bite.max(0.0),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NpcFile {
+    #[serde(default)]
+    gifts: HashMap<String, u32>,
+    #[serde(default)]
+    dialogue: Vec<DialogueLine>,
+    #[serde(default)]
+    shop: Vec<ShopOffer>,
+}
+
+impl NpcFile {
+    fn into_info(mut self) -> NpcInfo {
+        self.dialogue.sort_by_key(|line| line.level);
+        NpcInfo {
+            gifts: self.gifts,
+            dialogue: self.dialogue,
+            shop: self.shop,
+        }
+    }
+}
+
+fn default_corpse_time() -> f32 {
+    0.8
+}
+
+fn default_drop_chance() -> f32 {
+    1.0
+}
+
+fn default_drop_count() -> u32 {
+    1
+}
+
+fn default_leash_regen() -> f32 {
+    2.0
+}
+
+fn default_leash_return_speed() -> f32 {
+    1.5
+}
+
+fn default_patrol_mode() -> PatrolMode {
+    PatrolMode::Loop
+}
+
+fn default_hunger_max() -> f32 {
+    100.0
+}
+
+fn default_hunger_decay() -> f32 {
+    1.0
+}
+
+fn default_hunger_fed_at() -> f32 {
+    0.3
+}
+
+fn default_hunger_bite() -> f32 {
+    20.0
+}
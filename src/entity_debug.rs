@@ -0,0 +1,132 @@
+//! Entity debug overlay.
+//!
+//! When the debug profile in world.yaml allows the inspector, its key (F6 unless rebound) toggles
+//! it. While it is on, clicking an entity selects
+//! it and clicking it again drops it. For every selected entity the overlay draws the route its
+//! movement planned, a line to its current target, its leash around home, a circle per range
+//! condition in its behaviour tree and the positions in its blackboard, so pathfinding and
+//! perception can be checked in the world.
+
+use macroquad::prelude::*;
+use crate::blackboard::BlackboardValue;
+use crate::entity::{EntityDatabase, Target};
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::tooltip::entity_under;
+use crate::ui::{UiBatch, UiLayer};
+
+const PATH_COLOR: Color = Color::new(0.3, 0.9, 1.0, 0.9);
+const TARGET_COLOR: Color = Color::new(1.0, 0.35, 0.3, 0.9);
+const LEASH_COLOR: Color = Color::new(1.0, 0.85, 0.2, 0.8);
+/// Returning home draws the leash in this color instead.
+const RETURNING_COLOR: Color = Color::new(1.0, 0.5, 0.1, 0.9);
+const RANGE_COLOR: Color = Color::new(0.7, 0.5, 1.0, 0.7);
+const VAR_COLOR: Color = Color::new(0.4, 1.0, 0.5, 0.9);
+const SELECTED_COLOR: Color = Color::new(1.0, 1.0, 1.0, 0.9);
+
+pub struct EntityDebug {
+    enabled: bool,
+    key: KeyCode,
+    active: bool,
+    selected: Vec<EntityHandle>,
+}
+
+impl EntityDebug {
+    /// The overlay only turns on when `enabled` by the debug profile; `key` toggles it.
+    pub fn new(enabled: bool, key: KeyCode) -> Self {
+        Self {
+            enabled,
+            key,
+            active: false,
+            selected: Vec::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Forgets the selection, e.g. after loading a save or changing level.
+    pub fn clear(&mut self) {
+        self.selected.clear();
+    }
+
+    /// Toggles the overlay and the entity under the cursor, and drops entities that are gone.
+    pub fn update(&mut self, entities: &EntityPool, db: &EntityDatabase, mouse_world: Vec2, wants_mouse: bool) {
+        if self.enabled && is_key_pressed(self.key) {
+            self.active = !self.active;
+        }
+        self.selected.retain(|&handle| entities.get(handle).is_some());
+        if !self.active || wants_mouse || !is_mouse_button_pressed(MouseButton::Left) {
+            return;
+        }
+        if let Some((handle, _)) = entity_under(entities, db, mouse_world) {
+            match self.selected.iter().position(|&selected| selected == handle) {
+                Some(index) => {
+                    self.selected.remove(index);
+                }
+                None => self.selected.push(handle),
+            }
+        }
+    }
+
+    /// Draws the overlays of the selected entities in world space. `view_height` turns range
+    /// conditions into world pixels the way behaviour trees do. Blackboard names are queued on
+    /// `ui` at their screen positions under `camera`.
+    pub fn draw_world(
+        &self,
+        entities: &EntityPool,
+        db: &EntityDatabase,
+        view_height: f32,
+        camera: &Camera2D,
+        ui: &mut UiBatch,
+    ) {
+        if !self.active {
+            return;
+        }
+        for ent in self.selected.iter().filter_map(|&handle| entities.get(handle)) {
+            let def = &db.entities[ent.instance.def];
+            let hitbox = ent.hitbox(db);
+            let body = hitbox.center();
+            draw_rectangle_lines(hitbox.x, hitbox.y, hitbox.w, hitbox.h, 1.0, SELECTED_COLOR);
+
+            if let Some(leash) = def.leash {
+                let color = if ent.instance.returning_home { RETURNING_COLOR } else { LEASH_COLOR };
+                let home = ent.instance.home;
+                draw_circle_lines(home.x, home.y, leash.radius, 1.0, color);
+                draw_circle(home.x, home.y, 1.5, color);
+            }
+            for (_, range) in def.behavior_tree.iter().flat_map(|tree| tree.ranges()) {
+                draw_circle_lines(body.x, body.y, range * view_height.max(1.0), 1.0, RANGE_COLOR);
+            }
+
+            for runtime in ent.instance.behaviors.iter().filter(|runtime| !runtime.path.is_empty()) {
+                let mut from = body;
+                for &waypoint in &runtime.path {
+                    draw_line(from.x, from.y, waypoint.x, waypoint.y, 1.0, PATH_COLOR);
+                    draw_circle(waypoint.x, waypoint.y, 1.5, PATH_COLOR);
+                    from = waypoint;
+                }
+            }
+            if let Some(target) = ent.instance.current_target.as_ref().map(Target::position) {
+                draw_line(body.x, body.y, target.x, target.y, 1.0, TARGET_COLOR);
+                draw_circle_lines(target.x, target.y, 3.0, 1.0, TARGET_COLOR);
+            }
+            for (name, value) in ent.instance.blackboard.iter() {
+                if let BlackboardValue::Position(pos) = value {
+                    draw_rectangle_lines(pos.x - 2.0, pos.y - 2.0, 4.0, 4.0, 1.0, VAR_COLOR);
+                    let label = camera.world_to_screen(pos + vec2(4.0, -2.0));
+                    ui.text(UiLayer::Debug, name, label, 14.0, VAR_COLOR);
+                }
+            }
+        }
+    }
+
+    /// Queues a line saying the overlay is on and how to use it.
+    pub fn draw_ui(&self, ui: &mut UiBatch) {
+        if !self.active {
+            return;
+        }
+        let text = format!("Entity debug: click entities to inspect ({} selected)", self.selected.len());
+        ui.text(UiLayer::Debug, text, vec2(10.0, screen_height() - 12.0), 18.0, WHITE);
+    }
+}
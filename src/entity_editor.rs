@@ -0,0 +1,284 @@
+//! Entity tuning panel.
+//!
+//! When the debug profile in world.yaml allows the entity editor, its key (F4 unless rebound)
+//! toggles a panel listing every entity definition with
+//! a button that spawns one next to the player. Clicking a name selects it and shows a slider per
+//! stat. Dragging a slider writes straight into the loaded definition, so everything spawned
+//! afterwards uses the new value. Export writes the selected definition's file out again under
+//! `exports/entity/` with the tuned stats.
+
+use macroquad::prelude::*;
+use std::path::Path;
+use crate::entity::{EntityDatabase, EntityDef};
+use crate::ui::{UiBatch, UiLayer};
+
+const EXPORT_DIR: &str = "exports/entity";
+const STATUS_TIME: f32 = 3.0;
+
+const PANEL_WIDTH: f32 = 300.0;
+const PANEL_TOP: f32 = 60.0;
+const HEADER_HEIGHT: f32 = 32.0;
+const ROW_HEIGHT: f32 = 24.0;
+const SLIDER_X: f32 = 100.0;
+const SLIDER_WIDTH: f32 = 130.0;
+const BUTTON_SIZE: Vec2 = vec2(64.0, 20.0);
+/// A slider's range ends at this multiple of the stat's value when the entity was selected.
+const SLIDER_RANGE: f32 = 3.0;
+/// Kept on the definition itself when the file doesn't list it under `stats`.
+const SPEED_STAT: &str = "speed";
+
+struct Slider {
+    stat: String,
+    max: f32,
+}
+
+pub struct EntityEditor {
+    enabled: bool,
+    key: KeyCode,
+    open: bool,
+    /// Index into `EntityDatabase::entities`.
+    selected: Option<usize>,
+    sliders: Vec<Slider>,
+    dragging: Option<usize>,
+    /// Entity count at the last update, which sizes the panel.
+    rows: usize,
+    /// Entity ids to spawn next to the player.
+    pending_spawns: Vec<String>,
+    status: String,
+    status_timer: f32,
+}
+
+impl EntityEditor {
+    /// The panel only opens when `enabled` by the debug profile; `key` toggles it.
+    pub fn new(enabled: bool, key: KeyCode) -> Self {
+        Self {
+            enabled,
+            key,
+            open: false,
+            selected: None,
+            sliders: Vec::new(),
+            dragging: None,
+            rows: 0,
+            pending_spawns: Vec::new(),
+            status: String::new(),
+            status_timer: 0.0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Whether the cursor is over the open panel, so clicks shouldn't reach the world.
+    pub fn wants_mouse(&self) -> bool {
+        self.open && self.panel_rect().contains(mouse_position().into())
+    }
+
+    /// Takes the entity ids whose spawn button was pressed since the last call.
+    pub fn take_spawns(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending_spawns)
+    }
+
+    pub fn update(&mut self, db: &mut EntityDatabase, dt: f32) {
+        self.status_timer = (self.status_timer - dt).max(0.0);
+        if self.enabled && is_key_pressed(self.key) {
+            self.open = !self.open;
+            self.dragging = None;
+        }
+        if !self.open {
+            return;
+        }
+        self.rows = db.entities.len();
+        let mouse: Vec2 = mouse_position().into();
+
+        if let Some(index) = self.dragging {
+            if !is_mouse_button_down(MouseButton::Left) {
+                self.dragging = None;
+            } else if let (Some(def), Some(slider)) =
+                (self.selected.and_then(|i| db.entities.get_mut(i)), self.sliders.get(index))
+            {
+                let bar = self.slider_rect(index);
+                let t = ((mouse.x - bar.x) / bar.w).clamp(0.0, 1.0);
+                set_stat(def, &slider.stat, (t * slider.max * 10.0).round() / 10.0);
+            }
+            return;
+        }
+        if !is_mouse_button_pressed(MouseButton::Left) || !self.panel_rect().contains(mouse) {
+            return;
+        }
+
+        for (index, def) in db.entities.iter().enumerate() {
+            if self.spawn_button_rect(index).contains(mouse) {
+                self.pending_spawns.push(def.id.clone());
+                return;
+            }
+            if self.row_rect(index).contains(mouse) {
+                self.select(def, index);
+                return;
+            }
+        }
+        if let Some(index) = (0..self.sliders.len()).find(|&index| self.slider_rect(index).contains(mouse)) {
+            self.dragging = Some(index);
+            return;
+        }
+        if self.export_button_rect().contains(mouse)
+            && let Some(def) = self.selected.and_then(|i| db.entities.get(i))
+        {
+            match export(def) {
+                Ok(path) => self.set_status(format!("exported to {path}")),
+                Err(err) => self.set_status(format!("export failed: {err}")),
+            }
+        }
+    }
+
+    fn select(&mut self, def: &EntityDef, index: usize) {
+        self.selected = Some(index);
+        let mut stats: Vec<String> = def.base_stats.keys().into_iter().map(str::to_string).collect();
+        if !stats.iter().any(|stat| stat == SPEED_STAT) {
+            stats.push(SPEED_STAT.to_string());
+        }
+        self.sliders = stats
+            .into_iter()
+            .map(|stat| Slider {
+                max: (stat_value(def, &stat) * SLIDER_RANGE).max(1.0),
+                stat,
+            })
+            .collect();
+    }
+
+    fn set_status(&mut self, status: String) {
+        eprintln!("entity editor: {status}");
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    fn origin(&self) -> Vec2 {
+        vec2(screen_width() - PANEL_WIDTH - 20.0, PANEL_TOP)
+    }
+
+    fn panel_rect(&self) -> Rect {
+        let sliders = if self.selected.is_some() {
+            ROW_HEIGHT * (self.sliders.len() + 2) as f32
+        } else {
+            0.0
+        };
+        let origin = self.origin();
+        Rect::new(origin.x, origin.y, PANEL_WIDTH, HEADER_HEIGHT + ROW_HEIGHT * self.rows as f32 + sliders + 8.0)
+    }
+
+    fn row_rect(&self, index: usize) -> Rect {
+        let origin = self.origin();
+        Rect::new(origin.x, origin.y + HEADER_HEIGHT + ROW_HEIGHT * index as f32, PANEL_WIDTH, ROW_HEIGHT)
+    }
+
+    fn spawn_button_rect(&self, index: usize) -> Rect {
+        let row = self.row_rect(index);
+        Rect::new(
+            row.right() - BUTTON_SIZE.x - 8.0,
+            row.y + (ROW_HEIGHT - BUTTON_SIZE.y) * 0.5,
+            BUTTON_SIZE.x,
+            BUTTON_SIZE.y,
+        )
+    }
+
+    /// Sliders sit under the entity list, after a row naming the selected entity.
+    fn slider_row(&self, index: usize) -> Rect {
+        self.row_rect(self.rows + 1 + index)
+    }
+
+    fn slider_rect(&self, index: usize) -> Rect {
+        let row = self.slider_row(index);
+        Rect::new(row.x + SLIDER_X, row.y + ROW_HEIGHT * 0.5 - 4.0, SLIDER_WIDTH, 8.0)
+    }
+
+    fn export_button_rect(&self) -> Rect {
+        let row = self.slider_row(self.sliders.len());
+        Rect::new(row.x + 10.0, row.y + (ROW_HEIGHT - BUTTON_SIZE.y) * 0.5, BUTTON_SIZE.x * 1.5, BUTTON_SIZE.y)
+    }
+
+    /// Queues the open panel and the last status message.
+    pub fn draw_ui(&self, db: &EntityDatabase, ui: &mut UiBatch) {
+        if !self.open {
+            return;
+        }
+        let panel = self.panel_rect();
+        ui.panel(panel, "Entities");
+
+        for (index, def) in db.entities.iter().enumerate().take(self.rows) {
+            let row = self.row_rect(index);
+            let color = if self.selected == Some(index) { GOLD } else { WHITE };
+            ui.text(UiLayer::Panel, def.name.as_str(), vec2(row.x + 10.0, row.y + 17.0), 18.0, color);
+            ui.button(self.spawn_button_rect(index), "Spawn", true);
+        }
+
+        let Some(def) = self.selected.and_then(|i| db.entities.get(i)) else {
+            return;
+        };
+        let heading = self.row_rect(self.rows);
+        let heading_pos = vec2(heading.x + 10.0, heading.y + 17.0);
+        ui.text(UiLayer::Panel, format!("{} stats", def.id), heading_pos, 18.0, LIGHTGRAY);
+        for (index, slider) in self.sliders.iter().enumerate() {
+            let row = self.slider_row(index);
+            let bar = self.slider_rect(index);
+            let value = stat_value(def, &slider.stat);
+            let fill = (value / slider.max).clamp(0.0, 1.0);
+            ui.text(UiLayer::Panel, slider.stat.as_str(), vec2(row.x + 10.0, row.y + 17.0), 18.0, WHITE);
+            ui.rect(UiLayer::Panel, bar, DARKGRAY);
+            ui.rect(UiLayer::Panel, Rect::new(bar.x, bar.y, bar.w * fill, bar.h), SKYBLUE);
+            let knob = Rect::new(bar.x + bar.w * fill - 2.0, bar.y - 3.0, 4.0, bar.h + 6.0);
+            ui.rect(UiLayer::Panel, knob, WHITE);
+            ui.text(UiLayer::Panel, format!("{value:.1}"), vec2(bar.right() + 10.0, row.y + 17.0), 18.0, WHITE);
+        }
+        ui.button(self.export_button_rect(), "Export YAML", true);
+
+        if self.status_timer > 0.0 {
+            ui.text(UiLayer::Panel, self.status.as_str(), vec2(panel.x, panel.bottom() + 20.0), 18.0, WHITE);
+        }
+    }
+}
+
+/// A stat's base value; speed falls back to the definition's own field.
+fn stat_value(def: &EntityDef, stat: &str) -> f32 {
+    let fallback = if stat == SPEED_STAT { def.speed } else { 0.0 };
+    def.base_stats.get(stat, fallback)
+}
+
+fn set_stat(def: &mut EntityDef, stat: &str, value: f32) {
+    if stat == SPEED_STAT && def.base_stats.keys().iter().all(|&key| key != SPEED_STAT) {
+        def.speed = value;
+    } else {
+        def.base_stats.set(stat, value);
+    }
+}
+
+/// Rewrites the definition's source file with its current stats into `EXPORT_DIR`, returning
+/// the path written. Everything besides the stats is copied from the file as it was loaded.
+fn export(def: &EntityDef) -> Result<String, String> {
+    if cfg!(target_arch = "wasm32") {
+        return Err("exporting is not supported on this platform".to_string());
+    }
+    let source = std::fs::read_to_string(&def.source).map_err(|err| format!("{}: {err}", def.source))?;
+    let mut doc: serde_yaml::Value = serde_yaml::from_str(&source).map_err(|err| err.to_string())?;
+    let Some(fields) = doc.as_mapping_mut() else {
+        return Err(format!("{} is not a yaml mapping", def.source));
+    };
+    let mut stats = serde_yaml::Mapping::new();
+    for key in def.base_stats.keys() {
+        stats.insert(key.into(), yaml_number(def.base_stats.get(key, 0.0)));
+    }
+    if !stats.contains_key(SPEED_STAT) {
+        fields.insert(SPEED_STAT.into(), yaml_number(def.speed));
+    }
+    fields.insert("stats".into(), stats.into());
+
+    let path = Path::new(EXPORT_DIR).join(format!("{}.yaml", def.id));
+    std::fs::create_dir_all(EXPORT_DIR).map_err(|err| err.to_string())?;
+    let yaml = serde_yaml::to_string(&doc).map_err(|err| err.to_string())?;
+    std::fs::write(&path, yaml).map_err(|err| err.to_string())?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Rounded so values like 0.1 don't come out as 0.10000000149011612.
+fn yaml_number(value: f32) -> serde_yaml::Value {
+    ((value as f64 * 1000.0).round() / 1000.0).into()
+}
@@ -0,0 +1,143 @@
+//! Live entities with stable handles.
+//!
+//! Entities sit densely packed so per-frame passes walk a plain slice, and `EntityHandle`s point
+//! at slots that remember where their entity currently is. Removing an entity moves the last one
+//! into its place and bumps the slot's generation, so handles to other entities keep working and
+//! handles to the removed one go stale instead of silently pointing at whatever reuses the slot.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::entity::Entity;
+
+/// Reference to a pooled entity that survives other entities being removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EntityHandle {
+    slot: u32,
+    generation: u32,
+}
+
+struct Slot {
+    generation: u32,
+    /// Index into the dense entity list, or `None` while the slot is free.
+    dense: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct EntityPool {
+    entities: Vec<Entity>,
+    /// Handle of each entity, parallel to `entities`.
+    handles: Vec<EntityHandle>,
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+}
+
+impl EntityPool {
+    pub fn insert(&mut self, entity: Entity) -> EntityHandle {
+        let dense = self.entities.len();
+        let handle = match self.free.pop() {
+            Some(slot) => {
+                let entry = &mut self.slots[slot as usize];
+                entry.dense = Some(dense);
+                EntityHandle {
+                    slot,
+                    generation: entry.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    dense: Some(dense),
+                });
+                EntityHandle {
+                    slot: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+        self.entities.push(entity);
+        self.handles.push(handle);
+        handle
+    }
+
+    /// Removes the entity behind `handle`; `None` if it is already gone.
+    pub fn remove(&mut self, handle: EntityHandle) -> Option<Entity> {
+        let dense = self.dense_index(handle)?;
+        Some(self.remove_at(dense))
+    }
+
+    pub fn get(&self, handle: EntityHandle) -> Option<&Entity> {
+        self.dense_index(handle).map(|dense| &self.entities[dense])
+    }
+
+    pub fn get_mut(&mut self, handle: EntityHandle) -> Option<&mut Entity> {
+        self.dense_index(handle).map(|dense| &mut self.entities[dense])
+    }
+
+    /// Handle of the entity at `index` in the slice.
+    pub fn handle(&self, index: usize) -> EntityHandle {
+        self.handles[index]
+    }
+
+    pub fn iter_with_handles(&self) -> impl Iterator<Item = (EntityHandle, &Entity)> {
+        self.handles.iter().copied().zip(self.entities.iter())
+    }
+
+    pub fn iter_mut_with_handles(&mut self) -> impl Iterator<Item = (EntityHandle, &mut Entity)> {
+        self.handles.iter().copied().zip(self.entities.iter_mut())
+    }
+
+    /// Keeps the entities `keep` returns true for. Survivors may change places in the slice.
+    pub fn retain_mut(&mut self, mut keep: impl FnMut(&mut Entity) -> bool) {
+        let mut index = 0;
+        while index < self.entities.len() {
+            if keep(&mut self.entities[index]) {
+                index += 1;
+            } else {
+                self.remove_at(index);
+            }
+        }
+    }
+
+    fn dense_index(&self, handle: EntityHandle) -> Option<usize> {
+        let slot = self.slots.get(handle.slot as usize)?;
+        if slot.generation == handle.generation { slot.dense } else { None }
+    }
+
+    fn remove_at(&mut self, dense: usize) -> Entity {
+        let removed = self.handles.swap_remove(dense);
+        let slot = &mut self.slots[removed.slot as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.dense = None;
+        self.free.push(removed.slot);
+        if let Some(moved) = self.handles.get(dense) {
+            self.slots[moved.slot as usize].dense = Some(dense);
+        }
+        self.entities.swap_remove(dense)
+    }
+}
+
+/// Entities can be read and changed in place through the slice; insert and remove go through
+/// the pool so handles stay valid.
+impl Deref for EntityPool {
+    type Target = [Entity];
+
+    fn deref(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+impl DerefMut for EntityPool {
+    fn deref_mut(&mut self) -> &mut [Entity] {
+        &mut self.entities
+    }
+}
+
+impl FromIterator<Entity> for EntityPool {
+    fn from_iter<I: IntoIterator<Item = Entity>>(iter: I) -> Self {
+        let mut pool = Self::default();
+        for entity in iter {
+            pool.insert(entity);
+        }
+        pool
+    }
+}
@@ -0,0 +1,99 @@
+//! Factions and who attacks whom.
+//!
+//! Every entity belongs to one faction: the `faction` in its YAML, or its kind (`enemy`, `friend`
+//! or `misc`) when it names none. The player is always `player`. `src/entity/factions.yaml` lists
+//! the factions each faction is hostile to. Hostility is one-way, so rats going after chopbots
+//! doesn't make chopbots fight back. Target resolution and contact damage only pick hostile
+//! targets; an entity whose faction is hostile to nobody never targets anything.
+
+use crate::asset_pack::load_string;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::entity::EntityLoadError;
+use crate::helpers::data_path;
+
+pub const PLAYER_FACTION: &str = "player";
+/// Index of `player` in every table.
+pub const PLAYER: usize = 0;
+
+#[derive(Default, Deserialize)]
+pub struct FactionFile {
+    /// Factions each faction attacks, by name.
+    #[serde(default)]
+    pub hostile: HashMap<String, Vec<String>>,
+}
+
+impl FactionFile {
+    pub async fn load(path: &str) -> Result<Self, EntityLoadError> {
+        if cfg!(target_arch = "wasm32") {
+            let raw_str = load_string(&data_path(path))
+                .await
+                .map_err(|err| EntityLoadError::Io(std::io::Error::other(err.to_string())))?;
+            return Ok(serde_yaml::from_str(&raw_str)?);
+        }
+        Self::read(Path::new(path))
+    }
+
+    /// Reads `path` from disk; a missing file is an empty table.
+    pub fn read(path: &Path) -> Result<Self, EntityLoadError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_yaml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Faction names by index and the hostile pairs between them.
+pub struct FactionTable {
+    names: Vec<String>,
+    lookup: HashMap<String, usize>,
+    /// (attacker, target) pairs.
+    hostile: HashSet<(usize, usize)>,
+}
+
+impl Default for FactionTable {
+    fn default() -> Self {
+        Self {
+            names: vec![PLAYER_FACTION.to_string()],
+            lookup: HashMap::from([(PLAYER_FACTION.to_string(), PLAYER)]),
+            hostile: HashSet::new(),
+        }
+    }
+}
+
+impl FactionTable {
+    /// Index of `name`, adding it if the table hasn't seen it yet.
+    pub fn index(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.lookup.get(name) {
+            return index;
+        }
+        self.names.push(name.to_string());
+        self.lookup.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        self.names.get(index).map(String::as_str).unwrap_or("")
+    }
+
+    /// Adds the hostilities in `file` to the ones already loaded.
+    pub fn merge(&mut self, file: FactionFile) {
+        for (attacker, targets) in file.hostile {
+            let attacker = self.index(&attacker);
+            for target in targets {
+                let target = self.index(&target);
+                self.hostile.insert((attacker, target));
+            }
+        }
+    }
+
+    pub fn is_hostile(&self, attacker: usize, target: usize) -> bool {
+        self.hostile.contains(&(attacker, target))
+    }
+
+    /// Whether `attacker` is hostile to some faction other than the player's.
+    pub fn hunts_entities(&self, attacker: usize) -> bool {
+        self.hostile.iter().any(|&(from, to)| from == attacker && to != PLAYER)
+    }
+}
@@ -0,0 +1,613 @@
+//! Crop farming.
+//!
+//! Q works the tile in front of the player. On ground the tileset marks `tillable`, it digs farm
+//! soil (`farming.soil_tile` in `world.yaml`). On bare soil it spreads fertilizer if the player
+//! carries some and the soil has none yet, and otherwise plants the first seed in the inventory.
+//! On a growing crop it waters it, once a day. On a ripe crop it harvests. Crops come from
+//! `src/crop/*.yaml` and show each growth stage as an overlay tile. Growth follows world time
+//! rather than frames, so crops keep growing while their chunk is unloaded and between saves.
+//! Outdoors crops only grow by day. Inside a `greenhouse` region they grow around the clock and
+//! are always in season. Bees pollinating a crop give it `farming.pollination_boost` days of
+//! extra growth, once a day.
+//!
+//! A harvest comes in a quality tier set by how the crop was kept: watered every day it grew,
+//! planted in fertilized soil, and grown in its own seasons. Better tiers yield more, and a crop's
+//! `quality_harvest` items can stand in for its plain harvest, worth more in contracts.
+
+use macroquad::prelude::*;
+use crate::asset_pack::load_string;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::helpers::{data_path, load_wasm_manifest_files};
+use crate::item::Inventory;
+use crate::map::{LayerKind, TileMap, EMPTY_TILE};
+use crate::time::{Season, WorldTime};
+use crate::ui::{UiBatch, UiLayer};
+
+const FARM_KEY: KeyCode = KeyCode::Q;
+const STATUS_TIME: f32 = 2.5;
+/// Region tag of structure interiors that shelter crops from the night and the seasons.
+const GREENHOUSE_TAG: &str = "greenhouse";
+/// Item spread on bare soil to raise the quality of what grows there.
+pub const FERTILIZER_ITEM: &str = "fertilizer";
+/// Care scores (see `PlantedCrop::quality`) a harvest needs for each tier.
+const SILVER_SCORE: f32 = 1.0;
+const GOLD_SCORE: f32 = 1.75;
+
+#[derive(Debug)]
+pub enum CropLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for CropLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CropLoadError {}
+
+impl From<std::io::Error> for CropLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for CropLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+/// Farming tuning from `world.yaml`.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct FarmingSettings {
+    /// Background tile that tilled ground turns into.
+    pub soil_tile: u8,
+    /// Days of growth a crop gains the first time bees pollinate it each day.
+    pub pollination_boost: f32,
+}
+
+impl Default for FarmingSettings {
+    fn default() -> Self {
+        Self {
+            soil_tile: 9,
+            pollination_boost: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CropDef {
+    pub id: String,
+    /// Item that plants the crop.
+    pub seed: String,
+    /// Overlay tile for each growth stage; the last stage is ripe.
+    pub stages: Vec<u8>,
+    /// In-game days spent in each stage before the next.
+    #[serde(default = "default_stage_days")]
+    pub stage_days: f32,
+    /// Item a ripe crop yields.
+    pub harvest: String,
+    #[serde(default = "default_harvest_count")]
+    pub harvest_count: u32,
+    /// Seasons the crop is in season; none means all of them.
+    #[serde(default)]
+    pub seasons: Vec<Season>,
+    /// Items yielded in place of `harvest` at a better quality.
+    #[serde(default)]
+    pub quality_harvest: HashMap<CropQuality, String>,
+}
+
+impl CropDef {
+    /// Growth stage after `days` in the ground.
+    pub fn stage_at(&self, days: f32) -> usize {
+        let stage = (days.max(0.0) / self.stage_days.max(0.01)) as usize;
+        stage.min(self.stages.len().saturating_sub(1))
+    }
+
+    pub fn is_ripe(&self, days: f32) -> bool {
+        self.stage_at(days) + 1 >= self.stages.len()
+    }
+
+    /// Days of growth from planting to ripe.
+    pub fn days_to_ripen(&self) -> f32 {
+        self.stage_days.max(0.01) * self.stages.len().saturating_sub(1) as f32
+    }
+
+    pub fn in_season(&self, season: Season) -> bool {
+        self.seasons.is_empty() || self.seasons.contains(&season)
+    }
+}
+
+/// How well a crop was kept, judged when it's picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CropQuality {
+    Normal,
+    Silver,
+    Gold,
+}
+
+impl CropQuality {
+    fn from_score(score: f32) -> Self {
+        if score >= GOLD_SCORE {
+            Self::Gold
+        } else if score >= SILVER_SCORE {
+            Self::Silver
+        } else {
+            Self::Normal
+        }
+    }
+
+    /// Multiplier on the crop's `harvest_count`.
+    fn yield_scale(self) -> f32 {
+        match self {
+            Self::Normal => 1.0,
+            Self::Silver => 1.5,
+            Self::Gold => 2.0,
+        }
+    }
+}
+
+fn default_stage_days() -> f32 {
+    1.0
+}
+
+fn default_harvest_count() -> u32 {
+    1
+}
+
+pub struct CropDatabase {
+    crops: Vec<CropDef>,
+    lookup: HashMap<String, usize>,
+}
+
+impl CropDatabase {
+    pub fn empty() -> Self {
+        Self {
+            crops: Vec::new(),
+            lookup: HashMap::new(),
+        }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, CropLoadError> {
+        let dir = dir.as_ref();
+        let mut defs = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["berry.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| CropLoadError::Io(std::io::Error::other(err.to_string())))?;
+                defs.push(serde_yaml::from_str::<CropDef>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                defs.push(serde_yaml::from_str::<CropDef>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+
+        let mut crops = Vec::with_capacity(defs.len());
+        let mut lookup = HashMap::with_capacity(defs.len());
+        for def in defs {
+            if def.stages.is_empty() {
+                eprintln!("crop '{}' has no growth stages, skipping", def.id);
+                continue;
+            }
+            lookup.insert(def.id.clone(), crops.len());
+            crops.push(def);
+        }
+        Ok(Self { crops, lookup })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CropDef> {
+        self.lookup.get(id).and_then(|&idx| self.crops.get(idx))
+    }
+
+    /// Crop a seed item grows into.
+    pub fn by_seed(&self, seed: &str) -> Option<&CropDef> {
+        self.crops.iter().find(|def| def.seed == seed)
+    }
+}
+
+/// A crop in the ground, as saved.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlantedCrop {
+    pub crop: String,
+    pub x: usize,
+    pub y: usize,
+    /// World time in days when it was planted.
+    pub planted_at: f32,
+    /// Days of growth so far.
+    #[serde(default)]
+    pub growth: f32,
+    /// The part of `growth` that happened in one of the crop's seasons.
+    #[serde(default)]
+    pub growth_in_season: f32,
+    /// Days it was watered on, and the last one.
+    #[serde(default)]
+    pub watered_days: u32,
+    #[serde(default)]
+    pub last_watered: Option<u32>,
+    /// Planted in fertilized soil.
+    #[serde(default)]
+    pub fertilized: bool,
+    /// Inside a greenhouse, as of the last time its chunk was loaded.
+    #[serde(default)]
+    pub sheltered: bool,
+    /// Last day bees pollinated it.
+    #[serde(default)]
+    pub last_pollinated: Option<u32>,
+}
+
+impl PlantedCrop {
+    /// Scores watering (up to 1 for every day until ripe), fertilizer (0.5) and the share of growth
+    /// in season (up to 0.5) into a tier.
+    pub fn quality(&self, def: &CropDef) -> CropQuality {
+        let watering = (self.watered_days as f32 / def.days_to_ripen().ceil().max(1.0)).min(1.0);
+        let fertilizer = if self.fertilized { 0.5 } else { 0.0 };
+        let season = if self.growth > 0.0 {
+            self.growth_in_season / self.growth
+        } else {
+            1.0
+        };
+        CropQuality::from_score(watering + fertilizer + 0.5 * season)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlantError {
+    UnknownSeed,
+    NotSoil,
+    Occupied,
+}
+
+impl std::fmt::Display for PlantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownSeed => write!(f, "that doesn't grow into anything"),
+            Self::NotSoil => write!(f, "seeds need tilled soil"),
+            Self::Occupied => write!(f, "something is already growing there"),
+        }
+    }
+}
+
+impl std::error::Error for PlantError {}
+
+pub enum FarmEvent {
+    Tilled { pos: Vec2 },
+    Fertilized { pos: Vec2 },
+    Planted { pos: Vec2 },
+    Watered { pos: Vec2 },
+    /// A ripe crop was picked; the caller drops the items.
+    Harvested { item: String, count: u32, pos: Vec2 },
+}
+
+pub struct Farm {
+    defs: CropDatabase,
+    settings: FarmingSettings,
+    planted: HashMap<(usize, usize), PlantedCrop>,
+    /// Bare soil with fertilizer in it, waiting for a seed.
+    fertilized: HashSet<(usize, usize)>,
+    /// World time in days at the last `update`, and whether it was night then, to grow crops by
+    /// the time since.
+    last_update: Option<(f32, bool)>,
+    status: String,
+    status_timer: f32,
+}
+
+impl Farm {
+    pub fn new(defs: CropDatabase, settings: FarmingSettings) -> Self {
+        Self {
+            defs,
+            settings,
+            planted: HashMap::new(),
+            fertilized: HashSet::new(),
+            last_update: None,
+            status: String::new(),
+            status_timer: 0.0,
+        }
+    }
+
+    /// Turns tillable ground into soil. Returns whether it did.
+    pub fn till(&mut self, map: &mut TileMap, tile: (usize, usize)) -> bool {
+        let (x, y) = tile;
+        if !map.is_tile_loaded(x, y)
+            || map.is_solid(x, y)
+            || map.tile_at(LayerKind::Foreground, x, y) != EMPTY_TILE
+            || map.tile_at(LayerKind::Overlay, x, y) != EMPTY_TILE
+        {
+            return false;
+        }
+        let ground = map.tile_at(LayerKind::Background, x, y);
+        if !map.tile_properties(ground).tillable {
+            return false;
+        }
+        map.set_tile(LayerKind::Background, x, y, self.settings.soil_tile);
+        true
+    }
+
+    /// Spreads fertilizer on bare soil that has none. Returns whether it did.
+    pub fn fertilize(&mut self, map: &TileMap, tile: (usize, usize)) -> bool {
+        if !self.is_soil(map, tile) || self.planted.contains_key(&tile) {
+            return false;
+        }
+        self.fertilized.insert(tile)
+    }
+
+    /// Waters the crop on `tile` for `day`. Returns false if nothing grows there or it was
+    /// already watered that day.
+    pub fn water(&mut self, tile: (usize, usize), day: u32) -> bool {
+        let Some(planted) = self.planted.get_mut(&tile) else {
+            return false;
+        };
+        if planted.last_watered == Some(day) {
+            return false;
+        }
+        planted.last_watered = Some(day);
+        planted.watered_days += 1;
+        true
+    }
+
+    /// Tiles of unripe crops that haven't been pollinated on `day`, for bees to visit.
+    pub fn pollination_targets(&self, day: u32) -> Vec<(usize, usize)> {
+        self.planted
+            .iter()
+            .filter(|(_, planted)| planted.last_pollinated != Some(day))
+            .filter(|(_, planted)| {
+                self.defs
+                    .get(&planted.crop)
+                    .is_some_and(|def| !def.is_ripe(planted.growth))
+            })
+            .map(|(&tile, _)| tile)
+            .collect()
+    }
+
+    /// Gives the unripe crop on `tile` `pollination_boost` days of growth, once per day. Returns
+    /// whether it did.
+    pub fn pollinate(&mut self, tile: (usize, usize), time: &WorldTime) -> bool {
+        let Some(planted) = self.planted.get_mut(&tile) else {
+            return false;
+        };
+        let Some(def) = self.defs.get(&planted.crop) else {
+            return false;
+        };
+        let day = time.day();
+        if planted.last_pollinated == Some(day) || def.is_ripe(planted.growth) {
+            return false;
+        }
+        planted.last_pollinated = Some(day);
+        planted.growth += self.settings.pollination_boost;
+        if planted.sheltered || def.in_season(time.season()) {
+            planted.growth_in_season += self.settings.pollination_boost;
+        }
+        true
+    }
+
+    /// Plants the crop `seed_id` grows into on a soil tile at world time `now` (in days).
+    pub fn plant(&mut self, map: &mut TileMap, seed_id: &str, tile: (usize, usize), now: f32) -> Result<(), PlantError> {
+        let def = self.defs.by_seed(seed_id).ok_or(PlantError::UnknownSeed)?;
+        let (x, y) = tile;
+        if !self.is_soil(map, tile) {
+            return Err(PlantError::NotSoil);
+        }
+        if self.planted.contains_key(&tile) || map.tile_at(LayerKind::Overlay, x, y) != EMPTY_TILE {
+            return Err(PlantError::Occupied);
+        }
+        map.set_tile(LayerKind::Overlay, x, y, def.stages[0]);
+        self.planted.insert(
+            tile,
+            PlantedCrop {
+                crop: def.id.clone(),
+                x,
+                y,
+                planted_at: now,
+                growth: 0.0,
+                growth_in_season: 0.0,
+                watered_days: 0,
+                last_watered: None,
+                fertilized: self.fertilized.remove(&tile),
+                sheltered: false,
+                last_pollinated: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Picks a ripe crop, leaving the soil bare. Returns the item and count it yields at its
+    /// quality.
+    pub fn harvest(&mut self, map: &mut TileMap, tile: (usize, usize)) -> Option<(String, u32)> {
+        let planted = self.planted.get(&tile)?;
+        let def = self.defs.get(&planted.crop)?;
+        if !def.is_ripe(planted.growth) {
+            return None;
+        }
+        let quality = planted.quality(def);
+        let item = def.quality_harvest.get(&quality).unwrap_or(&def.harvest);
+        let count = (def.harvest_count as f32 * quality.yield_scale()).round() as u32;
+        let harvest = (item.clone(), count);
+        self.planted.remove(&tile);
+        map.set_tile(LayerKind::Overlay, tile.0, tile.1, EMPTY_TILE);
+        Some(harvest)
+    }
+
+    /// Grows every crop by the world time since the last call and shows each loaded crop's
+    /// current growth stage. Time that started at night only counts for crops in a greenhouse.
+    /// Crops whose soil is gone, or whose definition no longer exists, are dropped, and so is
+    /// fertilizer on soil that's gone.
+    pub fn update(&mut self, map: &mut TileMap, time: &WorldTime, dt: f32) {
+        self.status_timer = (self.status_timer - dt).max(0.0);
+        let now = time.days_elapsed();
+        let (grown, was_night) = self
+            .last_update
+            .map_or((0.0, false), |(last, night)| ((now - last).max(0.0), night));
+        self.last_update = Some((now, time.is_night()));
+        let season = time.season();
+        let soil = self.settings.soil_tile;
+        let tile_size = map.tile_size();
+        let defs = &self.defs;
+        self.planted.retain(|&(x, y), planted| {
+            let Some(def) = defs.get(&planted.crop) else {
+                return false;
+            };
+            let loaded = map.is_tile_loaded(x, y);
+            if loaded {
+                let center = vec2((x as f32 + 0.5) * tile_size, (y as f32 + 0.5) * tile_size);
+                planted.sheltered =
+                    map.regions_containing(center).any(|region| region.tag == GREENHOUSE_TAG);
+            }
+            if planted.sheltered || !was_night {
+                planted.growth += grown;
+                if planted.sheltered || def.in_season(season) {
+                    planted.growth_in_season += grown;
+                }
+            }
+            if !loaded {
+                return true;
+            }
+            if map.tile_at(LayerKind::Background, x, y) != soil {
+                map.set_tile(LayerKind::Overlay, x, y, EMPTY_TILE);
+                return false;
+            }
+            let stage = def.stage_at(planted.growth);
+            map.set_tile(LayerKind::Overlay, x, y, def.stages[stage]);
+            true
+        });
+        self.fertilized
+            .retain(|&(x, y)| !map.is_tile_loaded(x, y) || map.tile_at(LayerKind::Background, x, y) == soil);
+    }
+
+    /// Harvests, waters, plants, fertilizes or tills the tile the player faces. Only call while
+    /// the player is free to act.
+    pub fn handle_input(
+        &mut self,
+        map: &mut TileMap,
+        inventory: &mut Inventory,
+        player_pos: Vec2,
+        facing: Vec2,
+        time: &WorldTime,
+    ) -> Option<FarmEvent> {
+        if !is_key_pressed(FARM_KEY) {
+            return None;
+        }
+        let tile_size = map.tile_size();
+        let target = player_pos + facing.normalize_or_zero() * tile_size;
+        let index = map.grid_index(target)?;
+        let tile = (index.x as usize, index.y as usize);
+        let pos = vec2((tile.0 as f32 + 0.5) * tile_size, (tile.1 as f32 + 0.5) * tile_size);
+
+        if let Some(crop) = self.planted.get(&tile).map(|planted| planted.crop.clone()) {
+            if let Some((item, count)) = self.harvest(map, tile) {
+                return Some(FarmEvent::Harvested { item, count, pos });
+            }
+            if self.water(tile, time.day()) {
+                self.set_status(format!("watered the {crop}"));
+                return Some(FarmEvent::Watered { pos });
+            }
+            self.set_status(format!("the {crop} isn't ripe yet and has had its water today"));
+            return None;
+        }
+        if self.is_soil(map, tile) {
+            if inventory.has(FERTILIZER_ITEM) && self.fertilize(map, tile) {
+                inventory.remove(FERTILIZER_ITEM, 1);
+                self.set_status("fertilized the soil".to_string());
+                return Some(FarmEvent::Fertilized { pos });
+            }
+            let mut seeds: Vec<&str> = self
+                .defs
+                .crops
+                .iter()
+                .map(|def| def.seed.as_str())
+                .filter(|seed| inventory.has(seed))
+                .collect();
+            seeds.sort_unstable();
+            let Some(seed) = seeds.first().map(|seed| seed.to_string()) else {
+                self.set_status("no seeds to plant".to_string());
+                return None;
+            };
+            return match self.plant(map, &seed, tile, time.days_elapsed()) {
+                Ok(()) => {
+                    inventory.remove(&seed, 1);
+                    Some(FarmEvent::Planted { pos })
+                }
+                Err(err) => {
+                    self.set_status(err.to_string());
+                    None
+                }
+            };
+        }
+        if self.till(map, tile) {
+            return Some(FarmEvent::Tilled { pos });
+        }
+        self.set_status("nothing to farm here".to_string());
+        None
+    }
+
+    fn is_soil(&self, map: &TileMap, (x, y): (usize, usize)) -> bool {
+        map.is_tile_loaded(x, y) && map.tile_at(LayerKind::Background, x, y) == self.settings.soil_tile
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    /// Queues the last farming message.
+    pub fn draw_ui(&self, ui: &mut UiBatch) {
+        if self.status_timer > 0.0 {
+            let size = measure_text(&self.status, None, 20, 1.0);
+            let pos = vec2((screen_width() - size.width) * 0.5, screen_height() * 0.8);
+            ui.text(UiLayer::Toast, self.status.as_str(), pos, 20.0, WHITE);
+        }
+    }
+
+    pub fn to_save_data(&self) -> Vec<PlantedCrop> {
+        let mut planted: Vec<PlantedCrop> = self.planted.values().cloned().collect();
+        planted.sort_by_key(|crop| (crop.y, crop.x));
+        planted
+    }
+
+    /// Bare soil tiles with fertilizer in them, for the save.
+    pub fn fertilized_tiles(&self) -> Vec<(usize, usize)> {
+        let mut tiles: Vec<(usize, usize)> = self.fertilized.iter().copied().collect();
+        tiles.sort_by_key(|&(x, y)| (y, x));
+        tiles
+    }
+
+    /// Replaces the planted crops and fertilized soil with saved ones; crops without a
+    /// definition are skipped.
+    pub fn restore(&mut self, saved: &[PlantedCrop], fertilized: &[(usize, usize)]) {
+        self.planted.clear();
+        self.fertilized = fertilized.iter().copied().collect();
+        self.last_update = None;
+        for crop in saved {
+            if self.defs.get(&crop.crop).is_none() {
+                eprintln!("saved crop '{}' has no definition, skipping", crop.crop);
+                continue;
+            }
+            self.planted.insert((crop.x, crop.y), crop.clone());
+        }
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,246 @@
+use macroquad::prelude::*;
+use crate::asset_pack::load_string;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::cull::{ViewCull, circle_overlaps_rect};
+use crate::entity::{DamageEvent, EntityKind, EntityTarget, PlayerTarget, Target};
+use crate::helpers::{data_path, load_wasm_manifest_files};
+use crate::particle::{ParticleEmitter, ParticleSystem};
+
+#[derive(Debug)]
+pub enum HazardLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for HazardLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for HazardLoadError {}
+
+impl From<std::io::Error> for HazardLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for HazardLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone)]
+pub struct HazardTemplate {
+    pub radius: f32,
+    pub duration: f32,
+    pub tick_interval: f32,
+    pub damage_per_tick: f32,
+    pub hits_player: bool,
+    pub hits_owner_kind: bool,
+    pub color: Color,
+    pub particle: Option<String>,
+}
+
+struct ActiveHazard {
+    template: usize,
+    pos: Vec2,
+    /// Kind of the entity that created the hazard; that kind is spared unless the template opts in.
+    owner: Option<EntityKind>,
+    remaining: f32,
+    tick_timer: f32,
+    emitter: Option<ParticleEmitter>,
+}
+
+pub struct HazardSystem {
+    templates: Vec<HazardTemplate>,
+    lookup: HashMap<String, usize>,
+    active: Vec<ActiveHazard>,
+}
+
+impl HazardSystem {
+    pub fn empty() -> Self {
+        Self {
+            templates: Vec::new(),
+            lookup: HashMap::new(),
+            active: Vec::new(),
+        }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, HazardLoadError> {
+        let dir = dir.as_ref();
+        let mut raws = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["poison_cloud.yaml", "fire_patch.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| HazardLoadError::Io(std::io::Error::other(err.to_string())))?;
+                raws.push(serde_yaml::from_str::<HazardFile>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                raws.push(serde_yaml::from_str::<HazardFile>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+
+        let mut templates = Vec::with_capacity(raws.len());
+        let mut lookup = HashMap::with_capacity(raws.len());
+        for raw in raws {
+            let color = raw.color.unwrap_or([120, 200, 80, 90]);
+            lookup.insert(raw.id, templates.len());
+            templates.push(HazardTemplate {
+                radius: raw.radius.unwrap_or(16.0).max(1.0),
+                duration: raw.duration.unwrap_or(3.0).max(0.0),
+                tick_interval: raw.tick_interval.unwrap_or(0.5).max(0.05),
+                damage_per_tick: raw.damage_per_tick.unwrap_or(1.0).max(0.0),
+                hits_player: raw.hits_player.unwrap_or(true),
+                hits_owner_kind: raw.hits_owner_kind.unwrap_or(false),
+                color: Color::from_rgba(color[0], color[1], color[2], color[3]),
+                particle: raw.particle,
+            });
+        }
+
+        Ok(Self {
+            templates,
+            lookup,
+            active: Vec::new(),
+        })
+    }
+
+    pub fn spawn(&mut self, id: &str, pos: Vec2, owner: Option<EntityKind>, particles: &ParticleSystem) -> bool {
+        let Some(template) = self.lookup.get(id).copied() else {
+            eprintln!("unknown hazard template '{id}'");
+            return false;
+        };
+        let cfg = &self.templates[template];
+        let emitter = cfg
+            .particle
+            .as_deref()
+            .and_then(|particle| particles.emitter(particle, pos));
+        self.active.push(ActiveHazard {
+            template,
+            pos,
+            owner,
+            remaining: cfg.duration,
+            // First tick lands immediately so stepping into a fresh cloud hurts.
+            tick_timer: 0.0,
+            emitter,
+        });
+        true
+    }
+
+    /// Ticks every live hazard, pushing damage for whatever stands inside it, and drops expired ones.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        player: Option<PlayerTarget>,
+        entities: &[EntityTarget],
+        particles: &mut ParticleSystem,
+        out: &mut Vec<DamageEvent>,
+    ) {
+        let templates = &self.templates;
+        self.active.retain_mut(|hazard| {
+            hazard.remaining -= dt;
+            if hazard.remaining <= 0.0 {
+                return false;
+            }
+            let cfg = &templates[hazard.template];
+            if let Some(emitter) = hazard.emitter.as_mut() {
+                particles.update_emitter(emitter, hazard.pos, dt);
+            }
+
+            hazard.tick_timer -= dt;
+            if hazard.tick_timer > 0.0 {
+                return true;
+            }
+            hazard.tick_timer += cfg.tick_interval;
+
+            if cfg.hits_player
+                && let Some(player) = player
+                && circle_overlaps_rect(hazard.pos, cfg.radius, player.hitbox)
+            {
+                out.push(DamageEvent {
+                    amount: cfg.damage_per_tick,
+                    target: Target::Player(player),
+                    knockback: Vec2::ZERO,
+                    source: None,
+                });
+            }
+            for target in entities {
+                if !target.alive {
+                    continue;
+                }
+                if hazard.owner == Some(target.kind) && !cfg.hits_owner_kind {
+                    continue;
+                }
+                if circle_overlaps_rect(hazard.pos, cfg.radius, target.hitbox) {
+                    out.push(DamageEvent {
+                        amount: cfg.damage_per_tick,
+                        target: Target::Entity(*target),
+                        knockback: Vec2::ZERO,
+                        source: None,
+                    });
+                }
+            }
+            true
+        });
+    }
+
+    pub fn draw_in_view(&self, view: &ViewCull) {
+        for hazard in &self.active {
+            let cfg = &self.templates[hazard.template];
+            if !view.circle_visible(hazard.pos, cfg.radius) {
+                continue;
+            }
+            // Fade out over the last half second instead of popping.
+            let fade = (hazard.remaining / 0.5).clamp(0.0, 1.0);
+            let mut color = cfg.color;
+            color.a *= fade;
+            draw_circle(hazard.pos.x, hazard.pos.y, cfg.radius, color);
+        }
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize)]
+struct HazardFile {
+    id: String,
+    #[serde(default)]
+    radius: Option<f32>,
+    #[serde(default)]
+    duration: Option<f32>,
+    #[serde(default)]
+    tick_interval: Option<f32>,
+    #[serde(default)]
+    damage_per_tick: Option<f32>,
+    #[serde(default)]
+    hits_player: Option<bool>,
+    #[serde(default)]
+    hits_owner_kind: Option<bool>,
+    #[serde(default)]
+    color: Option<[u8; 4]>,
+    #[serde(default)]
+    particle: Option<String>,
+}
@@ -1,5 +1,5 @@
 use macroquad::prelude::*;
-use macroquad::file::load_string;
+use crate::asset_pack::{load_string, load_texture};
 use serde::Deserialize;
 
 pub fn random_u32() -> u32 {
@@ -180,6 +180,18 @@ pub fn resolve_collisions_axis(
     (pos, vel_axis)
 }
 
+/// Resolves northward movement against one-way ledges after the y step. Only ledges the hitbox
+/// was entirely south of at `prev_y` block it, so walking down through a ledge and turning back
+/// doesn't snap onto it. Drops the ledges that don't block from `ledges`.
+pub fn resolve_one_way(hitbox: Rect, prev_y: f32, pos: Vec2, vel_y: f32, ledges: &mut Vec<Rect>) -> (Vec2, f32) {
+    if vel_y >= 0.0 {
+        return (pos, vel_y);
+    }
+    let prev_top = prev_y + hitbox.y;
+    ledges.retain(|ledge| ledge.y + ledge.h <= prev_top + 0.01);
+    resolve_collisions_axis(hitbox, pos, vel_y, ledges, Axis::Y)
+}
+
 pub fn resolve_collision_with_velocity(
     hitbox: Rect,
     pos: Vec2,
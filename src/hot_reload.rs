@@ -0,0 +1,171 @@
+//! Hot reload for art and data iteration.
+//!
+//! On native builds the texture watcher checks the modification time of every registered PNG twice a
+//! second. When one changes, it decodes the file again and uploads the pixels into the existing
+//! texture, so everything holding a clone of it draws the new art on the next frame. A picture
+//! whose size changed can't be uploaded in place and needs a restart. Callers still react to the
+//! returned paths for things computed from pixels, like the tileset's colors and the chunk
+//! textures built from it.
+//!
+//! The data watcher does the same for folders of definitions: it notices any YAML or JSON file
+//! under a watched folder being added, removed or saved, and reports the folder's kind so the
+//! caller can load the definitions again and move live objects onto them.
+
+use macroquad::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Seconds between checks of the watched files.
+const POLL_INTERVAL: f32 = 0.5;
+
+struct WatchedTexture {
+    path: String,
+    modified: Option<SystemTime>,
+    texture: Texture2D,
+}
+
+#[derive(Default)]
+pub struct TextureWatcher {
+    watched: Vec<WatchedTexture>,
+    poll_timer: f32,
+}
+
+impl TextureWatcher {
+    /// Starts watching `path` for `texture`, which must have been loaded from it. Paths already
+    /// watched keep their first texture. Does nothing on the web build.
+    pub fn watch(&mut self, path: &str, texture: &Texture2D) {
+        if cfg!(target_arch = "wasm32") || self.watched.iter().any(|watched| watched.path == path) {
+            return;
+        }
+        self.watched.push(WatchedTexture {
+            path: path.to_string(),
+            modified: modified_time(path),
+            texture: texture.clone(),
+        });
+    }
+
+    /// Call every frame. Re-uploads textures whose file changed and returns their paths.
+    pub fn update(&mut self, dt: f32) -> Vec<String> {
+        self.poll_timer -= dt;
+        if self.watched.is_empty() || self.poll_timer > 0.0 {
+            return Vec::new();
+        }
+        self.poll_timer = POLL_INTERVAL;
+
+        let mut reloaded = Vec::new();
+        for watched in &mut self.watched {
+            let modified = modified_time(&watched.path);
+            if modified.is_none() || modified == watched.modified {
+                continue;
+            }
+            watched.modified = modified;
+            let image = match std::fs::read(&watched.path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| Image::from_file_with_format(&bytes, None).map_err(|err| err.to_string()))
+            {
+                Ok(image) => image,
+                // Editors often write in several steps; the next change retries.
+                Err(err) => {
+                    eprintln!("texture reload of '{}' failed: {err}", watched.path);
+                    continue;
+                }
+            };
+            if vec2(image.width as f32, image.height as f32) != watched.texture.size() {
+                eprintln!("'{}' changed size; restart to see it", watched.path);
+                continue;
+            }
+            watched.texture.update(&image);
+            eprintln!("reloaded {}", watched.path);
+            reloaded.push(watched.path.clone());
+        }
+        reloaded
+    }
+}
+
+struct WatchedDir {
+    path: PathBuf,
+    kind: &'static str,
+    stamp: DirStamp,
+}
+
+/// Newest modification time and file count under a folder; a change in either means something
+/// was saved, added or removed.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct DirStamp {
+    newest: Option<SystemTime>,
+    files: usize,
+}
+
+#[derive(Default)]
+pub struct DataWatcher {
+    watched: Vec<WatchedDir>,
+    poll_timer: f32,
+}
+
+impl DataWatcher {
+    /// Starts watching the definitions under `path`, reported as `kind` when they change. Does
+    /// nothing on the web build.
+    pub fn watch(&mut self, path: impl AsRef<Path>, kind: &'static str) {
+        let path = path.as_ref();
+        if cfg!(target_arch = "wasm32") || !path.is_dir() {
+            return;
+        }
+        self.watched.push(WatchedDir {
+            path: path.to_path_buf(),
+            kind,
+            stamp: dir_stamp(path),
+        });
+    }
+
+    /// Call every frame. Returns the kinds whose definitions changed since the last check.
+    pub fn update(&mut self, dt: f32) -> Vec<&'static str> {
+        self.poll_timer -= dt;
+        if self.watched.is_empty() || self.poll_timer > 0.0 {
+            return Vec::new();
+        }
+        self.poll_timer = POLL_INTERVAL;
+
+        let mut changed = Vec::new();
+        for watched in &mut self.watched {
+            let stamp = dir_stamp(&watched.path);
+            if stamp == watched.stamp {
+                continue;
+            }
+            watched.stamp = stamp;
+            if !changed.contains(&watched.kind) {
+                changed.push(watched.kind);
+            }
+        }
+        changed
+    }
+}
+
+fn dir_stamp(dir: &Path) -> DirStamp {
+    let mut stamp = DirStamp::default();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return stamp;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let inner = dir_stamp(&path);
+            stamp.files += inner.files;
+            stamp.newest = stamp.newest.max(inner.newest);
+            continue;
+        }
+        let is_data = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ["yaml", "yml", "json"].iter().any(|data| ext.eq_ignore_ascii_case(data)));
+        if !is_data {
+            continue;
+        }
+        stamp.files += 1;
+        stamp.newest = stamp.newest.max(entry.metadata().and_then(|meta| meta.modified()).ok());
+    }
+    stamp
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -2,13 +2,40 @@ use std::collections::HashMap;
 
 use macroquad::prelude::*;
 
-use crate::{map::TileMap, player::Player};
+use crate::{
+    map::{StructureInteractor, StructureTrigger, TileMap},
+    player::Player,
+};
+
+/// Feed one refill puts in a trough, and the most a trough holds.
+const TROUGH_REFILL: f32 = 60.0;
+const TROUGH_CAPACITY: f32 = 120.0;
 
 pub struct InteractContext<'a> {
     pub structure_id: &'a str,
     pub area: Rect,
     pub player: &'a mut Player,
     pub map: &'a mut TileMap,
+    pub events: &'a mut Vec<InteractEvent>,
+}
+
+/// Things an interaction asks the game loop to do outside the map and player.
+pub enum InteractEvent {
+    OpenContractBoard { area: Rect },
+    /// Talk to the vendor bound to the structure covering `area`.
+    OpenStall { area: Rect },
+    /// The player got back `amount` health.
+    Healed { amount: f32 },
+    /// The trough covering `area` now holds `stock` feed out of `capacity`.
+    TroughFilled { area: Rect, stock: f32, capacity: f32 },
+    /// Make where the player stands their respawn point on this map.
+    SetRespawn,
+    /// Skip the night, if it is one.
+    Sleep,
+    /// Show the upgrades sold at the station structure `station`.
+    OpenUpgrades { station: String, area: Rect },
+    /// Send in the encounter wave of the structure whose trigger pins cover `area`.
+    StartEncounter { structure_id: String, area: Rect },
 }
 
 pub type InteractFn = fn(&mut InteractContext<'_>);
@@ -17,6 +44,12 @@ pub struct InteractRegistry {
     funcs: HashMap<String, InteractFn>,
 }
 
+impl Default for InteractRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl InteractRegistry {
     pub fn new() -> Self {
         let mut registry = Self {
@@ -25,6 +58,13 @@ impl InteractRegistry {
         registry.register("log_interact", interact_log);
         registry.register("heal_player_small", interact_heal_player_small);
         registry.register("damage_player_small", interact_damage_player_small);
+        registry.register("fill_trough", interact_fill_trough);
+        registry.register("open_contract_board", interact_open_contract_board);
+        registry.register("open_stall", interact_open_stall);
+        registry.register("set_respawn", interact_set_respawn);
+        registry.register("sleep", interact_sleep);
+        registry.register("start_encounter", interact_start_encounter);
+        registry.register("open_upgrades", interact_open_upgrades);
         registry
     }
 
@@ -32,6 +72,10 @@ impl InteractRegistry {
         self.funcs.insert(name.to_string(), func);
     }
 
+    pub fn contains(&self, name: &str) -> bool {
+        self.funcs.contains_key(name)
+    }
+
     pub fn execute(&self, names: &[String], ctx: &mut InteractContext<'_>) {
         for name in names {
             if let Some(func) = self.funcs.get(name).copied() {
@@ -46,6 +90,130 @@ impl InteractRegistry {
     }
 }
 
+/// Identifies one placed interactor group across frames.
+type InteractorKey = (String, i32, i32);
+
+fn interactor_key(interactor: &StructureInteractor) -> InteractorKey {
+    (
+        interactor.structure_id.clone(),
+        interactor.group_rect.x as i32,
+        interactor.group_rect.y as i32,
+    )
+}
+
+fn trigger_key(trigger: &StructureTrigger) -> InteractorKey {
+    (
+        trigger.structure_id.clone(),
+        trigger.group_rect.x as i32,
+        trigger.group_rect.y as i32,
+    )
+}
+
+/// Remembers the trigger volumes the player is in, so each fires once per entry.
+#[derive(Default)]
+pub struct TriggerTracker {
+    inside: Vec<InteractorKey>,
+}
+
+impl TriggerTracker {
+    /// Triggers `hitbox` stepped into since the last call.
+    pub fn entered(&mut self, map: &TileMap, hitbox: Rect) -> Vec<StructureTrigger> {
+        let overlapping: Vec<&StructureTrigger> = map.triggers_overlapping(hitbox).collect();
+        let entered = overlapping
+            .iter()
+            .filter(|trigger| !self.inside.contains(&trigger_key(trigger)))
+            .map(|trigger| (*trigger).clone())
+            .collect();
+        self.inside = overlapping.into_iter().map(trigger_key).collect();
+        entered
+    }
+}
+
+struct ActiveHold {
+    key: InteractorKey,
+    rect: Rect,
+    elapsed: f32,
+    duration: f32,
+    keep_progress: bool,
+}
+
+/// Tracks hold-to-interact progress for interactors with a `hold_time`.
+#[derive(Default)]
+pub struct HoldInteraction {
+    active: Option<ActiveHold>,
+    /// Partial progress of interactors that opted into `keep_progress`.
+    saved: HashMap<InteractorKey, f32>,
+    /// Set when a hold completes; a new hold starts only after the button is released.
+    needs_release: bool,
+}
+
+impl HoldInteraction {
+    /// Advances the hold on `target`. Returns true on the frame the hold completes.
+    ///
+    /// Letting go, moving, or switching target cancels the hold; progress is kept only when the
+    /// interactor asks for it. After a hold completes, the button has to be released before the
+    /// next one starts.
+    pub fn update(
+        &mut self,
+        target: Option<&StructureInteractor>,
+        holding: bool,
+        moving: bool,
+        dt: f32,
+    ) -> bool {
+        if !holding {
+            self.needs_release = false;
+        }
+        if self.needs_release {
+            return false;
+        }
+        let Some(interactor) = target.filter(|interactor| holding && !moving && interactor.hold_time > 0.0)
+        else {
+            self.cancel();
+            return false;
+        };
+
+        let key = interactor_key(interactor);
+        if self.active.as_ref().map(|active| active.key != key).unwrap_or(true) {
+            self.cancel();
+            let elapsed = self.saved.remove(&key).unwrap_or(0.0);
+            self.active = Some(ActiveHold {
+                key,
+                rect: interactor.group_rect,
+                elapsed,
+                duration: interactor.hold_time,
+                keep_progress: interactor.keep_progress,
+            });
+        }
+
+        let Some(active) = self.active.as_mut() else {
+            return false;
+        };
+        active.elapsed += dt;
+        if active.elapsed < active.duration {
+            return false;
+        }
+        self.active = None;
+        self.needs_release = true;
+        true
+    }
+
+    /// Area and 0..1 progress of the hold in progress, for drawing the indicator.
+    pub fn progress(&self) -> Option<(Rect, f32)> {
+        self.active
+            .as_ref()
+            .map(|active| (active.rect, (active.elapsed / active.duration).clamp(0.0, 1.0)))
+    }
+
+    fn cancel(&mut self) {
+        let Some(active) = self.active.take() else {
+            return;
+        };
+        if active.keep_progress {
+            self.saved.insert(active.key, active.elapsed);
+        }
+    }
+}
+
 fn interact_log(ctx: &mut InteractContext<'_>) {
     let _ = ctx.map.tile_size();
     eprintln!(
@@ -55,9 +223,55 @@ fn interact_log(ctx: &mut InteractContext<'_>) {
 }
 
 fn interact_heal_player_small(ctx: &mut InteractContext<'_>) {
+    let before = ctx.player.hp();
     ctx.player.heal(25.0);
+    let amount = ctx.player.hp() - before;
+    if amount > 0.0 {
+        ctx.events.push(InteractEvent::Healed { amount });
+    }
 }
 
 fn interact_damage_player_small(ctx: &mut InteractContext<'_>) {
     ctx.player.apply_damage(25.0);
 }
+
+fn interact_fill_trough(ctx: &mut InteractContext<'_>) {
+    // Structures without a trough region have nowhere to put the feed.
+    if let Some(stock) = ctx.map.add_feed(ctx.area.center(), TROUGH_REFILL, TROUGH_CAPACITY) {
+        ctx.events.push(InteractEvent::TroughFilled {
+            area: ctx.area,
+            stock,
+            capacity: TROUGH_CAPACITY,
+        });
+    }
+}
+
+fn interact_open_contract_board(ctx: &mut InteractContext<'_>) {
+    ctx.events.push(InteractEvent::OpenContractBoard { area: ctx.area });
+}
+
+fn interact_open_stall(ctx: &mut InteractContext<'_>) {
+    ctx.events.push(InteractEvent::OpenStall { area: ctx.area });
+}
+
+fn interact_set_respawn(ctx: &mut InteractContext<'_>) {
+    ctx.events.push(InteractEvent::SetRespawn);
+}
+
+fn interact_sleep(ctx: &mut InteractContext<'_>) {
+    ctx.events.push(InteractEvent::Sleep);
+}
+
+fn interact_open_upgrades(ctx: &mut InteractContext<'_>) {
+    ctx.events.push(InteractEvent::OpenUpgrades {
+        station: ctx.structure_id.to_string(),
+        area: ctx.area,
+    });
+}
+
+fn interact_start_encounter(ctx: &mut InteractContext<'_>) {
+    ctx.events.push(InteractEvent::StartEncounter {
+        structure_id: ctx.structure_id.to_string(),
+        area: ctx.area,
+    });
+}
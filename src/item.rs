@@ -0,0 +1,300 @@
+use macroquad::prelude::*;
+use crate::asset_pack::{load_string, load_texture};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::cull::ViewCull;
+use crate::helpers::{asset_path, data_path, load_wasm_manifest_files};
+
+const WORLD_ITEM_SIZE: f32 = 8.0;
+const WORLD_ITEM_SCATTER: f32 = 6.0;
+/// Pixels a dropped item floats up and down, and how many times a second.
+const WORLD_ITEM_BOB_HEIGHT: f32 = 1.5;
+const WORLD_ITEM_BOB_RATE: f32 = 1.2;
+/// Item whose sprite every crate uses.
+const CRATE_ITEM: &str = "crate";
+/// Inventory ids of crated entities are this prefix followed by the entity id.
+const CRATE_PREFIX: &str = "crate:";
+
+#[derive(Debug)]
+pub enum ItemLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    Texture(String),
+}
+
+impl std::fmt::Display for ItemLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+            Self::Texture(err) => write!(f, "texture error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ItemLoadError {}
+
+impl From<std::io::Error> for ItemLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for ItemLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone)]
+pub struct ItemDef {
+    pub texture: Texture2D,
+    /// Coins each one earns in a delivery contract.
+    pub value: u32,
+}
+
+pub struct ItemDatabase {
+    items: Vec<ItemDef>,
+    lookup: HashMap<String, usize>,
+}
+
+impl ItemDatabase {
+    pub fn empty() -> Self {
+        Self {
+            items: Vec::new(),
+            lookup: HashMap::new(),
+        }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, ItemLoadError> {
+        let dir = dir.as_ref();
+        let mut raws = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["gear.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| ItemLoadError::Io(std::io::Error::other(err.to_string())))?;
+                raws.push(serde_yaml::from_str::<ItemFile>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                raws.push(serde_yaml::from_str::<ItemFile>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+
+        let mut items = Vec::with_capacity(raws.len());
+        let mut lookup = HashMap::with_capacity(raws.len());
+        for raw in raws {
+            let texture = load_texture(&asset_path(&raw.sprite))
+                .await
+                .map_err(|err| ItemLoadError::Texture(err.to_string()))?;
+            texture.set_filter(FilterMode::Nearest);
+            lookup.insert(raw.id, items.len());
+            items.push(ItemDef {
+                texture,
+                value: raw.value,
+            });
+        }
+
+        Ok(Self { items, lookup })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ItemDef> {
+        let idx = self
+            .lookup
+            .get(id)
+            .or_else(|| crated_entity(id).and_then(|_| self.lookup.get(CRATE_ITEM)))
+            .copied()?;
+        self.items.get(idx)
+    }
+
+    /// Coins one `id` earns in a delivery contract; unknown items earn the default.
+    pub fn value(&self, id: &str) -> u32 {
+        self.lookup.get(id).map_or_else(default_value, |&idx| self.items[idx].value)
+    }
+
+    /// Every item id, sorted. The crate sprite item isn't one.
+    pub fn ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .lookup
+            .keys()
+            .map(String::as_str)
+            .filter(|&id| id != CRATE_ITEM)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// Inventory id of a crate holding an entity.
+pub fn crate_item(entity: &str) -> String {
+    format!("{CRATE_PREFIX}{entity}")
+}
+
+/// Entity id inside a crate item, or None for other items.
+pub fn crated_entity(item: &str) -> Option<&str> {
+    item.strip_prefix(CRATE_PREFIX)
+}
+
+#[derive(Default)]
+pub struct Inventory {
+    counts: HashMap<String, u32>,
+    /// Creative mode: every item is on hand and taking items never uses them up.
+    unlimited: bool,
+}
+
+impl Inventory {
+    pub fn add(&mut self, id: &str, count: u32) {
+        if count == 0 {
+            return;
+        }
+        *self.counts.entry(id.to_string()).or_insert(0) += count;
+    }
+
+    /// Takes `count` of an item if there are enough; otherwise leaves the inventory alone and
+    /// returns false.
+    pub fn remove(&mut self, id: &str, count: u32) -> bool {
+        if self.unlimited {
+            return true;
+        }
+        let Some(have) = self.counts.get_mut(id) else {
+            return count == 0;
+        };
+        if *have < count {
+            return false;
+        }
+        *have -= count;
+        if *have == 0 {
+            self.counts.remove(id);
+        }
+        true
+    }
+
+    pub fn counts(&self) -> &HashMap<String, u32> {
+        &self.counts
+    }
+
+    pub fn has(&self, id: &str) -> bool {
+        self.unlimited || self.counts.contains_key(id)
+    }
+
+    pub fn set_unlimited(&mut self, unlimited: bool) {
+        self.unlimited = unlimited;
+    }
+}
+
+pub struct WorldItem {
+    pub item: String,
+    pub count: u32,
+    pub pos: Vec2,
+    /// Seconds on the ground, for the bob.
+    pub age: f32,
+}
+
+impl WorldItem {
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.pos.x - WORLD_ITEM_SIZE * 0.5,
+            self.pos.y - WORLD_ITEM_SIZE * 0.5,
+            WORLD_ITEM_SIZE,
+            WORLD_ITEM_SIZE,
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct WorldItems {
+    items: Vec<WorldItem>,
+}
+
+impl WorldItems {
+    /// Drops `count` of `item` near `pos`, scattered slightly so stacks from one death don't overlap.
+    pub fn drop_item(&mut self, item: &str, count: u32, pos: Vec2) {
+        if count == 0 {
+            return;
+        }
+        let offset = vec2(
+            crate::helpers::random_range(-WORLD_ITEM_SCATTER, WORLD_ITEM_SCATTER),
+            crate::helpers::random_range(-WORLD_ITEM_SCATTER, WORLD_ITEM_SCATTER),
+        );
+        self.items.push(WorldItem {
+            item: item.to_string(),
+            count,
+            pos: pos + offset,
+            age: crate::helpers::random_range(0.0, 1.0 / WORLD_ITEM_BOB_RATE),
+        });
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for item in &mut self.items {
+            item.age += dt;
+        }
+    }
+
+    /// Moves every item overlapping `picker` into `inventory`. Returns how many stacks were collected.
+    pub fn collect_overlapping(&mut self, picker: Rect, inventory: &mut Inventory) -> usize {
+        let before = self.items.len();
+        self.items.retain(|item| {
+            if item.rect().overlaps(&picker) {
+                inventory.add(&item.item, item.count);
+                false
+            } else {
+                true
+            }
+        });
+        before - self.items.len()
+    }
+
+    pub fn draw_in_view(&self, db: &ItemDatabase, view: &ViewCull) {
+        for item in &self.items {
+            let item_rect = item.rect();
+            if !view.rect_visible(item_rect) {
+                continue;
+            }
+            let Some(def) = db.get(&item.item) else {
+                continue;
+            };
+            // Only the sprite bobs; pickups use the resting rect.
+            let bob = (item.age * WORLD_ITEM_BOB_RATE * std::f32::consts::TAU).sin() * WORLD_ITEM_BOB_HEIGHT;
+            draw_texture_ex(
+                &def.texture,
+                item_rect.x,
+                item_rect.y - bob,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(item_rect.w, item_rect.h)),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize)]
+struct ItemFile {
+    id: String,
+    sprite: String,
+    #[serde(default = "default_value")]
+    value: u32,
+}
+
+fn default_value() -> u32 {
+    5
+}
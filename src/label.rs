@@ -0,0 +1,163 @@
+//! Floating text over the world.
+//!
+//! Gameplay systems call `WorldLabels::spawn` for short notices tied to a place, like the name of
+//! a dropped item, "+25 HP" over a heal, or a structure's name when the player first comes across
+//! it. Labels rise and fade out. The ones far from the player are drawn smaller, and past
+//! `MAX_DISTANCE` they are not drawn at all. Labels live in a fixed pool: dead entries get reused,
+//! and when the pool is full a new label replaces the oldest one.
+
+use macroquad::prelude::*;
+use crate::cull::ViewCull;
+use crate::ui::{UiBatch, UiLayer};
+
+const MAX_LABELS: usize = 48;
+/// World pixels from the player beyond which labels are hidden.
+const MAX_DISTANCE: f32 = 320.0;
+/// Labels at `MAX_DISTANCE` are drawn at this fraction of their size.
+const MIN_SCALE: f32 = 0.5;
+/// Fraction of a label's life spent fading out.
+const FADE_PORTION: f32 = 0.3;
+
+/// How a label looks and how long it stays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelStyle {
+    /// Items dropped on the ground.
+    Item,
+    /// Health gained.
+    Heal,
+    /// A structure seen for the first time.
+    Discovery,
+}
+
+impl LabelStyle {
+    fn color(self) -> Color {
+        match self {
+            Self::Item => Color::from_rgba(255, 236, 160, 255),
+            Self::Heal => Color::from_rgba(120, 230, 120, 255),
+            Self::Discovery => WHITE,
+        }
+    }
+
+    /// Seconds on screen.
+    fn lifetime(self) -> f32 {
+        match self {
+            Self::Item | Self::Heal => 1.2,
+            Self::Discovery => 2.5,
+        }
+    }
+
+    /// Font size in screen pixels next to the player.
+    fn size(self) -> f32 {
+        match self {
+            Self::Item | Self::Heal => 18.0,
+            Self::Discovery => 24.0,
+        }
+    }
+
+    /// World pixels per second the label drifts up.
+    fn rise(self) -> f32 {
+        match self {
+            Self::Item | Self::Heal => 14.0,
+            Self::Discovery => 4.0,
+        }
+    }
+}
+
+struct Label {
+    text: String,
+    pos: Vec2,
+    style: LabelStyle,
+    age: f32,
+    alive: bool,
+}
+
+#[derive(Default)]
+pub struct WorldLabels {
+    labels: Vec<Label>,
+}
+
+impl WorldLabels {
+    /// Shows `text` centered over a world position.
+    pub fn spawn(&mut self, text: &str, pos: Vec2, style: LabelStyle) {
+        let index = match self.labels.iter().position(|label| !label.alive) {
+            Some(index) => index,
+            None if self.labels.len() < MAX_LABELS => {
+                self.labels.push(Label {
+                    text: String::new(),
+                    pos,
+                    style,
+                    age: 0.0,
+                    alive: false,
+                });
+                self.labels.len() - 1
+            }
+            None => self
+                .labels
+                .iter()
+                .enumerate()
+                .max_by(|a, b| (a.1.age / a.1.style.lifetime()).total_cmp(&(b.1.age / b.1.style.lifetime())))
+                .map(|(index, _)| index)
+                .unwrap_or(0),
+        };
+        let label = &mut self.labels[index];
+        label.text.clear();
+        label.text.push_str(text);
+        label.pos = pos;
+        label.style = style;
+        label.age = 0.0;
+        label.alive = true;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for label in self.labels.iter_mut().filter(|label| label.alive) {
+            label.age += dt;
+            label.pos.y -= label.style.rise() * dt;
+            label.alive = label.age < label.style.lifetime();
+        }
+    }
+
+    /// Forgets every label, e.g. after loading a save.
+    pub fn clear(&mut self) {
+        for label in &mut self.labels {
+            label.alive = false;
+        }
+    }
+
+    /// Queues the labels near `focus` and in `view` at their screen positions under `camera`.
+    pub fn draw(&self, camera: &Camera2D, view: &ViewCull, focus: Vec2, ui: &mut UiBatch) {
+        for label in self.labels.iter().filter(|label| label.alive) {
+            let distance = label.pos.distance(focus);
+            if distance > MAX_DISTANCE || !view.point_visible(label.pos) {
+                continue;
+            }
+            let scale = 1.0 - (1.0 - MIN_SCALE) * distance / MAX_DISTANCE;
+            let size = label.style.size() * scale;
+            let lifetime = label.style.lifetime();
+            let fade_time = lifetime * FADE_PORTION;
+            let alpha = ((lifetime - label.age) / fade_time).clamp(0.0, 1.0);
+            let mut color = label.style.color();
+            color.a *= alpha;
+
+            let screen = camera.world_to_screen(label.pos);
+            let width = measure_text(&label.text, None, size as u16, 1.0).width;
+            let pos = vec2(screen.x - width * 0.5, screen.y);
+            ui.text(UiLayer::World, label.text.as_str(), pos + vec2(1.0, 1.0), size, Color::new(0.0, 0.0, 0.0, color.a * 0.6));
+            ui.text(UiLayer::World, label.text.as_str(), pos, size, color);
+        }
+    }
+}
+
+/// "big_greenhouse" -> "Big Greenhouse", for showing ids to the player.
+pub fn display_name(id: &str) -> String {
+    id.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,58 @@
+//! Game systems shared by the game and the content tools in `src/bin`.
+
+pub mod asset_pack;
+pub mod map;
+pub mod player;
+pub mod helpers;
+pub mod hot_reload;
+pub mod entity;
+pub mod faction;
+pub mod ability;
+pub mod entity_pool;
+pub mod blackboard;
+pub mod r#trait;
+pub mod particle;
+pub mod tilemap;
+pub mod cull;
+pub mod ysort;
+pub mod sound;
+pub mod interact;
+pub mod item;
+pub mod hazard;
+pub mod projectile;
+pub mod world;
+pub mod worldgen;
+pub mod save;
+pub mod tmx;
+pub mod pathfinding;
+pub mod spatial;
+pub mod autotile;
+pub mod editor;
+pub mod entity_editor;
+pub mod entity_debug;
+pub mod debug;
+pub mod quest;
+pub mod relationship;
+pub mod cutscene;
+pub mod time;
+pub mod capture;
+pub mod pet;
+pub mod farming;
+pub mod minimap;
+pub mod calendar;
+pub mod director;
+pub mod spawner;
+pub mod simulation;
+pub mod ambient;
+pub mod stall;
+pub mod upgrade;
+pub mod respawn;
+pub mod ui;
+pub mod label;
+pub mod statistics;
+pub mod tooltip;
+pub mod ping;
+pub mod load_menu;
+pub mod new_game;
+pub mod mods;
+pub mod validate;
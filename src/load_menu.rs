@@ -0,0 +1,103 @@
+//! The load menu: shows what a save holds, its thumbnail, day, time and world seed, before
+//! loading it.
+
+use macroquad::prelude::*;
+use std::path::Path;
+use crate::save::SaveFile;
+use crate::ui::{PanelLayout, UiBatch, UiLayer};
+
+/// Wide enough for a full-width thumbnail at `THUMBNAIL_SCALE`.
+const PANEL: PanelLayout = PanelLayout {
+    width: 320.0,
+    header: 36.0,
+    row_height: 24.0,
+    footer: 30.0,
+};
+/// Screen pixels per thumbnail pixel.
+const THUMBNAIL_SCALE: f32 = 3.0;
+
+#[derive(Default)]
+pub struct LoadMenu {
+    save: Option<SaveFile>,
+    thumbnail: Option<Texture2D>,
+}
+
+impl LoadMenu {
+    /// Reads the save at `path` and shows it; nothing opens if it can't be read.
+    pub fn open(&mut self, path: impl AsRef<Path>) {
+        match SaveFile::read(path) {
+            Ok(save) => {
+                self.thumbnail = save.meta.thumbnail.as_ref().and_then(|thumbnail| thumbnail.to_texture());
+                self.save = Some(save);
+            }
+            Err(err) => eprintln!("load failed: {err}"),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.save.is_some()
+    }
+
+    pub fn close(&mut self) {
+        self.save = None;
+        self.thumbnail = None;
+    }
+
+    /// Enter hands back the save to restore, Escape closes the menu.
+    pub fn handle_input(&mut self) -> Option<SaveFile> {
+        if !self.is_open() {
+            return None;
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.close();
+            return None;
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            self.thumbnail = None;
+            return self.save.take();
+        }
+        None
+    }
+
+    /// Queues the open menu: the picture takes as many rows as it needs, then the day and seed.
+    pub fn draw_ui(&self, ui: &mut UiBatch) {
+        let Some(save) = self.save.as_ref() else {
+            return;
+        };
+        // Saves without a picture get a line of text in its place.
+        let picture_rows = self.thumbnail.as_ref().map_or(1, |texture| {
+            (texture.height() * THUMBNAIL_SCALE / PANEL.row_height).ceil() as usize
+        });
+        let rows = picture_rows + 1 + usize::from(save.meta.seed.is_some());
+        let frame = PANEL.frame(rows);
+        ui.panel(frame, "Load game");
+
+        let top = PANEL.row(0);
+        match self.thumbnail.as_ref() {
+            Some(texture) => {
+                let size = texture.size() * THUMBNAIL_SCALE;
+                let rect = Rect::new(top.x + (top.w - size.x) * 0.5, top.y, size.x, size.y);
+                ui.texture(UiLayer::Panel, texture, rect, WHITE);
+                ui.rect_lines(UiLayer::Panel, rect, 1.0, GRAY);
+            }
+            None => ui.text(UiLayer::Panel, "(no picture)", text_pos(top), 18.0, GRAY),
+        }
+        let when = if save.meta.clock.is_empty() {
+            // Saves from before metadata was kept.
+            "Unknown day".to_string()
+        } else {
+            format!("Day {}, {}", save.meta.day, save.meta.clock)
+        };
+        ui.text(UiLayer::Panel, when, text_pos(PANEL.row(picture_rows)), 20.0, WHITE);
+        if let Some(seed) = save.meta.seed {
+            let pos = text_pos(PANEL.row(picture_rows + 1));
+            ui.text(UiLayer::Panel, format!("Seed {seed}"), pos, 18.0, LIGHTGRAY);
+        }
+        ui.panel_footer(frame, "Enter: load   Esc: cancel");
+    }
+}
+
+/// Baseline for a line of text in `row`.
+fn text_pos(row: Rect) -> Vec2 {
+    vec2(row.x + 10.0, row.y + 18.0)
+}
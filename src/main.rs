@@ -1,38 +1,87 @@
 use macroquad::prelude::*;
 use miniquad::conf::{Icon, Platform};
 use image::imageops::FilterType;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::poll_fn;
 use std::task::Poll;
 
-mod map;
-mod player;
-mod helpers;
-mod entity;
-mod r#trait;
-mod particle;
-mod tilemap;
-mod sound;
-mod interact;
-
-use map::{LayerKind, TileMap, TileSet, load_structures_from_dir};
-use player::Player;
-use entity::{DamageEvent, Entity, EntityContext, EntityDatabase, MovementRegistry, PlayerTarget, Target};
-
-use sound::SoundSystem;
-use particle::ParticleSystem;
-use interact::{InteractContext, InteractRegistry};
+use rustycropbot::{asset_pack, entity, helpers, relationship};
+use rustycropbot::asset_pack::load_texture;
+use rustycropbot::map::{
+    MapBorder, MapEdge, OccludedHighlight, StructureDef, StructureInteractor, TileMap, TileSet, load_structures_from_dir,
+};
+use rustycropbot::player::{Player, PlayerSprite};
+use rustycropbot::entity::{
+    DamageEvent, Entity, EntityContext, EntityDatabase, EntityRequest, MovementRegistry, ParentLink, PlayerTarget,
+    SummonRequest, Target,
+};
+use rustycropbot::entity_pool::EntityPool;
+
+use rustycropbot::sound::SoundSystem;
+use rustycropbot::particle::{ParticleLayer, ParticleSystem};
+use rustycropbot::interact::{HoldInteraction, InteractContext, InteractEvent, InteractRegistry, TriggerTracker};
+use rustycropbot::item::{Inventory, ItemDatabase, WorldItems};
+use rustycropbot::hazard::HazardSystem;
+use rustycropbot::hot_reload::{DataWatcher, TextureWatcher};
+use rustycropbot::projectile::{ProjectileEvent, ProjectileOwner, ProjectileSystem, ProjectileTargets};
+use rustycropbot::world::{FogSettings, WorldSettings};
+use rustycropbot::worldgen::WorldGenerator;
+use rustycropbot::autotile::AutotileRules;
+use rustycropbot::save::{SaveFile, SaveThumbnail};
+use rustycropbot::load_menu::LoadMenu;
+use rustycropbot::new_game;
+use rustycropbot::editor::MapEditor;
+use rustycropbot::entity_debug::EntityDebug;
+use rustycropbot::debug::DebugTools;
+use rustycropbot::entity_editor::EntityEditor;
+use rustycropbot::quest::ContractBoard;
+use rustycropbot::cull::{ViewCull, expand_rect};
+use rustycropbot::ysort::{Sorted, YSortPass};
+use rustycropbot::ping::{self, Pings};
+use rustycropbot::director::Director;
+use rustycropbot::spawner::Spawner;
+use rustycropbot::ambient::AmbientLife;
+use rustycropbot::ability;
+use rustycropbot::spatial::SpatialGrid;
+use rustycropbot::stall::StallRegistry;
+use rustycropbot::upgrade::{self, UpgradeDatabase, UpgradePanel, Upgrades};
+use rustycropbot::respawn::{RespawnAnchors, WORLD_MAP};
+use rustycropbot::statistics::Statistics;
+use rustycropbot::tooltip::{self, MONOCLE_ITEM};
+use rustycropbot::ui::{UiBatch, UiLayer};
+use rustycropbot::label::{display_name, LabelStyle, WorldLabels};
+use rustycropbot::mods::{MODS_DIR, ModSet};
+use rustycropbot::relationship::{NpcPanel, Relationships};
+use rustycropbot::cutscene::{CutsceneContext, CutsceneLibrary, CutscenePlayer};
+use rustycropbot::time::WorldTime;
+use rustycropbot::capture::{CaptureEvent, CaptureNet};
+use rustycropbot::pet::{PetEvent, Taming};
+use rustycropbot::simulation::Simulation;
+use rustycropbot::farming::{CropDatabase, Farm, FarmEvent};
+use rustycropbot::minimap::Minimap;
+use rustycropbot::calendar::Calendar;
 
 const CAMERA_DRAG: f32 = 5.0;
 const TILE_SIZE: f32 = 16.0;
 const MOVE_DEADZONE: f32 = 16.0;
 const FOOTSTEP_INTERVAL: f32 = 0.2;
+/// Cutscene played when a new game starts, anchored on the player.
+const INTRO_CUTSCENE: &str = "intro";
 const CAMERA_FOV: f32 = 300.0;
 const ENTITY_CULL_FADE_PAD: f32 = 96.0;
 const LOADING_SPIN_SPEED: f32 = 3.0;
-const STRUCTURE_APPLY_TIME_BUDGET_S: f32 = 0.01;
+/// Map chunks generated per frame while the player walks; the ones around the spawn are all
+/// generated while loading.
+const CHUNK_GENERATE_PER_FRAME: usize = 2;
 const CHUNK_ALLOC_PER_FRAME: usize = 6;
 const CHUNK_REBUILD_PER_FRAME: usize = 8;
+const SAVE_PATH: &str = "saves/world.json";
+/// Marker object where the player starts in authored levels.
+const PLAYER_SPAWN_MARKER: &str = "player_spawn";
+/// World pixels between a level's edge and a player arriving through it.
+const ARRIVAL_INSET: f32 = 16.0;
+/// Reach used for keyboard targeting of interactors that have no `interact_range`.
+const AUTO_INTERACT_RANGE: f32 = 40.0;
 
 fn window_conf() -> Conf {
     let icon = load_window_icon(&helpers::asset_path("src/assets/favicon.png"));
@@ -140,8 +189,24 @@ async fn main() {
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.0, loading_spin).await;
 
+    // One download for the web build's data and assets instead of a request per file.
+    if cfg!(target_arch = "wasm32") {
+        let mounted = await_with_loading(
+            asset_pack::mount(asset_pack::PACK_PATH),
+            &loading,
+            "Downloading assets",
+            0.0,
+            &mut loading_spin,
+        )
+        .await;
+        match mounted {
+            Ok(count) => eprintln!("mounted asset pack with {count} files"),
+            Err(err) => eprintln!("no asset pack ({err}); loading files one by one"),
+        }
+    }
+
     // Load the tileset atlas (tileset.json + tileset.png)
-    let tileset = await_with_loading(
+    let mut tileset = await_with_loading(
         TileSet::load("src/assets/tileset.json", "src/assets/tileset.png"),
         &loading,
         "Loading",
@@ -156,15 +221,53 @@ async fn main() {
         });
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.22, loading_spin).await;
-    let mut maps = TileMap::new_deferred(1024, 1024, TILE_SIZE, Vec2::new(TILE_SIZE, TILE_SIZE), 0.0);
+    let mut world = WorldSettings::load("src/world.yaml").await.unwrap_or_else(|err| {
+        eprintln!("world settings load failed: {err}");
+        WorldSettings::default()
+    });
+    let world_size = world.worldgen.size.max(1);
+    let mut maps = TileMap::new(world_size, world_size, TILE_SIZE, Vec2::new(TILE_SIZE, TILE_SIZE), 0.0);
     maps.set_chunk_work_budget(CHUNK_ALLOC_PER_FRAME, CHUNK_REBUILD_PER_FRAME);
-    let grass: u8 = if tileset.count() > 24 { 24 } else { 0 };
-    maps.fill_layer(LayerKind::Background, grass);
+    // Generated worlds start in the middle so there is room to roam in every direction.
+    let mut player_spawn = world.worldgen.spawn(TILE_SIZE);
+    let mut respawn = RespawnAnchors::new(WORLD_MAP, player_spawn);
+
+    // An authored Tiled level replaces the generated map and its tileset.
+    let mut authored_level = false;
+    if let Some(level_path) = level_arg() {
+        match TileMap::load_tmx(&level_path, 0.0).await {
+            Ok(level) => {
+                maps = level.map;
+                maps.set_chunk_work_budget(CHUNK_ALLOC_PER_FRAME, CHUNK_REBUILD_PER_FRAME);
+                tileset = level.tileset;
+                authored_level = true;
+                player_spawn = maps.marker(PLAYER_SPAWN_MARKER).unwrap_or_else(|| {
+                    eprintln!("level '{level_path}' has no '{PLAYER_SPAWN_MARKER}' marker; starting in the middle");
+                    vec2(maps.width() as f32, maps.height() as f32) * maps.tile_size() * 0.5
+                });
+                respawn.enter(&level_path, player_spawn);
+            }
+            Err(err) => eprintln!("level '{level_path}' failed to load, using generated map: {err}"),
+        }
+    }
+    let autotile_rules = AutotileRules::load("src/terrain.json").await.unwrap_or_else(|err| {
+        eprintln!("autotile rules load failed: {err}");
+        AutotileRules::default()
+    });
+    maps.set_autotile_rules(autotile_rules.clone());
+    maps.set_tile_properties(tileset.properties().to_vec());
+    maps.set_ambient_occlusion(world.ambient_occlusion);
+    maps.set_tile_colors(tileset.colors().to_vec());
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.35, loading_spin).await;
 
-    // Load structures from JSON and apply them with a fixed seed.
-    let structures = await_with_loading(
+    let mut mods = ModSet::scan(MODS_DIR);
+    for name in mods.names() {
+        eprintln!("loading mod '{name}'");
+    }
+
+    // Load structures from JSON; chunks roll them with a fixed seed as they generate.
+    let mut structures = await_with_loading(
         load_structures_from_dir("src/structure"),
         &loading,
         "Loading",
@@ -176,31 +279,52 @@ async fn main() {
         eprintln!("structure load failed: {err}");
         Vec::new()
     });
-    if !structures.is_empty() {
-        maps.start_structure_apply(structures, 1337);
-        while !maps.apply_structures_step(STRUCTURE_APPLY_TIME_BUDGET_S) {
-            loading_spin += LOADING_SPIN_SPEED * get_frame_time();
-            show_loading(&loading, "Placing structures", maps.structure_apply_progress() * 0.15 + 0.45, loading_spin).await;
-        }
+    mods.apply_structures(&mut structures).await;
+    let mut highlight_icons = load_highlight_icons(&structures).await;
+    // Kept around so interactors can be rebuilt when a save is loaded.
+    let mut structure_defs = structures.clone();
+    let generator = WorldGenerator::new(
+        world.worldgen.clone(),
+        structures,
+        autotile_rules.clone(),
+        TILE_SIZE,
+        tileset.count(),
+    );
+    // A generated world's residents and what its first structures asked for.
+    let mut world_spawns = Vec::new();
+    if !authored_level {
+        world.worldgen.seed = new_game::choose_seed(&generator, world.worldgen.seed).await;
+        eprintln!("generating world with seed {}", world.worldgen.seed);
+        loading_spin += LOADING_SPIN_SPEED * get_frame_time();
+        show_loading(&loading, "Generating world", 0.45, loading_spin).await;
+        (maps, world_spawns) = generator.generate(world.worldgen.seed);
+        maps.set_chunk_work_budget(CHUNK_ALLOC_PER_FRAME, CHUNK_REBUILD_PER_FRAME);
+        maps.set_tile_properties(tileset.properties().to_vec());
+        maps.set_ambient_occlusion(world.ambient_occlusion);
+        maps.set_tile_colors(tileset.colors().to_vec());
+        maps.set_borders(world.borders.clone());
     }
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.55, loading_spin).await;
 
     // Player
-    let player_texture = await_with_loading(
-        helpers::load_single_texture("src/assets/objects", "player08"),
+    let player_sprite = await_with_loading(
+        PlayerSprite::load(&world.player_sprite),
         &loading,
         "Loading",
         0.6,
         &mut loading_spin,
     )
     .await
-    .unwrap_or_else(Texture2D::empty);
+    .unwrap_or_else(|| {
+        eprintln!("player sprite '{}' failed to load", world.player_sprite.sheet);
+        PlayerSprite::single(Texture2D::empty())
+    });
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.65, loading_spin).await;
     let mut player = Player::new(
-        vec2(200.0, 300.0 + 16.0 / 2.0),
-        player_texture,
+        player_spawn,
+        player_sprite,
         Rect::new(-6.5 / 2.0, -8.0, 6.5, 8.0),
     );
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
@@ -224,6 +348,7 @@ async fn main() {
 
     let mut i: f32 = 0.0;
     let mut fps: i32 = 0;
+    let mut hud = UiBatch::default();
 
     let use_render_target = false;
     let render_scale = 0.5;
@@ -239,7 +364,7 @@ async fn main() {
 
     // Entity registry
     let registry = MovementRegistry::new();
-    let db = await_with_loading(
+    let mut db = await_with_loading(
         EntityDatabase::load_from("src/entity"),
         &loading,
         "Loading",
@@ -251,40 +376,53 @@ async fn main() {
             eprintln!("entity load failed: {err}");
             EntityDatabase::empty()
         });
+    mods.apply_entities(&mut db).await;
+    report_entity_issues(&db, &registry);
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.75, loading_spin).await;
 
-    let mut entities = Vec::<Entity>::new();
-    for _ in 0..2 {
-        let pos = vec2(
-            helpers::random_range(0.0, 500.0),
-            helpers::random_range(0.0, 500.0),
-        );
-        if let Some(virabird) = Entity::spawn(&db, "virabird", pos, &registry) {
-            entities.push(virabird);
-        }
-    }
-    for _ in 0..3 {
-        let pos = vec2(
-            helpers::random_range(0.0, 500.0),
-            helpers::random_range(0.0, 500.0),
-        );
-        if let Some(virat) = Entity::spawn(&db, "virat", pos, &registry) {
-            entities.push(virat);
+    let item_db = await_with_loading(
+        ItemDatabase::load_from("src/item"),
+        &loading,
+        "Loading",
+        0.77,
+        &mut loading_spin,
+    )
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("item load failed: {err}");
+            ItemDatabase::empty()
+        });
+    let crop_db = CropDatabase::load_from("src/crop").await.unwrap_or_else(|err| {
+        eprintln!("crop load failed: {err}");
+        CropDatabase::empty()
+    });
+    let upgrade_db = UpgradeDatabase::load_from("src/upgrade").await.unwrap_or_else(|err| {
+        eprintln!("upgrade load failed: {err}");
+        UpgradeDatabase::empty()
+    });
+    let mut world_items = WorldItems::default();
+    let mut inventory = Inventory::default();
+
+    let spawn_origin = player.position();
+
+    let mut entities = EntityPool::default();
+    // Generated worlds bring their residents along with the rest of `world_spawns`.
+    let residents = if authored_level { generator.population(spawn_origin) } else { Vec::new() };
+    for spawn in residents {
+        match Entity::spawn(&db, &spawn.entity, spawn.pos, &registry) {
+            Some(ent) => {
+                entities.insert(ent);
+            }
+            None => eprintln!("starting entity '{}' has no definition", spawn.entity),
         }
     }
-
-    for _ in 0..1 {
-        let pos = vec2(
-            helpers::random_range(0.0, 500.0),
-            helpers::random_range(0.0, 500.0),
-        );
-        if let Some(chopbot) = Entity::spawn(&db, "chopbot", pos, &registry) {
-            entities.push(chopbot);
-        }
+    for ent in entities.iter_mut() {
+        world.apply_tier(&mut ent.instance, spawn_origin);
     }
 
     let mut draw_order: Vec<usize> = Vec::new();
+    let mut y_sort = YSortPass::default();
 
     // Particle system
     let mut particles = await_with_loading(
@@ -299,13 +437,23 @@ async fn main() {
             eprintln!("particle load failed: {err}");
             ParticleSystem::empty()
         });
+    mods.apply_particles(&mut particles).await;
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.85, loading_spin).await;
-    let mut walk_trail = particles.emitter("dust_trail", player.position());
-    let mut dash_trail = particles.emitter("dash_afterimage", player.position());
+    let mut hazards = HazardSystem::load_from("src/hazard").await.unwrap_or_else(|err| {
+        eprintln!("hazard load failed: {err}");
+        HazardSystem::empty()
+    });
+    let mut projectiles = ProjectileSystem::load_from("src/projectile").await.unwrap_or_else(|err| {
+        eprintln!("projectile load failed: {err}");
+        ProjectileSystem::empty()
+    });
+    let mut walk_trail = particles.emitter(world.walk_particles(None), player.position());
+    let mut dash_trail = particles.emitter(world.dash_particles(None), player.position());
+    let mut swim_ripples = particles.emitter("ripple", player.position());
 
     // Load sounds
-    let sounds = await_with_loading(
+    let mut sounds = await_with_loading(
         SoundSystem::load_from("src/sound"),
         &loading,
         "Loading sounds",
@@ -317,17 +465,197 @@ async fn main() {
             eprintln!("sound load failed: {err}");
             SoundSystem::empty()
         });
+    mods.apply_sounds(&mut sounds).await;
+    let cutscenes = await_with_loading(
+        CutsceneLibrary::load_from("src/cutscene"),
+        &loading,
+        "Loading cutscenes",
+        0.95,
+        &mut loading_spin,
+    )
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("cutscene load failed: {err}");
+            CutsceneLibrary::empty()
+        });
+    let mut calendar = Calendar::load_from("src/event").await.unwrap_or_else(|err| {
+        eprintln!("calendar load failed: {err}");
+        Calendar::empty()
+    });
+    let mut spawner = Spawner::load_from("src/spawner", world.spawner.clone()).await.unwrap_or_else(|err| {
+        eprintln!("spawn table load failed: {err}");
+        Spawner::default()
+    });
+    let mut ambient_life = AmbientLife::load_from("src/ambient", world.ambient.clone()).await.unwrap_or_else(|err| {
+        eprintln!("critter load failed: {err}");
+        AmbientLife::default()
+    });
+    let mut simulation = Simulation::new(world.simulation.clone());
+    let mut stalls = StallRegistry::default();
     loading_spin += LOADING_SPIN_SPEED * get_frame_time();
     show_loading(&loading, "Loading", 0.98, loading_spin).await;
 
     let mut footstep_timer = 0.0f32;
     let mut damage_events: Vec<DamageEvent> = Vec::new();
-    let mut entity_target_cache: HashMap<(u64, u8), Option<entity::EntityTarget>> = HashMap::new();
+    let mut projectile_events: Vec<ProjectileEvent> = Vec::new();
+    let mut entity_target_cache: HashMap<u64, Option<entity::EntityTarget>> = HashMap::new();
     let mut player_dead = false;
     let interact_registry = InteractRegistry::new();
+    let mut hold_interaction = HoldInteraction::default();
+    let mut triggers = TriggerTracker::default();
+    let mut region_banner = RegionBanner::default();
+    let mut statistics = Statistics::default();
+    let mut labels = WorldLabels::default();
+    let mut director = Director::new(world.director.clone());
+    let mut pings = Pings::default();
+    // Top-left tiles of structures the player has already seen, so each is announced once.
+    let mut discovered_structures: HashSet<(usize, usize)> = HashSet::new();
+    let debug_profile = world.debug.clone().unwrap_or_default();
+    let mut editor = MapEditor::new(tileset.count(), debug_profile.editor, debug_profile.keys.editor);
+    if world.creative {
+        let entity_ids = db.entity_ids().into_iter().map(str::to_string).collect();
+        editor = editor.with_creative(structure_defs.clone(), entity_ids);
+    }
+    let mut entity_editor = EntityEditor::new(debug_profile.entity_editor, debug_profile.keys.entity_editor);
+    let mut entity_debug = EntityDebug::new(debug_profile.inspector, debug_profile.keys.inspector);
+    let mut debug_tools = DebugTools::new(debug_profile);
+    let mut world_time = WorldTime::new(world.time.clone());
+    let mut contract_board = ContractBoard::default();
+    let mut relationships = Relationships::default();
+    let mut npc_panel = NpcPanel::default();
+    let mut capture_net = CaptureNet::default();
+    let mut taming = Taming::default();
+    // Whatever last hurt the player, for pets to go after.
+    let mut player_attacker: Option<u64> = None;
+    let mut upgrades = Upgrades::default();
+    let mut upgrade_panel = UpgradePanel::default();
+    let mut farm = Farm::new(crop_db, world.farming.clone());
+    let mut load_menu = LoadMenu::default();
+    // F5 saves at the end of the frame, once the scene is drawn for the thumbnail.
+    let mut save_requested = false;
+    let mut minimap = Minimap::new();
+    let mut texture_watcher = watch_textures(&tileset, &db, &particles);
+    let mut data_watcher = DataWatcher::default();
+    for kind in ["entity", "particle", "structure"] {
+        data_watcher.watch(format!("src/{kind}"), kind);
+        for root in mods.roots() {
+            data_watcher.watch(root.join(kind), kind);
+        }
+    }
+    let npc_names = relationship::npc_names(&db);
+    let mut cutscene = CutscenePlayer::default();
+    if let Some(intro) = cutscenes.get(INTRO_CUTSCENE) {
+        cutscene.play(intro, player.position(), player.position());
+    }
+    let mut interact_events: Vec<InteractEvent> = Vec::new();
+    // Structure spawns held back until their time of day, starting with the generated world's.
+    let mut waiting_spawns = world_spawns;
     
     loop {
         let dt = get_frame_time();
+
+        let reloaded = texture_watcher.update(dt);
+        if reloaded.iter().any(|path| path == tileset.texture_path()) {
+            // Chunk textures and the minimap hold copies of the old tiles.
+            tileset.refresh_colors();
+            maps.set_tile_colors(tileset.colors().to_vec());
+            maps.mark_all_chunks_dirty();
+        }
+        for kind in data_watcher.update(dt) {
+            match kind {
+                "entity" => match EntityDatabase::load_from("src/entity").await {
+                    Ok(mut fresh) => {
+                        mods.apply_entities(&mut fresh).await;
+                        report_entity_issues(&fresh, &registry);
+                        let removed = db.replace(fresh, &mut entities);
+                        entity_target_cache.clear();
+                        texture_watcher = watch_textures(&tileset, &db, &particles);
+                        eprintln!("reloaded entity definitions; {removed} entities without one were removed");
+                    }
+                    Err(err) => eprintln!("entity reload failed, keeping the old definitions: {err}"),
+                },
+                "particle" => match ParticleSystem::load_from("src/particle").await {
+                    Ok(fresh) => {
+                        // Same ids keep their slots, so emitters held by entities stay valid.
+                        particles.merge(fresh, |_, _| {});
+                        mods.apply_particles(&mut particles).await;
+                        texture_watcher = watch_textures(&tileset, &db, &particles);
+                        eprintln!("reloaded particle definitions");
+                    }
+                    Err(err) => eprintln!("particle reload failed, keeping the old definitions: {err}"),
+                },
+                "structure" => match load_structures_from_dir("src/structure").await {
+                    Ok(mut fresh) => {
+                        mods.apply_structures(&mut fresh).await;
+                        highlight_icons = load_highlight_icons(&fresh).await;
+                        structure_defs = fresh;
+                        if !authored_level {
+                            maps.set_structure_defs(structure_defs.clone(), world.worldgen.seed);
+                        }
+                        eprintln!("reloaded structure definitions; chunks generated from now on use them");
+                    }
+                    Err(err) => eprintln!("structure reload failed, keeping the old definitions: {err}"),
+                },
+                _ => {}
+            }
+        }
+
+        if is_key_pressed(KeyCode::F5) && !load_menu.is_open() {
+            save_requested = true;
+        }
+        if is_key_pressed(KeyCode::F9) && !load_menu.is_open() {
+            load_menu.open(SAVE_PATH);
+        }
+        if let Some(save) = load_menu.handle_input() {
+            save.restore_player(&mut player, &mut inventory);
+            inventory.set_unlimited(editor.is_creative());
+            save.restore_quests(&mut contract_board, &mut world_time);
+            calendar.skip_to(world_time.day());
+            npc_panel.set_event_stock(calendar.shop_offers());
+            save.restore_relationships(&mut relationships);
+            save.restore_farm(&mut farm);
+            save.restore_respawn(&mut respawn);
+            save.restore_statistics(&mut statistics);
+            save.restore_upgrades(&mut upgrades);
+            upgrades.apply(&upgrade_db, &mut player, &mut capture_net);
+            cutscene.restore(&save.cutscenes);
+            npc_panel.close();
+            upgrade_panel.close();
+            // Saves from before seeds were kept are assumed to share this world's.
+            if let Some(seed) = save.meta.seed {
+                world.worldgen.seed = seed;
+            }
+            entities = save.restore_entities(&db, &registry, &world);
+            match TileMap::from_save_data(save.map, &structure_defs) {
+                Ok(map) => {
+                    maps = map;
+                    maps.set_chunk_work_budget(CHUNK_ALLOC_PER_FRAME, CHUNK_REBUILD_PER_FRAME);
+                    maps.set_autotile_rules(autotile_rules.clone());
+                    maps.set_tile_properties(tileset.properties().to_vec());
+                    maps.set_ambient_occlusion(world.ambient_occlusion);
+                    maps.set_tile_colors(tileset.colors().to_vec());
+                    if !authored_level {
+                        maps.set_worldgen(generator.worldgen(world.worldgen.seed));
+                        maps.set_borders(world.borders.clone());
+                        maps.set_structure_defs(structure_defs.clone(), world.worldgen.seed);
+                    }
+                }
+                Err(err) => eprintln!("saved map is invalid, keeping current map: {err}"),
+            }
+            world_items = WorldItems::default();
+            minimap = Minimap::new();
+            waiting_spawns.clear();
+            spawner.clear();
+            ambient_life.clear();
+            simulation.clear();
+            stalls.clear();
+            entity_debug.clear();
+            entity_target_cache.clear();
+            player_attacker = None;
+            player_dead = player.hp() <= 0.0;
+            camera.target = player.position();
+            eprintln!("loaded game from {SAVE_PATH}");
+        }
         
         // Check for resolution changes and recreate render target if needed
         if use_render_target {
@@ -339,10 +667,128 @@ async fn main() {
                 last_screen_height = current_height;
             }
         }
-        
-        if !player_dead {
+
+        cutscene.update(
+            dt,
+            &mut CutsceneContext {
+                entities: &mut entities,
+                db: &db,
+                particles: &mut particles,
+                sounds: &sounds,
+                player_pos: player.position(),
+            },
+        );
+        // Cutscenes freeze the world and take the player's controls.
+        let cutscene_playing = cutscene.is_playing();
+
+        if !player_dead && !cutscene_playing {
             player.update(&maps);
         }
+        match player.take_border_crossing().map(|edge| (edge, maps.border(edge).clone())) {
+            Some((_, MapBorder::Wrap)) => camera.target = player.position(),
+            Some((edge, MapBorder::Transition(level_path))) => match TileMap::load_tmx(&level_path, 0.0).await {
+                Ok(level) => {
+                    let arrival = arrival_position(
+                        edge,
+                        player.position(),
+                        maps.get_border_hitbox(),
+                        level.map.get_border_hitbox(),
+                    );
+                    maps = level.map;
+                    maps.set_chunk_work_budget(CHUNK_ALLOC_PER_FRAME, CHUNK_REBUILD_PER_FRAME);
+                    tileset = level.tileset;
+                    texture_watcher.watch(tileset.texture_path(), tileset.texture());
+                    authored_level = true;
+                    maps.set_autotile_rules(autotile_rules.clone());
+                    maps.set_tile_properties(tileset.properties().to_vec());
+                    maps.set_ambient_occlusion(world.ambient_occlusion);
+                    maps.set_tile_colors(tileset.colors().to_vec());
+                    // Levels don't keep their state once left.
+                    player.restore(arrival, player.hp(), player.max_hp());
+                    entities = EntityPool::default();
+                    world_items = WorldItems::default();
+                    farm.restore(&[], &[]);
+                    minimap = Minimap::new();
+                    waiting_spawns.clear();
+                    spawner.clear();
+                    ambient_life.clear();
+                    simulation.clear();
+                    stalls.clear();
+                    entity_debug.clear();
+                    entity_target_cache.clear();
+                    player_attacker = None;
+                    discovered_structures.clear();
+                    labels.clear();
+                    pings.clear();
+                    camera.target = arrival;
+                    respawn.enter(&level_path, arrival);
+                    eprintln!("entered level {level_path}");
+                }
+                Err(err) => {
+                    eprintln!("level '{level_path}' failed to load, closing that edge: {err}");
+                    maps.set_border(edge, MapBorder::Solid);
+                }
+            },
+            _ => {}
+        }
+        maps.stream_chunks(player.position(), CHUNK_GENERATE_PER_FRAME);
+        if let Some(fog) = &world.fog {
+            maps.reveal_circle(player.position(), fog.reveal_radius);
+        }
+        waiting_spawns.extend(maps.take_structure_spawns());
+        let (ready, waiting): (Vec<_>, Vec<_>) = waiting_spawns
+            .drain(..)
+            .partition(|spawn| world_time.allows(spawn.time));
+        waiting_spawns = waiting;
+        director.update(dt);
+        for spawn in ready {
+            // The director only thins out or adds to enemies, never merchants and the like.
+            let enemy = db.def(&spawn.entity).is_some_and(|def| def.kind == entity::EntityKind::Enemy);
+            let copies = if enemy { director.roll_spawn_count() } else { 1 };
+            for _ in 0..copies {
+                match Entity::spawn(&db, &spawn.entity, spawn.pos, &registry) {
+                    Some(mut ent) => {
+                        let elite = enemy && director.roll_elite();
+                        let tier = world.tier_at(spawn_origin, spawn.pos) + u32::from(elite);
+                        world.scale_to_tier(&mut ent.instance, tier);
+                        entities.insert(ent);
+                    }
+                    None => {
+                        eprintln!("structure spawn '{}' has no entity definition", spawn.entity);
+                        break;
+                    }
+                }
+            }
+        }
+        for spawn in maps.take_vendor_spawns() {
+            if stalls.has_vendor(&spawn, &entities, &db) {
+                continue;
+            }
+            match Entity::spawn(&db, &spawn.entity, spawn.pos, &registry) {
+                Some(mut ent) => {
+                    world.apply_tier(&mut ent.instance, spawn_origin);
+                    let handle = entities.insert(ent);
+                    stalls.link(&spawn, handle);
+                }
+                None => eprintln!("structure vendor '{}' has no entity definition", spawn.entity),
+            }
+        }
+        spawner.despawn_far(&mut entities, player.position());
+        simulation.update(&mut entities, &db, &registry, &world, player.position());
+        let ambient = spawner.update(dt, player.position(), camera_visible_rect(&camera), &maps, &world_time);
+        if let Some(spawn) = ambient {
+            match Entity::spawn(&db, &spawn.entity, spawn.pos, &registry) {
+                Some(mut ent) => {
+                    world.apply_tier(&mut ent.instance, spawn_origin);
+                    let handle = entities.insert(ent);
+                    spawner.track(&spawn, handle);
+                }
+                None => eprintln!("spawn table entity '{}' has no definition", spawn.entity),
+            }
+        }
+        ambient_life.update(dt, camera_visible_rect(&camera), &maps, &world_time, player.position());
+        let region = maps.regions_containing(player.position()).next().map(|region| region.tag.as_str());
+        region_banner.update(region, dt);
         
         let particle_budget = particle_budget_scale(
             screen_width(),
@@ -350,10 +796,12 @@ async fn main() {
             if use_render_target { render_scale } else { 1.0 },
         );
         particles.set_budget_scale(particle_budget);
+        particles.govern(get_frame_time());
 
         camera.zoom = camera_zoom_for_fov(CAMERA_FOV, use_render_target);
         let follow = 1.0 - (-CAMERA_DRAG * get_frame_time()).exp();
-        camera.target += (player.position() - camera.target) * follow;
+        let focus = cutscene.camera_focus().unwrap_or(player.position());
+        camera.target += (focus - camera.target) * follow;
         camera.render_target = if use_render_target {
             Some(scene_target.clone())
         } else {
@@ -365,36 +813,287 @@ async fn main() {
         let view_rect = camera_view_rect_logic(camera.target, CAMERA_FOV);
         let mouse_screen = mouse_position();
         let mouse_world = camera.screen_to_world(vec2(mouse_screen.0, mouse_screen.1));
+        editor.update(&mut maps, mouse_world, dt);
+        entity_editor.update(&mut db, dt);
+        entity_debug.update(&entities, &db, mouse_world, entity_editor.wants_mouse());
+        debug_tools.update();
+        for (structure, rect) in maps.structures_in(view_rect) {
+            if discovered_structures.insert((structure.x, structure.y)) {
+                labels.spawn(&display_name(&structure.id), vec2(rect.center().x, rect.y), LabelStyle::Discovery);
+            }
+        }
+        labels.update(dt);
+        world_items.update(dt);
+        pings.update(dt);
+        player.set_creative(editor.is_creative());
+        inventory.set_unlimited(editor.is_creative());
+        let panel_spawns = entity_editor
+            .take_spawns()
+            .into_iter()
+            .map(|id| (id, player.position() + vec2(24.0, 0.0)));
+        for (id, pos) in editor.take_entity_spawns().into_iter().chain(panel_spawns) {
+            match Entity::spawn(&db, &id, pos, &registry) {
+                Some(mut ent) => {
+                    world.apply_tier(&mut ent.instance, spawn_origin);
+                    entities.insert(ent);
+                }
+                None => eprintln!("editor spawn '{id}' has no entity definition"),
+            }
+        }
+        for pos in maps.take_removed_structures() {
+            particles.burst("structure_debris", pos);
+            sounds.play_at("structure_break", pos, camera.target);
+        }
+        for stall in maps.take_removed_stalls() {
+            stalls.remove_stall(stall, &mut entities);
+        }
         let player_pos = player.position();
-        let hovered_interactor = maps
-            .structure_interactors()
-            .iter()
-            .find(|interactor| {
-                point_in_rect(mouse_world, interactor.rect)
-                    && interactor_in_range(player_pos, interactor.group_rect, interactor.interact_range_world)
-            })
-            .cloned();
-
-        if is_mouse_button_pressed(MouseButton::Left) {
-            if let Some(interactor) = hovered_interactor.as_ref() {
+        // The editors and the debug overlay own the mouse while they're open.
+        let hovered_interactor = if editor.is_active()
+            || entity_editor.wants_mouse()
+            || entity_debug.is_active()
+            || cutscene_playing
+        {
+            None
+        } else {
+            maps.structure_interactors()
+                .iter()
+                .find(|interactor| {
+                    calendar.structure_open(&interactor.structure_id)
+                        && point_in_rect(mouse_world, interactor.rect)
+                        && interactor_in_range(player_pos, interactor.group_rect, interactor.interact_range_world)
+                })
+                .cloned()
+        };
+        // Without a mouse hover, fall back to the interactor the player is facing so gamepad and
+        // keyboard play can interact too.
+        let auto_interactor = if hovered_interactor.is_none() && !cutscene_playing {
+            let open = maps
+                .structure_interactors()
+                .iter()
+                .filter(|interactor| calendar.structure_open(&interactor.structure_id));
+            auto_target_interactor(open, player_pos, player.facing()).cloned()
+        } else {
+            None
+        };
+        let target_interactor = hovered_interactor.as_ref().or(auto_interactor.as_ref());
+
+        let mouse_pressed = hovered_interactor.is_some() && is_mouse_button_pressed(MouseButton::Left);
+        let mouse_down = hovered_interactor.is_some() && is_mouse_button_down(MouseButton::Left);
+        let clicked = (mouse_pressed || is_key_pressed(KeyCode::E))
+            && target_interactor.is_some_and(|interactor| interactor.hold_time <= 0.0);
+        let held = hold_interaction.update(
+            target_interactor,
+            mouse_down || is_key_down(KeyCode::E),
+            player.is_moving(MOVE_DEADZONE),
+            dt,
+        );
+        if clicked || held {
+            if let Some(interactor) = target_interactor {
                 let mut ctx = InteractContext {
                     structure_id: &interactor.structure_id,
                     area: interactor.group_rect,
                     player: &mut player,
                     map: &mut maps,
+                    events: &mut interact_events,
                 };
                 interact_registry.execute(&interactor.on_interact, &mut ctx);
             }
+        } else if is_key_pressed(KeyCode::E)
+            && target_interactor.is_none()
+            && !cutscene_playing
+            && !contract_board.is_open()
+            && !npc_panel.is_open()
+            && !upgrade_panel.is_open()
+            && !load_menu.is_open()
+        {
+            npc_panel.talk(&entities, &db, player_pos);
+        }
+        if !cutscene_playing && !editor.is_active() && !entity_editor.wants_mouse() && !load_menu.is_open() {
+            let ping_at = if is_mouse_button_pressed(ping::MOUSE_BUTTON) {
+                Some(mouse_world)
+            } else if is_key_pressed(ping::KEY) {
+                Some(player_pos)
+            } else {
+                None
+            };
+            if let Some(pos) = ping_at {
+                pings.place(pos);
+                sounds.play(ping::SOUND);
+            }
+        }
+        let entered = triggers.entered(&maps, player.world_hitbox());
+        if !player_dead && !cutscene_playing {
+            for trigger in entered.iter().filter(|trigger| calendar.structure_open(&trigger.structure_id)) {
+                let mut ctx = InteractContext {
+                    structure_id: &trigger.structure_id,
+                    area: trigger.group_rect,
+                    player: &mut player,
+                    map: &mut maps,
+                    events: &mut interact_events,
+                };
+                interact_registry.execute(&trigger.on_trigger, &mut ctx);
+            }
+        }
+        for event in interact_events.drain(..) {
+            match event {
+                InteractEvent::OpenContractBoard { area } => {
+                    npc_panel.close();
+                    upgrade_panel.close();
+                    contract_board.open(area);
+                }
+                InteractEvent::OpenUpgrades { station, area } => {
+                    npc_panel.close();
+                    upgrade_panel.open(&station, area);
+                }
+                InteractEvent::OpenStall { area } => {
+                    upgrade_panel.close();
+                    if let Some(vendor) = stalls.vendor_at(area, &entities) {
+                        npc_panel.open(vendor);
+                    }
+                }
+                InteractEvent::Healed { amount } => {
+                    let pos = player.world_hitbox().center() - vec2(0.0, TILE_SIZE);
+                    labels.spawn(&format!("+{amount:.0} HP"), pos, LabelStyle::Heal);
+                }
+                InteractEvent::TroughFilled { area, stock, capacity } => {
+                    let pos = area.center() - vec2(0.0, TILE_SIZE);
+                    labels.spawn(&format!("Feed {stock:.0}/{capacity:.0}"), pos, LabelStyle::Item);
+                }
+                InteractEvent::SetRespawn => {
+                    respawn.set(player.position());
+                    let pos = player.world_hitbox().center() - vec2(0.0, TILE_SIZE);
+                    labels.spawn("Respawn point set", pos, LabelStyle::Discovery);
+                }
+                InteractEvent::StartEncounter { structure_id, area } => {
+                    let encounter = structure_defs
+                        .iter()
+                        .find(|def| def.id == structure_id)
+                        .and_then(|def| def.encounter.as_ref());
+                    if let Some(encounter) = encounter {
+                        let wave = spawner.start_encounter(&structure_id, area, encounter, world_time.day(), &maps);
+                        if !wave.is_empty() {
+                            labels.spawn("Ambush!", area.center() - vec2(0.0, TILE_SIZE), LabelStyle::Discovery);
+                        }
+                        waiting_spawns.extend(wave);
+                    }
+                }
+                InteractEvent::Sleep => {
+                    let pos = player.world_hitbox().center() - vec2(0.0, TILE_SIZE * 2.0);
+                    if world_time.is_night() {
+                        world_time.skip_to_morning();
+                        labels.spawn("Slept until morning", pos, LabelStyle::Discovery);
+                    } else {
+                        labels.spawn("Not tired yet", pos, LabelStyle::Discovery);
+                    }
+                }
+            }
+        }
+        if !cutscene_playing {
+            world_time.update(dt);
+        }
+        if let Some(spawns) = calendar.update(world_time.day(), player.position(), dt) {
+            waiting_spawns.extend(spawns);
+            npc_panel.set_event_stock(calendar.shop_offers());
+        }
+        contract_board.restock_daily(world_time.day(), &item_db, &npc_names);
+        contract_board.update(&mut inventory, &mut relationships, player.position(), dt);
+        npc_panel.update(&entities, &db, player.position(), dt);
+        npc_panel.handle_input(
+            world_time.day(),
+            &entities,
+            &db,
+            &mut relationships,
+            &mut contract_board,
+            &mut inventory,
+        );
+        if upgrade_panel.update(&upgrade_db, &mut upgrades, &mut contract_board, &mut inventory, player.position(), dt) {
+            upgrades.apply(&upgrade_db, &mut player, &mut capture_net);
+            sounds.play("pickup");
+        }
+        // Dead, watching a cutscene or busy with a panel: the net, taming and farming keys are ignored.
+        let world_input_blocked = player_dead
+            || cutscene_playing
+            || editor.is_active()
+            || contract_board.is_open()
+            || npc_panel.is_open()
+            || upgrade_panel.is_open()
+            || load_menu.is_open();
+        capture_net.update(dt);
+        let capture_event = if world_input_blocked {
+            None
+        } else {
+            capture_net.handle_input(&mut entities, &db, &mut inventory, player_pos, player.facing())
+        };
+        match capture_event {
+            Some(CaptureEvent::Hit { pos }) => {
+                sounds.play("hurt");
+                particles.burst("near_miss_spark", pos);
+            }
+            Some(CaptureEvent::Captured { handle, pos }) => {
+                entities.remove(handle);
+                entity_target_cache.clear();
+                particles.burst("launch_dust", pos);
+                sounds.play("pickup");
+            }
+            Some(CaptureEvent::Released { entity, pos }) => match Entity::spawn(&db, &entity, pos, &registry) {
+                Some(mut ent) => {
+                    world.apply_tier(&mut ent.instance, spawn_origin);
+                    ent.instance.tamed = true;
+                    entities.insert(ent);
+                    particles.burst("launch_dust", pos);
+                }
+                None => eprintln!("crated entity '{entity}' has no definition"),
+            },
+            None => {}
+        }
+        taming.update(dt);
+        let pet_event = if world_input_blocked {
+            None
+        } else {
+            taming.handle_input(&mut entities, &db, &mut inventory, player_pos)
+        };
+        match pet_event {
+            Some(PetEvent::Tamed { pos }) => {
+                entity_target_cache.clear();
+                particles.burst("launch_dust", pos);
+                sounds.play("pickup");
+            }
+            Some(PetEvent::Refused { pos }) => particles.burst("launch_dust", pos),
+            None => {}
+        }
+        farm.update(&mut maps, &world_time, dt);
+        let farm_event = if world_input_blocked {
+            None
+        } else {
+            farm.handle_input(&mut maps, &mut inventory, player_pos, player.facing(), &world_time)
+        };
+        match farm_event {
+            Some(FarmEvent::Tilled { pos }) | Some(FarmEvent::Fertilized { pos }) => particles.burst("launch_dust", pos),
+            Some(FarmEvent::Watered { pos }) => particles.burst("splash", pos),
+            Some(FarmEvent::Planted { pos }) => {
+                particles.burst("grass_clippings", pos);
+                sounds.play("pickup");
+            }
+            Some(FarmEvent::Harvested { item, count, pos }) => {
+                world_items.drop_item(&item, count, pos);
+                labels.spawn(&item_label(&item, count), pos, LabelStyle::Item);
+                particles.burst("grass_clippings", pos);
+            }
+            None => {}
         }
 
         let mut entity_targets = Vec::with_capacity(entities.len());
-        for ent in &entities {
+        for (handle, ent) in entities.iter_with_handles() {
             let def = &db.entities[ent.instance.def];
             entity_targets.push(entity::EntityTarget {
                 id: ent.instance.uid,
+                handle,
                 def: ent.instance.def,
                 kind: def.kind,
+                layer: def.collision_layer,
                 pos: ent.instance.pos,
+                vel: ent.instance.vel,
                 hitbox: ent.hitbox(&db),
                 alive: ent.instance.hp > 0.0,
             });
@@ -402,61 +1101,218 @@ async fn main() {
 
         damage_events.clear();
         let mut ctx = EntityContext {
+            map: &maps,
             player: if player_dead || player.hp() <= 0.0 {
                 None
             } else {
                 Some(PlayerTarget {
                     pos: player.position(),
                     hitbox: player.world_hitbox(),
+                    dashing: player.is_dashing(),
+                    parrying: player.is_parrying(),
                 })
             },
             target: None,
+            grid: SpatialGrid::build(entity_targets.iter().map(|target| target.pos)),
             entities: entity_targets,
             target_cache: std::mem::take(&mut entity_target_cache),
             view_height: CAMERA_FOV,
             damage_events: Vec::new(),
+            night: world_time.is_night(),
+            player_attacker,
+            crops: farm.pollination_targets(world_time.day()),
         };
 
         let mut ent_idx = 0usize;
-        while ent_idx < entities.len() {
-            entities[ent_idx].update(dt, &db, &mut ctx, &maps, &registry);
-            entities[ent_idx].clamp_to_map(&maps, &db);
+        while ent_idx < entities.len() && !cutscene_playing {
+            if entities[ent_idx].instance.is_corpse() {
+                ent_idx += 1;
+                continue;
+            }
+            let asleep = simulation.is_asleep(entities[ent_idx].instance.pos, view_rect);
+            entities[ent_idx].instance.asleep = asleep;
+            if asleep {
+                entities[ent_idx].instance.sleep(dt);
+            } else {
+                entities[ent_idx].update(dt, &db, &mut ctx, &maps, &registry);
+                entities[ent_idx].clamp_to_map(&maps, &db);
+            }
             ent_idx += 1;
         }
         resolve_entity_overlaps(&mut entities, &db, &maps);
+        // Introduce an entity the first time one comes into view.
+        if !cutscene_playing {
+            let intro = entities.iter().find_map(|ent| {
+                let id = db.entities[ent.instance.def].intro_cutscene.as_deref()?;
+                let visible = !ent.instance.is_corpse() && view_rect.contains(ent.instance.pos);
+                (visible && !cutscene.has_played(id)).then_some((id, ent.instance.pos))
+            });
+            if let Some((id, pos)) = intro
+                && let Some(def) = cutscenes.get(id)
+            {
+                cutscene.play(def, pos, camera.target);
+            }
+        }
         damage_events.extend(ctx.damage_events.drain(..));
+
+        // Troughs and structures live on the map, which the entity context still borrows; eat and
+        // harvest afterwards.
+        let mut meals: Vec<(usize, Vec2, f32)> = Vec::new();
+        let mut summons: Vec<(u64, SummonRequest)> = Vec::new();
+        let mut harvests: Vec<(usize, usize)> = Vec::new();
+        for (ent_idx, ent) in entities.iter_mut().enumerate() {
+            let kind = db.entities[ent.instance.def].kind;
+            let owner = ProjectileOwner::Entity {
+                uid: ent.instance.uid,
+                kind,
+            };
+            for request in ent.instance.requests.drain(..) {
+                match request {
+                    EntityRequest::SpawnHazard { id, pos } => {
+                        hazards.spawn(&id, pos, Some(kind), &particles);
+                    }
+                    EntityRequest::SpawnProjectile { id, pos, dir } => {
+                        projectiles.spawn(&id, pos, dir, owner, &particles);
+                    }
+                    EntityRequest::LaunchImpact { pos } => {
+                        sounds.play("hurt");
+                        particles.burst("launch_dust", pos);
+                    }
+                    EntityRequest::Landed { pos } => {
+                        particles.burst("launch_dust", pos);
+                    }
+                    EntityRequest::DropItem { id, pos } => {
+                        world_items.drop_item(&id, 1, pos);
+                    }
+                    EntityRequest::Eat { pos, amount } => {
+                        meals.push((ent_idx, pos, amount));
+                    }
+                    EntityRequest::Burst { id, pos } => {
+                        particles.burst(&id, pos);
+                    }
+                    EntityRequest::Summon(summon) => {
+                        summons.push((ent.instance.uid, summon));
+                    }
+                    EntityRequest::Harvest { tile } => {
+                        harvests.push(tile);
+                    }
+                    EntityRequest::Pollinate { tile } => {
+                        if farm.pollinate(tile, &world_time) {
+                            let tile_size = maps.tile_size();
+                            let pos = vec2((tile.0 as f32 + 0.5) * tile_size, (tile.1 as f32 + 0.5) * tile_size);
+                            particles.burst("grass_clippings", pos);
+                        }
+                    }
+                }
+            }
+        }
+        projectile_events.clear();
+        if !cutscene_playing {
+            hazards.update(dt, ctx.player, &ctx.entities, &mut particles, &mut damage_events);
+            projectiles.update(
+                dt,
+                ProjectileTargets {
+                    player: ctx.player,
+                    entities: &ctx.entities,
+                },
+                &maps,
+                &mut particles,
+                &mut damage_events,
+                &mut projectile_events,
+            );
+            for (id, pos, owner) in projectiles.take_impact_hazards() {
+                hazards.spawn(&id, pos, owner, &particles);
+            }
+        }
+        for event in &projectile_events {
+            match *event {
+                ProjectileEvent::NearMiss { pos } => {
+                    sounds.play("near_miss");
+                    particles.burst("near_miss_spark", pos);
+                }
+                ProjectileEvent::PerfectDodge { pos } => {
+                    sounds.play("perfect_dodge");
+                    particles.burst("perfect_dodge_flash", pos);
+                }
+                ProjectileEvent::Parried { pos } => {
+                    sounds.play("parry");
+                    particles.burst("perfect_dodge_flash", pos);
+                }
+                ProjectileEvent::Reflected { pos } => {
+                    particles.burst("near_miss_spark", pos);
+                }
+            }
+        }
         entity_target_cache = std::mem::take(&mut ctx.target_cache);
+        for (ent_idx, pos, amount) in meals {
+            let Some(hunger) = entities[ent_idx].instance.hunger.as_mut() else {
+                continue;
+            };
+            hunger.eat(maps.take_feed(pos, amount.min(hunger.appetite())));
+        }
+        for (x, y) in harvests {
+            maps.remove_structure_at(x, y);
+        }
+        for (parent, summon) in summons {
+            let children = entities
+                .iter()
+                .filter(|ent| {
+                    !ent.instance.is_corpse() && ent.instance.parent.is_some_and(|link| link.uid == parent)
+                })
+                .count();
+            if summon.max > 0 && children >= summon.max as usize {
+                continue;
+            }
+            match Entity::spawn(&db, &summon.id, summon.pos, &registry) {
+                Some(mut ent) => {
+                    world.apply_tier(&mut ent.instance, spawn_origin);
+                    ent.instance.parent = Some(ParentLink {
+                        uid: parent,
+                        on_death: summon.on_parent_death,
+                    });
+                    entities.insert(ent);
+                }
+                None => eprintln!("summoned entity '{}' has no definition", summon.id),
+            }
+        }
 
         for ent in entities.iter_mut() {
             let def = &db.entities[ent.instance.def];
             let render_origin = ent.instance.pos + def.texture.draw.offset;
-            let size = def
-                .texture
-                .draw
-                .dest_size
-                .unwrap_or_else(|| def.texture.texture.size());
+            let size = def.texture.size();
             let pos = render_origin + size * 0.5;
+            // Only entities near the camera call out, and only while the world runs.
+            if !cutscene_playing && !ent.instance.is_corpse() && view_rect.contains(pos)
+                && let Some(sound) = ent.instance.tick_vocals(def, dt)
+            {
+                sounds.play_at(sound, pos, camera.target);
+            }
             if ent.instance.is_dashing() {
-                if ent.instance.dash_trail.is_none() {
-                    ent.instance.dash_trail = particles.emitter("dash_afterimage", pos);
-                }
+                let terrain = maps.tile_properties_at(ent.instance.pos).terrain.as_deref();
+                particles.retarget_emitter(&mut ent.instance.dash_trail, world.dash_particles(terrain), pos);
                 if let Some(emitter) = ent.instance.dash_trail.as_mut() {
                     particles.update_emitter_with_texture(
                         emitter,
                         pos,
                         dt,
-                        Some(&def.texture.texture),
+                        // Afterimages copy the whole texture, which for a sprite sheet is every frame.
+                        Some(&def.texture.texture).filter(|_| def.texture.sheet.is_none()),
                         Some(size),
                     );
                 }
             } else if let Some(emitter) = ent.instance.dash_trail.as_mut() {
                 particles.track_emitter(emitter, pos);
             }
-        }
-
-        let mut entity_index_by_uid = HashMap::with_capacity(entities.len());
-        for (idx, ent) in entities.iter().enumerate() {
-            entity_index_by_uid.insert(ent.instance.uid, idx);
+            if ent.instance.swimming && ent.instance.vel.length_squared() > 0.0 {
+                if ent.instance.ripple_trail.is_none() {
+                    ent.instance.ripple_trail = particles.emitter("ripple", pos);
+                }
+                if let Some(emitter) = ent.instance.ripple_trail.as_mut() {
+                    particles.update_emitter(emitter, pos, dt);
+                }
+            } else if let Some(emitter) = ent.instance.ripple_trail.as_mut() {
+                particles.track_emitter(emitter, pos);
+            }
         }
 
         for event in &damage_events {
@@ -465,29 +1321,104 @@ async fn main() {
                     if event.amount > 0.0 {
                         sounds.play("hurt2");
                     }
+                    if event.source.is_some() {
+                        player_attacker = event.source;
+                    }
+                    let before = player.hp();
                     player.apply_damage(event.amount);
+                    player.apply_knockback(event.knockback);
+                    director.record_damage(before - player.hp());
                 }
                 Target::Entity(target) => {
-                    if let Some(&ent_idx) = entity_index_by_uid.get(&target.id) {
-                        let ent = &mut entities[ent_idx];
+                    if let Some(ent) = entities.get_mut(target.handle) {
+                        if ent.instance.is_corpse() {
+                            continue;
+                        }
                         if event.amount > 0.0 {
                             sounds.play("hurt");
                         }
                         ent.instance.apply_damage(event.amount);
+                        let knockback = &db.entities[ent.instance.def].knockback;
+                        ent.instance.apply_knockback(event.knockback, knockback);
                     }
                 }
                 Target::Position(_) => {}
             }
         }
-        entities.retain(|ent| ent.instance.hp > 0.0);
+        let mut fallen: Vec<u64> = Vec::new();
+        for ent in entities.iter_mut() {
+            if ent.instance.hp > 0.0 || ent.instance.is_corpse() {
+                continue;
+            }
+            let def = &db.entities[ent.instance.def];
+            ent.instance.begin_corpse(def.death.corpse_time);
+            fallen.push(ent.instance.uid);
+            if def.kind == entity::EntityKind::Enemy {
+                director.record_kill();
+            }
+            statistics.record_kill(&def.id);
+            let center = ent.hitbox(&db).center();
+            if let Some(particle) = def.death.particle.as_deref() {
+                particles.burst(particle, center);
+            }
+            if let Some(sound) = def.death.sound.as_deref() {
+                sounds.play_at(sound, center, camera.target);
+            }
+            for drop in &def.death.drops {
+                let rolled = drop.roll();
+                if rolled == 0 {
+                    continue;
+                }
+                // Higher tiers add copies on top of what was rolled, and may upgrade the item.
+                let count = rolled + world.loot_count(ent.instance.tier) - 1;
+                let item = world.loot_item(&drop.item, ent.instance.tier);
+                world_items.drop_item(item, count, center);
+                statistics.record_drop(&def.id, item);
+                labels.spawn(&item_label(item, count), center, LabelStyle::Item);
+            }
+        }
+        // Children of the fallen despawn or enrage; despawned ones leave no drops.
+        if !fallen.is_empty() {
+            for ent in entities.iter_mut() {
+                if ent.instance.parent.is_some_and(|link| fallen.contains(&link.uid))
+                    && !ent.instance.is_corpse()
+                    && ent.instance.orphan()
+                {
+                    ent.instance.hp = 0.0;
+                    ent.instance.begin_corpse(db.entities[ent.instance.def].death.corpse_time);
+                }
+            }
+        }
+        entities.retain_mut(|ent| ent.instance.tick_corpse(&db, dt));
         if !player_dead && player.hp() <= 0.0 {
             player_dead = true;
+            director.record_death();
+        }
+        if respawn.update(player_dead, dt) {
+            let point = respawn.point();
+            player.restore(point, player.max_hp(), player.max_hp());
+            player_dead = false;
+            camera.target = point;
+        }
+        if !player_dead && world_items.collect_overlapping(player.world_hitbox(), &mut inventory) > 0 {
+            sounds.play("pickup");
         }
 
         let dashing = !player_dead && player.is_dashing();
         let moving = !player_dead && player.is_moving(MOVE_DEADZONE) && !dashing;
+        let swimming = !player_dead && player.is_swimming();
+        let terrain = maps.tile_properties_at(player.position()).terrain.as_deref();
+        particles.retarget_emitter(&mut walk_trail, world.walk_particles(terrain), player.position());
+        particles.retarget_emitter(&mut dash_trail, world.dash_particles(terrain), player.position());
         if let Some(emitter) = walk_trail.as_mut() {
-            if moving {
+            if moving && !swimming {
+                particles.update_emitter(emitter, player.position(), dt);
+            } else {
+                particles.track_emitter(emitter, player.position());
+            }
+        }
+        if let Some(emitter) = swim_ripples.as_mut() {
+            if moving && swimming {
                 particles.update_emitter(emitter, player.position(), dt);
             } else {
                 particles.track_emitter(emitter, player.position());
@@ -495,28 +1426,22 @@ async fn main() {
         }
 
         if let Some(emitter) = dash_trail.as_mut() {
+            let (frame, frame_size) = player.sprite_frame();
+            let center = player.position() - Vec2::new(0.0, frame_size.y * 0.5);
             if dashing {
-                particles.update_emitter_with_texture(
-                    emitter,
-                    player.position() - Vec2::new(0.0, player.texture.size().y / 8.0),
-                    dt,
-                    Some(&player.texture),
-                    Some(player.texture.size() * 0.25),
-                );
+                particles.update_emitter_with_texture(emitter, center, dt, Some(frame), Some(frame_size));
             } else {
-                particles.track_emitter(
-                    emitter,
-                    player.position() - Vec2::new(0.0, player.texture.size().y / 8.0),
-                );
+                particles.track_emitter(emitter, center);
             }
         }
 
         particles.update(dt);
 
-        if moving {
+        if moving && !swimming {
             footstep_timer -= dt;
             if footstep_timer <= 0.0 {
-                sounds.play("footstep");
+                let ground = maps.tile_properties_at(player.position());
+                sounds.play(ground.footstep_sound.as_deref().unwrap_or("footstep"));
                 footstep_timer = FOOTSTEP_INTERVAL;
             }
         } else {
@@ -541,34 +1466,49 @@ async fn main() {
             screen_height(),
         );
 
-        let cull_rect = expand_rect(view_rect, ENTITY_CULL_FADE_PAD);
+        let view = ViewCull::new(view_rect, ENTITY_CULL_FADE_PAD);
 
-        particles.draw_in_rect(cull_rect);
+        world_items.draw_in_view(&item_db, &view);
+        hazards.draw_in_view(&view);
+        pings.draw_in_view(&view);
+        projectiles.draw_in_view(&view);
+        particles.draw_in_view(ParticleLayer::BelowEntities, &view);
+        ambient_life.draw_in_view(&view);
 
+        y_sort.begin(&maps, view.bounds());
         if !player_dead {
-            player.draw();
+            y_sort.push(player.world_hitbox().bottom(), Sorted::Player);
         }
-        if !entities.is_empty() {
-            draw_order.clear();
-            for (idx, ent) in entities.iter().enumerate() {
-                let hb = ent.hitbox(&db);
-                if offscreen_fade_alpha(hb, view_rect, ENTITY_CULL_FADE_PAD) > 0.0 {
-                    draw_order.push(idx);
-                }
-            }
-            if draw_order.len() > 1 {
-                draw_order.sort_unstable_by_key(|&idx| entities[idx].instance.def);
+        draw_order.clear();
+        for (idx, ent) in entities.iter().enumerate() {
+            let hitbox = ent.hitbox(&db);
+            if view.fade_alpha(hitbox) > 0.0 {
+                draw_order.push(idx);
+                y_sort.push(hitbox.bottom(), Sorted::Entity(idx));
             }
-            for &idx in &draw_order {
-                let alpha = offscreen_fade_alpha(
-                    entities[idx].hitbox(&db),
-                    view_rect,
-                    ENTITY_CULL_FADE_PAD,
-                );
-                entities[idx].draw_with_alpha(&db, alpha);
+        }
+        for item in y_sort.sorted() {
+            match item {
+                Sorted::Tile(tile) => maps.draw_sorted_tile(&tileset, &tile),
+                Sorted::Entity(idx) => {
+                    let alpha = view.fade_alpha(entities[idx].hitbox(&db));
+                    entities[idx].draw_with_alpha(&db, alpha);
+                }
+                Sorted::Player => player.draw(),
             }
         }
-
+        particles.draw_in_view(ParticleLayer::AboveEntities, &view);
+        ability::draw_bars(&entities, &db, &view);
+
+        let mut canopy_occupants = vec![player.world_hitbox()];
+        canopy_occupants.extend(
+            draw_order
+                .iter()
+                .filter_map(|&idx| entities[..].get(idx))
+                .filter(|ent| !ent.instance.is_corpse())
+                .map(|ent| ent.hitbox(&db)),
+        );
+        maps.update_canopy_fade(&canopy_occupants, dt);
         maps.draw_overlay(
             &tileset,
             camera.target,
@@ -576,26 +1516,57 @@ async fn main() {
             screen_width(),
             screen_height(),
         );
-
-        if let Some(interactor) = hovered_interactor.as_ref() {
-            draw_rectangle(
-                interactor.group_rect.x,
-                interactor.group_rect.y,
-                interactor.group_rect.w,
-                interactor.group_rect.h,
-                Color::new(1.0, 0.95, 0.2, 0.2),
-            );
-            draw_rectangle_lines(
-                interactor.group_rect.x,
-                interactor.group_rect.y,
-                interactor.group_rect.w,
-                interactor.group_rect.h,
-                1.0,
-                Color::new(1.0, 0.95, 0.2, 0.95),
+        particles.draw_in_view(ParticleLayer::AboveOverlay, &view);
+        // Creative mode builds with the whole map in view.
+        if let Some(fog) = world.fog.as_ref().filter(|_| !editor.is_creative()) {
+            maps.draw_fog(expand_rect(camera_visible_rect(&camera), TILE_SIZE), fog.color());
+        }
+        world_time.draw_tint(expand_rect(camera_visible_rect(&camera), TILE_SIZE));
+
+        if let Some(interactor) = hovered_interactor.as_ref().or(auto_interactor.as_ref()) {
+            draw_interact_highlight(
+                interactor,
+                maps.is_interactor_occluded(interactor),
+                get_time() as f32,
+                &highlight_icons,
             );
         }
+        if let Some(interactor) = auto_interactor.as_ref() {
+            draw_selector_arrow(interactor.group_rect, get_time() as f32);
+        }
+        if let Some((area, progress)) = hold_interaction.progress() {
+            draw_hold_progress(area, progress);
+        }
+        editor.draw_world(&maps, &tileset, mouse_world);
+        entity_debug.draw_world(&entities, &db, CAMERA_FOV, &camera, &mut hud);
+        debug_tools.draw_collision(&maps, &camera, &entities, &db, player.world_hitbox(), &mut hud);
+        minimap.update(&mut maps, dt);
 
         set_default_camera();
+        if save_requested {
+            save_requested = false;
+            // The world is drawn but the HUD isn't yet, so the picture is just the scene.
+            let frame = if use_render_target {
+                scene_target.texture.get_texture_data()
+            } else {
+                get_screen_data()
+            };
+            let save = SaveFile::capture(&maps, &player, &inventory, &entities, &db, &contract_board, &world_time)
+                .with_relationships(&relationships)
+                .with_cutscenes(cutscene.played())
+                .with_farm(&farm)
+                .with_respawn(&respawn)
+                .with_statistics(&statistics)
+                .with_upgrades(&upgrades)
+                .with_dormant(&simulation)
+                .with_seed((!authored_level).then_some(world.worldgen.seed))
+                .with_thumbnail(SaveThumbnail::from_frame(&frame));
+            match save.write(SAVE_PATH) {
+                Ok(()) => eprintln!("saved game to {SAVE_PATH}"),
+                Err(err) => eprintln!("save failed: {err}"),
+            }
+        }
+        labels.draw(&camera, &view, player.position(), &mut hud);
         if use_render_target {
             draw_texture_ex(
                 &scene_target.texture,
@@ -609,6 +1580,10 @@ async fn main() {
                 },
             );
         }
+        particles.draw_in_view(
+            ParticleLayer::Screen,
+            &ViewCull::new(Rect::new(0.0, 0.0, screen_width(), screen_height()), 0.0),
+        );
 
         draw_player_health(
             player.hp(),
@@ -616,25 +1591,64 @@ async fn main() {
             CAMERA_FOV,
             &heart_full,
             &heart_empty,
+            &mut hud,
         );
+        region_banner.draw(&mut hud);
+        respawn.draw_ui(&mut hud);
+        contract_board.draw_hud(&world_time, &mut hud);
+        calendar.draw_ui(&mut hud);
+        capture_net.draw_ui(&inventory, &mut hud);
+        taming.draw_ui(&mut hud);
+        upgrade::draw_dash_charges(&player, &mut hud);
+        farm.draw_ui(&mut hud);
+        if (inventory.has(MONOCLE_ITEM) || entity_debug.is_active())
+            && !entity_editor.wants_mouse()
+            && let Some((_, ent)) = tooltip::entity_under(&entities, &db, mouse_world)
+            && !ent.instance.is_corpse()
+        {
+            tooltip::draw_entity_tooltip(ent, &db, &statistics, vec2(mouse_screen.0, mouse_screen.1), &mut hud);
+        }
 
         i += get_frame_time();
         if i >= 1.0 {
             fps = get_fps();
             i = 0.0;
         } 
-        draw_text(
-            &format!("FPS: {:.0}", fps),
-            20.0,
-            40.0,
-            30.0, // font size
-            WHITE
-        );
+        if debug_tools.fps_visible() {
+            hud.text(UiLayer::Debug, format!("FPS: {:.0}", fps), vec2(20.0, 40.0), 30.0, WHITE);
+        }
+        contract_board.draw_ui(world_time.day(), &item_db, &inventory, &mut hud);
+        npc_panel.draw_ui(&entities, &db, &item_db, &relationships, &mut hud);
+        upgrade_panel.draw_ui(&upgrade_db, &upgrades, &contract_board, &inventory, &mut hud);
+        entity_editor.draw_ui(&db, &mut hud);
+        entity_debug.draw_ui(&mut hud);
+        load_menu.draw_ui(&mut hud);
+        hud.flush();
+
+        if !cutscene_playing {
+            let fog = world.fog.as_ref().filter(|_| !editor.is_creative()).map(FogSettings::color);
+            minimap.draw(&maps, player.position(), &entities, &db, &pings, fog);
+        }
+        cutscene.draw_ui();
+        editor.draw_ui(&tileset);
 
         next_frame().await;
     }
 }
 
+/// Where the player enters a level reached through `edge` of the previous one: just inside the
+/// opposite edge, as far along it as they were along the edge they left by.
+fn arrival_position(edge: MapEdge, pos: Vec2, from: Rect, to: Rect) -> Vec2 {
+    let along = ((pos - from.point()) / from.size().max(Vec2::ONE)).clamp(Vec2::ZERO, Vec2::ONE);
+    let inside = to.point() + along * to.size();
+    match edge {
+        MapEdge::West => vec2(to.right() - ARRIVAL_INSET, inside.y),
+        MapEdge::East => vec2(to.left() + ARRIVAL_INSET, inside.y),
+        MapEdge::North => vec2(inside.x, to.bottom() - ARRIVAL_INSET),
+        MapEdge::South => vec2(inside.x, to.top() + ARRIVAL_INSET),
+    }
+}
+
 fn camera_zoom_for_fov(view_height: f32, render_target: bool) -> Vec2 {
     let view_h = view_height.max(1.0);
     let aspect = screen_width().max(1.0) / screen_height().max(1.0);
@@ -653,12 +1667,14 @@ fn camera_view_rect_logic(target: Vec2, view_height: f32) -> Rect {
     )
 }
 
-fn expand_rect(rect: Rect, pad: f32) -> Rect {
+/// Everything the camera shows, unlike `camera_view_rect_logic` which is square.
+fn camera_visible_rect(camera: &Camera2D) -> Rect {
+    let half = vec2(1.0 / camera.zoom.x.abs(), 1.0 / camera.zoom.y.abs());
     Rect::new(
-        rect.x - pad,
-        rect.y - pad,
-        rect.w + pad * 2.0,
-        rect.h + pad * 2.0,
+        camera.target.x - half.x,
+        camera.target.y - half.y,
+        half.x * 2.0,
+        half.y * 2.0,
     )
 }
 
@@ -685,23 +1701,6 @@ fn particle_budget_scale(screen_w: f32, screen_h: f32, render_scale: f32) -> f32
     (base_area / area).clamp(0.35, 1.0)
 }
 
-fn offscreen_fade_alpha(hitbox: Rect, view_rect: Rect, fade_pad: f32) -> f32 {
-    if hitbox.overlaps(&view_rect) {
-        return 1.0;
-    }
-    let expanded = expand_rect(view_rect, fade_pad.max(1.0));
-    if !hitbox.overlaps(&expanded) {
-        return 0.0;
-    }
-
-    let cx = hitbox.x + hitbox.w * 0.5;
-    let cy = hitbox.y + hitbox.h * 0.5;
-    let nearest_x = cx.clamp(view_rect.x, view_rect.x + view_rect.w);
-    let nearest_y = cy.clamp(view_rect.y, view_rect.y + view_rect.h);
-    let distance = vec2(cx - nearest_x, cy - nearest_y).length();
-    (1.0 - distance / fade_pad.max(1.0)).clamp(0.0, 1.0)
-}
-
 fn point_in_rect(point: Vec2, rect: Rect) -> bool {
     point.x >= rect.x
         && point.y >= rect.y
@@ -720,6 +1719,39 @@ fn interactor_in_range(player_pos: Vec2, area: Rect, range_world: f32) -> bool {
     player_pos.distance(nearest) <= range_world
 }
 
+/// Picks the interactor the player most likely means: close by and roughly in front of them.
+fn auto_target_interactor<'a>(
+    interactors: impl Iterator<Item = &'a StructureInteractor>,
+    player_pos: Vec2,
+    facing: Vec2,
+) -> Option<&'a StructureInteractor> {
+    let facing = facing.normalize_or_zero();
+    interactors
+        .filter_map(|interactor| {
+            let area = interactor.group_rect;
+            let range = if interactor.interact_range_world > 0.0 {
+                interactor.interact_range_world
+            } else {
+                AUTO_INTERACT_RANGE
+            };
+            let nearest = vec2(
+                player_pos.x.clamp(area.x, area.x + area.w),
+                player_pos.y.clamp(area.y, area.y + area.h),
+            );
+            let dist = player_pos.distance(nearest);
+            if dist > range {
+                return None;
+            }
+            // Facing scales the distance between half (straight ahead) and 1.5x (behind); the
+            // bare alignment term breaks ties when the player stands inside several areas.
+            let alignment = facing.dot((area.center() - player_pos).normalize_or_zero());
+            let score = dist * (1.0 - 0.5 * alignment) - alignment;
+            Some((score, interactor))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, interactor)| interactor)
+}
+
 fn resolve_entity_overlaps(entities: &mut [Entity], db: &EntityDatabase, map: &TileMap) {
     if entities.len() < 2 {
         return;
@@ -765,6 +1797,12 @@ fn resolve_entity_overlaps(entities: &mut [Entity], db: &EntityDatabase, map: &T
                         if j <= i {
                             continue;
                         }
+                        if entities[i].instance.is_corpse() || entities[j].instance.is_corpse() {
+                            continue;
+                        }
+                        if entities[i].instance.asleep || entities[j].instance.asleep {
+                            continue;
+                        }
                         if overlap_marks[j] == overlap_stamp {
                             continue;
                         }
@@ -832,26 +1870,180 @@ fn rect_cell_range(rect: Rect, cell_size: f32) -> (i32, i32, i32, i32) {
     (min_cx, max_cx, min_cy, max_cy)
 }
 
+/// Both entities have to collide with the other's layer to be pushed apart.
 fn entities_should_collide(db: &EntityDatabase, a_def_idx: usize, b_def_idx: usize) -> bool {
-    let a_flags = db.entities[a_def_idx].flags;
-    let b_flags = db.entities[b_def_idx].flags;
-    if (a_flags & entity::DEF_FLAG_NO_ENTITY_COLLISION) != 0
-        || (b_flags & entity::DEF_FLAG_NO_ENTITY_COLLISION) != 0
-    {
-        return false;
+    let a = &db.entities[a_def_idx];
+    let b = &db.entities[b_def_idx];
+    a.collides_with.contains(b.collision_layer) && b.collides_with.contains(a.collision_layer)
+}
+
+/// `--level <path.tmx>` on native builds.
+fn level_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--level" {
+            return args.next();
+        }
     }
+    None
+}
 
-    let a_kind = db.entities[a_def_idx].kind;
-    let b_kind = db.entities[b_def_idx].kind;
-    !blocks_kind(db, a_def_idx, b_kind) && !blocks_kind(db, b_def_idx, a_kind)
+/// Prints every problem `EntityDatabase::validate` finds.
+fn report_entity_issues(db: &EntityDatabase, registry: &MovementRegistry) {
+    let issues = db.validate(registry);
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+    if !issues.is_empty() {
+        eprintln!("{} problem(s) with entity definitions", issues.len());
+    }
 }
 
-fn blocks_kind(db: &EntityDatabase, def_idx: usize, kind: entity::EntityKind) -> bool {
-    let flags = db.entities[def_idx].flags;
-    match kind {
-        entity::EntityKind::Enemy => (flags & entity::DEF_FLAG_NO_ENEMY_COLLISION) != 0,
-        entity::EntityKind::Friend => (flags & entity::DEF_FLAG_NO_FRIEND_COLLISION) != 0,
-        entity::EntityKind::Misc => (flags & entity::DEF_FLAG_NO_MISC_COLLISION) != 0,
+/// Watches the tileset, every entity sprite and every particle texture.
+fn watch_textures(tileset: &TileSet, db: &EntityDatabase, particles: &ParticleSystem) -> TextureWatcher {
+    let mut watcher = TextureWatcher::default();
+    watcher.watch(tileset.texture_path(), tileset.texture());
+    for def in &db.entities {
+        for (path, texture) in &def.texture.files {
+            watcher.watch(path, texture);
+        }
+    }
+    for (path, texture) in particles.texture_files() {
+        watcher.watch(path, texture);
+    }
+    watcher
+}
+
+async fn load_highlight_icons(structures: &[StructureDef]) -> HashMap<String, Texture2D> {
+    let mut icons = HashMap::new();
+    for def in structures {
+        let Some(path) = def.highlight.icon.as_ref() else {
+            continue;
+        };
+        if icons.contains_key(path) {
+            continue;
+        }
+        match load_texture(&helpers::asset_path(path)).await {
+            Ok(texture) => {
+                texture.set_filter(FilterMode::Nearest);
+                icons.insert(path.clone(), texture);
+            }
+            Err(err) => eprintln!("highlight icon '{path}' failed to load: {err}"),
+        }
+    }
+    icons
+}
+
+fn draw_interact_highlight(
+    interactor: &StructureInteractor,
+    occluded: bool,
+    time: f32,
+    icons: &HashMap<String, Texture2D>,
+) {
+    let style = &interactor.highlight;
+    let mut rect = interactor.group_rect;
+    if occluded {
+        match style.occluded {
+            OccludedHighlight::Hide => return,
+            OccludedHighlight::Offset(offset) => rect.y -= offset,
+        }
+    }
+
+    let pulse = if style.pulse > 0.0 {
+        0.65 + 0.35 * (time * style.pulse * std::f32::consts::TAU).sin()
+    } else {
+        1.0
+    };
+    let fill = Color::new(style.color.r, style.color.g, style.color.b, style.fill_alpha);
+    let outline = Color::new(
+        style.color.r,
+        style.color.g,
+        style.color.b,
+        style.outline_alpha * pulse,
+    );
+    draw_rectangle(rect.x, rect.y, rect.w, rect.h, fill);
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, outline);
+
+    if let Some(icon) = style.icon.as_ref().and_then(|path| icons.get(path)) {
+        let size = icon.size();
+        draw_texture(
+            icon,
+            rect.x + (rect.w - size.x) * 0.5,
+            rect.y - size.y - 2.0,
+            WHITE,
+        );
+    }
+}
+
+fn draw_selector_arrow(area: Rect, time: f32) {
+    let bob = (time * 4.0).sin() * 1.5;
+    let tip = vec2(area.center().x, area.y - 3.0 + bob);
+    let color = Color::new(1.0, 0.95, 0.2, 0.95);
+    draw_triangle(tip, tip + vec2(-3.0, -4.0), tip + vec2(3.0, -4.0), color);
+}
+
+fn draw_hold_progress(area: Rect, progress: f32) {
+    let center = area.center();
+    let radius = (area.w.max(area.h) * 0.5 + 4.0).max(6.0);
+    draw_arc(center.x, center.y, 24, radius, -90.0, 2.0, 360.0, Color::new(0.0, 0.0, 0.0, 0.45));
+    draw_arc(
+        center.x,
+        center.y,
+        24,
+        radius,
+        -90.0,
+        2.0,
+        360.0 * progress.clamp(0.0, 1.0),
+        Color::new(1.0, 0.95, 0.2, 0.95),
+    );
+}
+
+const REGION_BANNER_TIME: f32 = 2.5;
+
+/// Names the tagged region the player just walked into, fading out after a moment.
+#[derive(Default)]
+struct RegionBanner {
+    current: Option<String>,
+    text: String,
+    timer: f32,
+}
+
+impl RegionBanner {
+    fn update(&mut self, region: Option<&str>, dt: f32) {
+        self.timer = (self.timer - dt).max(0.0);
+        if self.current.as_deref() == region {
+            return;
+        }
+        self.current = region.map(str::to_string);
+        if let Some(tag) = region {
+            self.text = display_name(tag);
+            self.timer = REGION_BANNER_TIME;
+        }
+    }
+
+    fn draw(&self, ui: &mut UiBatch) {
+        if self.timer <= 0.0 {
+            return;
+        }
+        let alpha = (self.timer / 0.5).min(1.0);
+        let font_size = 32.0;
+        let size = measure_text(&self.text, None, font_size as u16, 1.0);
+        ui.text(
+            UiLayer::Toast,
+            self.text.as_str(),
+            vec2((screen_width() - size.width) * 0.5, screen_height() * 0.2),
+            font_size,
+            Color::new(1.0, 1.0, 1.0, alpha),
+        );
+    }
+}
+
+/// Label over dropped items, e.g. "Honey x2".
+fn item_label(item: &str, count: u32) -> String {
+    if count > 1 {
+        format!("{} x{count}", display_name(item))
+    } else {
+        display_name(item)
     }
 }
 
@@ -861,6 +2053,7 @@ fn draw_player_health(
     view_height: f32,
     heart_full: &Texture2D,
     heart_empty: &Texture2D,
+    ui: &mut UiBatch,
 ) {
     if max_hp <= 0.0 {
         return;
@@ -900,16 +2093,7 @@ fn draw_player_health(
                 heart_empty
             };
             let x = start_x + i as f32 * step_x;
-            draw_texture_ex(
-                tex,
-                x,
-                y,
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(vec2(heart_w, heart_h)),
-                    ..Default::default()
-                },
-            );
+            ui.texture(UiLayer::Hud, tex, Rect::new(x, y, heart_w, heart_h), WHITE);
         }
     }
 }
@@ -1,11 +1,44 @@
 use macroquad::prelude::*;
-use macroquad::file::load_string;
-use serde::Deserialize;
+use crate::asset_pack::{load_string, load_texture};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use crate::autotile::{AutotileRules, NO_TERRAIN, TerrainId};
 use crate::helpers::{asset_path, data_path, load_wasm_manifest_files};
+use crate::tmx::{self, TmxError, TmxObject};
+use crate::time::SpawnTime;
+use crate::worldgen::{Biome, WorldGen};
 
-const EMPTY_TILE: u8 = u8::MAX;
+/// Tile id of an empty cell in any layer.
+pub const EMPTY_TILE: u8 = u8::MAX;
 const CHUNK_SIZE: usize = 32;
+const CHUNK_LEN: usize = CHUNK_SIZE * CHUNK_SIZE;
+/// Chunks kept generated around the player, in chunks from the player's chunk.
+const STREAM_RADIUS: usize = 2;
+/// Side, in chunks, of the squares of map each structure's `max_per_map` applies to.
+const QUOTA_REGION: usize = 8;
+/// Unmodified chunks further away than this are dropped.
+const EVICT_RADIUS: usize = STREAM_RADIUS + 2;
+/// Alpha that overlay tiles (tree canopies) fade to over a character standing under them.
+const CANOPY_FADE_ALPHA: f32 = 0.5;
+/// Alpha change per second while canopy tiles fade out or back in.
+const CANOPY_FADE_SPEED: f32 = 3.0;
+/// Tiles of canopy faded around a covered character.
+const CANOPY_FADE_PADDING: f32 = 1.0;
+/// Chunks the worker may be planning at once, so requests don't pile up behind a moving player.
+const MAX_PENDING_CHUNKS: usize = 8;
+/// How far ahead of a moving player, in chunks, the worker plans chunks before they are in range.
+const PREFETCH_DISTANCE: usize = 2;
+/// Finished plans kept waiting for their chunk to come into range.
+const MAX_PREFETCHED_PLANS: usize = 32;
+/// Tiles one `flood_fill` changes at most, so a fill that leaks into open ground stops.
+const MAX_FLOOD_TILES: usize = 1 << 16;
+/// Chunks holding render targets at once. Past this, the chunk drawn least recently hands its
+/// targets to the one that needs them and is re-rendered when it comes back into view.
+const MAX_CHUNK_TARGETS: usize = 48;
+/// Region tag of troughs that hold animal feed.
+const TROUGH_TAG: &str = "trough";
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct GridIndex {
@@ -32,6 +65,154 @@ struct TilesetFile {
     #[serde(default)]
     tile_count: Option<u16>,
     tiles: Vec<TileInfoFile>,
+    #[serde(default)]
+    properties: Vec<TilePropertiesFile>,
+}
+
+/// Properties shared by a list of tile ids.
+#[derive(Deserialize)]
+struct TilePropertiesFile {
+    tiles: Vec<u16>,
+    #[serde(default)]
+    friction: f32,
+    #[serde(default)]
+    damage_per_second: f32,
+    #[serde(default)]
+    footstep_sound: Option<String>,
+    #[serde(default)]
+    liquid: bool,
+    #[serde(default)]
+    terrain: Option<String>,
+    #[serde(default)]
+    tillable: bool,
+    #[serde(default)]
+    sort_by_bottom: bool,
+    /// `bouncy` or `sticky` pick the tile's `SurfaceMaterial`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Gameplay properties of a tile id, set in `tileset.json`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileProperties {
+    /// Fraction of movement speed lost on the tile (0..1).
+    pub friction: f32,
+    pub damage_per_second: f32,
+    /// Sound played for footsteps instead of the default one.
+    pub footstep_sound: Option<String>,
+    /// Water and the like: the player and ground entities swim instead of walking.
+    pub liquid: bool,
+    /// Kind of ground, such as grass or sand; picks walk and dash particles in `world.yaml`.
+    pub terrain: Option<String>,
+    /// Ground a hoe turns into farm soil.
+    pub tillable: bool,
+    /// Foreground tiles like tree trunks that characters walk in front of and behind. They are
+    /// left out of the chunk textures and drawn in the Y-sorted pass instead; see `SortedTile`.
+    pub sort_by_bottom: bool,
+    pub material: SurfaceMaterial,
+}
+
+static DEFAULT_TILE_PROPERTIES: TileProperties = TileProperties {
+    friction: 0.0,
+    damage_per_second: 0.0,
+    footstep_sound: None,
+    liquid: false,
+    terrain: None,
+    tillable: false,
+    sort_by_bottom: false,
+    material: SurfaceMaterial::Plain,
+};
+
+/// A `sort_by_bottom` tile stacks at most this many tiles high when finding its baseline.
+const MAX_SORTED_STACK: usize = 8;
+
+/// A foreground tile drawn in the Y-sorted pass.
+#[derive(Clone, Copy, Debug)]
+pub struct SortedTile {
+    pub id: u8,
+    pub x: usize,
+    pub y: usize,
+    /// World y of the bottom of the column of `sort_by_bottom` tiles this one belongs to, so a
+    /// whole tree sorts by where its trunk meets the ground.
+    pub baseline: f32,
+}
+
+/// Movement speed multiplier while swimming.
+pub const SWIM_SPEED_SCALE: f32 = 0.5;
+/// Fraction of their speed actors keep when they bounce off a bouncy wall.
+const BOUNCY_RESTITUTION: f32 = 0.8;
+/// Knockback dies off this many times faster on sticky ground.
+const STICKY_KNOCKBACK_FRICTION: f32 = 4.0;
+
+/// How a tile treats actors knocked back or dashing into it or across it, from the tileset's
+/// `tags`. Structures get it from the tiles they are built of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurfaceMaterial {
+    #[default]
+    Plain,
+    /// Mushroom caps and the like: knockback and dashes that hit it as a wall bounce back.
+    Bouncy,
+    /// Mud and the like: standing on it soaks up knockback and stops dashes.
+    Sticky,
+}
+
+impl SurfaceMaterial {
+    fn from_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut material = Self::Plain;
+        for tag in tags {
+            match tag {
+                "bouncy" => return Self::Bouncy,
+                "sticky" => material = Self::Sticky,
+                _ => {}
+            }
+        }
+        material
+    }
+
+    /// Fraction of its speed an actor keeps when it runs into this as a wall; 0 stops it.
+    pub fn restitution(self) -> f32 {
+        match self {
+            Self::Bouncy => BOUNCY_RESTITUTION,
+            Self::Plain | Self::Sticky => 0.0,
+        }
+    }
+
+    /// Multiplies how quickly knockback dies off for actors standing on it.
+    pub fn knockback_friction(self) -> f32 {
+        match self {
+            Self::Sticky => STICKY_KNOCKBACK_FRICTION,
+            Self::Plain | Self::Bouncy => 1.0,
+        }
+    }
+
+    /// Whether actors standing on it can dash.
+    pub fn allows_dash(self) -> bool {
+        self != Self::Sticky
+    }
+}
+
+/// Shades drawn per edge; overlapping bands fade the shade out away from the tall tile.
+const OCCLUSION_STEPS: usize = 3;
+
+/// Shade on the ground around tall foreground tiles (solid or Y-sorted ones, like walls and
+/// trunks), baked into the background chunk textures when they are rebuilt. Gives the flat map
+/// some depth without lighting. Set from `ambient_occlusion` in world.yaml; off when missing.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AmbientOcclusion {
+    /// Darkness at the foot of a tall tile, 0..1.
+    #[serde(default = "default_occlusion_strength")]
+    pub strength: f32,
+    /// How far the shade reaches into neighbouring tiles, as a fraction of a tile.
+    #[serde(default = "default_occlusion_reach")]
+    pub reach: f32,
+}
+
+fn default_occlusion_strength() -> f32 {
+    0.35
+}
+
+fn default_occlusion_reach() -> f32 {
+    0.45
 }
 
 #[derive(Deserialize)]
@@ -45,7 +226,13 @@ struct TileInfoFile {
 
 pub struct TileSet {
     texture: Texture2D,
+    /// File the texture was loaded from, for hot reload.
+    texture_path: String,
     tiles: Vec<Option<Rect>>,
+    /// Indexed by tile id; may be shorter than `tiles`.
+    properties: Vec<TileProperties>,
+    /// Average color of each tile, for map overviews.
+    colors: Vec<Color>,
 }
 
 impl TileSet {
@@ -102,6 +289,31 @@ impl TileSet {
             tiles.truncate(EMPTY_TILE as usize);
         }
 
+        let mut properties: Vec<TileProperties> = Vec::new();
+        for raw in parsed.properties {
+            let props = TileProperties {
+                friction: raw.friction.clamp(0.0, 1.0),
+                damage_per_second: raw.damage_per_second.max(0.0),
+                footstep_sound: raw.footstep_sound,
+                liquid: raw.liquid,
+                terrain: raw.terrain,
+                tillable: raw.tillable,
+                sort_by_bottom: raw.sort_by_bottom,
+                material: SurfaceMaterial::from_tags(raw.tags.iter().map(String::as_str)),
+            };
+            for id in raw.tiles {
+                let id = id as usize;
+                if id >= tiles.len() {
+                    eprintln!("tileset.json has properties for tile {id}, past the last tile");
+                    continue;
+                }
+                if id >= properties.len() {
+                    properties.resize(id + 1, TileProperties::default());
+                }
+                properties[id] = props.clone();
+            }
+        }
+
         let texture = load_texture(&texture_path).await?;
         texture.set_filter(FilterMode::Nearest);
 
@@ -111,10 +323,49 @@ impl TileSet {
             }
         }
 
-        Ok(Self { texture, tiles })
+        let colors = tile_colors(&texture, &tiles);
+        Ok(Self {
+            texture,
+            texture_path,
+            tiles,
+            properties,
+            colors,
+        })
+    }
+
+    /// Builds a tileset from a uniform grid atlas loaded from `texture_path`, as described by
+    /// Tiled tilesets.
+    pub fn from_grid(
+        texture: Texture2D,
+        texture_path: String,
+        tile_size: Vec2,
+        columns: usize,
+        tile_count: usize,
+    ) -> Self {
+        texture.set_filter(FilterMode::Nearest);
+        let columns = columns.max(1);
+        let tile_count = tile_count.min(EMPTY_TILE as usize);
+        let tiles = (0..tile_count)
+            .map(|i| {
+                Some(Rect::new(
+                    (i % columns) as f32 * tile_size.x,
+                    (i / columns) as f32 * tile_size.y,
+                    tile_size.x,
+                    tile_size.y,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let colors = tile_colors(&texture, &tiles);
+        Self {
+            texture,
+            texture_path,
+            tiles,
+            properties: Vec::new(),
+            colors,
+        }
     }
 
-    fn get(&self, id: u8) -> Option<Rect> {
+    pub fn get(&self, id: u8) -> Option<Rect> {
         if id == EMPTY_TILE {
             return None;
         }
@@ -125,9 +376,69 @@ impl TileSet {
         &self.texture
     }
 
+    pub fn texture_path(&self) -> &str {
+        &self.texture_path
+    }
+
+    /// Recomputes the tile colors after the texture's pixels changed.
+    pub fn refresh_colors(&mut self) {
+        self.colors = tile_colors(&self.texture, &self.tiles);
+    }
+
     pub fn count(&self) -> usize {
         self.tiles.len()
     }
+
+    pub fn properties(&self) -> &[TileProperties] {
+        &self.properties
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+}
+
+/// Average color of each tile's opaque pixels; fully transparent tiles get a transparent color.
+fn tile_colors(texture: &Texture2D, tiles: &[Option<Rect>]) -> Vec<Color> {
+    let image = texture.get_texture_data();
+    tiles
+        .iter()
+        .map(|rect| {
+            let Some(rect) = rect else {
+                return Color::new(0.0, 0.0, 0.0, 0.0);
+            };
+            let (mut sum, mut weight) = (Vec3::ZERO, 0.0);
+            for y in rect.y as u32..(rect.y + rect.h) as u32 {
+                for x in rect.x as u32..(rect.x + rect.w) as u32 {
+                    if x >= image.width() as u32 || y >= image.height() as u32 {
+                        continue;
+                    }
+                    let pixel = image.get_pixel(x, y);
+                    sum += vec3(pixel.r, pixel.g, pixel.b) * pixel.a;
+                    weight += pixel.a;
+                }
+            }
+            if weight <= 0.0 {
+                return Color::new(0.0, 0.0, 0.0, 0.0);
+            }
+            let average = sum / weight;
+            Color::new(average.x, average.y, average.z, 1.0)
+        })
+        .collect()
+}
+
+/// Tiles per edge of one chunk summary cell.
+const SUMMARY_CELL_TILES: usize = 4;
+/// Cells per edge of a chunk summary.
+pub const SUMMARY_CELLS: usize = CHUNK_SIZE / SUMMARY_CELL_TILES;
+
+/// A chunk shrunk to `SUMMARY_CELLS` squared cells, row-major, for overviews like the minimap
+/// that shouldn't depend on chunk render targets.
+pub struct ChunkSummary {
+    /// Color of the most common visible tile in each cell; transparent where nothing shows.
+    pub colors: Vec<Color>,
+    /// Cells where at least half the tiles are solid.
+    pub blocked: Vec<bool>,
 }
 
 #[derive(Clone)]
@@ -148,6 +459,11 @@ pub struct Structure {
 }
 
 impl Structure {
+    /// Footprint in tiles.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
     pub fn random(width: usize, height: usize, tile_count: usize, seed: u32) -> Self {
         let len = width * height;
         let mut background = vec![EMPTY_TILE; len];
@@ -244,8 +560,8 @@ impl Structure {
                 }
 
                 let collider = self.colliders.get(i).copied().unwrap_or(0);
-                let collider = collider & 0x0F;
-                if collider != 0 {
+                let collider = collider & COLLIDER_BITS;
+                if collider & PIN_BITS != 0 {
                     self.collider_offsets.push((x, y, collider));
                     occupied = true;
                 }
@@ -276,453 +592,992 @@ impl Structure {
 #[derive(Clone)]
 pub struct StructureDef {
     pub id: String,
+    /// The main tile grid, variant 0. Every variant has its size.
     pub structure: Structure,
+    /// Weight of the main grid when picking a variant.
+    pub weight: f32,
+    /// Alternative tile grids, variants 1 and up; each placement picks one by weight.
+    pub variants: Vec<StructureVariant>,
     pub on_interact: Vec<String>,
+    /// Interact functions fired when the player steps into the structure's trigger pins.
+    pub on_trigger: Vec<String>,
     pub interact_range: f32,
+    pub highlight: HighlightStyle,
+    /// Seconds the interact button must be held; 0 fires on click.
+    pub hold_time: f32,
+    /// Keep partial hold progress when the player lets go or walks away.
+    pub keep_progress: bool,
     pub frequency: f32,
+    /// Cap per square of `QUOTA_REGION` chunks, so it holds however the map is streamed in.
     pub max_per_map: usize,
     pub min_distance: f32,
+    /// Free-form tags; `blocks_projectiles` and `reflects_projectiles` set how the structure's
+    /// collider tiles treat projectiles.
+    pub tags: Vec<String>,
+    /// Placement chance per biome tag (0..1). Empty means any biome except water; otherwise
+    /// biomes that aren't listed get nothing.
+    pub biomes: HashMap<String, f32>,
+    /// Background tiles the whole footprint must stand on; empty allows any.
+    pub allowed_tiles: Vec<u8>,
+    /// Background tiles no part of the footprint may stand on.
+    pub forbidden_tiles: Vec<u8>,
+    /// Tagged areas the structure marks on the map when placed.
+    pub regions: Vec<StructureRegion>,
+    /// Named points the structure marks on the map when placed.
+    pub markers: Vec<StructureMarker>,
+    /// Entities spawned around the structure when it's placed.
+    pub spawns: Vec<StructureSpawn>,
+    /// The entity tending the structure, like the keeper of a market stall.
+    pub vendor: Option<StructureVendor>,
+    /// Wave started by the `start_encounter` trigger function.
+    pub encounter: Option<StructureEncounter>,
 }
 
 #[derive(Clone)]
-pub struct StructureInteractor {
-    pub structure_id: String,
-    pub rect: Rect,
-    pub group_rect: Rect,
-    pub on_interact: Vec<String>,
-    pub interact_range_world: f32,
-}
-
-#[derive(Clone, Copy)]
-pub enum LayerKind {
-    Background,
-    Foreground,
-    Overlay,
-}
-
-struct Chunk {
-    background: RenderTarget,
-    foreground: RenderTarget,
-    overlay: RenderTarget,
-    dirty_background: bool,
-    dirty_foreground: bool,
-    dirty_overlay: bool,
-    ready_background: bool,
-    ready_foreground: bool,
-    ready_overlay: bool,
+pub struct StructureVariant {
+    pub structure: Structure,
+    pub weight: f32,
 }
 
-struct StructureApplyState {
-    defs: Vec<StructureDef>,
-    seed: u32,
-    occupied: Vec<bool>,
-    placed_rects: Vec<Rect>,
-    spatial: Vec<Vec<usize>>,
-    cell_size: f32,
-    cell_cols: usize,
-    cell_rows: usize,
-    def_index: usize,
-    attempt_index: usize,
-    target: usize,
-    attempts: usize,
-    max_x: usize,
-    max_y: usize,
-    count: usize,
-    done: bool,
-}
-
-impl StructureApplyState {
-    fn new(map: &TileMap, defs: Vec<StructureDef>, seed: u32) -> Self {
-        let world_w = map.width as f32 * map.tile_size;
-        let world_h = map.height as f32 * map.tile_size;
-        let cell_size = map.chunk_pixel_size.max(map.tile_size);
-        let cell_cols = ((world_w / cell_size).ceil() as usize).max(1);
-        let cell_rows = ((world_h / cell_size).ceil() as usize).max(1);
-        let spatial = vec![Vec::new(); cell_cols * cell_rows];
-
-        let mut state = Self {
-            defs,
-            seed,
-            occupied: vec![false; map.width * map.height],
-            placed_rects: Vec::new(),
-            spatial,
-            cell_size,
-            cell_cols,
-            cell_rows,
-            def_index: 0,
-            attempt_index: 0,
-            target: 0,
-            attempts: 0,
-            max_x: 0,
-            max_y: 0,
-            count: 0,
-            done: false,
-        };
-        state.advance_def(map);
-        state
-    }
-
-    fn progress(&self) -> f32 {
-        if self.defs.is_empty() {
-            return 1.0;
+impl StructureDef {
+    /// The tile grid of a variant; out of range falls back to the main grid.
+    pub fn variant(&self, index: usize) -> &Structure {
+        match index.checked_sub(1).and_then(|i| self.variants.get(i)) {
+            Some(variant) => &variant.structure,
+            None => &self.structure,
         }
-        let total_defs = self.defs.len().max(1) as f32;
-        let base = (self.def_index.min(self.defs.len())) as f32 / total_defs;
-        let step = if self.attempts > 0 {
-            (self.attempt_index.min(self.attempts)) as f32 / self.attempts as f32 / total_defs
-        } else {
-            0.0
-        };
-        (base + step).clamp(0.0, 1.0)
     }
 
-    fn step(&mut self, map: &mut TileMap, time_budget_s: f32) -> bool {
-        if self.done {
-            return true;
+    /// Picks a variant index by weight from a hash, so placements stay reproducible.
+    fn pick_variant(&self, roll: u32) -> usize {
+        let total = self.weight.max(0.0) + self.variants.iter().map(|v| v.weight.max(0.0)).sum::<f32>();
+        if self.variants.is_empty() || total <= 0.0 {
+            return 0;
         }
-        let budget = time_budget_s.max(0.0001) as f64;
-        let start = get_time();
-
-        while (get_time() - start) < budget {
-            if self.done {
-                return true;
-            }
-            if self.attempt_index >= self.attempts || self.count >= self.target {
-                self.def_index += 1;
-                self.advance_def(map);
-                continue;
+        let mut target = unit_roll(roll) * total - self.weight.max(0.0);
+        if target < 0.0 {
+            return 0;
+        }
+        for (index, variant) in self.variants.iter().enumerate() {
+            target -= variant.weight.max(0.0);
+            if target < 0.0 {
+                return index + 1;
             }
+        }
+        self.variants.len()
+    }
+}
 
-            let def = &self.defs[self.def_index];
-            let i = self.attempt_index;
-            self.attempt_index += 1;
+#[derive(Clone, Deserialize)]
+pub struct StructureSpawn {
+    pub entity: String,
+    #[serde(default = "default_spawn_count")]
+    pub count: u32,
+    /// Spawns limited to day or night wait until it is that time.
+    #[serde(default)]
+    pub time: SpawnTime,
+}
 
-            let def_seed = (self.def_index as u32).wrapping_mul(2654435761);
-            let def_seed_y = (self.def_index as u32).wrapping_mul(2246822519);
-            let rx = hash_u32(i as u32, self.seed ^ def_seed, 31);
-            let ry = hash_u32(i as u32, self.seed ^ def_seed_y, 47);
-            let x = (rx as usize % (self.max_x + 1)).min(self.max_x);
-            let y = (ry as usize % (self.max_y + 1)).min(self.max_y);
+/// Guards that turn up when the player steps into a structure's trigger pins, at most once a
+/// day per placed structure.
+#[derive(Clone, Deserialize)]
+pub struct StructureEncounter {
+    pub wave: Vec<StructureSpawn>,
+    /// How far from the middle of the trigger pins the wave appears, in world pixels.
+    #[serde(default = "default_encounter_radius")]
+    pub radius: f32,
+}
 
-            let pos = vec2(x as f32 * map.tile_size, y as f32 * map.tile_size);
-            let size = vec2(
-                def.structure.width as f32 * map.tile_size,
-                def.structure.height as f32 * map.tile_size,
-            );
-            let rect = Rect::new(pos.x, pos.y, size.x, size.y);
-            let padded = if def.min_distance > 0.0 {
-                Rect::new(
-                    rect.x - def.min_distance,
-                    rect.y - def.min_distance,
-                    rect.w + def.min_distance * 2.0,
-                    rect.h + def.min_distance * 2.0,
-                )
-            } else {
-                rect
-            };
+fn default_encounter_radius() -> f32 {
+    48.0
+}
 
-            if spatial_overlaps(
-                &padded,
-                &self.placed_rects,
-                &self.spatial,
-                self.cell_size,
-                self.cell_cols,
-                self.cell_rows,
-            ) {
-                continue;
-            }
+/// The entity bound to a structure. Unlike `spawns` it comes back whenever the structure is
+/// loaded without one, and goes away when the structure is removed.
+#[derive(Clone, Deserialize)]
+pub struct StructureVendor {
+    pub entity: String,
+    /// Tile of the structure the vendor stands on.
+    #[serde(default)]
+    pub x: usize,
+    #[serde(default)]
+    pub y: usize,
+}
 
-            let mut blocked = false;
-            for &(sx, sy) in def.structure.occupied_offsets.iter() {
-                let idx = map.idx(x + sx, y + sy);
-                if self.occupied[idx] {
-                    blocked = true;
-                    break;
-                }
-            }
-            if blocked {
-                continue;
-            }
+/// A vendor a loaded structure wants, waiting for the game loop to bind or spawn one.
+pub struct VendorSpawn {
+    /// Top-left tile of the structure, which identifies it.
+    pub stall: (usize, usize),
+    pub entity: String,
+    pub pos: Vec2,
+    /// The structure's footprint in world space.
+    pub footprint: Rect,
+}
 
-            map.place_structure_unchecked(&def.structure, x, y);
-            map.register_structure_interactors(def, x, y);
-            for &(sx, sy) in def.structure.occupied_offsets.iter() {
-                let idx = map.idx(x + sx, y + sy);
-                self.occupied[idx] = true;
-            }
+/// An entity a structure, an event or a new world asks for, waiting to be spawned by the game
+/// loop.
+pub struct EntitySpawn {
+    pub entity: String,
+    pub pos: Vec2,
+    pub time: SpawnTime,
+}
 
-            self.placed_rects.push(padded);
-            let rect_index = self.placed_rects.len() - 1;
-            spatial_insert(
-                rect_index,
-                &padded,
-                &mut self.spatial,
-                self.cell_size,
-                self.cell_cols,
-                self.cell_rows,
-            );
+fn default_spawn_count() -> u32 {
+    1
+}
 
-            self.count += 1;
-        }
+/// A tagged rectangle in structure-local tiles.
+#[derive(Clone)]
+pub struct StructureRegion {
+    pub tag: String,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
 
-        self.done
-    }
+/// A named tile in structure-local tiles.
+#[derive(Clone)]
+pub struct StructureMarker {
+    pub name: String,
+    pub x: usize,
+    pub y: usize,
+}
 
-    fn advance_def(&mut self, map: &TileMap) {
-        while self.def_index < self.defs.len() {
-            let def = &self.defs[self.def_index];
-            let freq = def.frequency.clamp(0.0, 1.0);
-            if freq <= 0.0 || def.max_per_map == 0 || def.structure.is_empty() {
-                self.def_index += 1;
-                continue;
-            }
-            if def.structure.width == 0
-                || def.structure.height == 0
-                || map.width < def.structure.width
-                || map.height < def.structure.height
-            {
-                self.def_index += 1;
-                continue;
-            }
+/// A named point on the map, in world pixels, like a spawn point. Comes from structures and
+/// from marker objects in Tiled levels.
+#[derive(Clone)]
+pub struct MapMarker {
+    pub name: String,
+    pub pos: Vec2,
+}
 
-            let area = (map.width * map.height) as f32;
-            let target = ((area * freq).round() as usize).min(def.max_per_map);
-            if target == 0 {
-                self.def_index += 1;
-                continue;
-            }
+/// What actors do at one edge of the map. Written as `solid`, `wrap` or the path of the level
+/// the edge leads to (e.g. `levels/cave.tmx`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum MapBorder {
+    /// Actors stop at the edge.
+    #[default]
+    Solid,
+    /// Actors walking off come back in at the opposite edge.
+    Wrap,
+    /// Actors stop at the edge; the player reaching it moves on to another level.
+    Transition(String),
+}
 
-            self.target = target;
-            self.attempts = (target * 12).max(24);
-            self.max_x = map.width - def.structure.width;
-            self.max_y = map.height - def.structure.height;
-            self.attempt_index = 0;
-            self.count = 0;
-            return;
+impl From<String> for MapBorder {
+    fn from(value: String) -> Self {
+        match value.trim() {
+            "" | "solid" => Self::Solid,
+            "wrap" => Self::Wrap,
+            level => Self::Transition(level.to_string()),
         }
-
-        self.done = true;
     }
 }
 
-pub struct TileMap {
-    width: usize,
-    height: usize,
-    tile_size: f32,
-    background: Vec<u8>,
-    foreground: Vec<u8>,
-    overlay: Vec<u8>,
-    solid: Vec<bool>,
-    collision_mask: Vec<u8>,
-    collision_blocks: Vec<Rect>,
-    collision_dirty: bool,
-    chunk_cols: usize,
-    chunk_rows: usize,
-    chunk_pixel_size: f32,
-    chunks: Vec<Option<Chunk>>,
-    pending_dirty_background: Vec<bool>,
-    pending_dirty_foreground: Vec<bool>,
-    pending_dirty_overlay: Vec<bool>,
-    chunk_alloc_cursor: usize,
-    chunk_alloc_budget_per_frame: usize,
-    chunk_rebuild_budget_per_frame: usize,
-    chunk_allocs_this_frame: usize,
-    chunk_rebuilds_this_frame: usize,
-    structure_apply: Option<StructureApplyState>,
-    structure_interactors: Vec<StructureInteractor>,
-    grid_size: Vec2,
-    border_thickness: f32,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapEdge {
+    North,
+    South,
+    East,
+    West,
 }
 
-impl TileMap {
-    pub fn demo(width: usize, height: usize, tile_size: f32, tile_count: usize, border_thickness: f32) -> Self {
-        let mut map = Self::new(width, height, tile_size, Vec2::new(tile_size, tile_size), border_thickness);
+/// Border of each map edge. Set from world.yaml for generated worlds and from `border_north`,
+/// `border_south`, `border_east` and `border_west` map properties in Tiled levels.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MapBorders {
+    pub north: MapBorder,
+    pub south: MapBorder,
+    pub east: MapBorder,
+    pub west: MapBorder,
+}
 
-        if tile_count > 0 {
-            map.fill_layer(LayerKind::Background, 24);
+impl MapBorders {
+    pub fn get(&self, edge: MapEdge) -> &MapBorder {
+        match edge {
+            MapEdge::North => &self.north,
+            MapEdge::South => &self.south,
+            MapEdge::East => &self.east,
+            MapEdge::West => &self.west,
         }
-
-        map
     }
 
-    pub fn new(width: usize, height: usize, tile_size: f32, grid_size: Vec2, border_thickness: f32) -> Self {
-        let len = width * height;
-        let chunk_cols = (width + CHUNK_SIZE - 1) / CHUNK_SIZE;
-        let chunk_rows = (height + CHUNK_SIZE - 1) / CHUNK_SIZE;
-        let chunk_pixel_size = tile_size * CHUNK_SIZE as f32;
-        let chunk_size_u32 = chunk_pixel_size.round().max(1.0) as u32;
-        let mut chunks = Vec::with_capacity(chunk_cols * chunk_rows);
-        for _ in 0..chunk_cols * chunk_rows {
-            let background = render_target(chunk_size_u32, chunk_size_u32);
-            let foreground = render_target(chunk_size_u32, chunk_size_u32);
-            let overlay = render_target(chunk_size_u32, chunk_size_u32);
-            background.texture.set_filter(FilterMode::Nearest);
-            foreground.texture.set_filter(FilterMode::Nearest);
-            overlay.texture.set_filter(FilterMode::Nearest);
-            chunks.push(Some(Chunk {
-                background,
-                foreground,
-                overlay,
-                dirty_background: true,
-                dirty_foreground: true,
-                dirty_overlay: true,
-                ready_background: false,
-                ready_foreground: false,
-                ready_overlay: false,
-            }));
+    fn from_tmx_properties(properties: &HashMap<String, String>) -> Self {
+        let border = |key: &str| properties.get(key).cloned().map(MapBorder::from).unwrap_or_default();
+        Self {
+            north: border("border_north"),
+            south: border("border_south"),
+            east: border("border_east"),
+            west: border("border_west"),
         }
+    }
+}
+
+/// A tagged area on the map, in world pixels. Emitted by placed structures so systems can ask
+/// what kind of place a position is in (e.g. inside a greenhouse).
+#[derive(Clone)]
+pub struct MapRegion {
+    pub tag: String,
+    pub rect: Rect,
+}
+
+#[derive(Clone)]
+pub struct StructureInteractor {
+    pub structure_id: String,
+    pub rect: Rect,
+    pub group_rect: Rect,
+    pub on_interact: Vec<String>,
+    pub interact_range_world: f32,
+    pub highlight: HighlightStyle,
+    pub hold_time: f32,
+    pub keep_progress: bool,
+    /// Overlay tile the structure itself put on each interactor tile (`None` = left empty).
+    /// Anything else found there later means something is drawn over the interactor.
+    own_overlay: Vec<(usize, usize, Option<u8>)>,
+}
 
-        let chunk_count = chunk_cols * chunk_rows;
+/// How the hover highlight of an interactable structure is drawn.
+#[derive(Clone)]
+pub struct HighlightStyle {
+    pub color: Color,
+    pub fill_alpha: f32,
+    pub outline_alpha: f32,
+    /// Pulses per second of the outline; 0 keeps it steady.
+    pub pulse: f32,
+    pub icon: Option<String>,
+    pub occluded: OccludedHighlight,
+}
+
+#[derive(Clone, Copy)]
+pub enum OccludedHighlight {
+    Hide,
+    /// Draw the highlight raised by this many pixels so it peeks out above whatever covers it.
+    Offset(f32),
+}
 
+impl Default for HighlightStyle {
+    fn default() -> Self {
         Self {
-            width,
-            height,
-            tile_size,
-            background: vec![EMPTY_TILE; len],
-            foreground: vec![EMPTY_TILE; len],
-            overlay: vec![EMPTY_TILE; len],
-            solid: vec![false; len],
-            collision_mask: vec![0; len],
-            collision_blocks: Vec::new(),
-            collision_dirty: true,
-            chunk_cols,
-            chunk_rows,
-            chunk_pixel_size,
-            chunks,
-            pending_dirty_background: vec![false; chunk_count],
-            pending_dirty_foreground: vec![false; chunk_count],
-            pending_dirty_overlay: vec![false; chunk_count],
-            chunk_alloc_cursor: 0,
-            chunk_alloc_budget_per_frame: usize::MAX,
-            chunk_rebuild_budget_per_frame: usize::MAX,
-            chunk_allocs_this_frame: 0,
-            chunk_rebuilds_this_frame: 0,
-            structure_apply: None,
-            structure_interactors: Vec::new(),
-            grid_size,
-            border_thickness,
+            color: Color::new(1.0, 0.95, 0.2, 1.0),
+            fill_alpha: 0.2,
+            outline_alpha: 0.95,
+            pulse: 0.0,
+            icon: None,
+            occluded: OccludedHighlight::Hide,
         }
     }
+}
 
-    pub fn new_deferred(width: usize, height: usize, tile_size: f32, grid_size: Vec2, border_thickness: f32) -> Self {
-        let len = width * height;
-        let chunk_cols = (width + CHUNK_SIZE - 1) / CHUNK_SIZE;
-        let chunk_rows = (height + CHUNK_SIZE - 1) / CHUNK_SIZE;
-        let chunk_pixel_size = tile_size * CHUNK_SIZE as f32;
-        let total_chunks = chunk_cols * chunk_rows;
+/// Collision pins take the low four bits of a tile's collision mask, one per quadrant; the two
+/// bits above them hold the collider kind.
+const PIN_BITS: u8 = 0x0F;
+const COLLIDER_BITS: u8 = 0x3F;
+const COLLIDER_KIND_SHIFT: u8 = 4;
+/// Share of its speed something keeps inside a slow zone.
+pub const SLOW_ZONE_SPEED: f32 = 0.5;
 
-        let mut chunks = Vec::with_capacity(total_chunks);
-        for _ in 0..total_chunks {
-            chunks.push(None);
-        }
+/// What a tile's collision pins do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColliderKind {
+    Solid,
+    /// Blocks only movement heading north, so a ledge can be hopped down but not climbed.
+    OneWay,
+    /// Doesn't block, but slows whatever moves through it.
+    Slow,
+    /// Doesn't block; the structure's `on_trigger` functions fire when the player steps in.
+    Trigger,
+}
 
-        Self {
-            width,
-            height,
-            tile_size,
-            background: vec![EMPTY_TILE; len],
-            foreground: vec![EMPTY_TILE; len],
-            overlay: vec![EMPTY_TILE; len],
-            solid: vec![false; len],
-            collision_mask: vec![0; len],
-            collision_blocks: Vec::new(),
-            collision_dirty: true,
-            chunk_cols,
-            chunk_rows,
-            chunk_pixel_size,
-            chunks,
-            pending_dirty_background: vec![true; total_chunks],
-            pending_dirty_foreground: vec![true; total_chunks],
-            pending_dirty_overlay: vec![true; total_chunks],
-            chunk_alloc_cursor: 0,
-            chunk_alloc_budget_per_frame: usize::MAX,
-            chunk_rebuild_budget_per_frame: usize::MAX,
-            chunk_allocs_this_frame: 0,
-            chunk_rebuilds_this_frame: 0,
-            structure_apply: None,
-            structure_interactors: Vec::new(),
-            grid_size,
-            border_thickness,
+impl ColliderKind {
+    fn from_mask(mask: u8) -> Self {
+        match (mask >> COLLIDER_KIND_SHIFT) & 0x03 {
+            1 => Self::OneWay,
+            2 => Self::Slow,
+            3 => Self::Trigger,
+            _ => Self::Solid,
         }
     }
 
-    pub fn allocate_chunks_step(&mut self, time_budget_s: f32) -> bool {
-        let budget = time_budget_s.max(0.0001) as f64;
-        let start = get_time();
-        let total = self.chunks.len();
-        if total == 0 {
-            return true;
-        }
+    fn bits(self) -> u8 {
+        (self as u8) << COLLIDER_KIND_SHIFT
+    }
+}
 
-        let mut scanned = 0usize;
-        while scanned < total && (get_time() - start) < budget {
-            let idx = self.chunk_alloc_cursor;
-            self.chunk_alloc_cursor = (self.chunk_alloc_cursor + 1) % total;
-            scanned += 1;
-            if self.chunks[idx].is_some() {
-                continue;
+/// A collider added at runtime with `TileMap::add_obstacle`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObstacleId(u32);
+
+/// A placed structure's trigger pins, fired as one volume.
+#[derive(Clone)]
+pub struct StructureTrigger {
+    pub structure_id: String,
+    /// Bounds of every pin; also tells placements of the same structure apart.
+    pub group_rect: Rect,
+    pub rects: Vec<Rect>,
+    pub on_trigger: Vec<String>,
+}
+
+/// How a tile treats projectiles that fly into it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProjectileSurface {
+    #[default]
+    Open,
+    Block,
+    Reflect,
+}
+
+impl ProjectileSurface {
+    fn from_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut surface = Self::Open;
+        for tag in tags {
+            match tag {
+                "reflects_projectiles" | "reflect" => return Self::Reflect,
+                "blocks_projectiles" | "block" => surface = Self::Block,
+                _ => {}
             }
-            self.create_chunk(idx);
         }
-
-        self.chunks.iter().all(|chunk| chunk.is_some())
+        surface
     }
 
-    pub fn allocate_chunks_progress(&self) -> f32 {
-        let total = (self.chunk_cols * self.chunk_rows).max(1) as f32;
-        let done = self.chunks.iter().filter(|chunk| chunk.is_some()).count() as f32;
-        (done / total).clamp(0.0, 1.0)
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Open => 0,
+            Self::Block => 1,
+            Self::Reflect => 2,
+        }
     }
 
-    pub fn set_chunk_work_budget(&mut self, alloc_per_frame: usize, rebuild_per_frame: usize) {
-        self.chunk_alloc_budget_per_frame = alloc_per_frame.max(1);
-        self.chunk_rebuild_budget_per_frame = rebuild_per_frame.max(1);
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Block,
+            2 => Self::Reflect,
+            _ => Self::Open,
+        }
     }
+}
 
-    pub fn begin_frame_chunk_work(&mut self) {
-        self.chunk_allocs_this_frame = 0;
-        self.chunk_rebuilds_this_frame = 0;
+/// A map and its tileset imported from a Tiled `.tmx` file.
+pub struct TmxLevel {
+    pub map: TileMap,
+    pub tileset: TileSet,
+}
+
+/// A structure stamped into the map, kept so interactors can be rebuilt after loading a save.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlacedStructure {
+    pub id: String,
+    pub x: usize,
+    pub y: usize,
+    /// Which of the definition's tile grids was stamped; see `StructureDef::variant`.
+    #[serde(default)]
+    pub variant: usize,
+}
+
+/// Everything needed to rebuild a `TileMap`. Only chunks that can't be regenerated are stored;
+/// their tile layers are run-length encoded as `[value, run]` pairs.
+#[derive(Serialize, Deserialize)]
+pub struct MapSaveData {
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: f32,
+    pub grid_size: [f32; 2],
+    pub border_thickness: f32,
+    /// Chunks edited since they were generated, or every chunk of a map without a generator.
+    pub chunks: Vec<ChunkSaveData>,
+    /// Chunks that have been generated at least once, as `[x, y]` chunk coordinates.
+    pub generated: Vec<[usize; 2]>,
+    /// Every structure placed in a generated chunk, loaded or not.
+    pub structures: Vec<PlacedStructure>,
+    /// Feed stocked in troughs, by the trough's top-left tile.
+    #[serde(default)]
+    pub feed: Vec<([usize; 2], f32)>,
+    /// Explored tiles of every chunk the player has seen part of.
+    #[serde(default)]
+    pub explored: Vec<ExploredSaveData>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExploredSaveData {
+    /// Chunk coordinates, in chunks.
+    pub x: usize,
+    pub y: usize,
+    /// 1 for explored tiles, run-length encoded like the tile layers.
+    pub tiles: Vec<[u32; 2]>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChunkSaveData {
+    /// Chunk coordinates, in chunks.
+    pub x: usize,
+    pub y: usize,
+    pub background: Vec<[u32; 2]>,
+    pub foreground: Vec<[u32; 2]>,
+    pub overlay: Vec<[u32; 2]>,
+    pub collision_mask: Vec<[u32; 2]>,
+    pub terrain: Vec<[u32; 2]>,
+    pub projectile_surfaces: Vec<[u32; 2]>,
+}
+
+/// Map layout of version 1 saves, with each tile layer covering the whole map.
+#[derive(Deserialize)]
+pub struct LegacyMapSaveData {
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: f32,
+    pub grid_size: [f32; 2],
+    pub border_thickness: f32,
+    pub background: Vec<[u32; 2]>,
+    pub foreground: Vec<[u32; 2]>,
+    pub overlay: Vec<[u32; 2]>,
+    pub collision_mask: Vec<[u32; 2]>,
+    pub structures: Vec<PlacedStructure>,
+    #[serde(default)]
+    pub terrain: Vec<[u32; 2]>,
+    #[serde(default)]
+    pub projectile_surfaces: Vec<[u32; 2]>,
+}
+
+impl LegacyMapSaveData {
+    /// Splits the whole-map layers into saved chunks. Every chunk is marked generated, so the
+    /// world generator never overwrites the old map.
+    pub fn into_chunked(self) -> Result<MapSaveData, String> {
+        let len = self.width * self.height;
+        let decode = |runs: &[[u32; 2]], what: &str| {
+            rle_decode(runs, len).ok_or_else(|| format!("{what} size mismatch"))
+        };
+        // Layers older saves didn't have start out empty.
+        let decode_optional = |runs: &[[u32; 2]], what: &str| {
+            if runs.is_empty() { Ok(vec![0; len]) } else { decode(runs, what) }
+        };
+        let layers = [
+            decode(&self.background, "background")?,
+            decode(&self.foreground, "foreground")?,
+            decode(&self.overlay, "overlay")?,
+            decode(&self.collision_mask, "collision mask")?,
+            decode_optional(&self.terrain, "terrain")?,
+            decode_optional(&self.projectile_surfaces, "projectile surfaces")?,
+        ];
+
+        let mut chunks = Vec::new();
+        let mut generated = Vec::new();
+        for cy in 0..self.height.div_ceil(CHUNK_SIZE) {
+            for cx in 0..self.width.div_ceil(CHUNK_SIZE) {
+                // Tiles past the map edge stay empty, as in a freshly allocated chunk.
+                let [background, foreground, overlay, collision_mask, terrain, projectile_surfaces] =
+                    layers.each_ref().map(|layer| {
+                        let mut tiles = vec![0; CHUNK_LEN];
+                        for ly in 0..CHUNK_SIZE.min(self.height - cy * CHUNK_SIZE) {
+                            let row = (cy * CHUNK_SIZE + ly) * self.width + cx * CHUNK_SIZE;
+                            let cols = CHUNK_SIZE.min(self.width - cx * CHUNK_SIZE);
+                            tiles[ly * CHUNK_SIZE..ly * CHUNK_SIZE + cols].copy_from_slice(&layer[row..row + cols]);
+                        }
+                        rle_encode(&tiles)
+                    });
+                chunks.push(ChunkSaveData {
+                    x: cx,
+                    y: cy,
+                    background,
+                    foreground,
+                    overlay,
+                    collision_mask,
+                    terrain,
+                    projectile_surfaces,
+                });
+                generated.push([cx, cy]);
+            }
+        }
+        Ok(MapSaveData {
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_size,
+            grid_size: self.grid_size,
+            border_thickness: self.border_thickness,
+            chunks,
+            generated,
+            structures: self.structures,
+            feed: Vec::new(),
+            explored: Vec::new(),
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LayerKind {
+    Background,
+    Foreground,
+    Overlay,
+}
+
+/// Chunk coordinates, in chunks rather than tiles.
+pub type ChunkCoord = (usize, usize);
+
+/// A rectangle of tiles, in tile coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+impl TileRect {
+    pub fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Self { x, y, w, h }
+    }
+}
+
+/// Tile data for one `CHUNK_SIZE` square, row-major in chunk-local tiles.
+struct TileChunk {
+    background: Vec<u8>,
+    foreground: Vec<u8>,
+    overlay: Vec<u8>,
+    collision_mask: Vec<u8>,
+    terrain: Vec<TerrainId>,
+    projectile_surfaces: Vec<ProjectileSurface>,
+    /// Edited after generation. Modified chunks are saved and never evicted, since regenerating
+    /// them would lose the edits.
+    modified: bool,
+}
+
+impl TileChunk {
+    fn new() -> Self {
+        Self {
+            background: vec![EMPTY_TILE; CHUNK_LEN],
+            foreground: vec![EMPTY_TILE; CHUNK_LEN],
+            overlay: vec![EMPTY_TILE; CHUNK_LEN],
+            collision_mask: vec![0; CHUNK_LEN],
+            terrain: vec![NO_TERRAIN; CHUNK_LEN],
+            projectile_surfaces: vec![ProjectileSurface::Open; CHUNK_LEN],
+            modified: false,
+        }
+    }
+
+    fn layer(&self, layer: LayerKind) -> &[u8] {
+        match layer {
+            LayerKind::Background => &self.background,
+            LayerKind::Foreground => &self.foreground,
+            LayerKind::Overlay => &self.overlay,
+        }
+    }
+
+    fn layer_mut(&mut self, layer: LayerKind) -> &mut [u8] {
+        match layer {
+            LayerKind::Background => &mut self.background,
+            LayerKind::Foreground => &mut self.foreground,
+            LayerKind::Overlay => &mut self.overlay,
+        }
+    }
+
+    /// Stamps a structure whose origin is at chunk-local `(x, y)`; the caller guarantees it fits.
+    /// Structure floors replace the terrain so autotiling leaves them alone.
+    fn stamp(&mut self, structure: &Structure, x: usize, y: usize) {
+        let local = |sx: usize, sy: usize| (y + sy) * CHUNK_SIZE + x + sx;
+        for &(sx, sy, tile) in structure.background_updates.iter() {
+            let i = local(sx, sy);
+            self.background[i] = tile;
+            self.terrain[i] = NO_TERRAIN;
+        }
+        for &(sx, sy, tile) in structure.foreground_updates.iter() {
+            self.foreground[local(sx, sy)] = tile;
+        }
+        for &(sx, sy, tile) in structure.overlay_updates.iter() {
+            self.overlay[local(sx, sy)] = tile;
+        }
+        for &(sx, sy, mask) in structure.collider_offsets.iter() {
+            self.collision_mask[local(sx, sy)] = mask & COLLIDER_BITS;
+        }
+    }
+
+    fn to_save_data(&self, coord: ChunkCoord) -> ChunkSaveData {
+        ChunkSaveData {
+            x: coord.0,
+            y: coord.1,
+            background: rle_encode(&self.background),
+            foreground: rle_encode(&self.foreground),
+            overlay: rle_encode(&self.overlay),
+            collision_mask: rle_encode(&self.collision_mask),
+            terrain: rle_encode(&self.terrain),
+            projectile_surfaces: rle_encode(
+                &self
+                    .projectile_surfaces
+                    .iter()
+                    .map(|surface| surface.to_u8())
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+
+    fn from_save_data(data: &ChunkSaveData) -> Result<Self, String> {
+        let coord = (data.x, data.y);
+        let decode = |runs: &[[u32; 2]], what: &str| {
+            rle_decode(runs, CHUNK_LEN).ok_or_else(|| format!("chunk {coord:?} {what} size mismatch"))
+        };
+        Ok(Self {
+            background: decode(&data.background, "background")?,
+            foreground: decode(&data.foreground, "foreground")?,
+            overlay: decode(&data.overlay, "overlay")?,
+            collision_mask: decode(&data.collision_mask, "collision mask")?,
+            terrain: decode(&data.terrain, "terrain")?,
+            projectile_surfaces: decode(&data.projectile_surfaces, "projectile surfaces")?
+                .into_iter()
+                .map(ProjectileSurface::from_u8)
+                .collect(),
+            modified: true,
+        })
+    }
+}
+
+/// Render targets for one chunk's layers.
+struct Chunk {
+    background: RenderTarget,
+    foreground: RenderTarget,
+    overlay: RenderTarget,
+    dirty_background: bool,
+    dirty_foreground: bool,
+    dirty_overlay: bool,
+    ready_background: bool,
+    ready_foreground: bool,
+    ready_overlay: bool,
+    /// `TileMap::chunk_frame` when the chunk was last drawn or prewarmed.
+    last_used: u64,
+}
+
+/// A tile map stored in `CHUNK_SIZE` chunks.
+///
+/// With a `WorldGen` attached, chunks are generated on demand as `stream_chunks` walks around
+/// the player, structures are placed the first time a chunk is generated, and unmodified chunks
+/// far away are dropped and regenerated identically later. `width` and `height` only bound the
+/// coordinates, so a generated world can be made very large. Without a generator (Tiled levels,
+/// saves) every chunk is kept. Either way, at most `MAX_CHUNK_TARGETS` chunks hold render targets.
+pub struct TileMap {
+    width: usize,
+    height: usize,
+    tile_size: f32,
+    tiles: HashMap<ChunkCoord, TileChunk>,
+    worldgen: Option<WorldGen>,
+    /// Plans generated chunks off the main thread; started by `stream_chunks`.
+    chunk_worker: Option<ChunkWorker>,
+    chunk_worker_failed: bool,
+    /// Plans from the worker, applied once their chunk is within `STREAM_RADIUS`.
+    prefetched: HashMap<ChunkCoord, ChunkPlan>,
+    /// Where `stream_chunks` was last centered, to tell which way the player is heading.
+    last_stream_center: Option<Vec2>,
+    chunk_cols: usize,
+    chunk_rows: usize,
+    chunk_pixel_size: f32,
+    chunks: HashMap<ChunkCoord, Chunk>,
+    /// Render targets released by evicted chunks, reused before allocating new ones.
+    spare_chunks: Vec<Chunk>,
+    chunk_frame: u64,
+    chunk_alloc_budget_per_frame: usize,
+    chunk_rebuild_budget_per_frame: usize,
+    chunk_allocs_this_frame: usize,
+    chunk_rebuilds_this_frame: usize,
+    structure_defs: Vec<StructureDef>,
+    structure_seed: u32,
+    /// Structures placed in each chunk that has been generated, replayed on regeneration.
+    chunk_structures: HashMap<ChunkCoord, Vec<PlacedStructure>>,
+    structure_interactors: Vec<StructureInteractor>,
+    structure_triggers: Vec<StructureTrigger>,
+    /// Solid rectangles registered at runtime, in world space. Not saved.
+    obstacles: Vec<(ObstacleId, Rect)>,
+    /// Number of obstacles over each tile, so pathfinding can test tiles without scanning them.
+    obstacle_tiles: HashMap<(usize, usize), u32>,
+    next_obstacle: u32,
+    regions: Vec<MapRegion>,
+    markers: Vec<MapMarker>,
+    /// Feed stocked in each trough region, by its top-left tile. Kept while the trough's chunk is
+    /// unloaded.
+    feed: HashMap<(usize, usize), f32>,
+    /// Tiles the player has seen, one flag per chunk-local tile. Kept while chunks are unloaded.
+    explored: HashMap<ChunkCoord, Vec<bool>>,
+    /// Entity ids and world positions requested by newly placed structures.
+    pending_spawns: Vec<EntitySpawn>,
+    /// Vendors wanted by structures recorded since `take_vendor_spawns`.
+    pending_vendors: Vec<VendorSpawn>,
+    /// Chunks whose tiles were loaded or edited since `take_changed_chunks`.
+    changed_chunks: HashSet<ChunkCoord>,
+    /// World centers of structures removed since `take_removed_structures`.
+    removed_structures: Vec<Vec2>,
+    /// Top-left tiles of removed structures that had a vendor, since `take_removed_stalls`.
+    removed_stalls: Vec<(usize, usize)>,
+    autotile: AutotileRules,
+    /// Copied from the tileset, indexed by tile id.
+    tile_properties: Vec<TileProperties>,
+    tile_colors: Vec<Color>,
+    /// Alpha of overlay tiles that are faded or fading back in; absent tiles are opaque.
+    canopy_fade: HashMap<(usize, usize), f32>,
+    grid_size: Vec2,
+    border_thickness: f32,
+    borders: MapBorders,
+    ambient_occlusion: Option<AmbientOcclusion>,
+}
+
+impl TileMap {
+    pub fn new(width: usize, height: usize, tile_size: f32, grid_size: Vec2, border_thickness: f32) -> Self {
+        Self {
+            width,
+            height,
+            tile_size,
+            tiles: HashMap::new(),
+            worldgen: None,
+            chunk_worker: None,
+            chunk_worker_failed: false,
+            prefetched: HashMap::new(),
+            last_stream_center: None,
+            chunk_cols: width.div_ceil(CHUNK_SIZE),
+            chunk_rows: height.div_ceil(CHUNK_SIZE),
+            chunk_pixel_size: tile_size * CHUNK_SIZE as f32,
+            chunks: HashMap::new(),
+            spare_chunks: Vec::new(),
+            chunk_frame: 0,
+            chunk_alloc_budget_per_frame: usize::MAX,
+            chunk_rebuild_budget_per_frame: usize::MAX,
+            chunk_allocs_this_frame: 0,
+            chunk_rebuilds_this_frame: 0,
+            structure_defs: Vec::new(),
+            structure_seed: 0,
+            chunk_structures: HashMap::new(),
+            structure_interactors: Vec::new(),
+            structure_triggers: Vec::new(),
+            obstacles: Vec::new(),
+            obstacle_tiles: HashMap::new(),
+            next_obstacle: 0,
+            regions: Vec::new(),
+            markers: Vec::new(),
+            feed: HashMap::new(),
+            explored: HashMap::new(),
+            pending_spawns: Vec::new(),
+            pending_vendors: Vec::new(),
+            changed_chunks: HashSet::new(),
+            removed_structures: Vec::new(),
+            removed_stalls: Vec::new(),
+            autotile: AutotileRules::default(),
+            tile_properties: Vec::new(),
+            tile_colors: Vec::new(),
+            canopy_fade: HashMap::new(),
+            grid_size,
+            border_thickness,
+            borders: MapBorders::default(),
+            ambient_occlusion: None,
+        }
+    }
+
+    /// Turns tile edge shading on or off; the background chunk textures are baked again.
+    pub fn set_ambient_occlusion(&mut self, occlusion: Option<AmbientOcclusion>) {
+        self.ambient_occlusion = occlusion;
+        for chunk in self.chunks.values_mut() {
+            chunk.dirty_background = true;
+        }
+    }
+
+    pub fn set_chunk_work_budget(&mut self, alloc_per_frame: usize, rebuild_per_frame: usize) {
+        self.chunk_alloc_budget_per_frame = alloc_per_frame.max(1);
+        self.chunk_rebuild_budget_per_frame = rebuild_per_frame.max(1);
+    }
+
+    pub fn begin_frame_chunk_work(&mut self) {
+        self.chunk_frame += 1;
+        self.chunk_allocs_this_frame = 0;
+        self.chunk_rebuilds_this_frame = 0;
     }
 
     pub fn prewarm_visible_chunks(&mut self, camera_target: Vec2, camera_zoom: Vec2) {
         let (min_cx, max_cx, min_cy, max_cy) = self.visible_chunk_range(camera_target, camera_zoom);
         for cy in min_cy..=max_cy {
             for cx in min_cx..=max_cx {
-                let chunk_index = self.chunk_index(cx as usize, cy as usize);
-                if !self.ensure_chunk_allocated(chunk_index) {
+                if !self.ensure_chunk_allocated((cx as usize, cy as usize)) {
                     return;
                 }
             }
         }
     }
 
-    pub fn start_structure_apply(&mut self, defs: Vec<StructureDef>, seed: u32) {
-        self.structure_interactors.clear();
-        self.structure_apply = Some(StructureApplyState::new(self, defs, seed));
+    /// Generates chunks from now on; set the autotile rules first so biome terrains resolve.
+    pub fn set_worldgen(&mut self, worldgen: WorldGen) {
+        self.worldgen = Some(worldgen);
+        self.restart_chunk_worker();
+    }
+
+    /// Structures rolled into each newly generated chunk.
+    pub fn set_structure_defs(&mut self, defs: Vec<StructureDef>, seed: u32) {
+        self.structure_defs = defs;
+        self.structure_seed = seed;
+        self.restart_chunk_worker();
+    }
+
+    /// Generates every missing chunk within `STREAM_RADIUS` chunks of `center` right away,
+    /// nearest first, without the worker.
+    pub fn generate_around(&mut self, center: Vec2) {
+        if self.chunk_cols == 0 || self.chunk_rows == 0 {
+            return;
+        }
+        let center = self.chunk_at(center);
+        for ring in 0..=STREAM_RADIUS {
+            for coord in self.chunk_ring(center, ring) {
+                if !self.tiles.contains_key(&coord) {
+                    self.generate_chunk(coord);
+                }
+            }
+        }
     }
 
-    pub fn apply_structures_step(&mut self, time_budget_s: f32) -> bool {
-        let Some(mut state) = self.structure_apply.take() else {
+    /// Loads up to `max_generate` missing chunks within `STREAM_RADIUS` chunks of `center`,
+    /// nearest first, and evicts unmodified chunks beyond `EVICT_RADIUS`. Returns true once
+    /// every chunk in range is loaded.
+    ///
+    /// Where threads are available, a worker plans new chunks and this only applies the plans
+    /// that are ready, so a chunk requested this frame arrives on a later one. While the player
+    /// moves, the worker also plans the chunks `PREFETCH_DISTANCE` ahead of them, so those are
+    /// usually ready by the time they come into range.
+    pub fn stream_chunks(&mut self, center: Vec2, max_generate: usize) -> bool {
+        if self.chunk_cols == 0 || self.chunk_rows == 0 {
             return true;
-        };
-        let done = state.step(self, time_budget_s);
-        if !done {
-            self.structure_apply = Some(state);
         }
-        done
+        let heading = self.last_stream_center.map_or(Vec2::ZERO, |last| center - last);
+        self.last_stream_center = Some(center);
+        let center_pos = center;
+        let center = self.chunk_at(center);
+        let mut complete = true;
+        if self.worldgen.is_some() {
+            self.receive_chunk_plans(center);
+            let mut generated = 0;
+            for ring in 0..=STREAM_RADIUS {
+                for coord in self.chunk_ring(center, ring) {
+                    if self.tiles.contains_key(&coord) {
+                        continue;
+                    }
+                    if generated < max_generate
+                        && let Some(plan) = self.prefetched.remove(&coord)
+                    {
+                        self.apply_chunk_plan(plan);
+                        generated += 1;
+                    } else if self.prefetched.contains_key(&coord) || self.request_chunk(coord) {
+                        complete = false;
+                    } else if generated < max_generate {
+                        self.generate_chunk(coord);
+                        generated += 1;
+                    } else {
+                        complete = false;
+                    }
+                }
+            }
+            if heading.length_squared() > f32::EPSILON {
+                self.prefetch_ahead(center_pos, heading);
+            }
+        }
+
+        let far: Vec<ChunkCoord> = self
+            .tiles
+            .iter()
+            .filter(|&(&coord, chunk)| !chunk.modified && chunk_distance(coord, center) > EVICT_RADIUS)
+            .map(|(&coord, _)| coord)
+            .collect();
+        for coord in far {
+            self.evict_chunk(coord);
+        }
+        complete
     }
 
-    pub fn structure_apply_progress(&self) -> f32 {
-        self.structure_apply
-            .as_ref()
-            .map(|state| state.progress())
-            .unwrap_or(1.0)
+    /// Fraction of the chunks within `STREAM_RADIUS` of `center` that are loaded.
+    pub fn stream_progress(&self, center: Vec2) -> f32 {
+        let center = self.chunk_at(center);
+        let (mut loaded, mut total) = (0usize, 0usize);
+        for ring in 0..=STREAM_RADIUS {
+            for coord in self.chunk_ring(center, ring) {
+                total += 1;
+                if self.tiles.contains_key(&coord) {
+                    loaded += 1;
+                }
+            }
+        }
+        if total == 0 { 1.0 } else { loaded as f32 / total as f32 }
     }
 
     pub fn structure_interactors(&self) -> &[StructureInteractor] {
         &self.structure_interactors
     }
 
+    /// Trigger volumes with a pin overlapping `rect`.
+    pub fn triggers_overlapping(&self, rect: Rect) -> impl Iterator<Item = &StructureTrigger> {
+        self.structure_triggers
+            .iter()
+            .filter(move |trigger| trigger.rects.iter().any(|pin| pin.overlaps(&rect)))
+    }
+
+    /// True when overlay tiles from something else (e.g. a tree canopy) cover the interactor.
+    pub fn is_interactor_occluded(&self, interactor: &StructureInteractor) -> bool {
+        interactor.own_overlay.iter().any(|&(x, y, own)| {
+            let tile = self.get_tile(LayerKind::Overlay, x, y);
+            tile != EMPTY_TILE && Some(tile) != own
+        })
+    }
+
+    pub fn set_borders(&mut self, borders: MapBorders) {
+        self.borders = borders;
+    }
+
+    pub fn border(&self, edge: MapEdge) -> &MapBorder {
+        self.borders.get(edge)
+    }
+
+    pub fn set_border(&mut self, edge: MapEdge, border: MapBorder) {
+        match edge {
+            MapEdge::North => self.borders.north = border,
+            MapEdge::South => self.borders.south = border,
+            MapEdge::East => self.borders.east = border,
+            MapEdge::West => self.borders.west = border,
+        }
+    }
+
+    /// Moves a hitbox at `pos` back inside the map by each edge's border: solid and transition
+    /// edges stop it, wrapping edges send it to the opposite side once its center is past the
+    /// edge. Also returns the edge it wrapped through or pushed against, if that edge isn't solid.
+    pub fn apply_borders(&self, hitbox: Rect, pos: Vec2) -> (Vec2, Option<MapEdge>) {
+        let bounds = self.get_border_hitbox();
+        let clamped = crate::helpers::clamp_hitbox_to_rect(hitbox, pos, bounds);
+        let center = pos + hitbox.center();
+        let mut out = pos;
+        let mut crossed = None;
+        let edges = [
+            (MapEdge::West, pos.x < clamped.x, center.x < bounds.left()),
+            (MapEdge::East, pos.x > clamped.x, center.x > bounds.right()),
+            (MapEdge::North, pos.y < clamped.y, center.y < bounds.top()),
+            (MapEdge::South, pos.y > clamped.y, center.y > bounds.bottom()),
+        ];
+        for (edge, outside, center_past) in edges {
+            if !outside {
+                continue;
+            }
+            let horizontal = matches!(edge, MapEdge::West | MapEdge::East);
+            match self.borders.get(edge) {
+                MapBorder::Wrap => {
+                    if center_past {
+                        match edge {
+                            MapEdge::West => out.x += bounds.w,
+                            MapEdge::East => out.x -= bounds.w,
+                            MapEdge::North => out.y += bounds.h,
+                            MapEdge::South => out.y -= bounds.h,
+                        }
+                        crossed = Some(edge);
+                    }
+                    continue;
+                }
+                MapBorder::Transition(_) => crossed = Some(edge),
+                MapBorder::Solid => {}
+            }
+            if horizontal {
+                out.x = clamped.x;
+            } else {
+                out.y = clamped.y;
+            }
+        }
+        (out, crossed)
+    }
+
     pub fn get_border_hitbox(&self) -> Rect {
         let world_w = self.width as f32 * self.tile_size;
         let world_h = self.height as f32 * self.tile_size;
@@ -788,246 +1643,994 @@ impl TileMap {
         );
     }
 
-    pub fn place_structure(&mut self, structure: &Structure, x: usize, y: usize) {
-        if x >= self.width || y >= self.height || structure.is_empty() {
-            return;
+    /// Fades the overlay around every hitbox in `occupants` that overlay tiles cover, so
+    /// characters under tree canopies stay visible, and brings the rest back to opaque.
+    pub fn update_canopy_fade(&mut self, occupants: &[Rect], dt: f32) {
+        let mut covered = HashSet::new();
+        for rect in occupants {
+            if !self.overlay_covers(*rect) {
+                continue;
+            }
+            let pad = CANOPY_FADE_PADDING * self.tile_size;
+            let area = Rect::new(rect.x - pad, rect.y - pad, rect.w + pad * 2.0, rect.h + pad * 2.0);
+            for (x, y) in self.tiles_in(area) {
+                if self.get_tile(LayerKind::Overlay, x, y) != EMPTY_TILE {
+                    covered.insert((x, y));
+                }
+            }
+        }
+        let step = CANOPY_FADE_SPEED * dt;
+        for &tile in &covered {
+            let alpha = self.canopy_fade.entry(tile).or_insert(1.0);
+            *alpha = (*alpha - step).max(CANOPY_FADE_ALPHA);
+        }
+        self.canopy_fade.retain(|tile, alpha| {
+            if !covered.contains(tile) {
+                *alpha += step;
+            }
+            *alpha < 1.0
+        });
+    }
+
+    /// Whether any overlay tile lies under a world-space rect.
+    fn overlay_covers(&self, rect: Rect) -> bool {
+        self.tiles_in(rect)
+            .into_iter()
+            .any(|(x, y)| self.get_tile(LayerKind::Overlay, x, y) != EMPTY_TILE)
+    }
+
+    /// Tiles on the map overlapping a world-space rect.
+    fn tiles_in(&self, rect: Rect) -> Vec<(usize, usize)> {
+        let min_x = (rect.x / self.tile_size).floor().max(0.0) as usize;
+        let min_y = (rect.y / self.tile_size).floor().max(0.0) as usize;
+        let max_x = ((rect.right() / self.tile_size).ceil().max(0.0) as usize).min(self.width);
+        let max_y = ((rect.bottom() / self.tile_size).ceil().max(0.0) as usize).min(self.height);
+        (min_y..max_y).flat_map(|y| (min_x..max_x).map(move |x| (x, y))).collect()
+    }
+
+    fn chunk_at(&self, pos: Vec2) -> ChunkCoord {
+        let tile_x = (pos.x / self.tile_size).floor().max(0.0) as usize;
+        let tile_y = (pos.y / self.tile_size).floor().max(0.0) as usize;
+        (
+            (tile_x / CHUNK_SIZE).min(self.chunk_cols.saturating_sub(1)),
+            (tile_y / CHUNK_SIZE).min(self.chunk_rows.saturating_sub(1)),
+        )
+    }
+
+    /// Chunks on the map exactly `ring` chunks (Chebyshev) from `center`.
+    fn chunk_ring(&self, center: ChunkCoord, ring: usize) -> Vec<ChunkCoord> {
+        let (cx, cy) = (center.0 as i64, center.1 as i64);
+        let r = ring as i64;
+        let mut out = Vec::new();
+        for y in cy - r..=cy + r {
+            for x in cx - r..=cx + r {
+                if (x - cx).abs().max((y - cy).abs()) != r {
+                    continue;
+                }
+                if x < 0 || y < 0 || x >= self.chunk_cols as i64 || y >= self.chunk_rows as i64 {
+                    continue;
+                }
+                out.push((x as usize, y as usize));
+            }
         }
+        out
+    }
 
-        if x + structure.width <= self.width && y + structure.height <= self.height {
-            self.place_structure_unchecked(structure, x, y);
+    pub fn chunk_world_rect(&self, coord: ChunkCoord) -> Rect {
+        Rect::new(
+            coord.0 as f32 * self.chunk_pixel_size,
+            coord.1 as f32 * self.chunk_pixel_size,
+            self.chunk_pixel_size,
+            self.chunk_pixel_size,
+        )
+    }
+
+    /// Fills a chunk from the generator right away, on this thread.
+    fn generate_chunk(&mut self, coord: ChunkCoord) {
+        let Some(source) = self.chunk_source() else {
             return;
+        };
+        let plan = plan_chunk(&source, coord);
+        self.apply_chunk_plan(plan);
+    }
+
+    /// What chunk planning reads from the map, or `None` without a generator.
+    fn chunk_source(&self) -> Option<ChunkSource> {
+        let worldgen = self.worldgen.clone()?;
+        let terrain_for = |biome: Biome| self.autotile.terrain_id(biome.tag()).unwrap_or(NO_TERRAIN);
+        Some(ChunkSource {
+            worldgen,
+            autotile: self.autotile.clone(),
+            defs: self.structure_defs.clone(),
+            terrain_ids: Biome::ALL.map(terrain_for),
+            seed: self.structure_seed,
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_size,
+        })
+    }
+
+    /// The chunk worker, started on first use. `None` where threads aren't available, in which
+    /// case chunks generate on this thread.
+    fn chunk_worker(&mut self) -> Option<&mut ChunkWorker> {
+        if self.chunk_worker.is_none() && !self.chunk_worker_failed {
+            let source = self.chunk_source()?;
+            self.chunk_worker = ChunkWorker::spawn(source);
+            self.chunk_worker_failed = self.chunk_worker.is_none();
         }
+        self.chunk_worker.as_mut()
+    }
 
-        let max_x = (x + structure.width).min(self.width);
-        let max_y = (y + structure.height).min(self.height);
-        let mut collision_changed = false;
-        let mut bg_changed = false;
-        let mut fg_changed = false;
-        let mut ov_changed = false;
+    /// Hands `coord` to the chunk worker. False when there is no worker to take it, so the caller
+    /// generates the chunk itself.
+    fn request_chunk(&mut self, coord: ChunkCoord) -> bool {
+        let Some(worker) = self.chunk_worker() else {
+            return false;
+        };
+        if worker.request(coord) {
+            return true;
+        }
+        self.drop_chunk_worker();
+        false
+    }
 
-        for &(sx, sy, tile) in structure.background_updates.iter() {
-            let tx = x + sx;
-            let ty = y + sy;
-            if tx >= max_x || ty >= max_y {
-                continue;
+    /// Gives up on a worker whose thread has stopped; chunks generate on this thread from then on,
+    /// as they do on the web build.
+    fn drop_chunk_worker(&mut self) {
+        eprintln!("chunk worker stopped, generating on the main thread");
+        self.chunk_worker = None;
+        self.chunk_worker_failed = true;
+    }
+
+    /// Stops the chunk worker so the next one starts from the current settings. Plans still in
+    /// flight are dropped with it.
+    fn restart_chunk_worker(&mut self) {
+        self.chunk_worker = None;
+        self.chunk_worker_failed = false;
+        self.prefetched.clear();
+        self.last_stream_center = None;
+    }
+
+    /// Moves every finished plan out of the worker, dropping plans for chunks that are already
+    /// loaded or that the player has left behind.
+    fn receive_chunk_plans(&mut self, center: ChunkCoord) {
+        let Some(worker) = self.chunk_worker.as_mut() else {
+            return;
+        };
+        loop {
+            match worker.try_recv() {
+                Ok(plan) => {
+                    self.prefetched.insert(plan.coord, plan);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.drop_chunk_worker();
+                    break;
+                }
             }
-            let idx = self.idx(tx, ty);
-            if self.background[idx] != tile {
-                self.background[idx] = tile;
-                bg_changed = true;
+        }
+        let keep = EVICT_RADIUS + PREFETCH_DISTANCE;
+        let tiles = &self.tiles;
+        self.prefetched
+            .retain(|&coord, _| !tiles.contains_key(&coord) && chunk_distance(coord, center) <= keep);
+    }
+
+    /// Asks the worker for the chunks around the point `PREFETCH_DISTANCE` chunks along
+    /// `heading`. Requests for chunks in range go first, so this only uses a spare half of the
+    /// worker's queue.
+    fn prefetch_ahead(&mut self, center: Vec2, heading: Vec2) {
+        if self.prefetched.len() >= MAX_PREFETCHED_PLANS {
+            return;
+        }
+        let ahead = center + heading.normalize() * (PREFETCH_DISTANCE as f32 * self.chunk_pixel_size);
+        let ahead = self.chunk_at(ahead);
+        let wanted: Vec<ChunkCoord> = (0..=STREAM_RADIUS)
+            .flat_map(|ring| self.chunk_ring(ahead, ring))
+            .filter(|coord| !self.tiles.contains_key(coord) && !self.prefetched.contains_key(coord))
+            .collect();
+        let Some(worker) = self.chunk_worker.as_mut() else {
+            return;
+        };
+        for coord in wanted {
+            if worker.pending.len() >= MAX_PENDING_CHUNKS / 2 {
+                break;
+            }
+            if !worker.request(coord) {
+                self.drop_chunk_worker();
+                return;
             }
         }
-        for &(sx, sy, tile) in structure.foreground_updates.iter() {
-            let tx = x + sx;
-            let ty = y + sy;
-            if tx >= max_x || ty >= max_y {
-                continue;
+    }
+
+    /// Loads a planned chunk, then places (or replays) its structures and blends its terrain with
+    /// the loaded chunks around it. The plan already autotiled the chunk's inside, so only the
+    /// tiles along its edge and the neighbours' tiles touching it are picked here.
+    fn apply_chunk_plan(&mut self, plan: ChunkPlan) {
+        let coord = plan.coord;
+        let mut chunk = TileChunk::new();
+        chunk.background = plan.background;
+        chunk.foreground = plan.foreground;
+        chunk.overlay = plan.overlay;
+        chunk.terrain = plan.terrain;
+        self.tiles.insert(coord, chunk);
+
+        // Autotile before structures go down so their floors aren't overwritten.
+        let (origin_x, origin_y) = (coord.0 * CHUNK_SIZE, coord.1 * CHUNK_SIZE);
+        let min_x = origin_x.saturating_sub(1);
+        let min_y = origin_y.saturating_sub(1);
+        let max_x = (origin_x + CHUNK_SIZE + 1).min(self.width);
+        let max_y = (origin_y + CHUNK_SIZE + 1).min(self.height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                if !planned_autotile(x, y, (origin_x, origin_y), self.width, self.height) {
+                    self.refresh_autotile(x, y);
+                }
             }
-            let idx = self.idx(tx, ty);
-            if self.foreground[idx] != tile {
-                self.foreground[idx] = tile;
-                fg_changed = true;
+        }
+        self.populate_chunk(coord, plan.structures);
+        self.mark_render_dirty(coord);
+    }
+
+    /// Replays the structures a chunk got when it was first generated, or places the planned
+    /// ones now and queues what they spawn.
+    fn populate_chunk(&mut self, coord: ChunkCoord, planned: Vec<PlacedStructure>) {
+        let defs = std::mem::take(&mut self.structure_defs);
+        let first_time = !self.chunk_structures.contains_key(&coord);
+        let placed = if first_time {
+            planned
+        } else {
+            self.chunk_structures[&coord].clone()
+        };
+        for placed in &placed {
+            if let Some(def) = defs.iter().find(|def| def.id == placed.id) {
+                self.stamp_structure(def, placed.variant, placed.x, placed.y);
+                self.record_structure(def, placed.variant, placed.x, placed.y);
+                if first_time {
+                    self.queue_structure_spawns(def, placed.x, placed.y);
+                }
             }
         }
-        for &(sx, sy, tile) in structure.overlay_updates.iter() {
-            let tx = x + sx;
-            let ty = y + sy;
-            if tx >= max_x || ty >= max_y {
-                continue;
+        if first_time {
+            self.chunk_structures.insert(coord, placed);
+        }
+        self.structure_defs = defs;
+    }
+
+    /// Writes a structure's tiles into its (loaded) chunk; structures never cross chunks.
+    fn stamp_structure(&mut self, def: &StructureDef, variant: usize, x: usize, y: usize) {
+        let (coord, _) = chunk_local(x, y);
+        let Some(chunk) = self.tiles.get_mut(&coord) else {
+            return;
+        };
+        chunk.stamp(def.variant(variant), x % CHUNK_SIZE, y % CHUNK_SIZE);
+    }
+
+    /// Drops a far chunk's tiles, render targets, interactors, regions and markers; it comes back
+    /// unchanged when regenerated.
+    fn evict_chunk(&mut self, coord: ChunkCoord) {
+        self.tiles.remove(&coord);
+        if let Some(chunk) = self.chunks.remove(&coord) {
+            self.spare_chunks.push(chunk);
+        }
+        let bounds = self.chunk_world_rect(coord);
+        self.structure_interactors
+            .retain(|interactor| !bounds.contains(interactor.group_rect.point()));
+        self.structure_triggers
+            .retain(|trigger| !bounds.contains(trigger.group_rect.point()));
+        self.regions.retain(|region| !bounds.contains(region.rect.point()));
+        self.markers.retain(|marker| !bounds.contains(marker.pos));
+    }
+
+    /// Rules used by `set_terrain`; terrain ids already on the map are kept.
+    pub fn set_autotile_rules(&mut self, rules: AutotileRules) {
+        self.autotile = rules;
+        self.restart_chunk_worker();
+    }
+
+    /// Paints `terrain_id` at a tile and re-picks the autotile for it and its eight neighbours.
+    /// Returns false for unknown terrains or tiles outside the map.
+    pub fn set_terrain(&mut self, x: usize, y: usize, terrain_id: &str) -> bool {
+        let Some(terrain) = self.autotile.terrain_id(terrain_id) else {
+            eprintln!("unknown terrain '{terrain_id}'");
+            return false;
+        };
+        self.set_terrain_index(x, y, terrain)
+    }
+
+    /// Removes the terrain at a tile, leaving its tile as is but updating the neighbours' edges.
+    pub fn clear_terrain(&mut self, x: usize, y: usize) -> bool {
+        self.set_terrain_index(x, y, NO_TERRAIN)
+    }
+
+    pub fn terrain_id(&self, id: &str) -> Option<TerrainId> {
+        self.autotile.terrain_id(id)
+    }
+
+    /// Terrain at a tile; `None` outside the map or in a chunk that isn't loaded.
+    pub fn terrain_at(&self, x: usize, y: usize) -> Option<TerrainId> {
+        self.loaded_chunk(x, y).map(|(chunk, i)| chunk.terrain[i])
+    }
+
+    fn set_terrain_index(&mut self, x: usize, y: usize, terrain: TerrainId) -> bool {
+        let Some((chunk, i)) = self.edit_chunk(x, y) else {
+            return false;
+        };
+        chunk.terrain[i] = terrain;
+        for dy in -1..=1i32 {
+            for dx in -1..=1i32 {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if nx >= 0 && ny >= 0 {
+                    self.refresh_autotile(nx as usize, ny as usize);
+                }
             }
-            let idx = self.idx(tx, ty);
-            if self.overlay[idx] != tile {
-                self.overlay[idx] = tile;
-                ov_changed = true;
+        }
+        true
+    }
+
+    /// Re-picks a terrain tile from its neighbours. Neighbours in unloaded chunks count as
+    /// connected and get fixed up when their chunk loads.
+    fn refresh_autotile(&mut self, x: usize, y: usize) {
+        let Some(terrain) = self.terrain_at(x, y) else {
+            return;
+        };
+        let Some(rule) = self.autotile.rule(terrain) else {
+            return;
+        };
+        let mask = self.autotile.mask(terrain, |dx, dy| {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if nx < 0 || ny < 0 {
+                return None;
             }
+            self.terrain_at(nx as usize, ny as usize)
+        });
+        let (layer, tile) = (rule.layer, rule.tile_for(mask));
+        self.write_loaded_tile(layer, x, y, tile);
+    }
+
+    /// What a projectile does on entering a tile: tagged tiles use their tag, other solid tiles
+    /// block.
+    pub fn projectile_surface(&self, x: usize, y: usize) -> ProjectileSurface {
+        if x >= self.width || y >= self.height {
+            return ProjectileSurface::Block;
         }
-        for &(sx, sy, mask) in structure.collider_offsets.iter() {
-            let tx = x + sx;
-            let ty = y + sy;
-            if tx >= max_x || ty >= max_y {
-                continue;
+        let Some((chunk, i)) = self.loaded_chunk(x, y) else {
+            return ProjectileSurface::Open;
+        };
+        match chunk.projectile_surfaces[i] {
+            ProjectileSurface::Open if self.collision_mask_at(x, y) != 0 => ProjectileSurface::Block,
+            surface => surface,
+        }
+    }
+
+    /// Whether nothing that blocks projectiles lies between `from` and `to`. Walks every tile the
+    /// straight line between them crosses, leaving out the two end tiles, which whoever stands
+    /// there may share with a partial collider.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let size = self.tile_size;
+        let tile_of = |pos: Vec2| ((pos.x / size).floor() as i32, (pos.y / size).floor() as i32);
+        let (mut x, mut y) = tile_of(from);
+        let end = tile_of(to);
+        let delta = to - from;
+        let step_x = if delta.x > 0.0 { 1 } else { -1 };
+        let step_y = if delta.y > 0.0 { 1 } else { -1 };
+        // Fractions of the line between tile edges on each axis, and to the next edge. A line
+        // that doesn't move along an axis never reaches one of its edges.
+        let fraction = |distance: f32, length: f32| {
+            if length == 0.0 { f32::INFINITY } else { distance / length.abs() }
+        };
+        let first_edge = |pos: f32, tile: i32, step: i32| {
+            if step > 0 { (tile + 1) as f32 * size - pos } else { pos - tile as f32 * size }
+        };
+        let span_x = fraction(size, delta.x);
+        let span_y = fraction(size, delta.y);
+        let mut next_x = fraction(first_edge(from.x, x, step_x), delta.x);
+        let mut next_y = fraction(first_edge(from.y, y, step_y), delta.y);
+        let steps = (end.0 - x).abs() + (end.1 - y).abs();
+        for _ in 1..steps {
+            if next_x < next_y {
+                x += step_x;
+                next_x += span_x;
+            } else {
+                y += step_y;
+                next_y += span_y;
             }
-            let idx = self.idx(tx, ty);
-            let next_mask = mask & 0x0F;
-            if self.collision_mask[idx] != next_mask {
-                self.collision_mask[idx] = next_mask;
-                self.solid[idx] = next_mask != 0;
-                collision_changed = true;
+            if x < 0 || y < 0 || self.projectile_surface(x as usize, y as usize) != ProjectileSurface::Open {
+                return false;
             }
         }
+        true
+    }
 
-        if collision_changed {
-            self.collision_dirty = true;
+    /// Biome the generator puts at a world position; `None` on maps without a generator.
+    pub fn biome_at(&self, pos: Vec2) -> Option<Biome> {
+        let worldgen = self.worldgen.as_ref()?;
+        if pos.x < 0.0 || pos.y < 0.0 {
+            return None;
         }
+        Some(worldgen.biome((pos.x / self.tile_size) as usize, (pos.y / self.tile_size) as usize))
+    }
 
-        let width = max_x.saturating_sub(x);
-        let height = max_y.saturating_sub(y);
-        self.mark_chunks_dirty_rect(
-            x,
-            y,
-            width,
-            height,
-            bg_changed,
-            fg_changed,
-            ov_changed,
-        );
+    /// Regions covering a world position.
+    pub fn regions_containing(&self, pos: Vec2) -> impl Iterator<Item = &MapRegion> {
+        self.regions.iter().filter(move |region| region.rect.contains(pos))
     }
 
-    fn place_structure_unchecked(&mut self, structure: &Structure, x: usize, y: usize) {
-        let mut collision_changed = false;
-        let mut bg_changed = false;
-        let mut fg_changed = false;
-        let mut ov_changed = false;
+    /// Position of the first loaded marker called `name`.
+    pub fn marker(&self, name: &str) -> Option<Vec2> {
+        self.markers.iter().find(|marker| marker.name == name).map(|marker| marker.pos)
+    }
 
-        for &(sx, sy, tile) in structure.background_updates.iter() {
-            let idx = self.idx(x + sx, y + sy);
-            if self.background[idx] != tile {
-                self.background[idx] = tile;
-                bg_changed = true;
+    fn feed_key(&self, rect: Rect) -> (usize, usize) {
+        (
+            (rect.x / self.tile_size).round().max(0.0) as usize,
+            (rect.y / self.tile_size).round().max(0.0) as usize,
+        )
+    }
+
+    fn trough_at(&self, pos: Vec2) -> Option<Rect> {
+        self.regions_containing(pos)
+            .find(|region| region.tag == TROUGH_TAG)
+            .map(|region| region.rect)
+    }
+
+    /// Adds feed to the trough covering `pos`, up to `capacity`. Returns the new stock, or
+    /// `None` if there is no trough there.
+    pub fn add_feed(&mut self, pos: Vec2, amount: f32, capacity: f32) -> Option<f32> {
+        let key = self.feed_key(self.trough_at(pos)?);
+        let stock = self.feed.entry(key).or_insert(0.0);
+        *stock = (*stock + amount.max(0.0)).min(capacity.max(*stock));
+        Some(*stock)
+    }
+
+    /// Takes up to `amount` feed from the trough covering `pos` and returns how much was taken.
+    pub fn take_feed(&mut self, pos: Vec2, amount: f32) -> f32 {
+        let Some(trough) = self.trough_at(pos) else {
+            return 0.0;
+        };
+        let key = self.feed_key(trough);
+        let Some(stock) = self.feed.get_mut(&key) else {
+            return 0.0;
+        };
+        let taken = amount.clamp(0.0, *stock);
+        *stock -= taken;
+        if *stock <= 0.0 {
+            self.feed.remove(&key);
+        }
+        taken
+    }
+
+    /// Closest loaded trough with feed whose center is within `range` of `pos`.
+    pub fn nearest_feed(&self, pos: Vec2, range: f32) -> Option<Rect> {
+        self.regions
+            .iter()
+            .filter(|region| region.tag == TROUGH_TAG && self.feed.contains_key(&self.feed_key(region.rect)))
+            .map(|region| (region.rect, region.rect.center().distance(pos)))
+            .filter(|&(_, dist)| dist <= range)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(rect, _)| rect)
+    }
+
+    /// Footprint and top-left tile of the closest placed structure tagged `tag` whose center is
+    /// within `range` of `pos`.
+    pub fn nearest_structure(&self, pos: Vec2, tag: &str, range: f32) -> Option<(Rect, (usize, usize))> {
+        let area = Rect::new(pos.x - range, pos.y - range, range * 2.0, range * 2.0);
+        self.structures_in(area)
+            .into_iter()
+            .filter(|(placed, _)| {
+                self.structure_defs
+                    .iter()
+                    .any(|def| def.id == placed.id && def.tags.iter().any(|t| t == tag))
+            })
+            .map(|(placed, rect)| (rect, (placed.x, placed.y), rect.center().distance(pos)))
+            .filter(|&(_, _, dist)| dist <= range)
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(rect, tile, _)| (rect, tile))
+    }
+
+    /// Registers what a placed structure adds beyond its tiles: projectile surfaces, regions,
+    /// markers, interactors and its vendor. Runs again whenever its chunk is regenerated or loaded from a save.
+    fn record_structure(&mut self, def: &StructureDef, variant: usize, x: usize, y: usize) {
+        let surface = ProjectileSurface::from_tags(def.tags.iter().map(String::as_str));
+        if surface != ProjectileSurface::Open {
+            for &(sx, sy, mask) in def.variant(variant).collider_offsets.iter() {
+                if ColliderKind::from_mask(mask) != ColliderKind::Solid {
+                    continue;
+                }
+                if let Some((chunk, i)) = self.loaded_chunk_mut(x + sx, y + sy) {
+                    chunk.projectile_surfaces[i] = surface;
+                }
             }
         }
-        for &(sx, sy, tile) in structure.foreground_updates.iter() {
-            let idx = self.idx(x + sx, y + sy);
-            if self.foreground[idx] != tile {
-                self.foreground[idx] = tile;
-                fg_changed = true;
+        for region in def.regions.iter() {
+            let origin = self.tile_bounds(x + region.x, y + region.y);
+            self.regions.push(MapRegion {
+                tag: region.tag.clone(),
+                rect: Rect::new(
+                    origin.x,
+                    origin.y,
+                    region.width as f32 * self.tile_size,
+                    region.height as f32 * self.tile_size,
+                ),
+            });
+        }
+        for marker in def.markers.iter() {
+            self.markers.push(MapMarker {
+                name: marker.name.clone(),
+                pos: self.tile_bounds(x + marker.x, y + marker.y).center(),
+            });
+        }
+        self.register_structure_interactors(def, variant, x, y);
+        self.register_structure_trigger(def, variant, x, y);
+        if let Some(vendor) = def.vendor.as_ref() {
+            let origin = self.tile_bounds(x, y);
+            self.pending_vendors.push(VendorSpawn {
+                stall: (x, y),
+                entity: vendor.entity.clone(),
+                pos: self.tile_bounds(x + vendor.x, y + vendor.y).center(),
+                footprint: Rect::new(
+                    origin.x,
+                    origin.y,
+                    def.structure.width as f32 * self.tile_size,
+                    def.structure.height as f32 * self.tile_size,
+                ),
+            });
+        }
+    }
+
+    fn register_structure_trigger(&mut self, def: &StructureDef, variant: usize, x: usize, y: usize) {
+        if def.on_trigger.is_empty() {
+            return;
+        }
+        let mut rects = Vec::new();
+        for &(sx, sy, mask) in def.variant(variant).collider_offsets.iter() {
+            if ColliderKind::from_mask(mask) == ColliderKind::Trigger {
+                pin_rects(self.tile_bounds(x + sx, y + sy), mask, &mut rects);
             }
         }
-        for &(sx, sy, tile) in structure.overlay_updates.iter() {
-            let idx = self.idx(x + sx, y + sy);
-            if self.overlay[idx] != tile {
-                self.overlay[idx] = tile;
-                ov_changed = true;
+        let Some(&first) = rects.first() else {
+            return;
+        };
+        let group_rect = rects.iter().skip(1).fold(first, |group, rect| merge_rect(group, *rect));
+        self.structure_triggers.push(StructureTrigger {
+            structure_id: def.id.clone(),
+            group_rect,
+            rects,
+            on_trigger: def.on_trigger.clone(),
+        });
+    }
+
+    /// Spawns only happen the first time a structure is placed, not when its chunk regenerates.
+    fn queue_structure_spawns(&mut self, def: &StructureDef, x: usize, y: usize) {
+        if def.spawns.is_empty() {
+            return;
+        }
+        let origin = self.tile_bounds(x, y);
+        let center = vec2(
+            origin.x + def.structure.width as f32 * self.tile_size * 0.5,
+            origin.y + def.structure.height as f32 * self.tile_size * 0.5,
+        );
+        for spawn in def.spawns.iter() {
+            for _ in 0..spawn.count {
+                self.pending_spawns.push(EntitySpawn {
+                    entity: spawn.entity.clone(),
+                    pos: center,
+                    time: spawn.time,
+                });
             }
         }
-        for &(sx, sy, mask) in structure.collider_offsets.iter() {
-            let idx = self.idx(x + sx, y + sy);
-            let next_mask = mask & 0x0F;
-            if self.collision_mask[idx] != next_mask {
-                self.collision_mask[idx] = next_mask;
-                self.solid[idx] = next_mask != 0;
-                collision_changed = true;
+    }
+
+    /// Takes the entity spawns requested by structures placed since the last call.
+    pub fn take_structure_spawns(&mut self) -> Vec<EntitySpawn> {
+        std::mem::take(&mut self.pending_spawns)
+    }
+
+    /// Takes the vendors wanted by structures loaded since the last call. A structure asks again
+    /// every time its chunk loads, so callers skip the ones already bound.
+    pub fn take_vendor_spawns(&mut self) -> Vec<VendorSpawn> {
+        std::mem::take(&mut self.pending_vendors)
+    }
+
+    /// Marks every tile whose center is within `radius` of `center` as explored.
+    pub fn reveal_circle(&mut self, center: Vec2, radius: f32) {
+        if radius <= 0.0 {
+            return;
+        }
+        let Some((min_x, min_y, max_x, max_y)) = self.tile_span(Rect::new(
+            center.x - radius,
+            center.y - radius,
+            radius * 2.0,
+            radius * 2.0,
+        )) else {
+            return;
+        };
+        let radius_sq = radius * radius;
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let tile_center = vec2((x as f32 + 0.5) * self.tile_size, (y as f32 + 0.5) * self.tile_size);
+                if tile_center.distance_squared(center) > radius_sq {
+                    continue;
+                }
+                let (coord, i) = chunk_local(x, y);
+                self.explored.entry(coord).or_insert_with(|| vec![false; CHUNK_LEN])[i] = true;
             }
         }
+    }
+
+    pub fn is_explored(&self, x: usize, y: usize) -> bool {
+        let (coord, i) = chunk_local(x, y);
+        self.explored.get(&coord).is_some_and(|tiles| tiles[i])
+    }
 
-        if collision_changed {
-            self.collision_dirty = true;
+    /// Covers the unexplored tiles inside `area` (in world space) with `color`. Call with the
+    /// world camera set, after everything the fog should hide has been drawn.
+    pub fn draw_fog(&self, area: Rect, color: Color) {
+        let Some((min_x, min_y, max_x, max_y)) = self.tile_span(area) else {
+            return;
+        };
+        // One rectangle per horizontal run of unexplored tiles.
+        for y in min_y..=max_y {
+            let mut run_start = None;
+            for x in min_x..=max_x + 1 {
+                let hidden = x <= max_x && !self.is_explored(x, y);
+                match (hidden, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        draw_rectangle(
+                            start as f32 * self.tile_size,
+                            y as f32 * self.tile_size,
+                            (x - start) as f32 * self.tile_size,
+                            self.tile_size,
+                            color,
+                        );
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
         }
+    }
 
-        self.mark_chunks_dirty_rect(
-            x,
-            y,
-            structure.width,
-            structure.height,
-            bg_changed,
-            fg_changed,
-            ov_changed,
-        );
+    /// Inclusive tile range covering a world rect, clamped to the map.
+    fn tile_span(&self, area: Rect) -> Option<(usize, usize, usize, usize)> {
+        if self.width == 0 || self.height == 0 || area.right() < 0.0 || area.bottom() < 0.0 {
+            return None;
+        }
+        let to_tile = |v: f32, limit: usize| ((v / self.tile_size).floor().max(0.0) as usize).min(limit - 1);
+        Some((
+            to_tile(area.x, self.width),
+            to_tile(area.y, self.height),
+            to_tile(area.right(), self.width),
+            to_tile(area.bottom(), self.height),
+        ))
     }
 
-    pub fn apply_structures(&mut self, defs: &[StructureDef], seed: u32) {
-        self.structure_interactors.clear();
-        let mut occupied = vec![false; self.width * self.height];
-        let mut placed_rects: Vec<Rect> = Vec::new();
+    pub fn to_save_data(&self) -> MapSaveData {
+        let mut chunks: Vec<ChunkSaveData> = self
+            .tiles
+            .iter()
+            .filter(|(_, chunk)| chunk.modified)
+            .map(|(&coord, chunk)| chunk.to_save_data(coord))
+            .collect();
+        chunks.sort_by_key(|chunk| (chunk.y, chunk.x));
+        let mut generated: Vec<[usize; 2]> = self.chunk_structures.keys().map(|&(x, y)| [x, y]).collect();
+        generated.sort_by_key(|&[x, y]| (y, x));
+        let structures = generated
+            .iter()
+            .flat_map(|&[x, y]| self.chunk_structures[&(x, y)].iter().cloned())
+            .collect();
+        let mut feed: Vec<([usize; 2], f32)> = self.feed.iter().map(|(&(x, y), &stock)| ([x, y], stock)).collect();
+        feed.sort_by_key(|&([x, y], _)| (y, x));
+        let mut explored: Vec<ExploredSaveData> = self
+            .explored
+            .iter()
+            .map(|(&(x, y), tiles)| ExploredSaveData {
+                x,
+                y,
+                tiles: rle_encode(&tiles.iter().map(|&seen| seen as u8).collect::<Vec<u8>>()),
+            })
+            .collect();
+        explored.sort_by_key(|chunk| (chunk.y, chunk.x));
+        MapSaveData {
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_size,
+            grid_size: self.grid_size.to_array(),
+            border_thickness: self.border_thickness,
+            chunks,
+            generated,
+            structures,
+            feed,
+            explored,
+        }
+    }
 
-        let world_w = self.width as f32 * self.tile_size;
-        let world_h = self.height as f32 * self.tile_size;
-        let cell_size = self.chunk_pixel_size.max(self.tile_size);
-        let cell_cols = ((world_w / cell_size).ceil() as usize).max(1);
-        let cell_rows = ((world_h / cell_size).ceil() as usize).max(1);
-        let mut spatial: Vec<Vec<usize>> = vec![Vec::new(); cell_cols * cell_rows];
-
-        let area = (self.width * self.height) as f32;
-        for (def_index, def) in defs.iter().enumerate() {
-            let freq = def.frequency.clamp(0.0, 1.0);
-            if freq <= 0.0 || def.max_per_map == 0 || def.structure.is_empty() {
-                continue;
+    /// Rebuilds a map from a save. Interactors of structures in saved chunks are re-registered
+    /// from `defs`; the rest come back when their chunks regenerate. Structures whose definition
+    /// no longer exists keep their tiles but lose their interactions. Attach the generator and
+    /// structure definitions again afterwards.
+    pub fn from_save_data(data: MapSaveData, defs: &[StructureDef]) -> Result<Self, String> {
+        let mut map = Self::new(
+            data.width,
+            data.height,
+            data.tile_size,
+            Vec2::from(data.grid_size),
+            data.border_thickness,
+        );
+        for saved in &data.chunks {
+            if saved.x >= map.chunk_cols || saved.y >= map.chunk_rows {
+                return Err(format!("chunk ({}, {}) is outside the map", saved.x, saved.y));
             }
+            map.tiles.insert((saved.x, saved.y), TileChunk::from_save_data(saved)?);
+        }
+        for &[x, y] in &data.generated {
+            map.chunk_structures.insert((x, y), Vec::new());
+        }
+        map.feed = data.feed.iter().map(|&([x, y], stock)| ((x, y), stock)).collect();
+        for saved in &data.explored {
+            let tiles = rle_decode(&saved.tiles, CHUNK_LEN)
+                .ok_or_else(|| format!("explored tiles of chunk ({}, {}) are corrupt", saved.x, saved.y))?;
+            map.explored.insert((saved.x, saved.y), tiles.into_iter().map(|seen| seen != 0).collect());
+        }
 
-            let target = ((area * freq).round() as usize).min(def.max_per_map);
-            if target == 0 {
-                continue;
+        for placed in data.structures {
+            let (coord, _) = chunk_local(placed.x, placed.y);
+            match defs.iter().find(|def| def.id == placed.id) {
+                Some(def) => {
+                    if map.tiles.contains_key(&coord) {
+                        map.record_structure(def, placed.variant, placed.x, placed.y);
+                    }
+                }
+                None => eprintln!("saved structure '{}' has no definition; skipping its interactors", placed.id),
             }
+            map.chunk_structures.entry(coord).or_default().push(placed);
+        }
+        Ok(map)
+    }
 
-            let attempts = (target * 12).max(24);
-            if def.structure.width == 0
-                || def.structure.height == 0
-                || self.width < def.structure.width
-                || self.height < def.structure.height
-            {
-                continue;
+    /// Loads a level authored in Tiled.
+    ///
+    /// Tile layers map to `LayerKind` through a `layer` property or their name
+    /// (background/foreground/overlay), falling back to file order. Objects whose class (or
+    /// object layer name) is `collider` become collision (a `projectile` property of `block` or
+    /// `reflect` sets how they treat projectiles), `interactor` objects become
+    /// interactors configured by their `on_interact`, `interact_range`, `hold_time` and
+    /// `keep_progress` properties, `region` objects become regions tagged with their name and
+    /// `marker` objects become markers at their center, named after the object. The map's
+    /// `border_*` properties set its `MapBorders`. Only the first tileset is used for rendering.
+    pub async fn load_tmx(path: &str, border_thickness: f32) -> Result<TmxLevel, Box<dyn std::error::Error>> {
+        let path = data_path(path);
+        let base_dir = Path::new(&path).parent().map(Path::to_path_buf).unwrap_or_default();
+        let resolve = |rel: &str| base_dir.join(rel).to_string_lossy().replace('\\', "/");
+
+        let doc = tmx::parse_tmx(&load_string(&path).await?)?;
+        if doc.tile_width != doc.tile_height {
+            return Err(TmxError(format!(
+                "non-square tiles ({}x{}) are not supported",
+                doc.tile_width, doc.tile_height
+            ))
+            .into());
+        }
+        let Some(tileset_ref) = doc.tilesets.first() else {
+            return Err(TmxError("map has no tileset".to_string()).into());
+        };
+        if doc.tilesets.len() > 1 {
+            eprintln!("{path}: only the first of {} tilesets is used", doc.tilesets.len());
+        }
+        let external;
+        let (tileset_def, image_path) = match (&tileset_ref.tileset, &tileset_ref.source) {
+            (Some(embedded), _) => (embedded, resolve(&embedded.image)),
+            (None, Some(source)) => {
+                let tsx_path = resolve(source);
+                external = tmx::parse_tsx(&load_string(&tsx_path).await?)?;
+                let tsx_dir = Path::new(&tsx_path).parent().map(Path::to_path_buf).unwrap_or_default();
+                let image = tsx_dir.join(&external.image).to_string_lossy().replace('\\', "/");
+                (&external, image)
             }
-            let max_x = self.width - def.structure.width;
-            let max_y = self.height - def.structure.height;
+            (None, None) => return Err(TmxError("tileset has neither image nor source".to_string()).into()),
+        };
+        let texture = load_texture(&image_path).await?;
+        let tileset = TileSet::from_grid(
+            texture,
+            image_path.clone(),
+            vec2(tileset_def.tile_width, tileset_def.tile_height),
+            tileset_def.columns,
+            tileset_def.tile_count,
+        );
+        let first_gid = tileset_ref.first_gid;
+        let tile_count = tileset_def.tile_count as u32;
 
-            let mut count = 0usize;
-            for i in 0..attempts {
-                if count >= target {
-                    break;
-                }
-                let def_seed = (def_index as u32).wrapping_mul(2654435761);
-                let def_seed_y = (def_index as u32).wrapping_mul(2246822519);
-                let rx = hash_u32(i as u32, seed ^ def_seed, 31);
-                let ry = hash_u32(i as u32, seed ^ def_seed_y, 47);
-                let x = (rx as usize % (max_x + 1)).min(max_x);
-                let y = (ry as usize % (max_y + 1)).min(max_y);
-
-                let pos = vec2(x as f32 * self.tile_size, y as f32 * self.tile_size);
-                let size = vec2(
-                    def.structure.width as f32 * self.tile_size,
-                    def.structure.height as f32 * self.tile_size,
-                );
-                let rect = Rect::new(pos.x, pos.y, size.x, size.y);
-                let padded = if def.min_distance > 0.0 {
-                    Rect::new(
-                        rect.x - def.min_distance,
-                        rect.y - def.min_distance,
-                        rect.w + def.min_distance * 2.0,
-                        rect.h + def.min_distance * 2.0,
-                    )
+        let tile_size = doc.tile_width;
+        let mut map = Self::new(
+            doc.width,
+            doc.height,
+            tile_size,
+            vec2(tile_size, tile_size),
+            border_thickness,
+        );
+
+        let mut skipped_gids = 0usize;
+        for (order, layer) in doc.layers.iter().enumerate() {
+            let Some(kind) = tmx_layer_kind(&layer.name, layer.properties.get("layer"), order) else {
+                eprintln!("{path}: layer '{}' has no matching LayerKind; skipping", layer.name);
+                continue;
+            };
+            for (i, &gid) in layer.gids.iter().enumerate().take(doc.width * doc.height) {
+                let tile = if gid == 0 {
+                    EMPTY_TILE
+                } else if gid >= first_gid && gid - first_gid < tile_count && gid - first_gid < EMPTY_TILE as u32 {
+                    (gid - first_gid) as u8
                 } else {
-                    rect
+                    skipped_gids += 1;
+                    EMPTY_TILE
                 };
-
-                if spatial_overlaps(&padded, &placed_rects, &spatial, cell_size, cell_cols, cell_rows) {
-                    continue;
+                if tile != EMPTY_TILE {
+                    map.set_tile(kind, i % doc.width, i / doc.width, tile);
                 }
+            }
+        }
+        if skipped_gids > 0 {
+            eprintln!("{path}: {skipped_gids} tiles reference other tilesets or ids past 254; left empty");
+        }
 
-                let mut blocked = false;
-                for &(sx, sy) in def.structure.occupied_offsets.iter() {
-                    let idx = self.idx(x + sx, y + sy);
-                    if occupied[idx] {
-                        blocked = true;
-                        break;
-                    }
+        map.borders = MapBorders::from_tmx_properties(&doc.properties);
+        for group in &doc.object_groups {
+            for object in &group.objects {
+                let class = if object.class.is_empty() {
+                    group.name.as_str()
+                } else {
+                    object.class.as_str()
+                };
+                match class.to_ascii_lowercase().as_str() {
+                    "collider" | "colliders" | "collision" => map.add_tmx_collider(object),
+                    "interactor" | "interactors" => map.add_tmx_interactor(object),
+                    "region" | "regions" => map.add_tmx_region(object),
+                    "marker" | "markers" => map.add_tmx_marker(object),
+                    _ => {}
                 }
+            }
+        }
+
+        Ok(TmxLevel { map, tileset })
+    }
 
-                if blocked {
+    fn add_tmx_region(&mut self, object: &TmxObject) {
+        if object.name.is_empty() || object.width <= 0.0 || object.height <= 0.0 {
+            eprintln!("tmx region at ({}, {}) needs a name and a size; skipping", object.x, object.y);
+            return;
+        }
+        self.regions.push(MapRegion {
+            tag: object.name.clone(),
+            rect: Rect::new(object.x, object.y, object.width, object.height),
+        });
+    }
+
+    /// Point objects mark their position; rectangles mark their center.
+    fn add_tmx_marker(&mut self, object: &TmxObject) {
+        if object.name.is_empty() {
+            eprintln!("tmx marker at ({}, {}) has no name; skipping", object.x, object.y);
+            return;
+        }
+        self.markers.push(MapMarker {
+            name: object.name.clone(),
+            pos: vec2(object.x + object.width * 0.5, object.y + object.height * 0.5),
+        });
+    }
+
+    /// Sets collision pins for every tile quadrant the object's rectangle covers.
+    fn add_tmx_collider(&mut self, object: &TmxObject) {
+        let rect = Rect::new(object.x, object.y, object.width, object.height);
+        let surface = ProjectileSurface::from_tags(object.properties.get("projectile").map(String::as_str));
+        for (x, y, mask) in self.quadrants_covered(rect) {
+            let Some((chunk, i)) = self.edit_chunk(x, y) else {
+                continue;
+            };
+            chunk.collision_mask[i] |= mask;
+            if surface != ProjectileSurface::Open {
+                chunk.projectile_surfaces[i] = surface;
+            }
+        }
+    }
+
+    fn add_tmx_interactor(&mut self, object: &TmxObject) {
+        let group_rect = Rect::new(object.x, object.y, object.width, object.height);
+        let on_interact: Vec<String> = object
+            .properties
+            .get("on_interact")
+            .map(|list| {
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        if on_interact.is_empty() {
+            eprintln!("tmx interactor '{}' has no on_interact; skipping", object.name);
+            return;
+        }
+        let prop = |key: &str| object.properties.get(key).and_then(|v| v.parse::<f32>().ok());
+        let structure_id = if object.name.is_empty() {
+            "tmx_interactor".to_string()
+        } else {
+            object.name.clone()
+        };
+        let half = self.tile_size * 0.5;
+        let covered = self.quadrants_covered(group_rect);
+        let own_overlay: Vec<(usize, usize, Option<u8>)> = covered
+            .iter()
+            .map(|&(x, y, _)| {
+                let tile = self.get_tile(LayerKind::Overlay, x, y);
+                (x, y, (tile != EMPTY_TILE).then_some(tile))
+            })
+            .collect();
+        for (x, y, mask) in covered {
+            let tile_x = x as f32 * self.tile_size;
+            let tile_y = y as f32 * self.tile_size;
+            for (bit, offset) in [(0b0001, vec2(0.0, 0.0)), (0b0010, vec2(half, 0.0)), (0b0100, vec2(0.0, half)), (0b1000, vec2(half, half))] {
+                if (mask & bit) == 0 {
                     continue;
                 }
+                self.structure_interactors.push(StructureInteractor {
+                    structure_id: structure_id.clone(),
+                    rect: Rect::new(tile_x + offset.x, tile_y + offset.y, half, half),
+                    group_rect,
+                    on_interact: on_interact.clone(),
+                    interact_range_world: prop("interact_range").unwrap_or(0.0).max(0.0) * self.tile_size,
+                    highlight: HighlightStyle::default(),
+                    hold_time: prop("hold_time").unwrap_or(0.0).max(0.0),
+                    keep_progress: object.properties.get("keep_progress").map(|v| v == "true").unwrap_or(false),
+                    own_overlay: own_overlay.clone(),
+                });
+            }
+        }
+    }
 
-                self.place_structure_unchecked(&def.structure, x, y);
-                self.register_structure_interactors(def, x, y);
-                for &(sx, sy) in def.structure.occupied_offsets.iter() {
-                    let idx = self.idx(x + sx, y + sy);
-                    occupied[idx] = true;
+    /// Tiles touched by `rect` with the 4-bit quadrant mask (same layout as collider pins).
+    fn quadrants_covered(&self, rect: Rect) -> Vec<(usize, usize, u8)> {
+        let mut out = Vec::new();
+        if rect.w <= 0.0 || rect.h <= 0.0 {
+            return out;
+        }
+        let half = self.tile_size * 0.5;
+        let min_x = (rect.x / self.tile_size).floor().max(0.0) as usize;
+        let min_y = (rect.y / self.tile_size).floor().max(0.0) as usize;
+        let max_x = (((rect.x + rect.w) / self.tile_size).ceil() as usize).min(self.width);
+        let max_y = (((rect.y + rect.h) / self.tile_size).ceil() as usize).min(self.height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let tile_x = x as f32 * self.tile_size;
+                let tile_y = y as f32 * self.tile_size;
+                let mut mask = 0u8;
+                for (bit, qx, qy) in [(0b0001, 0.0, 0.0), (0b0010, half, 0.0), (0b0100, 0.0, half), (0b1000, half, half)] {
+                    let quadrant = Rect::new(tile_x + qx, tile_y + qy, half, half);
+                    // Strict overlap so rectangles that only touch an edge don't claim it.
+                    if rect.x < quadrant.x + quadrant.w
+                        && quadrant.x < rect.x + rect.w
+                        && rect.y < quadrant.y + quadrant.h
+                        && quadrant.y < rect.y + rect.h
+                    {
+                        mask |= bit;
+                    }
+                }
+                if mask != 0 {
+                    out.push((x, y, mask));
                 }
-
-                placed_rects.push(padded);
-                let rect_index = placed_rects.len() - 1;
-                spatial_insert(rect_index, &padded, &mut spatial, cell_size, cell_cols, cell_rows);
-                count += 1;
             }
         }
+        out
     }
 
-    fn register_structure_interactors(&mut self, def: &StructureDef, x: usize, y: usize) {
-        if def.structure.interactor_offsets.is_empty() || def.on_interact.is_empty() {
+    fn register_structure_interactors(&mut self, def: &StructureDef, variant: usize, x: usize, y: usize) {
+        let structure = def.variant(variant);
+        if structure.interactor_offsets.is_empty() || def.on_interact.is_empty() {
             return;
         }
         let tile_size = self.tile_size;
         let mut rects: Vec<Rect> = Vec::new();
-        for &(sx, sy, mask) in def.structure.interactor_offsets.iter() {
+        for &(sx, sy, mask) in structure.interactor_offsets.iter() {
             let tile_x = (x + sx) as f32 * tile_size;
             let tile_y = (y + sy) as f32 * tile_size;
             let half_w = tile_size * 0.5;
@@ -1036,151 +2639,548 @@ impl TileMap {
             if (mask & 0b0001) != 0 {
                 rects.push(Rect::new(tile_x, tile_y, half_w, half_h));
             }
-            if (mask & 0b0010) != 0 {
-                rects.push(Rect::new(tile_x + half_w, tile_y, half_w, half_h));
+            if (mask & 0b0010) != 0 {
+                rects.push(Rect::new(tile_x + half_w, tile_y, half_w, half_h));
+            }
+            if (mask & 0b0100) != 0 {
+                rects.push(Rect::new(tile_x, tile_y + half_h, half_w, half_h));
+            }
+            if (mask & 0b1000) != 0 {
+                rects.push(Rect::new(tile_x + half_w, tile_y + half_h, half_w, half_h));
+            }
+        }
+
+        if rects.is_empty() {
+            return;
+        }
+        let interact_range_world = def.interact_range * tile_size;
+        let own_overlay: Vec<(usize, usize, Option<u8>)> = structure
+            .interactor_offsets
+            .iter()
+            .map(|&(sx, sy, _)| {
+                let own = structure
+                    .overlay_updates
+                    .iter()
+                    .find(|&&(ox, oy, _)| ox == sx && oy == sy)
+                    .map(|&(_, _, tile)| tile);
+                (x + sx, y + sy, own)
+            })
+            .collect();
+
+        let mut group = rects[0];
+        for rect in rects.iter().skip(1) {
+            group = merge_rect(group, *rect);
+        }
+
+        for rect in rects {
+            self.structure_interactors.push(StructureInteractor {
+                structure_id: def.id.clone(),
+                rect,
+                group_rect: group,
+                on_interact: def.on_interact.clone(),
+                interact_range_world,
+                highlight: def.highlight.clone(),
+                hold_time: def.hold_time,
+                keep_progress: def.keep_progress,
+                own_overlay: own_overlay.clone(),
+            });
+        }
+    }
+
+    pub fn set_tile(&mut self, layer: LayerKind, x: usize, y: usize, id: u8) {
+        let Some((chunk, i)) = self.edit_chunk(x, y) else {
+            return;
+        };
+        let tiles = chunk.layer_mut(layer);
+        if tiles[i] == id {
+            return;
+        }
+        tiles[i] = id;
+        self.mark_chunk_dirty(x, y, layer);
+    }
+
+    /// Sets every tile of `rect` on the map to `id`, generating chunks as needed like `set_tile`.
+    /// Each chunk is looked up and marked dirty once rather than per tile. Returns the number of
+    /// tiles changed.
+    pub fn fill_rect(&mut self, layer: LayerKind, rect: TileRect, id: u8) -> usize {
+        let x_end = (rect.x + rect.w).min(self.width);
+        let y_end = (rect.y + rect.h).min(self.height);
+        if rect.x >= x_end || rect.y >= y_end {
+            return 0;
+        }
+        let mut changed = 0;
+        for cy in rect.y / CHUNK_SIZE..=(y_end - 1) / CHUNK_SIZE {
+            for cx in rect.x / CHUNK_SIZE..=(x_end - 1) / CHUNK_SIZE {
+                let xs = rect.x.max(cx * CHUNK_SIZE)..x_end.min((cx + 1) * CHUNK_SIZE);
+                let ys = rect.y.max(cy * CHUNK_SIZE)..y_end.min((cy + 1) * CHUNK_SIZE);
+                let Some((chunk, _)) = self.edit_chunk(xs.start, ys.start) else {
+                    continue;
+                };
+                let tiles = chunk.layer_mut(layer);
+                let mut chunk_changed = 0;
+                for y in ys {
+                    for x in xs.clone() {
+                        let (_, i) = chunk_local(x, y);
+                        if tiles[i] != id {
+                            tiles[i] = id;
+                            chunk_changed += 1;
+                        }
+                    }
+                }
+                if chunk_changed > 0 {
+                    self.mark_layer_dirty((cx, cy), layer);
+                    changed += chunk_changed;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Replaces the 4-connected area of tiles matching the one at `(x, y)` with `id`. The fill
+    /// stays within loaded chunks and stops after `MAX_FLOOD_TILES`; the chunks it reaches are
+    /// marked edited and dirty once each. Returns the number of tiles changed.
+    pub fn flood_fill(&mut self, layer: LayerKind, x: usize, y: usize, id: u8) -> usize {
+        let Some((chunk, i)) = self.loaded_chunk(x, y) else {
+            return 0;
+        };
+        let target = chunk.layer(layer)[i];
+        if target == id {
+            return 0;
+        }
+        let mut touched = HashSet::new();
+        let mut open = vec![(x, y)];
+        let mut changed = 0;
+        while let Some((x, y)) = open.pop() {
+            if changed >= MAX_FLOOD_TILES {
+                break;
+            }
+            let Some((chunk, i)) = self.loaded_chunk_mut(x, y) else {
+                continue;
+            };
+            let tiles = chunk.layer_mut(layer);
+            if tiles[i] != target {
+                continue;
             }
-            if (mask & 0b0100) != 0 {
-                rects.push(Rect::new(tile_x, tile_y + half_h, half_w, half_h));
+            tiles[i] = id;
+            chunk.modified = true;
+            changed += 1;
+            touched.insert(chunk_local(x, y).0);
+            if x > 0 {
+                open.push((x - 1, y));
             }
-            if (mask & 0b1000) != 0 {
-                rects.push(Rect::new(tile_x + half_w, tile_y + half_h, half_w, half_h));
+            if y > 0 {
+                open.push((x, y - 1));
             }
+            open.push((x + 1, y));
+            open.push((x, y + 1));
+        }
+        for coord in touched {
+            self.mark_layer_dirty(coord, layer);
         }
+        changed
+    }
 
-        if rects.is_empty() {
+    /// Sets a tile in a loaded chunk without marking the chunk as edited; used for tiles that
+    /// regeneration reproduces anyway.
+    fn write_loaded_tile(&mut self, layer: LayerKind, x: usize, y: usize, id: u8) {
+        let Some((chunk, i)) = self.loaded_chunk_mut(x, y) else {
+            return;
+        };
+        let tiles = chunk.layer_mut(layer);
+        if tiles[i] == id {
             return;
         }
-        let interact_range_world = def.interact_range * tile_size;
+        tiles[i] = id;
+        self.mark_chunk_dirty(x, y, layer);
+    }
 
-        let mut group = rects[0];
-        for rect in rects.iter().skip(1) {
-            group = merge_rect(group, *rect);
+    /// Brush stroke for editors: sets one tile, ignoring positions off the map. Returns whether
+    /// the tile changed.
+    pub fn paint_tile(&mut self, layer: LayerKind, x: usize, y: usize, id: u8) -> bool {
+        if x >= self.width || y >= self.height || self.get_tile(layer, x, y) == id {
+            return false;
         }
+        self.set_tile(layer, x, y, id);
+        true
+    }
 
-        for rect in rects {
-            self.structure_interactors.push(StructureInteractor {
-                structure_id: def.id.clone(),
-                rect,
-                group_rect: group,
-                on_interact: def.on_interact.clone(),
-                interact_range_world,
-            });
-        }
+    pub fn erase_tile(&mut self, layer: LayerKind, x: usize, y: usize) -> bool {
+        self.paint_tile(layer, x, y, EMPTY_TILE)
     }
 
-    pub fn fill_layer(&mut self, layer: LayerKind, id: u8) {
-        let tiles = match layer {
-            LayerKind::Background => &mut self.background,
-            LayerKind::Foreground => &mut self.foreground,
-            LayerKind::Overlay => &mut self.overlay,
-        };
-        if tiles.iter().all(|&tile| tile == id) {
-            return;
+    /// Places a structure by hand with its origin at `(x, y)`, skipping the placement rules
+    /// but not the chunk boundary. The structure is remembered like a generated one and its
+    /// spawns are queued. Returns false when it doesn't fit in the chunk.
+    pub fn place_structure(&mut self, def: &StructureDef, x: usize, y: usize) -> bool {
+        let (coord, _) = chunk_local(x, y);
+        let (lx, ly) = (x % CHUNK_SIZE, y % CHUNK_SIZE);
+        let fits = def.structure.width > 0
+            && def.structure.height > 0
+            && lx + def.structure.width <= CHUNK_SIZE
+            && ly + def.structure.height <= CHUNK_SIZE
+            && x + def.structure.width <= self.width
+            && y + def.structure.height <= self.height;
+        if !fits || self.edit_chunk(x, y).is_none() {
+            return false;
         }
-        tiles.fill(id);
+        let variant = def.pick_variant(hash_u32(x as u32, y as u32, self.structure_seed ^ 0x5eed));
+        self.stamp_structure(def, variant, x, y);
+        self.record_structure(def, variant, x, y);
+        self.queue_structure_spawns(def, x, y);
+        self.chunk_structures.entry(coord).or_default().push(PlacedStructure {
+            id: def.id.clone(),
+            x,
+            y,
+            variant,
+        });
+        self.mark_render_dirty(coord);
+        true
+    }
 
-        for cy in 0..self.chunk_rows {
-            for cx in 0..self.chunk_cols {
-                let chunk_index = self.chunk_index(cx, cy);
-                if let Some(chunk) = self.chunks[chunk_index].as_mut() {
-                    match layer {
-                        LayerKind::Background => chunk.dirty_background = true,
-                        LayerKind::Foreground => chunk.dirty_foreground = true,
-                        LayerKind::Overlay => chunk.dirty_overlay = true,
-                    }
-                } else {
-                    match layer {
-                        LayerKind::Background => self.pending_dirty_background[chunk_index] = true,
-                        LayerKind::Foreground => self.pending_dirty_foreground[chunk_index] = true,
-                        LayerKind::Overlay => self.pending_dirty_overlay[chunk_index] = true,
+    /// Placed structures overlapping a world-space area, with their footprints in world pixels.
+    pub fn structures_in(&self, area: Rect) -> Vec<(&PlacedStructure, Rect)> {
+        let chunk_span = self.chunk_pixel_size.max(1.0);
+        let min_x = (area.x / chunk_span).floor().max(0.0) as usize;
+        let min_y = (area.y / chunk_span).floor().max(0.0) as usize;
+        let max_x = (area.right() / chunk_span).floor().max(0.0) as usize;
+        let max_y = (area.bottom() / chunk_span).floor().max(0.0) as usize;
+        let mut found = Vec::new();
+        for cy in min_y..=max_y.min(self.chunk_rows.saturating_sub(1)) {
+            for cx in min_x..=max_x.min(self.chunk_cols.saturating_sub(1)) {
+                let Some(placed) = self.chunk_structures.get(&(cx, cy)) else {
+                    continue;
+                };
+                for structure in placed {
+                    let Some(def) = self.structure_defs.iter().find(|def| def.id == structure.id) else {
+                        continue;
+                    };
+                    let (w, h) = def.structure.size();
+                    let rect = Rect::new(
+                        structure.x as f32 * self.tile_size,
+                        structure.y as f32 * self.tile_size,
+                        w as f32 * self.tile_size,
+                        h as f32 * self.tile_size,
+                    );
+                    if rect.overlaps(&area) {
+                        found.push((structure, rect));
                     }
                 }
             }
         }
+        found
     }
 
-    pub fn set_tile(&mut self, layer: LayerKind, x: usize, y: usize, id: u8) {
-        let i = self.idx(x, y);
-        let old = match layer {
-            LayerKind::Background => self.background[i],
-            LayerKind::Foreground => self.foreground[i],
-            LayerKind::Overlay => self.overlay[i],
+    /// Removes the placed structure covering tile `(x, y)`: its tiles (the generated ground comes
+    /// back under its floor), collision, interactors, regions and markers. It stays gone when its chunk
+    /// regenerates or the map is saved. Returns false when no structure covers the tile.
+    pub fn remove_structure_at(&mut self, x: usize, y: usize) -> bool {
+        let defs = std::mem::take(&mut self.structure_defs);
+        let removed = self.remove_structure_with(&defs, x, y);
+        self.structure_defs = defs;
+        removed
+    }
+
+    fn remove_structure_with(&mut self, defs: &[StructureDef], x: usize, y: usize) -> bool {
+        let (coord, _) = chunk_local(x, y);
+        // Structures never cross chunks; the last one placed is on top.
+        let found = self.chunk_structures.get(&coord).and_then(|placed| {
+            placed.iter().enumerate().rev().find_map(|(index, placed)| {
+                let def = defs.iter().find(|def| def.id == placed.id)?;
+                let (w, h) = def.structure.size();
+                let covers = (placed.x..placed.x + w).contains(&x) && (placed.y..placed.y + h).contains(&y);
+                covers.then_some((index, def))
+            })
+        });
+        let Some((index, def)) = found else {
+            return false;
         };
-        if old == id {
-            return;
+        if self.edit_chunk(x, y).is_none() {
+            return false;
         }
-        match layer {
-            LayerKind::Background => self.background[i] = id,
-            LayerKind::Foreground => self.foreground[i] = id,
-            LayerKind::Overlay => self.overlay[i] = id,
+        let Some(placed) = self.chunk_structures.get_mut(&coord).map(|placed| placed.remove(index)) else {
+            return false;
+        };
+        let (ox, oy) = (placed.x, placed.y);
+        let structure = def.variant(placed.variant);
+
+        let ground: Vec<(usize, u8, TerrainId)> = structure
+            .background_updates
+            .iter()
+            .map(|&(sx, sy, _)| {
+                let (tx, ty) = (ox + sx, oy + sy);
+                let (tile, terrain) = match self.worldgen.as_ref() {
+                    Some(worldgen) => {
+                        let biome = worldgen.biome(tx, ty);
+                        let terrain = self.autotile.terrain_id(biome.tag()).unwrap_or(NO_TERRAIN);
+                        (worldgen.background(biome), terrain)
+                    }
+                    None => (EMPTY_TILE, NO_TERRAIN),
+                };
+                ((ty % CHUNK_SIZE) * CHUNK_SIZE + tx % CHUNK_SIZE, tile, terrain)
+            })
+            .collect();
+        let Some(chunk) = self.tiles.get_mut(&coord) else {
+            return false;
+        };
+        let local = |sx: usize, sy: usize| ((oy + sy) % CHUNK_SIZE) * CHUNK_SIZE + (ox + sx) % CHUNK_SIZE;
+        for (i, tile, terrain) in ground {
+            chunk.background[i] = tile;
+            chunk.terrain[i] = terrain;
         }
-        self.mark_chunk_dirty(x, y, layer);
+        for &(sx, sy, _) in structure.foreground_updates.iter() {
+            chunk.foreground[local(sx, sy)] = EMPTY_TILE;
+        }
+        for &(sx, sy, _) in structure.overlay_updates.iter() {
+            chunk.overlay[local(sx, sy)] = EMPTY_TILE;
+        }
+        for &(sx, sy, _) in structure.collider_offsets.iter() {
+            chunk.collision_mask[local(sx, sy)] = 0;
+            chunk.projectile_surfaces[local(sx, sy)] = ProjectileSurface::Open;
+        }
+
+        let (w, h) = structure.size();
+        for ty in oy.saturating_sub(1)..(oy + h + 1).min(self.height) {
+            for tx in ox.saturating_sub(1)..(ox + w + 1).min(self.width) {
+                self.refresh_autotile(tx, ty);
+            }
+        }
+
+        let footprint = Rect::new(
+            ox as f32 * self.tile_size,
+            oy as f32 * self.tile_size,
+            w as f32 * self.tile_size,
+            h as f32 * self.tile_size,
+        );
+        self.structure_interactors
+            .retain(|interactor| interactor.structure_id != def.id || !footprint.contains(interactor.rect.center()));
+        self.structure_triggers
+            .retain(|trigger| trigger.structure_id != def.id || !footprint.contains(trigger.group_rect.center()));
+        for region in def.regions.iter() {
+            let origin = self.tile_bounds(ox + region.x, oy + region.y);
+            let rect = Rect::new(
+                origin.x,
+                origin.y,
+                region.width as f32 * self.tile_size,
+                region.height as f32 * self.tile_size,
+            );
+            if let Some(index) = self.regions.iter().position(|placed| placed.tag == region.tag && placed.rect == rect) {
+                self.regions.remove(index);
+            }
+        }
+        for marker in def.markers.iter() {
+            let pos = self.tile_bounds(ox + marker.x, oy + marker.y).center();
+            if let Some(index) = self.markers.iter().position(|placed| placed.name == marker.name && placed.pos == pos) {
+                self.markers.remove(index);
+            }
+        }
+        self.mark_render_dirty(coord);
+        self.removed_structures.push(footprint.center());
+        if def.vendor.is_some() {
+            self.removed_stalls.push((ox, oy));
+        }
+        true
+    }
+
+    /// Centers of structures removed since the last call, for break effects.
+    pub fn take_removed_structures(&mut self) -> Vec<Vec2> {
+        std::mem::take(&mut self.removed_structures)
+    }
+
+    /// Top-left tiles of removed structures whose vendor should go, since the last call.
+    pub fn take_removed_stalls(&mut self) -> Vec<(usize, usize)> {
+        std::mem::take(&mut self.removed_stalls)
+    }
+
+    /// Serializes a rectangle of the map (in tiles, clamped to the map) as a structure JSON
+    /// that `load_structures_from_dir` can read back. Collision pins are kept; interactions,
+    /// placement rules and regions are left for the author to fill in. Tiles in chunks that
+    /// aren't loaded export as empty.
+    pub fn export_structure_json(
+        &self,
+        id: &str,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<String, serde_json::Error> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let mut export = StructureExport {
+            id: id.to_string(),
+            width,
+            height,
+            background: Vec::with_capacity(width * height),
+            foreground: Vec::with_capacity(width * height),
+            overlay: Vec::with_capacity(width * height),
+            colliders: Vec::with_capacity(width * height),
+            interactors: vec![0; width * height],
+        };
+        for ty in y..y + height {
+            for tx in x..x + width {
+                export.background.push(self.get_tile(LayerKind::Background, tx, ty));
+                export.foreground.push(self.get_tile(LayerKind::Foreground, tx, ty));
+                export.overlay.push(self.get_tile(LayerKind::Overlay, tx, ty));
+                export.colliders.push(self.collider_at(tx, ty));
+            }
+        }
+        serde_json::to_string_pretty(&export)
     }
 
     pub fn set_collision(&mut self, x: usize, y: usize, solid: bool) {
-        if x >= self.width || y >= self.height {
+        let next_mask = if solid { 0x0F } else { 0 };
+        if self.loaded_chunk(x, y).is_some_and(|(chunk, i)| chunk.collision_mask[i] == next_mask) {
             return;
         }
-        let i = self.idx(x, y);
-        let next_mask = if solid { 0x0F } else { 0 };
-        if self.solid[i] != solid || self.collision_mask[i] != next_mask {
-            self.solid[i] = solid;
-            self.collision_mask[i] = next_mask;
-            self.collision_dirty = true;
+        if let Some((chunk, i)) = self.edit_chunk(x, y) {
+            chunk.collision_mask[i] = next_mask;
         }
     }
 
-    pub fn fill_collision(&mut self, solid: bool) {
-        self.solid.fill(solid);
-        self.collision_mask.fill(if solid { 0x0F } else { 0 });
-        self.collision_dirty = true;
+    /// True for tiles with any collision or under an obstacle. Tiles in chunks that aren't
+    /// loaded are open unless an obstacle covers them.
+    pub fn is_solid(&self, x: usize, y: usize) -> bool {
+        self.collision_mask_at(x, y) != 0 || self.obstacle_tiles.contains_key(&(x, y))
     }
 
-    pub fn is_solid(&self, x: usize, y: usize) -> bool {
-        if x >= self.width || y >= self.height {
-            return false;
+    /// Adds a solid world-space rectangle that blocks movement and pathfinding like a wall,
+    /// without touching the tiles' collision. Obstacles outlive chunk unloading but aren't saved.
+    pub fn add_obstacle(&mut self, rect: Rect) -> ObstacleId {
+        let id = ObstacleId(self.next_obstacle);
+        self.next_obstacle = self.next_obstacle.wrapping_add(1);
+        for (x, y, _) in self.quadrants_covered(rect) {
+            *self.obstacle_tiles.entry((x, y)).or_default() += 1;
         }
-        self.solid[self.idx(x, y)]
+        self.obstacles.push((id, rect));
+        id
     }
 
-    pub fn set_collision_from_layer(&mut self, layer: LayerKind, solid_ids: &[u8]) {
-        let mut max_id = 0u8;
-        for &id in solid_ids {
-            if id > max_id {
-                max_id = id;
+    /// Removes an obstacle; false if it was already gone.
+    pub fn remove_obstacle(&mut self, id: ObstacleId) -> bool {
+        let Some(index) = self.obstacles.iter().position(|&(other, _)| other == id) else {
+            return false;
+        };
+        let (_, rect) = self.obstacles.swap_remove(index);
+        for (x, y, _) in self.quadrants_covered(rect) {
+            if let Some(count) = self.obstacle_tiles.get_mut(&(x, y)) {
+                *count -= 1;
+                if *count == 0 {
+                    self.obstacle_tiles.remove(&(x, y));
+                }
             }
         }
-        let mut lookup = vec![false; max_id as usize + 1];
-        for &id in solid_ids {
-            if (id as usize) < lookup.len() {
-                lookup[id as usize] = true;
-            }
+        true
+    }
+
+    pub fn obstacle(&self, id: ObstacleId) -> Option<Rect> {
+        self.obstacles.iter().find(|&&(other, _)| other == id).map(|&(_, rect)| rect)
+    }
+
+    pub fn set_tile_properties(&mut self, properties: Vec<TileProperties>) {
+        self.tile_properties = properties;
+        // Which foreground tiles the chunk textures leave out may have changed.
+        for chunk in self.chunks.values_mut() {
+            chunk.dirty_foreground = true;
         }
+    }
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let tile = self.get_tile(layer, x, y);
-                let solid = tile != EMPTY_TILE
-                    && (tile as usize) < lookup.len()
-                    && lookup[tile as usize];
-                let idx = self.idx(x, y);
-                self.solid[idx] = solid;
-                self.collision_mask[idx] = if solid { 0x0F } else { 0 };
+    fn sorts_by_bottom(&self, id: u8) -> bool {
+        id != EMPTY_TILE && self.tile_properties(id).sort_by_bottom
+    }
+
+    /// Collects the `sort_by_bottom` foreground tiles overlapping `area` into `out`.
+    pub fn collect_sorted_tiles(&self, area: Rect, out: &mut Vec<SortedTile>) {
+        out.clear();
+        if !self.tile_properties.iter().any(|props| props.sort_by_bottom) {
+            return;
+        }
+        let size = self.tile_size.max(1.0);
+        let min_x = (area.x / size).floor().max(0.0) as usize;
+        let min_y = (area.y / size).floor().max(0.0) as usize;
+        let max_x = ((area.right() / size).ceil().max(0.0) as usize).min(self.width);
+        let max_y = ((area.bottom() / size).ceil().max(0.0) as usize).min(self.height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let id = self.get_tile(LayerKind::Foreground, x, y);
+                if !self.sorts_by_bottom(id) {
+                    continue;
+                }
+                let below = (1..MAX_SORTED_STACK)
+                    .take_while(|&dy| {
+                        y + dy < self.height
+                            && self.sorts_by_bottom(self.get_tile(LayerKind::Foreground, x, y + dy))
+                    })
+                    .count();
+                out.push(SortedTile {
+                    id,
+                    x,
+                    y,
+                    baseline: (y + below + 1) as f32 * self.tile_size,
+                });
             }
         }
+    }
+
+    /// Draws a tile from `collect_sorted_tiles` in world space.
+    pub fn draw_sorted_tile(&self, tileset: &TileSet, tile: &SortedTile) {
+        let Some(source) = tileset.get(tile.id) else {
+            return;
+        };
+        draw_texture_ex(
+            tileset.texture(),
+            tile.x as f32 * self.tile_size,
+            tile.y as f32 * self.tile_size,
+            WHITE,
+            DrawTextureParams {
+                source: Some(source),
+                dest_size: Some(vec2(self.tile_size, self.tile_size)),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Tile colors for `chunk_summary`, indexed by tile id; see `TileSet::colors`.
+    pub fn set_tile_colors(&mut self, colors: Vec<Color>) {
+        self.tile_colors = colors;
+        self.changed_chunks.extend(self.tiles.keys().copied());
+    }
+
+    /// Properties of a tile id; defaults for ids the tileset gives none.
+    pub fn tile_properties(&self, id: u8) -> &TileProperties {
+        self.tile_properties.get(id as usize).unwrap_or(&DEFAULT_TILE_PROPERTIES)
+    }
+
+    /// Properties of the ground at a world position: the foreground tile's if it has any,
+    /// otherwise the background tile's.
+    pub fn tile_properties_at(&self, pos: Vec2) -> &TileProperties {
+        if pos.x < 0.0 || pos.y < 0.0 {
+            return &DEFAULT_TILE_PROPERTIES;
+        }
+        let x = (pos.x / self.tile_size) as usize;
+        let y = (pos.y / self.tile_size) as usize;
+        [LayerKind::Foreground, LayerKind::Background]
+            .into_iter()
+            .filter_map(|layer| self.tile_properties.get(self.get_tile(layer, x, y) as usize))
+            .find(|props| **props != DEFAULT_TILE_PROPERTIES)
+            .unwrap_or(&DEFAULT_TILE_PROPERTIES)
+    }
 
-        self.collision_dirty = true;
+    /// Material of the wall hit by an actor with `hitbox` (world space) moving along `dir`: the
+    /// tile just past the hitbox's edge.
+    pub fn wall_material(&self, hitbox: Rect, dir: Vec2) -> SurfaceMaterial {
+        let step = dir.normalize_or_zero();
+        let reach = vec2(step.x * (hitbox.w * 0.5 + 1.0), step.y * (hitbox.h * 0.5 + 1.0));
+        self.tile_properties_at(hitbox.center() + reach).material
     }
 
     pub fn tile_at(&self, layer: LayerKind, x: usize, y: usize) -> u8 {
         self.get_tile(layer, x, y)
     }
 
-    pub fn collision_blocks(&mut self) -> &[Rect] {
-        if self.collision_dirty {
-            self.rebuild_collision_blocks();
-        }
-        &self.collision_blocks
+    /// Whether the tile's chunk is in memory; tiles elsewhere read as empty.
+    pub fn is_tile_loaded(&self, x: usize, y: usize) -> bool {
+        self.loaded_chunk(x, y).is_some()
     }
 
     pub fn grid_index(&self, position: Vec2) -> Option<GridIndex> {
@@ -1225,12 +3225,12 @@ impl TileMap {
 
         for cy in min_cy..=max_cy {
             for cx in min_cx..=max_cx {
-                let chunk_index = self.chunk_index(cx as usize, cy as usize);
-                if !self.ensure_chunk_allocated(chunk_index) {
+                let coord = (cx as usize, cy as usize);
+                if !self.tiles.contains_key(&coord) || !self.ensure_chunk_allocated(coord) {
                     continue;
                 }
-                self.rebuild_chunk_layer_if_dirty(chunk_index, layer, tileset);
-                self.draw_chunk_layer(chunk_index, layer, cx as usize, cy as usize);
+                self.rebuild_chunk_layer_if_dirty(coord, layer, tileset);
+                self.draw_chunk_layer(coord, layer);
             }
         }
     }
@@ -1262,39 +3262,30 @@ impl TileMap {
 
     fn rebuild_chunk_layer_if_dirty(
         &mut self,
-        chunk_index: usize,
+        coord: ChunkCoord,
         layer: LayerKind,
         tileset: &TileSet,
     ) {
-        if self.chunks.get(chunk_index).and_then(|c| c.as_ref()).is_none() {
+        let Some(chunk) = self.chunks.get(&coord) else {
             return;
-        }
-        let is_dirty = match layer {
-            LayerKind::Background => self.chunks[chunk_index].as_ref().map(|c| c.dirty_background).unwrap_or(false),
-            LayerKind::Foreground => self.chunks[chunk_index].as_ref().map(|c| c.dirty_foreground).unwrap_or(false),
-            LayerKind::Overlay => self.chunks[chunk_index].as_ref().map(|c| c.dirty_overlay).unwrap_or(false),
         };
-
+        let (is_dirty, target) = match layer {
+            LayerKind::Background => (chunk.dirty_background, &chunk.background),
+            LayerKind::Foreground => (chunk.dirty_foreground, &chunk.foreground),
+            LayerKind::Overlay => (chunk.dirty_overlay, &chunk.overlay),
+        };
         if !is_dirty {
             return;
         }
         if self.chunk_rebuilds_this_frame >= self.chunk_rebuild_budget_per_frame {
             return;
         }
+        let target = target.clone();
 
-        let target = match layer {
-            LayerKind::Background => self.chunks[chunk_index].as_ref().map(|c| c.background.clone()),
-            LayerKind::Foreground => self.chunks[chunk_index].as_ref().map(|c| c.foreground.clone()),
-            LayerKind::Overlay => self.chunks[chunk_index].as_ref().map(|c| c.overlay.clone()),
-        };
-        let Some(target) = target else {
-            return;
-        };
-
-        self.render_chunk_layer(target, chunk_index, layer, tileset);
+        self.render_chunk_layer(target, coord, layer, tileset);
         self.chunk_rebuilds_this_frame += 1;
 
-        let Some(chunk) = self.chunks[chunk_index].as_mut() else {
+        let Some(chunk) = self.chunks.get_mut(&coord) else {
             return;
         };
         match layer {
@@ -1316,17 +3307,14 @@ impl TileMap {
     fn render_chunk_layer(
         &self,
         target: RenderTarget,
-        chunk_index: usize,
+        coord: ChunkCoord,
         layer: LayerKind,
         tileset: &TileSet,
     ) {
-        let chunk_x = chunk_index % self.chunk_cols;
-        let chunk_y = chunk_index / self.chunk_cols;
-
-        let origin_x = chunk_x * CHUNK_SIZE;
-        let origin_y = chunk_y * CHUNK_SIZE;
-        let max_x = (origin_x + CHUNK_SIZE).min(self.width);
-        let max_y = (origin_y + CHUNK_SIZE).min(self.height);
+        let Some(tiles) = self.tiles.get(&coord) else {
+            return;
+        };
+        let tiles = tiles.layer(layer);
 
         let mut cam = Camera2D::from_display_rect(Rect::new(
             0.0,
@@ -1341,36 +3329,144 @@ impl TileMap {
         clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
 
         let dest = Some(vec2(self.tile_size, self.tile_size));
-        for ty in origin_y..max_y {
-            for tx in origin_x..max_x {
-                let tile = self.get_tile(layer, tx, ty);
-                let Some(source) = tileset.get(tile) else {
+        for (i, &tile) in tiles.iter().enumerate() {
+            if matches!(layer, LayerKind::Foreground) && self.sorts_by_bottom(tile) {
+                continue;
+            }
+            let Some(source) = tileset.get(tile) else {
+                continue;
+            };
+
+            let local_x = (i % CHUNK_SIZE) as f32 * self.tile_size;
+            let local_y = (i / CHUNK_SIZE) as f32 * self.tile_size;
+            draw_texture_ex(
+                tileset.texture(),
+                local_x,
+                local_y,
+                WHITE,
+                DrawTextureParams {
+                    source: Some(source),
+                    dest_size: dest,
+                    ..Default::default()
+                },
+            );
+        }
+        if matches!(layer, LayerKind::Background)
+            && let Some(occlusion) = self.ambient_occlusion
+        {
+            self.draw_occlusion(coord, occlusion);
+        }
+
+        pop_camera_state();
+    }
+
+    /// Whether a tile shades the ground around it: a solid or Y-sorted foreground tile.
+    fn casts_occlusion(&self, x: usize, y: usize) -> bool {
+        let id = self.get_tile(LayerKind::Foreground, x, y);
+        id != EMPTY_TILE && (self.sorts_by_bottom(id) || self.collision_mask_at(x, y) != 0)
+    }
+
+    /// Shades a chunk's ground under and around tall tiles, in chunk-local pixels. Call while
+    /// rendering its background layer.
+    fn draw_occlusion(&self, coord: ChunkCoord, occlusion: AmbientOcclusion) {
+        let size = self.tile_size;
+        let strength = occlusion.strength.clamp(0.0, 1.0);
+        let reach = size * occlusion.reach.clamp(0.0, 1.0);
+        let band = Color::new(0.0, 0.0, 0.0, strength / OCCLUSION_STEPS as f32);
+        let (origin_x, origin_y) = (coord.0 * CHUNK_SIZE, coord.1 * CHUNK_SIZE);
+        for ly in 0..CHUNK_SIZE {
+            for lx in 0..CHUNK_SIZE {
+                let (x, y) = (origin_x + lx, origin_y + ly);
+                if x >= self.width || y >= self.height {
+                    continue;
+                }
+                let (px, py) = (lx as f32 * size, ly as f32 * size);
+                if self.casts_occlusion(x, y) {
+                    draw_rectangle(px, py, size, size, Color::new(0.0, 0.0, 0.0, strength));
                     continue;
+                }
+                let tall = |dx: isize, dy: isize| {
+                    x.checked_add_signed(dx)
+                        .zip(y.checked_add_signed(dy))
+                        .is_some_and(|(nx, ny)| nx < self.width && ny < self.height && self.casts_occlusion(nx, ny))
                 };
-
-                let local_x = (tx - origin_x) as f32 * self.tile_size;
-                let local_y = (ty - origin_y) as f32 * self.tile_size;
-                draw_texture_ex(
-                    tileset.texture(),
-                    local_x,
-                    local_y,
-                    WHITE,
-                    DrawTextureParams {
-                        source: Some(source),
-                        dest_size: dest,
-                        ..Default::default()
-                    },
-                );
+                let (north, south, west, east) = (tall(0, -1), tall(0, 1), tall(-1, 0), tall(1, 0));
+                // Corners only shade where neither edge next to them already does.
+                let corners = [
+                    (tall(-1, -1) && !north && !west, 0.0, 0.0),
+                    (tall(1, -1) && !north && !east, 1.0, 0.0),
+                    (tall(-1, 1) && !south && !west, 0.0, 1.0),
+                    (tall(1, 1) && !south && !east, 1.0, 1.0),
+                ];
+                for step in 1..=OCCLUSION_STEPS {
+                    let depth = reach * step as f32 / OCCLUSION_STEPS as f32;
+                    if north {
+                        draw_rectangle(px, py, size, depth, band);
+                    }
+                    if south {
+                        draw_rectangle(px, py + size - depth, size, depth, band);
+                    }
+                    if west {
+                        draw_rectangle(px, py, depth, size, band);
+                    }
+                    if east {
+                        draw_rectangle(px + size - depth, py, depth, size, band);
+                    }
+                    for &(_, fx, fy) in corners.iter().filter(|corner| corner.0) {
+                        draw_rectangle(px + (size - depth) * fx, py + (size - depth) * fy, depth, depth, band);
+                    }
+                }
             }
         }
+    }
 
-        pop_camera_state();
+    /// Chunks loaded or edited since the last call, for caches built from `chunk_summary` such
+    /// as the minimap.
+    pub fn take_changed_chunks(&mut self) -> Vec<ChunkCoord> {
+        self.changed_chunks.drain().collect()
+    }
+
+    /// Downsampled colors and collision of a loaded chunk; `None` if it isn't loaded.
+    pub fn chunk_summary(&self, cx: usize, cy: usize) -> Option<ChunkSummary> {
+        let chunk = self.tiles.get(&(cx, cy))?;
+        let cells = SUMMARY_CELLS * SUMMARY_CELLS;
+        let mut colors = vec![Color::new(0.0, 0.0, 0.0, 0.0); cells];
+        let mut blocked = vec![false; cells];
+        let mut counts: HashMap<u8, usize> = HashMap::new();
+        for cell_y in 0..SUMMARY_CELLS {
+            for cell_x in 0..SUMMARY_CELLS {
+                counts.clear();
+                let mut solid = 0;
+                for ly in cell_y * SUMMARY_CELL_TILES..(cell_y + 1) * SUMMARY_CELL_TILES {
+                    for lx in cell_x * SUMMARY_CELL_TILES..(cell_x + 1) * SUMMARY_CELL_TILES {
+                        let i = ly * CHUNK_SIZE + lx;
+                        // The topmost tile that has something to show.
+                        let visible = [&chunk.overlay, &chunk.foreground, &chunk.background]
+                            .into_iter()
+                            .map(|layer| layer[i])
+                            .find(|&id| self.tile_colors.get(id as usize).is_some_and(|color| color.a > 0.0));
+                        if let Some(id) = visible {
+                            *counts.entry(id).or_default() += 1;
+                        }
+                        if self.is_solid(cx * CHUNK_SIZE + lx, cy * CHUNK_SIZE + ly) {
+                            solid += 1;
+                        }
+                    }
+                }
+                let cell = cell_y * SUMMARY_CELLS + cell_x;
+                // Ties go to the lower id so summaries don't flicker between equal counts.
+                if let Some((&id, _)) = counts.iter().max_by_key(|&(&id, &count)| (count, std::cmp::Reverse(id))) {
+                    colors[cell] = self.tile_colors[id as usize];
+                }
+                blocked[cell] = solid * 2 >= SUMMARY_CELL_TILES * SUMMARY_CELL_TILES;
+            }
+        }
+        Some(ChunkSummary { colors, blocked })
     }
 
-    fn draw_chunk_layer(&self, chunk_index: usize, layer: LayerKind, cx: usize, cy: usize) {
-        let chunk = match self.chunks.get(chunk_index).and_then(|c| c.as_ref()) {
-            Some(chunk) => chunk,
-            None => return,
+    fn draw_chunk_layer(&self, coord: ChunkCoord, layer: LayerKind) {
+        let Some(chunk) = self.chunks.get(&coord) else {
+            return;
         };
         let ready = match layer {
             LayerKind::Background => chunk.ready_background,
@@ -1386,8 +3482,12 @@ impl TileMap {
             LayerKind::Overlay => &chunk.overlay.texture,
         };
 
-        let world_x = cx as f32 * self.chunk_pixel_size;
-        let world_y = cy as f32 * self.chunk_pixel_size;
+        if matches!(layer, LayerKind::Overlay) && self.draw_faded_overlay(coord, texture) {
+            return;
+        }
+
+        let world_x = coord.0 as f32 * self.chunk_pixel_size;
+        let world_y = coord.1 as f32 * self.chunk_pixel_size;
         let dest = Some(vec2(self.chunk_pixel_size, self.chunk_pixel_size));
 
         draw_texture_ex(
@@ -1403,66 +3503,95 @@ impl TileMap {
         );
     }
 
-    fn get_tile(&self, layer: LayerKind, x: usize, y: usize) -> u8 {
-        let i = self.idx(x, y);
-        match layer {
-            LayerKind::Background => self.background[i],
-            LayerKind::Foreground => self.foreground[i],
-            LayerKind::Overlay => self.overlay[i],
+    /// Draws an overlay chunk with its faded canopy tiles see-through: runs of tiles that share
+    /// an alpha go out as one piece, and rows with nothing faded as one band. Returns false,
+    /// drawing nothing, when no tile in the chunk is faded.
+    fn draw_faded_overlay(&self, coord: ChunkCoord, texture: &Texture2D) -> bool {
+        let (origin_x, origin_y) = (coord.0 * CHUNK_SIZE, coord.1 * CHUNK_SIZE);
+        let mut alphas = Vec::new();
+        for (&(x, y), &alpha) in &self.canopy_fade {
+            if (x / CHUNK_SIZE, y / CHUNK_SIZE) == coord {
+                alphas.resize(CHUNK_LEN, 1.0);
+                alphas[(y - origin_y) * CHUNK_SIZE + x - origin_x] = alpha;
+            }
+        }
+        if alphas.is_empty() {
+            return false;
         }
-    }
-
-    fn rebuild_collision_blocks(&mut self) {
-        self.collision_blocks.clear();
-        let mut visited = vec![false; self.solid.len()];
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let i = self.idx(x, y);
-                if visited[i] || !self.solid[i] {
+        let mut band_start = 0;
+        for ly in 0..CHUNK_SIZE {
+            let row = &alphas[ly * CHUNK_SIZE..(ly + 1) * CHUNK_SIZE];
+            if row.iter().all(|&alpha| alpha >= 1.0) {
+                continue;
+            }
+            if band_start < ly {
+                self.draw_chunk_piece(coord, texture, Rect::new(0.0, band_start as f32, CHUNK_SIZE as f32, (ly - band_start) as f32), 1.0);
+            }
+            band_start = ly + 1;
+            let mut run_start = 0;
+            for lx in 1..=CHUNK_SIZE {
+                if lx < CHUNK_SIZE && row[lx] == row[run_start] {
                     continue;
                 }
+                let piece = Rect::new(run_start as f32, ly as f32, (lx - run_start) as f32, 1.0);
+                self.draw_chunk_piece(coord, texture, piece, row[run_start]);
+                run_start = lx;
+            }
+        }
+        if band_start < CHUNK_SIZE {
+            self.draw_chunk_piece(coord, texture, Rect::new(0.0, band_start as f32, CHUNK_SIZE as f32, (CHUNK_SIZE - band_start) as f32), 1.0);
+        }
+        true
+    }
 
-                let mut max_w = 0;
-                while x + max_w < self.width {
-                    let idx = self.idx(x + max_w, y);
-                    if self.solid[idx] && !visited[idx] {
-                        max_w += 1;
-                    } else {
-                        break;
-                    }
-                }
+    /// Draws the part of a chunk layer covering `tiles`, in chunk-local tiles.
+    fn draw_chunk_piece(&self, coord: ChunkCoord, texture: &Texture2D, tiles: Rect, alpha: f32) {
+        let texel = texture.width() / CHUNK_SIZE as f32;
+        // Render targets are stored upside down, so the source counts rows from the bottom.
+        let source = Rect::new(
+            tiles.x * texel,
+            (CHUNK_SIZE as f32 - tiles.y - tiles.h) * texel,
+            tiles.w * texel,
+            tiles.h * texel,
+        );
+        draw_texture_ex(
+            texture,
+            coord.0 as f32 * self.chunk_pixel_size + tiles.x * self.tile_size,
+            coord.1 as f32 * self.chunk_pixel_size + tiles.y * self.tile_size,
+            Color::new(1.0, 1.0, 1.0, alpha),
+            DrawTextureParams {
+                dest_size: Some(tiles.size() * self.tile_size),
+                source: Some(source),
+                flip_y: true,
+                ..Default::default()
+            },
+        );
+    }
 
-                let mut max_h = 1;
-                'height: loop {
-                    if y + max_h >= self.height {
-                        break;
-                    }
-                    for tx in 0..max_w {
-                        let idx = self.idx(x + tx, y + max_h);
-                        if !self.solid[idx] || visited[idx] {
-                            break 'height;
-                        }
-                    }
-                    max_h += 1;
-                }
+    /// Tile in a loaded chunk; empty elsewhere.
+    fn get_tile(&self, layer: LayerKind, x: usize, y: usize) -> u8 {
+        self.loaded_chunk(x, y)
+            .map(|(chunk, i)| chunk.layer(layer)[i])
+            .unwrap_or(EMPTY_TILE)
+    }
 
-                for dy in 0..max_h {
-                    for dx in 0..max_w {
-                        visited[self.idx(x + dx, y + dy)] = true;
-                    }
-                }
+    /// A tile's collision pins with their kind bits.
+    fn collider_at(&self, x: usize, y: usize) -> u8 {
+        self.loaded_chunk(x, y)
+            .map(|(chunk, i)| chunk.collision_mask[i] & COLLIDER_BITS)
+            .unwrap_or(0)
+    }
 
-                self.collision_blocks.push(Rect::new(
-                    x as f32 * self.tile_size,
-                    y as f32 * self.tile_size,
-                    max_w as f32 * self.tile_size,
-                    max_h as f32 * self.tile_size,
-                ));
-            }
-        }
+    /// Pins of a tile's collider if it is of `kind`.
+    fn pins_at(&self, x: usize, y: usize, kind: ColliderKind) -> u8 {
+        let mask = self.collider_at(x, y);
+        if ColliderKind::from_mask(mask) == kind { mask & PIN_BITS } else { 0 }
+    }
 
-        self.collision_dirty = false;
+    /// Solid pins only; the other collider kinds don't block.
+    fn collision_mask_at(&self, x: usize, y: usize) -> u8 {
+        self.pins_at(x, y, ColliderKind::Solid)
     }
 
     pub fn hitboxes_around_grid(&self, grid: GridIndex, radius: i32) -> Vec<Rect> {
@@ -1471,7 +3600,38 @@ impl TileMap {
         hitboxes
     }
 
+    /// Solid pins around a grid cell, plus the obstacles reaching into that area.
     pub fn fill_hitboxes_around_grid(&self, grid: GridIndex, radius: i32, out: &mut Vec<Rect>) {
+        self.fill_pins_around_grid(grid, radius, ColliderKind::Solid, out);
+        if self.obstacles.is_empty() {
+            return;
+        }
+        let span = (radius * 2 + 1) as f32 * self.tile_size;
+        let area = Rect::new(
+            (grid.x - radius) as f32 * self.tile_size,
+            (grid.y - radius) as f32 * self.tile_size,
+            span,
+            span,
+        );
+        out.extend(self.obstacles.iter().map(|&(_, rect)| rect).filter(|rect| rect.overlaps(&area)));
+    }
+
+    /// One-way ledges around a grid cell; see `ColliderKind::OneWay`.
+    pub fn fill_one_way_around_grid(&self, grid: GridIndex, radius: i32, out: &mut Vec<Rect>) {
+        self.fill_pins_around_grid(grid, radius, ColliderKind::OneWay, out);
+    }
+
+    /// Speed multiplier for something whose hitbox is `rect`: `SLOW_ZONE_SPEED` when it touches
+    /// a slow pin, otherwise 1.
+    pub fn slow_factor(&self, rect: Rect) -> f32 {
+        let slowed = self
+            .quadrants_covered(rect)
+            .into_iter()
+            .any(|(x, y, mask)| self.pins_at(x, y, ColliderKind::Slow) & mask != 0);
+        if slowed { SLOW_ZONE_SPEED } else { 1.0 }
+    }
+
+    fn fill_pins_around_grid(&self, grid: GridIndex, radius: i32, kind: ColliderKind, out: &mut Vec<Rect>) {
         out.clear();
         let start_x = grid.x - radius;
         let end_x = grid.x + radius;
@@ -1487,133 +3647,162 @@ impl TileMap {
                 if ux >= self.width || uy >= self.height {
                     continue;
                 }
-                let mask = self.collision_mask[self.idx(ux, uy)] & 0x0F;
-                if mask == 0 {
-                    continue;
-                }
-                let tile = self.tile_bounds(ux, uy);
-                if mask == 0x0F {
-                    out.push(tile);
-                    continue;
-                }
-                let half_w = tile.w * 0.5;
-                let half_h = tile.h * 0.5;
-                if (mask & 0b0001) != 0 {
-                    out.push(Rect::new(tile.x, tile.y, half_w, half_h));
-                }
-                if (mask & 0b0010) != 0 {
-                    out.push(Rect::new(tile.x + half_w, tile.y, half_w, half_h));
-                }
-                if (mask & 0b0100) != 0 {
-                    out.push(Rect::new(tile.x, tile.y + half_h, half_w, half_h));
-                }
-                if (mask & 0b1000) != 0 {
-                    out.push(Rect::new(tile.x + half_w, tile.y + half_h, half_w, half_h));
+                let mask = self.pins_at(ux, uy, kind);
+                if mask != 0 {
+                    pin_rects(self.tile_bounds(ux, uy), mask, out);
                 }
             }
         }
     }
 
-    pub fn tile_size(&self) -> f32 {
-        self.tile_size
+    pub fn tile_size(&self) -> f32 {
+        self.tile_size
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The loaded chunk holding a tile and the tile's index in it.
+    fn loaded_chunk(&self, x: usize, y: usize) -> Option<(&TileChunk, usize)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let (coord, i) = chunk_local(x, y);
+        self.tiles.get(&coord).map(|chunk| (chunk, i))
+    }
+
+    fn loaded_chunk_mut(&mut self, x: usize, y: usize) -> Option<(&mut TileChunk, usize)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let (coord, i) = chunk_local(x, y);
+        self.tiles.get_mut(&coord).map(|chunk| (chunk, i))
     }
 
-    fn mark_chunks_dirty_rect(
-        &mut self,
-        x: usize,
-        y: usize,
-        width: usize,
-        height: usize,
-        mark_background: bool,
-        mark_foreground: bool,
-        mark_overlay: bool,
-    ) {
-        if width == 0 || height == 0 || (!mark_background && !mark_foreground && !mark_overlay) {
-            return;
+    /// The chunk holding a tile for a lasting edit: generated first if needed (or created empty
+    /// on maps without a generator) and marked modified so it is saved and kept loaded.
+    fn edit_chunk(&mut self, x: usize, y: usize) -> Option<(&mut TileChunk, usize)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let (coord, i) = chunk_local(x, y);
+        if !self.tiles.contains_key(&coord) {
+            if self.worldgen.is_some() {
+                self.generate_chunk(coord);
+            } else {
+                self.tiles.insert(coord, TileChunk::new());
+                self.mark_render_dirty(coord);
+            }
         }
+        let chunk = self.tiles.get_mut(&coord)?;
+        chunk.modified = true;
+        Some((chunk, i))
+    }
 
-        let end_x = (x + width - 1).min(self.width.saturating_sub(1));
-        let end_y = (y + height - 1).min(self.height.saturating_sub(1));
-        let start_cx = x / CHUNK_SIZE;
-        let start_cy = y / CHUNK_SIZE;
-        let end_cx = end_x / CHUNK_SIZE;
-        let end_cy = end_y / CHUNK_SIZE;
+    /// Re-renders every chunk texture, e.g. after the tileset's pixels changed.
+    pub fn mark_all_chunks_dirty(&mut self) {
+        let coords: Vec<ChunkCoord> = self.tiles.keys().copied().collect();
+        for coord in coords {
+            self.mark_render_dirty(coord);
+        }
+    }
 
-        for cy in start_cy..=end_cy {
-            for cx in start_cx..=end_cx {
-                let chunk_index = self.chunk_index(cx, cy);
-                if let Some(chunk) = self.chunks[chunk_index].as_mut() {
-                    if mark_background {
-                        chunk.dirty_background = true;
-                    }
-                    if mark_foreground {
-                        chunk.dirty_foreground = true;
-                    }
-                    if mark_overlay {
-                        chunk.dirty_overlay = true;
-                    }
-                } else {
-                    if mark_background {
-                        self.pending_dirty_background[chunk_index] = true;
-                    }
-                    if mark_foreground {
-                        self.pending_dirty_foreground[chunk_index] = true;
-                    }
-                    if mark_overlay {
-                        self.pending_dirty_overlay[chunk_index] = true;
-                    }
+    fn mark_render_dirty(&mut self, coord: ChunkCoord) {
+        self.mark_occlusion_dirty(coord);
+        self.changed_chunks.insert(coord);
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.dirty_background = true;
+            chunk.dirty_foreground = true;
+            chunk.dirty_overlay = true;
+        }
+    }
+
+    /// Shade reaches across chunk edges, so foreground changes rebake the neighbours' ground too.
+    fn mark_occlusion_dirty(&mut self, coord: ChunkCoord) {
+        if self.ambient_occlusion.is_none() {
+            return;
+        }
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let neighbour = coord.0.checked_add_signed(dx).zip(coord.1.checked_add_signed(dy));
+                if let Some(chunk) = neighbour.and_then(|neighbour| self.chunks.get_mut(&neighbour)) {
+                    chunk.dirty_background = true;
                 }
             }
         }
     }
 
     fn mark_chunk_dirty(&mut self, x: usize, y: usize, layer: LayerKind) {
-        let cx = x / CHUNK_SIZE;
-        let cy = y / CHUNK_SIZE;
-        if cx >= self.chunk_cols || cy >= self.chunk_rows {
-            return;
+        let (coord, _) = chunk_local(x, y);
+        self.mark_layer_dirty(coord, layer);
+    }
+
+    fn mark_layer_dirty(&mut self, coord: ChunkCoord, layer: LayerKind) {
+        if matches!(layer, LayerKind::Foreground) {
+            self.mark_occlusion_dirty(coord);
         }
-        let chunk_index = self.chunk_index(cx, cy);
-        if let Some(chunk) = self.chunks[chunk_index].as_mut() {
+        self.changed_chunks.insert(coord);
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
             match layer {
                 LayerKind::Background => chunk.dirty_background = true,
                 LayerKind::Foreground => chunk.dirty_foreground = true,
                 LayerKind::Overlay => chunk.dirty_overlay = true,
             }
-        } else {
-            match layer {
-                LayerKind::Background => self.pending_dirty_background[chunk_index] = true,
-                LayerKind::Foreground => self.pending_dirty_foreground[chunk_index] = true,
-                LayerKind::Overlay => self.pending_dirty_overlay[chunk_index] = true,
-            }
         }
     }
 
-    fn chunk_index(&self, cx: usize, cy: usize) -> usize {
-        cy * self.chunk_cols + cx
-    }
-
-    fn idx(&self, x: usize, y: usize) -> usize {
-        y * self.width + x
-    }
-
-    fn ensure_chunk_allocated(&mut self, chunk_index: usize) -> bool {
-        if self.chunks.get(chunk_index).and_then(|c| c.as_ref()).is_some() {
+    /// Gives `coord` render targets: spare ones first, then new ones while under
+    /// `MAX_CHUNK_TARGETS`, then the targets of the chunk drawn least recently. Returns false when
+    /// the frame's allocation budget is spent or every chunk with targets is in view.
+    fn ensure_chunk_allocated(&mut self, coord: ChunkCoord) -> bool {
+        let frame = self.chunk_frame;
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.last_used = frame;
+            return true;
+        }
+        let spare = match self.spare_chunks.pop() {
+            Some(chunk) => Some(chunk),
+            None if self.chunks.len() < MAX_CHUNK_TARGETS => None,
+            None => {
+                let oldest = self
+                    .chunks
+                    .iter()
+                    .filter(|(_, chunk)| chunk.last_used < frame)
+                    .min_by_key(|(_, chunk)| chunk.last_used)
+                    .map(|(&oldest, _)| oldest);
+                match oldest.and_then(|oldest| self.chunks.remove(&oldest)) {
+                    Some(chunk) => Some(chunk),
+                    None => return false,
+                }
+            }
+        };
+        if let Some(mut chunk) = spare {
+            // Whatever the targets held belongs to another chunk; render this one on demand.
+            chunk.dirty_background = true;
+            chunk.dirty_foreground = true;
+            chunk.dirty_overlay = true;
+            chunk.ready_background = false;
+            chunk.ready_foreground = false;
+            chunk.ready_overlay = false;
+            chunk.last_used = frame;
+            self.chunks.insert(coord, chunk);
             return true;
         }
         if self.chunk_allocs_this_frame >= self.chunk_alloc_budget_per_frame {
             return false;
         }
-        self.create_chunk(chunk_index);
-        if self.chunks.get(chunk_index).and_then(|c| c.as_ref()).is_some() {
-            self.chunk_allocs_this_frame += 1;
-            true
-        } else {
-            false
-        }
+        self.create_chunk(coord);
+        self.chunk_allocs_this_frame += 1;
+        true
     }
 
-    fn create_chunk(&mut self, chunk_index: usize) {
+    /// Render targets start dirty; chunks without tiles yet simply stay dirty until they load.
+    fn create_chunk(&mut self, coord: ChunkCoord) {
         let chunk_size_u32 = self.chunk_pixel_size.round().max(1.0) as u32;
         let background = render_target(chunk_size_u32, chunk_size_u32);
         let foreground = render_target(chunk_size_u32, chunk_size_u32);
@@ -1621,97 +3810,380 @@ impl TileMap {
         background.texture.set_filter(FilterMode::Nearest);
         foreground.texture.set_filter(FilterMode::Nearest);
         overlay.texture.set_filter(FilterMode::Nearest);
-        let dirty_background = self.pending_dirty_background.get(chunk_index).copied().unwrap_or(true);
-        let dirty_foreground = self.pending_dirty_foreground.get(chunk_index).copied().unwrap_or(true);
-        let dirty_overlay = self.pending_dirty_overlay.get(chunk_index).copied().unwrap_or(true);
-        if let Some(slot) = self.chunks.get_mut(chunk_index) {
-            *slot = Some(Chunk {
+        self.chunks.insert(
+            coord,
+            Chunk {
                 background,
                 foreground,
                 overlay,
-                dirty_background,
-                dirty_foreground,
-                dirty_overlay,
+                dirty_background: true,
+                dirty_foreground: true,
+                dirty_overlay: true,
                 ready_background: false,
                 ready_foreground: false,
                 ready_overlay: false,
-            });
-        }
-        if let Some(flag) = self.pending_dirty_background.get_mut(chunk_index) {
-            *flag = false;
+                last_used: self.chunk_frame,
+            },
+        );
+    }
+}
+
+/// Chunk of a tile and the tile's index inside it.
+fn chunk_local(x: usize, y: usize) -> (ChunkCoord, usize) {
+    (
+        (x / CHUNK_SIZE, y / CHUNK_SIZE),
+        (y % CHUNK_SIZE) * CHUNK_SIZE + x % CHUNK_SIZE,
+    )
+}
+
+/// Chebyshev distance between chunks.
+fn chunk_distance(a: ChunkCoord, b: ChunkCoord) -> usize {
+    a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+}
+
+/// Whether `plan_chunk` already picked the autotile at `(x, y)` for the chunk whose first tile is
+/// `origin`: tiles whose eight neighbours all lie inside that chunk and the map.
+fn planned_autotile(x: usize, y: usize, origin: (usize, usize), width: usize, height: usize) -> bool {
+    let inside = |v: usize, start: usize, limit: usize| v > start && v + 1 < (start + CHUNK_SIZE).min(limit);
+    inside(x, origin.0, width) && inside(y, origin.1, height)
+}
+
+/// Everything chunk planning reads, owned so a worker thread can keep a copy.
+struct ChunkSource {
+    worldgen: WorldGen,
+    autotile: AutotileRules,
+    defs: Vec<StructureDef>,
+    /// Autotile terrain of each biome, indexed like `Biome::ALL`.
+    terrain_ids: [TerrainId; 4],
+    seed: u32,
+    width: usize,
+    height: usize,
+    tile_size: f32,
+}
+
+/// A generated chunk before it joins the map: its ground, autotiled everywhere but along its
+/// edge, and the structures it gets. Depends only on the world seed, so it can be worked out off
+/// the main thread.
+struct ChunkPlan {
+    coord: ChunkCoord,
+    background: Vec<u8>,
+    foreground: Vec<u8>,
+    overlay: Vec<u8>,
+    terrain: Vec<TerrainId>,
+    structures: Vec<PlacedStructure>,
+}
+
+/// A structure a chunk picked for itself, with its footprint padded by `min_distance` in world
+/// pixels.
+struct StructurePick {
+    placed: PlacedStructure,
+    padded: Rect,
+}
+
+/// Rolls a chunk's ground and structures.
+fn plan_chunk(source: &ChunkSource, coord: ChunkCoord) -> ChunkPlan {
+    let worldgen = &source.worldgen;
+    let mut background = vec![EMPTY_TILE; CHUNK_LEN];
+    let mut terrain = vec![NO_TERRAIN; CHUNK_LEN];
+    let (origin_x, origin_y) = (coord.0 * CHUNK_SIZE, coord.1 * CHUNK_SIZE);
+    for ly in 0..CHUNK_SIZE {
+        for lx in 0..CHUNK_SIZE {
+            let (x, y) = (origin_x + lx, origin_y + ly);
+            if x >= source.width || y >= source.height {
+                continue;
+            }
+            let i = ly * CHUNK_SIZE + lx;
+            let biome = worldgen.biome(x, y);
+            background[i] = worldgen.background(biome);
+            terrain[i] = source.terrain_ids[biome as usize];
         }
-        if let Some(flag) = self.pending_dirty_foreground.get_mut(chunk_index) {
-            *flag = false;
+    }
+
+    let mut foreground = vec![EMPTY_TILE; CHUNK_LEN];
+    let mut overlay = vec![EMPTY_TILE; CHUNK_LEN];
+    for ly in 0..CHUNK_SIZE {
+        for lx in 0..CHUNK_SIZE {
+            let (x, y) = (origin_x + lx, origin_y + ly);
+            if !planned_autotile(x, y, (origin_x, origin_y), source.width, source.height) {
+                continue;
+            }
+            let here = terrain[ly * CHUNK_SIZE + lx];
+            let Some(rule) = source.autotile.rule(here) else {
+                continue;
+            };
+            let mask = source.autotile.mask(here, |dx, dy| {
+                let (nx, ny) = ((lx as i32 + dx) as usize, (ly as i32 + dy) as usize);
+                Some(terrain[ny * CHUNK_SIZE + nx])
+            });
+            let layer = match rule.layer {
+                LayerKind::Background => &mut background,
+                LayerKind::Foreground => &mut foreground,
+                LayerKind::Overlay => &mut overlay,
+            };
+            layer[ly * CHUNK_SIZE + lx] = rule.tile_for(mask);
         }
-        if let Some(flag) = self.pending_dirty_overlay.get_mut(chunk_index) {
-            *flag = false;
+    }
+
+    ChunkPlan {
+        coord,
+        background,
+        foreground,
+        overlay,
+        terrain,
+        structures: place_structures(source, coord),
+    }
+}
+
+/// The structures a chunk gets, from the seed alone: its own picks, minus those too close to
+/// the picks of a neighbour that outranks it. Neighbours' picks are rolled again here rather than
+/// read from the map, so the result doesn't depend on which chunks happen to be loaded, and no
+/// two kept structures are closer than `min_distance` allows.
+fn place_structures(source: &ChunkSource, coord: ChunkCoord) -> Vec<PlacedStructure> {
+    let picks = chunk_picks(source, coord);
+    if picks.is_empty() {
+        return Vec::new();
+    }
+    let rank = chunk_rank(source.seed, coord);
+    let mut rivals = Vec::new();
+    for y in coord.1.saturating_sub(1)..=coord.1 + 1 {
+        for x in coord.0.saturating_sub(1)..=coord.0 + 1 {
+            let other = (x, y);
+            if other == coord || x * CHUNK_SIZE >= source.width || y * CHUNK_SIZE >= source.height {
+                continue;
+            }
+            if chunk_rank(source.seed, other) > rank {
+                rivals.extend(chunk_picks(source, other).into_iter().map(|pick| pick.padded));
+            }
         }
     }
+    picks
+        .into_iter()
+        .filter(|pick| !rivals.iter().any(|rival| rival.overlaps(&pick.padded)))
+        .map(|pick| pick.placed)
+        .collect()
+}
+
+/// Which of two neighbouring chunks keeps its structures when they crowd each other.
+fn chunk_rank(seed: u32, coord: ChunkCoord) -> (u32, usize, usize) {
+    (hash_u32(coord.0 as u32, coord.1 as u32, seed ^ 0x0bad_5eed), coord.0, coord.1)
 }
 
-fn spatial_cell_range(
-    rect: &Rect,
-    cell_size: f32,
-    cols: usize,
-    rows: usize,
-) -> (usize, usize, usize, usize) {
-    let max_col = cols.saturating_sub(1) as i32;
-    let max_row = rows.saturating_sub(1) as i32;
-    let min_cx = (rect.x / cell_size).floor() as i32;
-    let max_cx = ((rect.x + rect.w) / cell_size).floor() as i32;
-    let min_cy = (rect.y / cell_size).floor() as i32;
-    let max_cy = ((rect.y + rect.h) / cell_size).floor() as i32;
+/// The structures a chunk would place if it were alone. Each definition expects `frequency`
+/// structures per tile (fractions rolled as a chance), up to the chunk's share of `max_per_map`;
+/// footprints stay inside the chunk and keep their `min_distance` from each other.
+fn chunk_picks(source: &ChunkSource, coord: ChunkCoord) -> Vec<StructurePick> {
+    let worldgen = &source.worldgen;
+    let (origin_x, origin_y) = (coord.0 * CHUNK_SIZE, coord.1 * CHUNK_SIZE);
+    let chunk_w = CHUNK_SIZE.min(source.width - origin_x);
+    let chunk_h = CHUNK_SIZE.min(source.height - origin_y);
+    let chunk_seed = hash_u32(coord.0 as u32, coord.1 as u32, source.seed);
+    let mut picks: Vec<StructurePick> = Vec::new();
+    let mut occupied = vec![false; CHUNK_LEN];
+    for (def_index, def) in source.defs.iter().enumerate() {
+        let freq = def.frequency.clamp(0.0, 1.0);
+        if freq <= 0.0 || def.structure.is_empty() || def.max_per_map == 0 {
+            continue;
+        }
+        if def.structure.width == 0
+            || def.structure.height == 0
+            || chunk_w < def.structure.width
+            || chunk_h < def.structure.height
+        {
+            continue;
+        }
+
+        let def_seed = (def_index as u32).wrapping_mul(2654435761);
+        let def_seed_y = (def_index as u32).wrapping_mul(2246822519);
+        let expected = (chunk_w * chunk_h) as f32 * freq;
+        let mut target = expected.floor() as usize;
+        if unit_roll(hash_u32(def_index as u32, chunk_seed, 13)) < expected.fract() {
+            target += 1;
+        }
+        let target = target.min(chunk_quota(source.seed, def_index, def.max_per_map, coord));
+        if target == 0 {
+            continue;
+        }
 
-    let min_cx = min_cx.clamp(0, max_col);
-    let max_cx = max_cx.clamp(0, max_col);
-    let min_cy = min_cy.clamp(0, max_row);
-    let max_cy = max_cy.clamp(0, max_row);
+        let attempts = (target * 12).max(24);
+        let max_x = chunk_w - def.structure.width;
+        let max_y = chunk_h - def.structure.height;
+        let mut count = 0usize;
+        for i in 0..attempts {
+            if count >= target {
+                break;
+            }
+            let rx = hash_u32(i as u32, chunk_seed ^ def_seed, 31);
+            let ry = hash_u32(i as u32, chunk_seed ^ def_seed_y, 47);
+            let x = origin_x + rx as usize % (max_x + 1);
+            let y = origin_y + ry as usize % (max_y + 1);
+            if !biome_accepts(worldgen, def, x, y, hash_u32(i as u32, chunk_seed ^ def_seed, 59)) {
+                continue;
+            }
+            if !ground_accepts(source, def, x, y) {
+                continue;
+            }
+            let margin = def.min_distance.max(0.0);
+            let padded = Rect::new(
+                x as f32 * source.tile_size - margin,
+                y as f32 * source.tile_size - margin,
+                def.structure.width as f32 * source.tile_size + margin * 2.0,
+                def.structure.height as f32 * source.tile_size + margin * 2.0,
+            );
+            if picks.iter().any(|pick| pick.padded.overlaps(&padded)) {
+                continue;
+            }
+            let variant = def.pick_variant(hash_u32(i as u32, chunk_seed ^ def_seed, 71));
+            let (lx, ly) = (x - origin_x, y - origin_y);
+            let local = |sx: usize, sy: usize| (ly + sy) * CHUNK_SIZE + lx + sx;
+            let offsets = &def.variant(variant).occupied_offsets;
+            if offsets.iter().any(|&(sx, sy)| occupied[local(sx, sy)]) {
+                continue;
+            }
+            for &(sx, sy) in offsets.iter() {
+                occupied[local(sx, sy)] = true;
+            }
+            picks.push(StructurePick {
+                placed: PlacedStructure {
+                    id: def.id.clone(),
+                    x,
+                    y,
+                    variant,
+                },
+                padded,
+            });
+            count += 1;
+        }
+    }
+    picks
+}
 
-    (min_cx as usize, max_cx as usize, min_cy as usize, max_cy as usize)
+/// A chunk's share of a definition's `max_per_map`. Each `QUOTA_REGION` square of chunks splits
+/// the cap evenly, and a seeded shuffle of the region decides which chunks get the remainder.
+fn chunk_quota(seed: u32, def_index: usize, max: usize, coord: ChunkCoord) -> usize {
+    let cells = QUOTA_REGION * QUOTA_REGION;
+    let (base, extra) = (max / cells, max % cells);
+    if extra == 0 {
+        return base;
+    }
+    let region = (coord.0 / QUOTA_REGION, coord.1 / QUOTA_REGION);
+    let region_seed = hash_u32(region.0 as u32, region.1 as u32, seed ^ (def_index as u32).wrapping_mul(0x27d4_eb2d));
+    let key = |cell: usize| (hash_u32(cell as u32, region_seed, 89), cell);
+    let mine = key((coord.1 % QUOTA_REGION) * QUOTA_REGION + coord.0 % QUOTA_REGION);
+    let rank = (0..cells).filter(|&cell| key(cell) < mine).count();
+    base + usize::from(rank < extra)
 }
 
-fn spatial_overlaps(
-    rect: &Rect,
-    placed: &[Rect],
-    grid: &[Vec<usize>],
-    cell_size: f32,
-    cols: usize,
-    rows: usize,
-) -> bool {
-    if placed.is_empty() {
-        return false;
+/// Checks the generated background under the footprint against `allowed_tiles` and
+/// `forbidden_tiles`.
+fn ground_accepts(source: &ChunkSource, def: &StructureDef, x: usize, y: usize) -> bool {
+    if def.allowed_tiles.is_empty() && def.forbidden_tiles.is_empty() {
+        return true;
     }
+    (y..y + def.structure.height).all(|ty| {
+        (x..x + def.structure.width).all(|tx| {
+            let tile = generated_background(source, tx, ty);
+            (def.allowed_tiles.is_empty() || def.allowed_tiles.contains(&tile))
+                && !def.forbidden_tiles.contains(&tile)
+        })
+    })
+}
 
-    let (min_cx, max_cx, min_cy, max_cy) = spatial_cell_range(rect, cell_size, cols, rows);
-    for cy in min_cy..=max_cy {
-        for cx in min_cx..=max_cx {
-            let cell = &grid[cy * cols + cx];
-            for &idx in cell {
-                if placed[idx].overlaps(rect) {
-                    return true;
+/// The background tile generation leaves at a tile once the chunks around it are loaded too: its
+/// biome's tile, autotiled against the biomes next to it.
+fn generated_background(source: &ChunkSource, x: usize, y: usize) -> u8 {
+    let worldgen = &source.worldgen;
+    let biome = worldgen.biome(x, y);
+    let here = source.terrain_ids[biome as usize];
+    match source.autotile.rule(here) {
+        Some(rule) if matches!(rule.layer, LayerKind::Background) => {
+            let mask = source.autotile.mask(here, |dx, dy| {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || ny < 0 || nx as usize >= source.width || ny as usize >= source.height {
+                    return None;
                 }
-            }
+                Some(source.terrain_ids[worldgen.biome(nx as usize, ny as usize) as usize])
+            });
+            rule.tile_for(mask)
         }
+        _ => worldgen.background(biome),
     }
-    false
 }
 
-fn spatial_insert(
-    index: usize,
-    rect: &Rect,
-    grid: &mut [Vec<usize>],
-    cell_size: f32,
-    cols: usize,
-    rows: usize,
-) {
-    let (min_cx, max_cx, min_cy, max_cy) = spatial_cell_range(rect, cell_size, cols, rows);
-    for cy in min_cy..=max_cy {
-        for cx in min_cx..=max_cx {
-            grid[cy * cols + cx].push(index);
+/// Rolls the structure's biome weight at its base tile.
+fn biome_accepts(worldgen: &WorldGen, def: &StructureDef, x: usize, y: usize, roll: u32) -> bool {
+    let biome = worldgen.biome(
+        x + def.structure.width / 2,
+        y + def.structure.height.saturating_sub(1),
+    );
+    let weight = if def.biomes.is_empty() {
+        if biome.is_water() { 0.0 } else { 1.0 }
+    } else {
+        def.biomes.get(biome.tag()).copied().unwrap_or(0.0)
+    };
+    unit_roll(roll) < weight.clamp(0.0, 1.0)
+}
+
+/// Plans requested chunks on a background thread. The thread stops once the worker is dropped.
+struct ChunkWorker {
+    requests: Sender<ChunkCoord>,
+    plans: Receiver<ChunkPlan>,
+    /// Requested chunks whose plans haven't been received yet.
+    pending: HashSet<ChunkCoord>,
+}
+
+impl ChunkWorker {
+    /// Starts the thread; `None` on the web build or if the thread can't start.
+    fn spawn(source: ChunkSource) -> Option<Self> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        let (requests, request_rx) = mpsc::channel::<ChunkCoord>();
+        let (plan_tx, plans) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("chunk-planner".to_string())
+            .spawn(move || {
+                for coord in request_rx {
+                    if plan_tx.send(plan_chunk(&source, coord)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .inspect_err(|err| eprintln!("chunk worker unavailable, generating on the main thread: {err}"))
+            .ok()?;
+        Some(Self {
+            requests,
+            plans,
+            pending: HashSet::new(),
+        })
+    }
+
+    /// Asks for a chunk unless it is already on the way or too many are. False once the thread
+    /// has stopped.
+    fn request(&mut self, coord: ChunkCoord) -> bool {
+        if self.pending.len() >= MAX_PENDING_CHUNKS || self.pending.contains(&coord) {
+            return true;
         }
+        if self.requests.send(coord).is_err() {
+            return false;
+        }
+        self.pending.insert(coord);
+        true
+    }
+
+    /// The next finished plan; `Disconnected` once the thread has stopped and every plan it sent
+    /// has been received.
+    fn try_recv(&mut self) -> Result<ChunkPlan, TryRecvError> {
+        let plan = self.plans.try_recv()?;
+        self.pending.remove(&plan.coord);
+        Ok(plan)
     }
 }
 
+fn unit_roll(roll: u32) -> f32 {
+    roll as f32 / u32::MAX as f32
+}
+
+
 fn hash_u32(x: u32, y: u32, seed: u32) -> u32 {
     let mut v = x.wrapping_mul(0x9E3779B1) ^ y.wrapping_mul(0x85EBCA6B) ^ seed;
     v ^= v >> 16;
@@ -1720,6 +4192,47 @@ fn hash_u32(x: u32, y: u32, seed: u32) -> u32 {
     v
 }
 
+fn tmx_layer_kind(name: &str, property: Option<&String>, order: usize) -> Option<LayerKind> {
+    let key = property.map(String::as_str).unwrap_or(name).to_ascii_lowercase();
+    if key.contains("background") || key == "bg" {
+        return Some(LayerKind::Background);
+    }
+    if key.contains("foreground") || key == "fg" {
+        return Some(LayerKind::Foreground);
+    }
+    if key.contains("overlay") {
+        return Some(LayerKind::Overlay);
+    }
+    match order {
+        0 => Some(LayerKind::Background),
+        1 => Some(LayerKind::Foreground),
+        2 => Some(LayerKind::Overlay),
+        _ => None,
+    }
+}
+
+/// Quarter-tile rects for the pins in `mask`, or the whole tile when all four are set.
+fn pin_rects(tile: Rect, mask: u8, out: &mut Vec<Rect>) {
+    if mask & PIN_BITS == PIN_BITS {
+        out.push(tile);
+        return;
+    }
+    let half_w = tile.w * 0.5;
+    let half_h = tile.h * 0.5;
+    if (mask & 0b0001) != 0 {
+        out.push(Rect::new(tile.x, tile.y, half_w, half_h));
+    }
+    if (mask & 0b0010) != 0 {
+        out.push(Rect::new(tile.x + half_w, tile.y, half_w, half_h));
+    }
+    if (mask & 0b0100) != 0 {
+        out.push(Rect::new(tile.x, tile.y + half_h, half_w, half_h));
+    }
+    if (mask & 0b1000) != 0 {
+        out.push(Rect::new(tile.x + half_w, tile.y + half_h, half_w, half_h));
+    }
+}
+
 fn merge_rect(a: Rect, b: Rect) -> Rect {
     let min_x = a.x.min(b.x);
     let min_y = a.y.min(b.y);
@@ -1742,7 +4255,7 @@ pub async fn load_structures_from_dir(dir: impl AsRef<Path>) -> Result<Vec<Struc
             let raw: StructureFile = serde_json::from_str(&raw_str)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
             let tile_len = raw.width * raw.height;
-            let colliders = normalized_collider_pins(raw.colliders, tile_len);
+            let colliders = raw.colliders.into_masks(tile_len);
             let interactors = normalized_collider_pins(raw.interactors, tile_len);
             let structure = Structure::new(
                 raw.width,
@@ -1753,15 +4266,34 @@ pub async fn load_structures_from_dir(dir: impl AsRef<Path>) -> Result<Vec<Struc
                 colliders,
                 interactors,
             );
+            let regions = structure_regions(&raw.id, raw.width, raw.height, raw.regions);
+            let markers = structure_markers(&raw.id, raw.width, raw.height, raw.markers);
+            let variants = structure_variants(&raw.id, raw.width, raw.height, raw.variants);
+            let vendor = structure_vendor(&raw.id, raw.width, raw.height, raw.vendor);
 
             defs.push(StructureDef {
                 id: raw.id,
                 structure,
+                weight: raw.weight.unwrap_or(1.0),
+                variants,
                 on_interact: raw.on_interact.unwrap_or_default(),
+                on_trigger: raw.on_trigger,
                 interact_range: raw.interact_range.unwrap_or(0.0).max(0.0),
+                highlight: raw.highlight.map(HighlightFile::into_style).unwrap_or_default(),
+                hold_time: raw.hold_time.unwrap_or(0.0).max(0.0),
+                keep_progress: raw.keep_progress.unwrap_or(false),
                 frequency: raw.frequency.unwrap_or(0.05),
                 max_per_map: raw.max_per_map.unwrap_or(10),
                 min_distance: raw.min_distance.unwrap_or(64.0),
+                tags: raw.tags,
+                biomes: raw.biomes.unwrap_or_default(),
+                allowed_tiles: raw.allowed_tiles,
+                forbidden_tiles: raw.forbidden_tiles,
+                regions,
+                markers,
+                spawns: raw.spawns,
+                vendor,
+                encounter: raw.encounter,
             });
         }
         return Ok(defs);
@@ -1784,7 +4316,7 @@ pub async fn load_structures_from_dir(dir: impl AsRef<Path>) -> Result<Vec<Struc
         let raw: StructureFile = serde_json::from_str(&std::fs::read_to_string(&path)?)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
         let tile_len = raw.width * raw.height;
-        let colliders = normalized_collider_pins(raw.colliders, tile_len);
+        let colliders = raw.colliders.into_masks(tile_len);
         let interactors = normalized_collider_pins(raw.interactors, tile_len);
         let structure = Structure::new(
             raw.width,
@@ -1795,21 +4327,60 @@ pub async fn load_structures_from_dir(dir: impl AsRef<Path>) -> Result<Vec<Struc
             colliders,
             interactors,
         );
+        let regions = structure_regions(&raw.id, raw.width, raw.height, raw.regions);
+        let markers = structure_markers(&raw.id, raw.width, raw.height, raw.markers);
+        let variants = structure_variants(&raw.id, raw.width, raw.height, raw.variants);
+        let vendor = structure_vendor(&raw.id, raw.width, raw.height, raw.vendor);
 
         defs.push(StructureDef {
             id: raw.id,
             structure,
+            weight: raw.weight.unwrap_or(1.0),
+            variants,
             on_interact: raw.on_interact.unwrap_or_default(),
+            on_trigger: raw.on_trigger,
             interact_range: raw.interact_range.unwrap_or(0.0).max(0.0),
+            highlight: raw.highlight.map(HighlightFile::into_style).unwrap_or_default(),
+            hold_time: raw.hold_time.unwrap_or(0.0).max(0.0),
+            keep_progress: raw.keep_progress.unwrap_or(false),
             frequency: raw.frequency.unwrap_or(0.05),
             max_per_map: raw.max_per_map.unwrap_or(10),
             min_distance: raw.min_distance.unwrap_or(64.0),
+            tags: raw.tags,
+            biomes: raw.biomes.unwrap_or_default(),
+            allowed_tiles: raw.allowed_tiles,
+            forbidden_tiles: raw.forbidden_tiles,
+            regions,
+            markers,
+            spawns: raw.spawns,
+            vendor,
+            encounter: raw.encounter,
         });
     }
 
     Ok(defs)
 }
 
+fn rle_encode(tiles: &[u8]) -> Vec<[u32; 2]> {
+    let mut out: Vec<[u32; 2]> = Vec::new();
+    for &tile in tiles {
+        match out.last_mut() {
+            Some([value, run]) if *value == tile as u32 => *run += 1,
+            _ => out.push([tile as u32, 1]),
+        }
+    }
+    out
+}
+
+fn rle_decode(runs: &[[u32; 2]], len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    for &[value, run] in runs {
+        let value = u8::try_from(value).ok()?;
+        out.extend(std::iter::repeat_n(value, run as usize));
+    }
+    (out.len() == len).then_some(out)
+}
+
 #[derive(Deserialize)]
 struct StructureFile {
     id: String,
@@ -1820,20 +4391,264 @@ struct StructureFile {
     foreground: Vec<u8>,
     #[serde(default)]
     overlay: Vec<u8>,
-    #[serde(default)]
-    colliders: Option<ColliderPinsFile>,
+    #[serde(flatten)]
+    colliders: ColliderLayersFile,
     #[serde(default)]
     interactors: Option<ColliderPinsFile>,
     #[serde(default)]
     on_interact: Option<Vec<String>>,
     #[serde(default)]
+    on_trigger: Vec<String>,
+    #[serde(default)]
     interact_range: Option<f32>,
     #[serde(default)]
+    highlight: Option<HighlightFile>,
+    #[serde(default)]
+    hold_time: Option<f32>,
+    #[serde(default)]
+    keep_progress: Option<bool>,
+    #[serde(default)]
     frequency: Option<f32>,
     #[serde(default)]
     max_per_map: Option<usize>,
     #[serde(default)]
     min_distance: Option<f32>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    biomes: Option<HashMap<String, f32>>,
+    #[serde(default)]
+    allowed_tiles: Vec<u8>,
+    #[serde(default)]
+    forbidden_tiles: Vec<u8>,
+    #[serde(default)]
+    regions: Vec<RegionFile>,
+    #[serde(default)]
+    markers: Vec<MarkerFile>,
+    #[serde(default)]
+    spawns: Vec<StructureSpawn>,
+    #[serde(default)]
+    vendor: Option<StructureVendor>,
+    #[serde(default)]
+    encounter: Option<StructureEncounter>,
+    /// Weight of the main tile grid among `variants`.
+    #[serde(default)]
+    weight: Option<f32>,
+    #[serde(default)]
+    variants: Vec<VariantFile>,
+}
+
+/// An alternative tile grid. It has the structure's size; everything but its tiles and pins
+/// comes from the structure.
+#[derive(Deserialize)]
+struct VariantFile {
+    #[serde(default = "default_variant_weight")]
+    weight: f32,
+    background: Vec<u8>,
+    #[serde(default)]
+    foreground: Vec<u8>,
+    #[serde(default)]
+    overlay: Vec<u8>,
+    #[serde(flatten)]
+    colliders: ColliderLayersFile,
+    #[serde(default)]
+    interactors: Option<ColliderPinsFile>,
+}
+
+fn default_variant_weight() -> f32 {
+    1.0
+}
+
+/// The subset of `StructureFile` written by `TileMap::export_structure_json`.
+#[derive(Serialize)]
+struct StructureExport {
+    id: String,
+    width: usize,
+    height: usize,
+    background: Vec<u8>,
+    foreground: Vec<u8>,
+    overlay: Vec<u8>,
+    colliders: Vec<u8>,
+    interactors: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct RegionFile {
+    tag: String,
+    #[serde(default)]
+    x: usize,
+    #[serde(default)]
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Drops regions that are empty or reach outside the structure.
+fn structure_regions(id: &str, width: usize, height: usize, raw: Vec<RegionFile>) -> Vec<StructureRegion> {
+    raw.into_iter()
+        .filter(|region| {
+            let fits = region.width > 0
+                && region.height > 0
+                && region.x + region.width <= width
+                && region.y + region.height <= height;
+            if !fits {
+                eprintln!("structure '{id}' region '{}' is outside the structure; ignoring", region.tag);
+            }
+            fits
+        })
+        .map(|region| StructureRegion {
+            tag: region.tag,
+            x: region.x,
+            y: region.y,
+            width: region.width,
+            height: region.height,
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct MarkerFile {
+    name: String,
+    x: usize,
+    y: usize,
+}
+
+/// Drops a vendor standing outside the structure.
+fn structure_vendor(id: &str, width: usize, height: usize, raw: Option<StructureVendor>) -> Option<StructureVendor> {
+    let vendor = raw?;
+    if vendor.x >= width || vendor.y >= height {
+        eprintln!("structure '{id}' vendor '{}' stands outside the structure; ignoring", vendor.entity);
+        return None;
+    }
+    Some(vendor)
+}
+
+/// Drops markers outside the structure.
+fn structure_markers(id: &str, width: usize, height: usize, raw: Vec<MarkerFile>) -> Vec<StructureMarker> {
+    raw.into_iter()
+        .filter(|marker| {
+            let fits = marker.x < width && marker.y < height;
+            if !fits {
+                eprintln!("structure '{id}' marker '{}' is outside the structure; ignoring", marker.name);
+            }
+            fits
+        })
+        .map(|marker| StructureMarker {
+            name: marker.name,
+            x: marker.x,
+            y: marker.y,
+        })
+        .collect()
+}
+
+/// Builds the variant grids, dropping any whose layers don't match the structure's size.
+fn structure_variants(id: &str, width: usize, height: usize, raw: Vec<VariantFile>) -> Vec<StructureVariant> {
+    let tile_len = width * height;
+    raw.into_iter()
+        .enumerate()
+        .filter(|(index, variant)| {
+            let layer_fits = |layer: &[u8]| layer.is_empty() || layer.len() == tile_len;
+            let fits = variant.background.len() == tile_len
+                && layer_fits(&variant.foreground)
+                && layer_fits(&variant.overlay);
+            if !fits {
+                eprintln!("structure '{id}' variant {} isn't {width}x{height}; ignoring", index + 1);
+            }
+            fits
+        })
+        .map(|(_, variant)| StructureVariant {
+            structure: Structure::new(
+                width,
+                height,
+                variant.background,
+                variant.foreground,
+                variant.overlay,
+                variant.colliders.into_masks(tile_len),
+                normalized_collider_pins(variant.interactors, tile_len),
+            ),
+            weight: variant.weight,
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct HighlightFile {
+    #[serde(default)]
+    color: Option<[u8; 3]>,
+    #[serde(default)]
+    fill_alpha: Option<f32>,
+    #[serde(default)]
+    outline_alpha: Option<f32>,
+    #[serde(default)]
+    pulse: Option<f32>,
+    #[serde(default)]
+    icon: Option<String>,
+    /// "hide" (default) or "offset".
+    #[serde(default)]
+    occluded: Option<String>,
+    #[serde(default)]
+    occluded_offset: Option<f32>,
+}
+
+impl HighlightFile {
+    fn into_style(self) -> HighlightStyle {
+        let base = HighlightStyle::default();
+        let color = self
+            .color
+            .map(|[r, g, b]| Color::from_rgba(r, g, b, 255))
+            .unwrap_or(base.color);
+        let occluded = match self.occluded.as_deref() {
+            Some("offset") => OccludedHighlight::Offset(self.occluded_offset.unwrap_or(16.0)),
+            Some("hide") | None => OccludedHighlight::Hide,
+            Some(other) => {
+                eprintln!("unknown highlight occlusion mode '{other}', hiding instead");
+                OccludedHighlight::Hide
+            }
+        };
+        HighlightStyle {
+            color,
+            fill_alpha: self.fill_alpha.unwrap_or(base.fill_alpha).clamp(0.0, 1.0),
+            outline_alpha: self.outline_alpha.unwrap_or(base.outline_alpha).clamp(0.0, 1.0),
+            pulse: self.pulse.unwrap_or(0.0).max(0.0),
+            icon: self.icon,
+            occluded,
+        }
+    }
+}
+
+/// Collider pin grids of a structure or variant, one per collider kind.
+#[derive(Deserialize)]
+struct ColliderLayersFile {
+    #[serde(default)]
+    colliders: Option<ColliderPinsFile>,
+    #[serde(default)]
+    one_way: Option<ColliderPinsFile>,
+    #[serde(default)]
+    slow: Option<ColliderPinsFile>,
+    #[serde(default)]
+    triggers: Option<ColliderPinsFile>,
+}
+
+impl ColliderLayersFile {
+    /// Merges the grids into collision masks. A tile takes the first kind with pins on it, in
+    /// the order solid, one-way, slow, trigger.
+    fn into_masks(self, tile_len: usize) -> Vec<u8> {
+        let layers = [
+            (ColliderKind::Solid, self.colliders),
+            (ColliderKind::OneWay, self.one_way),
+            (ColliderKind::Slow, self.slow),
+            (ColliderKind::Trigger, self.triggers),
+        ]
+        .map(|(kind, raw)| (kind, normalized_collider_pins(raw, tile_len)));
+        (0..tile_len)
+            .map(|i| {
+                layers
+                    .iter()
+                    .find(|(_, pins)| pins[i] != 0)
+                    .map_or(0, |(kind, pins)| pins[i] | kind.bits())
+            })
+            .collect()
+    }
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,178 @@
+//! Corner minimap.
+//!
+//! Every time a chunk loads or changes, the minimap turns its `chunk_summary` into a small
+//! thumbnail, with solid ground shaded darker, so chunks stay on the minimap after they are
+//! unloaded. The minimap is composed in its own render target around the player, with dots for the
+//! player and entities and rings for pings. M cycles the zoom (back to the closest zoom also
+//! recenters) and the arrow keys pan.
+
+use macroquad::prelude::*;
+use std::collections::HashMap;
+use crate::entity::{Entity, EntityDatabase, EntityKind};
+use crate::map::{ChunkCoord, TileMap, SUMMARY_CELLS};
+use crate::ping::{self, Pings};
+
+const SIZE: f32 = 144.0;
+const MARGIN: f32 = 20.0;
+/// Brightness of cells the summary marks as blocked.
+const BLOCKED_SHADE: f32 = 0.6;
+/// Minimap pixels per tile at each zoom level.
+const ZOOM_LEVELS: [f32; 3] = [3.0, 1.5, 0.75];
+const ZOOM_KEY: KeyCode = KeyCode::M;
+/// Minimap pixels panned per second.
+const PAN_SPEED: f32 = 120.0;
+const DOT_RADIUS: f32 = 2.0;
+const BACKGROUND: Color = Color::new(0.05, 0.05, 0.08, 1.0);
+
+pub struct Minimap {
+    canvas: RenderTarget,
+    thumbs: HashMap<ChunkCoord, Texture2D>,
+    zoom: usize,
+    /// World offset of the minimap center from the player.
+    pan: Vec2,
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Minimap {
+    pub fn new() -> Self {
+        let canvas = render_target(SIZE as u32, SIZE as u32);
+        canvas.texture.set_filter(FilterMode::Nearest);
+        Self {
+            canvas,
+            thumbs: HashMap::new(),
+            zoom: 0,
+            pan: Vec2::ZERO,
+        }
+    }
+
+    /// Handles zoom and pan, and refreshes the thumbnails of chunks that changed.
+    pub fn update(&mut self, map: &mut TileMap, dt: f32) {
+        if is_key_pressed(ZOOM_KEY) {
+            self.zoom = (self.zoom + 1) % ZOOM_LEVELS.len();
+            if self.zoom == 0 {
+                self.pan = Vec2::ZERO;
+            }
+        }
+        let mut dir = Vec2::ZERO;
+        if is_key_down(KeyCode::Left) {
+            dir.x -= 1.0;
+        }
+        if is_key_down(KeyCode::Right) {
+            dir.x += 1.0;
+        }
+        if is_key_down(KeyCode::Up) {
+            dir.y -= 1.0;
+        }
+        if is_key_down(KeyCode::Down) {
+            dir.y += 1.0;
+        }
+        self.pan += dir * PAN_SPEED * dt / self.scale(map);
+
+        for coord in map.take_changed_chunks() {
+            self.refresh_thumb(map, coord);
+        }
+    }
+
+    /// Minimap pixels per world pixel.
+    fn scale(&self, map: &TileMap) -> f32 {
+        ZOOM_LEVELS[self.zoom] / map.tile_size().max(1.0)
+    }
+
+    fn refresh_thumb(&mut self, map: &TileMap, coord: ChunkCoord) {
+        let Some(summary) = map.chunk_summary(coord.0, coord.1) else {
+            return;
+        };
+        let mut pixels = Vec::with_capacity(summary.colors.len() * 4);
+        for (color, &blocked) in summary.colors.iter().zip(&summary.blocked) {
+            let shade = if blocked { BLOCKED_SHADE } else { 1.0 };
+            let rgba: [u8; 4] = Color::new(color.r * shade, color.g * shade, color.b * shade, color.a).into();
+            pixels.extend_from_slice(&rgba);
+        }
+        let texture = Texture2D::from_rgba8(SUMMARY_CELLS as u16, SUMMARY_CELLS as u16, &pixels);
+        texture.set_filter(FilterMode::Nearest);
+        self.thumbs.insert(coord, texture);
+    }
+
+    /// Draws the minimap in the bottom-right corner. With `fog`, unexplored tiles are covered
+    /// in that color and the entities on them are left out.
+    pub fn draw(
+        &self,
+        map: &TileMap,
+        player_pos: Vec2,
+        entities: &[Entity],
+        db: &EntityDatabase,
+        pings: &Pings,
+        fog: Option<Color>,
+    ) {
+        let scale = self.scale(map);
+        let half = SIZE * 0.5 / scale;
+        let center = player_pos + self.pan;
+        let view = Rect::new(center.x - half, center.y - half, half * 2.0, half * 2.0);
+        let mut cam = Camera2D::from_display_rect(view);
+        cam.render_target = Some(self.canvas.clone());
+
+        push_camera_state();
+        set_camera(&cam);
+        clear_background(BACKGROUND);
+        for (&coord, thumb) in &self.thumbs {
+            let area = map.chunk_world_rect(coord);
+            if !area.overlaps(&view) {
+                continue;
+            }
+            draw_texture_ex(
+                thumb,
+                area.x,
+                area.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(area.size()),
+                    ..Default::default()
+                },
+            );
+        }
+        if let Some(color) = fog {
+            map.draw_fog(view, color);
+        }
+        let explored = |pos: Vec2| {
+            fog.is_none() || map.grid_index(pos).is_some_and(|grid| map.is_explored(grid.x as usize, grid.y as usize))
+        };
+        for ent in entities.iter().filter(|ent| !ent.instance.is_corpse()) {
+            let pos = ent.hitbox(db).center();
+            if !view.contains(pos) || !explored(pos) {
+                continue;
+            }
+            let color = match db.entities[ent.instance.def].kind {
+                EntityKind::Enemy => RED,
+                EntityKind::Friend => GREEN,
+                EntityKind::Misc => YELLOW,
+            };
+            draw_circle(pos.x, pos.y, DOT_RADIUS / scale, color);
+        }
+        for pos in pings.positions() {
+            // Pings show through fog, and ones off the minimap sit on its edge.
+            let edge = vec2(pos.x.clamp(view.x, view.right()), pos.y.clamp(view.y, view.bottom()));
+            draw_circle_lines(edge.x, edge.y, (DOT_RADIUS + 2.0) / scale, 1.0 / scale, ping::COLOR);
+        }
+        draw_circle(player_pos.x, player_pos.y, (DOT_RADIUS + 1.0) / scale, WHITE);
+        pop_camera_state();
+
+        let origin = vec2(screen_width() - SIZE - MARGIN, screen_height() - SIZE - MARGIN);
+        draw_texture_ex(
+            &self.canvas.texture,
+            origin.x,
+            origin.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(Vec2::splat(SIZE)),
+                flip_y: true,
+                ..Default::default()
+            },
+        );
+        draw_rectangle_lines(origin.x, origin.y, SIZE, SIZE, 1.0, WHITE);
+    }
+}
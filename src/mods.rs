@@ -0,0 +1,162 @@
+//! Content mods loaded from a `mods/` directory next to the game.
+//!
+//! Every subdirectory of `mods/` is one mod, laid out like `src/`: `entity/{enemy,friend,misc}`
+//! with `entity/trait` and `entity/behaviour`, then `structure`, `particle` and `sound`. Mods apply
+//! in name order after the built-in content. Sprite and sound paths inside their files are
+//! relative to the game directory, like the built-in ones (e.g. `mods/thorns/assets/thorn.png`).
+//!
+//! A definition reusing a taken id replaces the earlier one of the same kind. Replacing built-in
+//! content is what mods are for, so that is only logged. When two mods define the same id, the mod
+//! later in name order wins and the clash is reported as a warning. Entities copy their behavior
+//! tree when they load, so a replaced behavior only reaches entities loaded after it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::entity::EntityDatabase;
+use crate::map::{StructureDef, load_structures_from_dir};
+use crate::particle::ParticleSystem;
+use crate::sound::SoundSystem;
+
+pub const MODS_DIR: &str = "mods";
+
+struct Mod {
+    name: String,
+    root: PathBuf,
+}
+
+#[derive(Default)]
+pub struct ModSet {
+    mods: Vec<Mod>,
+    /// Mod that last defined each `(kind, id)`. Ids only the built-in content defines are absent.
+    owners: HashMap<(&'static str, String), usize>,
+}
+
+impl ModSet {
+    /// Finds the mods under `dir`. The web build can't list directories, so it never has mods.
+    pub fn scan(dir: impl AsRef<Path>) -> Self {
+        let mut set = Self::default();
+        if cfg!(target_arch = "wasm32") {
+            return set;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return set;
+        };
+        for entry in entries.flatten() {
+            let root = entry.path();
+            if root.is_dir() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                set.mods.push(Mod { name, root });
+            }
+        }
+        set.mods.sort_by(|a, b| a.name.cmp(&b.name));
+        set
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.mods.iter().map(|m| m.name.as_str())
+    }
+
+    /// Content directories of the mods, in load order.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.mods.iter().map(|m| m.root.as_path())
+    }
+
+    /// `subdir` of every mod that has one, with the mod's index.
+    fn dirs(&self, subdir: &str) -> Vec<(usize, PathBuf)> {
+        self.mods
+            .iter()
+            .enumerate()
+            .map(|(index, m)| (index, m.root.join(subdir)))
+            .filter(|(_, dir)| dir.is_dir())
+            .collect()
+    }
+
+    /// Records that mod `index` defined `id`, reporting what it took over.
+    fn claim(&mut self, index: usize, kind: &'static str, id: &str, replaced: bool) {
+        let name = &self.mods[index].name;
+        match self.owners.insert((kind, id.to_string()), index) {
+            Some(previous) if previous != index => eprintln!(
+                "warning: mod '{name}' overrides {kind} '{id}' from mod '{}'",
+                self.mods[previous].name
+            ),
+            Some(_) => {}
+            None if replaced => eprintln!("mod '{name}' replaces built-in {kind} '{id}'"),
+            None => {}
+        }
+    }
+
+    pub async fn apply_structures(&mut self, defs: &mut Vec<StructureDef>) {
+        for (index, dir) in self.dirs("structure") {
+            let modded = match load_structures_from_dir(&dir).await {
+                Ok(modded) => modded,
+                Err(err) => {
+                    eprintln!("mod '{}' structure load failed: {err}", self.mods[index].name);
+                    continue;
+                }
+            };
+            for def in modded {
+                let id = def.id.clone();
+                let replaced = match defs.iter().position(|existing| existing.id == id) {
+                    Some(pos) => {
+                        defs[pos] = def;
+                        true
+                    }
+                    None => {
+                        defs.push(def);
+                        false
+                    }
+                };
+                self.claim(index, "structure", &id, replaced);
+            }
+        }
+    }
+
+    pub async fn apply_entities(&mut self, db: &mut EntityDatabase) {
+        for (index, dir) in self.dirs("entity") {
+            let mut merged = Vec::new();
+            let result = db
+                .load_mod(&dir, |kind, id, replaced| merged.push((kind, id.to_string(), replaced)))
+                .await;
+            for (kind, id, replaced) in merged {
+                self.claim(index, kind, &id, replaced);
+            }
+            if let Err(err) = result {
+                eprintln!("mod '{}' entity load failed: {err}", self.mods[index].name);
+            }
+        }
+    }
+
+    pub async fn apply_particles(&mut self, particles: &mut ParticleSystem) {
+        for (index, dir) in self.dirs("particle") {
+            match ParticleSystem::load_from(&dir).await {
+                Ok(modded) => particles.merge(modded, |id, replaced| self.claim(index, "particle", id, replaced)),
+                Err(err) => eprintln!("mod '{}' particle load failed: {err}", self.mods[index].name),
+            }
+        }
+    }
+
+    pub async fn apply_sounds(&mut self, sounds: &mut SoundSystem) {
+        for (index, dir) in self.dirs("sound") {
+            match SoundSystem::load_from(&dir).await {
+                Ok(modded) => sounds.merge(modded, |id, replaced| self.claim(index, "sound", id, replaced)),
+                Err(err) => eprintln!("mod '{}' sound load failed: {err}", self.mods[index].name),
+            }
+        }
+    }
+}
+
+/// Stores `def` under `id`, replacing the definition already there. Returns whether one was.
+pub fn upsert<T>(defs: &mut Vec<T>, lookup: &mut HashMap<String, usize>, id: String, def: T) -> bool {
+    match lookup.get(&id) {
+        Some(&index) => {
+            defs[index] = def;
+            true
+        }
+        None => {
+            lookup.insert(id, defs.len());
+            defs.push(def);
+            false
+        }
+    }
+}
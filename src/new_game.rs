@@ -0,0 +1,121 @@
+//! The new-game screen, shown before a generated world is built.
+//!
+//! It starts on the seed from world.yaml. Typing replaces it with any text, which goes through
+//! `seed_from_text`, so players can share a word or a number and get the same world. Tab rolls a
+//! random seed. The preview shows the biomes around the spawn for the seed as typed, and Enter
+//! generates the world with it.
+
+use macroquad::prelude::*;
+use crate::helpers::random_u32;
+use crate::worldgen::{seed_from_text, WorldGenerator};
+
+/// Preview pixels per side; each is one biome sample.
+const PREVIEW_SIZE: u16 = 160;
+/// Screen pixels per preview pixel.
+const PREVIEW_SCALE: f32 = 2.0;
+const MAX_ENTRY_LEN: usize = 32;
+const RANDOM_KEY: KeyCode = KeyCode::Tab;
+
+struct NewGameScreen<'a> {
+    generator: &'a WorldGenerator,
+    entry: String,
+    /// Seed the preview was drawn for.
+    seed: u32,
+    preview: Texture2D,
+}
+
+impl<'a> NewGameScreen<'a> {
+    fn new(generator: &'a WorldGenerator, seed: u32) -> Self {
+        Self {
+            generator,
+            entry: seed.to_string(),
+            seed,
+            preview: preview_texture(generator, seed),
+        }
+    }
+
+    /// Applies this frame's typing; true once Enter was pressed.
+    fn handle_input(&mut self) -> bool {
+        if is_key_pressed(KeyCode::Enter) {
+            return true;
+        }
+        if is_key_pressed(RANDOM_KEY) {
+            let clock = (miniquad::date::now() * 1000.0) as u64 as u32;
+            self.entry = (random_u32() ^ clock).to_string();
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.entry.pop();
+        }
+        while let Some(ch) = get_char_pressed() {
+            let allowed = ch.is_alphanumeric() || matches!(ch, ' ' | '-' | '_');
+            if allowed && self.entry.chars().count() < MAX_ENTRY_LEN {
+                self.entry.push(ch);
+            }
+        }
+        let seed = seed_from_text(&self.entry);
+        if seed != self.seed {
+            self.seed = seed;
+            self.preview = preview_texture(self.generator, seed);
+        }
+        false
+    }
+
+    fn draw(&self) {
+        set_default_camera();
+        clear_background(BLACK);
+        let picture = Vec2::splat(PREVIEW_SIZE as f32 * PREVIEW_SCALE);
+        let width = picture.x + 40.0;
+        let height = picture.y + 150.0;
+        let origin = vec2((screen_width() - width) * 0.5, (screen_height() - height) * 0.5);
+        draw_text("New game", origin.x + 20.0, origin.y + 28.0, 30.0, WHITE);
+
+        let entry_y = origin.y + 44.0;
+        draw_rectangle_lines(origin.x + 20.0, entry_y, picture.x, 30.0, 1.0, WHITE);
+        let cursor = if ((get_time() * 2.0) as u64).is_multiple_of(2) { "_" } else { "" };
+        draw_text(&format!("Seed: {}{cursor}", self.entry), origin.x + 28.0, entry_y + 21.0, 22.0, WHITE);
+
+        let picture_y = entry_y + 40.0;
+        draw_texture_ex(
+            &self.preview,
+            origin.x + 20.0,
+            picture_y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(picture),
+                ..Default::default()
+            },
+        );
+        // The spawn is always in the middle.
+        let center = vec2(origin.x + 20.0, picture_y) + picture * 0.5;
+        draw_circle_lines(center.x, center.y, 4.0, 2.0, WHITE);
+        draw_rectangle_lines(origin.x + 20.0, picture_y, picture.x, picture.y, 1.0, GRAY);
+
+        draw_text(
+            &format!("World {}   Enter: start   Tab: random", self.seed),
+            origin.x + 20.0,
+            picture_y + picture.y + 26.0,
+            18.0,
+            LIGHTGRAY,
+        );
+    }
+}
+
+fn preview_texture(generator: &WorldGenerator, seed: u32) -> Texture2D {
+    let texture = Texture2D::from_image(&generator.preview(seed, PREVIEW_SIZE));
+    texture.set_filter(FilterMode::Nearest);
+    texture
+}
+
+/// Runs the screen until the player starts the game, returning the seed they chose.
+pub async fn choose_seed(generator: &WorldGenerator, seed: u32) -> u32 {
+    let mut screen = NewGameScreen::new(generator, seed);
+    // Keys typed during loading shouldn't end up in the entry.
+    while get_char_pressed().is_some() {}
+    loop {
+        if screen.handle_input() {
+            return screen.seed;
+        }
+        screen.draw();
+        next_frame().await;
+    }
+}
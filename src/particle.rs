@@ -1,9 +1,22 @@
 use macroquad::prelude::*;
-use macroquad::file::load_string;
+use crate::asset_pack::{load_string, load_texture};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::Path;
+use crate::cull::ViewCull;
 use crate::helpers::{asset_path, data_path, load_wasm_manifest_files};
+use crate::mods;
+
+/// Frame time the governor aims for; slower frames thin out non-critical particles.
+const TARGET_FRAME_TIME: f32 = 1.0 / 55.0;
+/// Share of non-critical particles the governor always lets through.
+const GOVERNOR_FLOOR: f32 = 0.2;
+/// How fast the governor cuts particles while frames are slow, and lets them back in
+/// afterwards, per second.
+const GOVERNOR_DROP: f32 = 1.5;
+const GOVERNOR_RECOVER: f32 = 0.25;
+/// Weight of the newest frame in the smoothed frame time.
+const FRAME_SMOOTHING: f32 = 0.1;
 
 #[derive(Debug)]
 pub enum ParticleLoadError {
@@ -44,6 +57,21 @@ pub enum ParticleShape {
     Texture,
 }
 
+/// When a template's particles are drawn relative to the rest of the frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleLayer {
+    /// Under sprites, like dust kicked up from the ground.
+    #[default]
+    BelowEntities,
+    /// Over sprites but under tree canopies and other overlay tiles.
+    AboveEntities,
+    /// Over everything in the world, like rain.
+    AboveOverlay,
+    /// In screen pixels rather than world space; emitters are placed in screen coordinates.
+    Screen,
+}
+
 #[derive(Clone)]
 pub struct ParticleConfig {
     pub id: String,
@@ -70,12 +98,18 @@ pub struct ParticleConfig {
     pub rotation_speed: f32,
     pub rotation_speed_variance: f32,
     pub dynamic_sprite: bool,
+    /// Gameplay-critical effects, such as hazard areas, that the frame-time governor leaves
+    /// alone.
+    pub critical: bool,
+    pub layer: ParticleLayer,
 }
 
 #[derive(Clone)]
 struct ParticleTemplate {
     config: ParticleConfig,
     texture: Option<Texture2D>,
+    /// File `texture` was loaded from, for hot reload.
+    texture_path: Option<String>,
 }
 
 #[derive(Clone)]
@@ -225,11 +259,14 @@ impl ParticlePool {
         }
     }
 
-    fn draw_in_rect(&self, templates: &[ParticleTemplate], rect: Rect) {
+    fn draw_in_view(&self, templates: &[ParticleTemplate], layer: ParticleLayer, view: &ViewCull) {
         for &idx in &self.active {
             let particle = &self.particles[idx];
             let template = &templates[particle.template];
             let cfg = &template.config;
+            if cfg.layer != layer {
+                continue;
+            }
 
             let t = 1.0 - (particle.life / particle.life_max).clamp(0.0, 1.0);
             let size = particle.size_start + (particle.size_end - particle.size_start) * t;
@@ -250,11 +287,7 @@ impl ParticlePool {
                 radius = 0.0;
             }
 
-            if particle.pos.x + radius < rect.x
-                || particle.pos.y + radius < rect.y
-                || particle.pos.x - radius > rect.x + rect.w
-                || particle.pos.y - radius > rect.y + rect.h
-            {
+            if !view.circle_visible(particle.pos, radius) {
                 continue;
             }
 
@@ -327,6 +360,9 @@ pub struct ParticleSystem {
     pool: ParticlePool,
     template_counts: Vec<usize>,
     budget_scale: f32,
+    /// Frame-time governor: share of non-critical particles spawned, 1 when frames are fast.
+    governor: f32,
+    smoothed_frame_time: f32,
 }
 
 impl ParticleSystem {
@@ -337,6 +373,8 @@ impl ParticleSystem {
             pool: ParticlePool::new(1),
             template_counts: vec![0],
             budget_scale: 1.0,
+            governor: 1.0,
+            smoothed_frame_time: TARGET_FRAME_TIME,
         }
     }
 
@@ -348,7 +386,7 @@ impl ParticleSystem {
 
         if cfg!(target_arch = "wasm32") {
             let dir = data_path(&dir.to_string_lossy());
-            let files = load_wasm_manifest_files(&dir, &["trail.yaml", "dash.yaml"]).await;
+            let files = load_wasm_manifest_files(&dir, &["trail.yaml", "dash.yaml", "death.yaml", "near_miss.yaml", "perfect_dodge.yaml", "launch_dust.yaml"]).await;
             for file in files {
                 let path = format!("{}/{}", dir, file);
                 let raw_str = load_string(&path)
@@ -358,8 +396,8 @@ impl ParticleSystem {
                 let (config, texture_path) = config_from_file(raw);
                 total_capacity = total_capacity.saturating_add(config.max_particles);
 
-                let texture = if let Some(path) = texture_path {
-                    let tex = load_texture(&asset_path(&path))
+                let texture = if let Some(path) = texture_path.as_deref() {
+                    let tex = load_texture(&asset_path(path))
                         .await
                         .map_err(|err| ParticleLoadError::Texture(err.to_string()))?;
                     tex.set_filter(FilterMode::Nearest);
@@ -369,7 +407,11 @@ impl ParticleSystem {
                 };
 
                 lookup.insert(config.id.clone(), templates.len());
-                templates.push(ParticleTemplate { config, texture });
+                templates.push(ParticleTemplate {
+                    config,
+                    texture,
+                    texture_path,
+                });
             }
         } else if dir.exists() {
             for entry in std::fs::read_dir(dir)? {
@@ -382,8 +424,8 @@ impl ParticleSystem {
                 let (config, texture_path) = config_from_file(raw);
                 total_capacity = total_capacity.saturating_add(config.max_particles);
 
-                let texture = if let Some(path) = texture_path {
-                    let tex = load_texture(&asset_path(&path))
+                let texture = if let Some(path) = texture_path.as_deref() {
+                    let tex = load_texture(&asset_path(path))
                         .await
                         .map_err(|err| ParticleLoadError::Texture(err.to_string()))?;
                     tex.set_filter(FilterMode::Nearest);
@@ -393,7 +435,11 @@ impl ParticleSystem {
                 };
 
                 lookup.insert(config.id.clone(), templates.len());
-                templates.push(ParticleTemplate { config, texture });
+                templates.push(ParticleTemplate {
+                    config,
+                    texture,
+                    texture_path,
+                });
             }
         }
 
@@ -408,9 +454,31 @@ impl ParticleSystem {
             pool: ParticlePool::new(total_capacity),
             template_counts: vec![0; template_count],
             budget_scale: 1.0,
+            governor: 1.0,
+            smoothed_frame_time: TARGET_FRAME_TIME,
         })
     }
 
+    /// Every template texture with the file it came from.
+    pub fn texture_files(&self) -> impl Iterator<Item = (&str, &Texture2D)> {
+        self.templates
+            .iter()
+            .filter_map(|template| Some((template.texture_path.as_deref()?, template.texture.as_ref()?)))
+    }
+
+    /// Adds `other`'s templates, replacing those with the same id. `merged` sees each id and
+    /// whether it replaced one. Particles alive before the merge are dropped.
+    pub fn merge(&mut self, other: Self, mut merged: impl FnMut(&str, bool)) {
+        for template in other.templates {
+            let id = template.config.id.clone();
+            let replaced = mods::upsert(&mut self.templates, &mut self.lookup, id.clone(), template);
+            merged(&id, replaced);
+        }
+        let total_capacity: usize = self.templates.iter().map(|template| template.config.max_particles).sum();
+        self.pool = ParticlePool::new(total_capacity.max(1));
+        self.template_counts = vec![0; self.templates.len()];
+    }
+
     pub fn emitter(&self, id: &str, pos: Vec2) -> Option<ParticleEmitter> {
         let idx = self.lookup.get(id).copied()?;
         Some(ParticleEmitter::new(idx, pos))
@@ -429,6 +497,7 @@ impl ParticleSystem {
         dest_size: Option<Vec2>,
     ) {
         let cfg = self.templates[emitter.template].config.clone();
+        let scale = self.governed_scale(emitter.template);
 
         if emitter.first {
             emitter.last_pos = pos;
@@ -436,7 +505,7 @@ impl ParticleSystem {
         }
 
         if !emitter.burst_done && cfg.burst > 0 {
-            for _ in 0..cfg.burst {
+            for _ in 0..scaled_burst(cfg.burst, scale) {
                 self.spawn_particle(emitter.template, pos, Vec2::ZERO, texture, dest_size);
             }
             emitter.burst_done = true;
@@ -444,7 +513,7 @@ impl ParticleSystem {
 
         // Rate-based spawn
         if cfg.spawn_rate > 0.0 {
-            emitter.spawn_accum += cfg.spawn_rate * dt;
+            emitter.spawn_accum += cfg.spawn_rate * scale * dt;
             let count = emitter.spawn_accum.floor() as u32;
             emitter.spawn_accum -= count as f32;
             for _ in 0..count {
@@ -461,7 +530,7 @@ impl ParticleSystem {
         // Trail-based spawn (per unit distance)
         if cfg.trail_rate > 0.0 {
             let dist = pos.distance(emitter.last_pos);
-            let total = dist * cfg.trail_rate + emitter.trail_accum;
+            let total = dist * cfg.trail_rate * scale + emitter.trail_accum;
             let count = total.floor() as u32;
             emitter.trail_accum = total - count as f32;
             if count > 0 {
@@ -483,6 +552,28 @@ impl ParticleSystem {
         emitter.last_pos = pos;
     }
 
+    /// Spawns a template's `burst` count once at `pos` without keeping an emitter around.
+    pub fn burst(&mut self, id: &str, pos: Vec2) {
+        let Some(template) = self.lookup.get(id).copied() else {
+            return;
+        };
+        let count = scaled_burst(self.templates[template].config.burst, self.governed_scale(template));
+        for _ in 0..count {
+            self.spawn_particle(template, pos, Vec2::ZERO, None, None);
+        }
+    }
+
+    /// Switches an emitter to template `id` at `pos`, unless it already uses it. Unknown ids
+    /// leave the emitter as it is.
+    pub fn retarget_emitter(&self, emitter: &mut Option<ParticleEmitter>, id: &str, pos: Vec2) {
+        let Some(template) = self.lookup.get(id).copied() else {
+            return;
+        };
+        if emitter.as_ref().is_none_or(|current| current.template != template) {
+            *emitter = Some(ParticleEmitter::new(template, pos));
+        }
+    }
+
     pub fn track_emitter(&mut self, emitter: &mut ParticleEmitter, pos: Vec2) {
         emitter.last_pos = pos;
         emitter.first = false;
@@ -499,14 +590,36 @@ impl ParticleSystem {
         self.pool.draw(&self.templates);
     }
 
-    pub fn draw_in_rect(&self, rect: Rect) {
-        self.pool.draw_in_rect(&self.templates, rect);
+    /// Draws the particles of templates on `layer` that `view` can see. For `Screen`, `view` is
+    /// in screen pixels.
+    pub fn draw_in_view(&self, layer: ParticleLayer, view: &ViewCull) {
+        self.pool.draw_in_view(&self.templates, layer, view);
     }
 
     pub fn set_budget_scale(&mut self, scale: f32) {
         self.budget_scale = scale.clamp(0.1, 1.0);
     }
 
+    /// Feeds the governor the last frame's duration. While frames run slower than the target,
+    /// non-critical templates spawn fewer particles and keep fewer alive.
+    pub fn govern(&mut self, frame_time: f32) {
+        let frame_time = frame_time.clamp(0.0, 0.25);
+        self.smoothed_frame_time += (frame_time - self.smoothed_frame_time) * FRAME_SMOOTHING;
+        self.governor = if self.smoothed_frame_time > TARGET_FRAME_TIME {
+            (self.governor - GOVERNOR_DROP * frame_time).max(GOVERNOR_FLOOR)
+        } else {
+            (self.governor + GOVERNOR_RECOVER * frame_time).min(1.0)
+        };
+    }
+
+    fn governed_scale(&self, template: usize) -> f32 {
+        if self.templates[template].config.critical {
+            1.0
+        } else {
+            self.governor
+        }
+    }
+
     fn spawn_particle(
         &mut self,
         template: usize,
@@ -515,8 +628,9 @@ impl ParticleSystem {
         override_texture: Option<&Texture2D>,
         override_dest_size: Option<Vec2>,
     ) {
+        let governed = self.governed_scale(template);
         let cfg = &self.templates[template].config;
-        let max_particles = ((cfg.max_particles as f32) * self.budget_scale)
+        let max_particles = ((cfg.max_particles as f32) * self.budget_scale * governed)
             .round()
             .max(1.0) as usize;
         if self.template_counts[template] >= max_particles {
@@ -566,6 +680,11 @@ impl ParticleSystem {
     }
 }
 
+/// Burst size under the governor; a burst never drops to nothing.
+fn scaled_burst(burst: u32, scale: f32) -> u32 {
+    ((burst as f32 * scale).ceil() as u32).min(burst)
+}
+
 fn rand_range(amount: f32) -> f32 {
     if amount == 0.0 {
         0.0
@@ -649,6 +768,8 @@ fn config_from_file(raw: ParticleConfigFile) -> (ParticleConfig, Option<String>)
         rotation_speed,
         rotation_speed_variance,
         dynamic_sprite,
+        critical: raw.critical,
+        layer: raw.layer,
     };
 
     let texture = raw.texture.map(|path| asset_path(&path));
@@ -659,6 +780,10 @@ fn config_from_file(raw: ParticleConfigFile) -> (ParticleConfig, Option<String>)
 struct ParticleConfigFile {
     id: String,
     #[serde(default)]
+    critical: bool,
+    #[serde(default)]
+    layer: ParticleLayer,
+    #[serde(default)]
     max_particles: Option<usize>,
     #[serde(default)]
     spawn_rate: Option<f32>,
@@ -0,0 +1,120 @@
+//! Tile-grid A* over the map's collision mask and runtime obstacles.
+
+use macroquad::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use crate::map::TileMap;
+
+/// Upper bound on expanded tiles so an unreachable goal can't stall a frame.
+pub const MAX_SEARCH_NODES: usize = 4096;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+type Tile = (i32, i32);
+/// Best known cost to reach a tile and the tile it was reached from.
+type Visited = HashMap<Tile, (u32, Tile)>;
+
+/// Finds a walkable route between two world positions.
+///
+/// Returns waypoints in world space (tile centers, ending at `to`), excluding the start. The
+/// result is empty when either end is off the map or inside a wall, or no route was found within
+/// `MAX_SEARCH_NODES` expanded tiles.
+pub fn find_path(map: &TileMap, from: Vec2, to: Vec2) -> Vec<Vec2> {
+    find_path_within(map, from, to, MAX_SEARCH_NODES)
+}
+
+pub fn find_path_within(map: &TileMap, from: Vec2, to: Vec2, max_nodes: usize) -> Vec<Vec2> {
+    let (Some(start), Some(goal)) = (map.grid_index(from), map.grid_index(to)) else {
+        return Vec::new();
+    };
+    let start = (start.x, start.y);
+    let goal = (goal.x, goal.y);
+    if start == goal {
+        return vec![to];
+    }
+    if blocked(map, goal.0, goal.1) {
+        return Vec::new();
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut visited = Visited::new();
+    visited.insert(start, (0, start));
+    open.push(Reverse((heuristic(start, goal), 0u32, start)));
+
+    let mut expanded = 0usize;
+    while let Some(Reverse((_, cost, tile))) = open.pop() {
+        if tile == goal {
+            return build_path(map, &visited, start, goal, to);
+        }
+        if visited.get(&tile).is_some_and(|&(best, _)| best < cost) {
+            continue;
+        }
+        expanded += 1;
+        if expanded > max_nodes {
+            break;
+        }
+
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let next = (tile.0 + dx, tile.1 + dy);
+            if blocked(map, next.0, next.1) {
+                continue;
+            }
+            let diagonal = dx != 0 && dy != 0;
+            // Don't cut corners between two walls.
+            if diagonal && (blocked(map, tile.0 + dx, tile.1) || blocked(map, tile.0, tile.1 + dy)) {
+                continue;
+            }
+            let next_cost = cost + if diagonal { DIAGONAL_COST } else { STRAIGHT_COST };
+            if visited.get(&next).is_some_and(|&(best, _)| best <= next_cost) {
+                continue;
+            }
+            visited.insert(next, (next_cost, tile));
+            open.push(Reverse((next_cost + heuristic(next, goal), next_cost, next)));
+        }
+    }
+    Vec::new()
+}
+
+fn blocked(map: &TileMap, x: i32, y: i32) -> bool {
+    if x < 0 || y < 0 || x as usize >= map.width() || y as usize >= map.height() {
+        return true;
+    }
+    map.is_solid(x as usize, y as usize)
+}
+
+/// Octile distance in path cost units.
+fn heuristic(a: Tile, b: Tile) -> u32 {
+    let dx = a.0.abs_diff(b.0);
+    let dy = a.1.abs_diff(b.1);
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+/// Walks parents back from the goal and drops waypoints in the middle of straight runs.
+fn build_path(
+    map: &TileMap,
+    visited: &Visited,
+    start: Tile,
+    goal: Tile,
+    to: Vec2,
+) -> Vec<Vec2> {
+    let mut tiles = vec![goal];
+    let mut current = goal;
+    while current != start {
+        current = visited[&current].1;
+        tiles.push(current);
+    }
+    tiles.reverse();
+
+    let mut path = Vec::new();
+    for i in 1..tiles.len() - 1 {
+        let (prev, here, next) = (tiles[i - 1], tiles[i], tiles[i + 1]);
+        let dir_in = (here.0 - prev.0, here.1 - prev.1);
+        let dir_out = (next.0 - here.0, next.1 - here.1);
+        if dir_in != dir_out {
+            path.push(map.tile_bounds(here.0 as usize, here.1 as usize).center());
+        }
+    }
+    path.push(to);
+    path
+}
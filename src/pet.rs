@@ -0,0 +1,120 @@
+//! Pets: tamed creatures that follow the player around.
+//!
+//! Entities with a `tame` block can be tamed by walking up to one and pressing T. Each try feeds
+//! it the block's `item`, if it names one, and works with the block's `chance`. A pet drops its
+//! behaviour tree for `follow_owner`: it keeps close to the player, teleports back when left far
+//! behind, and goes after whatever last hurt the player. Pets never turn on the player, leave
+//! the spawner's care and are kept in saves.
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+use crate::entity::EntityDatabase;
+use crate::entity_pool::EntityPool;
+use crate::helpers::random_f32;
+use crate::item::Inventory;
+use crate::label::display_name;
+use crate::ui::{UiBatch, UiLayer};
+
+/// Action every pet runs in place of its behaviour tree.
+pub const PET_ACTION: &str = "follow_owner";
+const TAME_KEY: KeyCode = KeyCode::T;
+/// How close the player has to be to the creature.
+const TAME_RANGE: f32 = 24.0;
+const TAME_COOLDOWN: f32 = 0.5;
+const STATUS_TIME: f32 = 2.5;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TameInfo {
+    /// Item used up by each try; none means the creature only needs a friendly word.
+    #[serde(default)]
+    pub item: Option<String>,
+    /// Chance each try works.
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+}
+
+fn default_chance() -> f32 {
+    1.0
+}
+
+pub enum PetEvent {
+    /// A try didn't take.
+    Refused { pos: Vec2 },
+    Tamed { pos: Vec2 },
+}
+
+#[derive(Default)]
+pub struct Taming {
+    cooldown: f32,
+    status: String,
+    status_timer: f32,
+}
+
+impl Taming {
+    pub fn update(&mut self, dt: f32) {
+        self.cooldown = (self.cooldown - dt).max(0.0);
+        self.status_timer = (self.status_timer - dt).max(0.0);
+    }
+
+    /// Tries to tame the nearest tamable creature. Only call while the player is free to act.
+    pub fn handle_input(
+        &mut self,
+        entities: &mut EntityPool,
+        db: &EntityDatabase,
+        inventory: &mut Inventory,
+        player_pos: Vec2,
+    ) -> Option<PetEvent> {
+        if !is_key_pressed(TAME_KEY) || self.cooldown > 0.0 {
+            return None;
+        }
+        let target = entities
+            .iter_mut()
+            .filter(|ent| {
+                !ent.instance.is_corpse()
+                    && !ent.instance.pet
+                    && ent.instance.hp > 0.0
+                    && db.entities[ent.instance.def].tame.is_some()
+            })
+            .map(|ent| {
+                let distance = ent.hitbox(db).center().distance(player_pos);
+                (ent, distance)
+            })
+            .filter(|(_, distance)| *distance <= TAME_RANGE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(target, _)| target)?;
+        self.cooldown = TAME_COOLDOWN;
+
+        let def = &db.entities[target.instance.def];
+        let tame = def.tame.as_ref()?;
+        if let Some(item) = tame.item.as_deref() {
+            if !inventory.has(item) {
+                self.set_status(format!("the {} wants {}", def.name, display_name(item)));
+                return None;
+            }
+            inventory.remove(item, 1);
+        }
+        let pos = target.hitbox(db).center();
+        if random_f32() >= tame.chance {
+            self.set_status(format!("the {} isn't sure about you", def.name));
+            return Some(PetEvent::Refused { pos });
+        }
+        target.instance.make_pet();
+        self.set_status(format!("the {} is your pet now", def.name));
+        Some(PetEvent::Tamed { pos })
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    /// Queues the last taming message.
+    pub fn draw_ui(&self, ui: &mut UiBatch) {
+        if self.status_timer <= 0.0 {
+            return;
+        }
+        let size = measure_text(&self.status, None, 20, 1.0);
+        let pos = vec2((screen_width() - size.width) * 0.5, screen_height() * 0.8 - 24.0);
+        ui.text(UiLayer::Toast, self.status.as_str(), pos, 20.0, WHITE);
+    }
+}
@@ -0,0 +1,80 @@
+//! Map pings.
+//!
+//! A middle click (or P, at the player's feet) drops a ping: a pulsing marker in the world that
+//! also shows on the minimap and fades after `LIFETIME` seconds. Solo it marks a spot to come
+//! back to; with co-op it is how players point things out to each other.
+
+use macroquad::prelude::*;
+use crate::cull::ViewCull;
+
+pub const MOUSE_BUTTON: MouseButton = MouseButton::Middle;
+pub const KEY: KeyCode = KeyCode::P;
+pub const SOUND: &str = "ping";
+const LIFETIME: f32 = 6.0;
+/// Placing another ping past this replaces the oldest.
+const MAX_PINGS: usize = 4;
+/// Seconds per pulse of the ring.
+const PULSE_TIME: f32 = 1.0;
+const RADIUS: f32 = 10.0;
+/// Fraction of the lifetime spent fading out.
+const FADE_PORTION: f32 = 0.3;
+pub const COLOR: Color = Color::new(0.35, 0.85, 1.0, 1.0);
+
+struct Ping {
+    pos: Vec2,
+    age: f32,
+}
+
+#[derive(Default)]
+pub struct Pings {
+    pings: Vec<Ping>,
+}
+
+impl Pings {
+    pub fn place(&mut self, pos: Vec2) {
+        if self.pings.len() >= MAX_PINGS {
+            self.pings.remove(0);
+        }
+        self.pings.push(Ping { pos, age: 0.0 });
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for ping in &mut self.pings {
+            ping.age += dt;
+        }
+        self.pings.retain(|ping| ping.age < LIFETIME);
+    }
+
+    pub fn clear(&mut self) {
+        self.pings.clear();
+    }
+
+    /// World positions of the live pings, for the minimap.
+    pub fn positions(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.pings.iter().map(|ping| ping.pos)
+    }
+
+    /// Draws the pings in `view` in world space.
+    pub fn draw_in_view(&self, view: &ViewCull) {
+        for ping in &self.pings {
+            if !view.circle_visible(ping.pos, RADIUS) {
+                continue;
+            }
+            let fade = ((LIFETIME - ping.age) / (LIFETIME * FADE_PORTION)).clamp(0.0, 1.0);
+            let pulse = (ping.age / PULSE_TIME).fract();
+            let mut ring = COLOR;
+            ring.a = fade * (1.0 - pulse);
+            draw_circle_lines(ping.pos.x, ping.pos.y, RADIUS * pulse, 1.0, ring);
+            let mut pin = COLOR;
+            pin.a = fade;
+            draw_circle(ping.pos.x, ping.pos.y, 1.5, pin);
+            draw_line(ping.pos.x, ping.pos.y, ping.pos.x, ping.pos.y - 8.0, 1.0, pin);
+            draw_triangle(
+                vec2(ping.pos.x, ping.pos.y - 8.0),
+                vec2(ping.pos.x + 5.0, ping.pos.y - 6.5),
+                vec2(ping.pos.x, ping.pos.y - 5.0),
+                pin,
+            );
+        }
+    }
+}
@@ -1,37 +1,225 @@
 use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::helpers::{asset_path, resolve_collisions_axis, resolve_one_way, Axis};
+use crate::map::{MapEdge, TileMap, SWIM_SPEED_SCALE};
+
+/// Speed in pixels per second under which the player counts as standing still.
+const WALK_ANIM_SPEED: f32 = 8.0;
+/// How quickly knockback from hits dies off, per second.
+const KNOCKBACK_FRICTION: f32 = 10.0;
+
+/// Layout of the player's sprite sheet, set under `player_sprite` in world.yaml. Each row of the
+/// sheet is a facing, down, left, right and up in that order; a sheet with fewer rows reuses its
+/// last one. Clips are runs of frames along a row.
+#[derive(Clone, Deserialize)]
+pub struct PlayerSpriteSettings {
+    pub sheet: String,
+    /// Frame size in sheet pixels; 0 uses the whole sheet.
+    #[serde(default)]
+    pub frame_width: u32,
+    #[serde(default)]
+    pub frame_height: u32,
+    /// World pixels per sheet pixel.
+    #[serde(default = "default_sprite_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub idle: PlayerClip,
+    #[serde(default)]
+    pub walk: PlayerClip,
+    #[serde(default)]
+    pub dash: PlayerClip,
+}
+
+impl Default for PlayerSpriteSettings {
+    fn default() -> Self {
+        Self {
+            sheet: "src/assets/objects/player08.png".to_string(),
+            frame_width: 0,
+            frame_height: 0,
+            scale: default_sprite_scale(),
+            idle: PlayerClip::default(),
+            walk: PlayerClip::default(),
+            dash: PlayerClip::default(),
+        }
+    }
+}
+
+/// A run of `frames` columns starting at `start`. Walking and dashing move on a frame every
+/// `stride` world pixels travelled; standing still moves on every `frame_time` seconds.
+#[derive(Clone, Copy, Deserialize)]
+pub struct PlayerClip {
+    #[serde(default)]
+    pub start: u32,
+    #[serde(default = "default_clip_frames")]
+    pub frames: u32,
+    #[serde(default = "default_clip_stride")]
+    pub stride: f32,
+    #[serde(default = "default_clip_frame_time")]
+    pub frame_time: f32,
+}
+
+impl Default for PlayerClip {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            frames: default_clip_frames(),
+            stride: default_clip_stride(),
+            frame_time: default_clip_frame_time(),
+        }
+    }
+}
+
+fn default_sprite_scale() -> f32 {
+    0.25
+}
+
+fn default_clip_frames() -> u32 {
+    1
+}
+
+fn default_clip_stride() -> f32 {
+    6.0
+}
+
+fn default_clip_frame_time() -> f32 {
+    0.3
+}
+
+/// The player's sprite sheet cut into one texture per frame, so a frame can also be handed to
+/// the dash afterimage particles.
+pub struct PlayerSprite {
+    /// Row-major.
+    frames: Vec<Texture2D>,
+    columns: usize,
+    rows: usize,
+    settings: PlayerSpriteSettings,
+}
+
+impl PlayerSprite {
+    /// Loads and cuts the sheet; `None` when the image can't be read.
+    pub async fn load(settings: &PlayerSpriteSettings) -> Option<Self> {
+        let image = load_image(&asset_path(&settings.sheet)).await.ok()?;
+        let frame_w = if settings.frame_width == 0 { image.width as u32 } else { settings.frame_width };
+        let frame_h = if settings.frame_height == 0 { image.height as u32 } else { settings.frame_height };
+        let columns = (image.width as u32 / frame_w.max(1)).max(1) as usize;
+        let rows = (image.height as u32 / frame_h.max(1)).max(1) as usize;
+        let mut frames = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let rect = Rect::new(
+                    (column as u32 * frame_w) as f32,
+                    (row as u32 * frame_h) as f32,
+                    frame_w as f32,
+                    frame_h as f32,
+                );
+                let texture = Texture2D::from_image(&image.sub_image(rect));
+                texture.set_filter(FilterMode::Nearest);
+                frames.push(texture);
+            }
+        }
+        Some(Self {
+            frames,
+            columns,
+            rows,
+            settings: settings.clone(),
+        })
+    }
+
+    /// A one-frame sprite, for when the sheet is missing.
+    pub fn single(texture: Texture2D) -> Self {
+        Self {
+            frames: vec![texture],
+            columns: 1,
+            rows: 1,
+            settings: PlayerSpriteSettings::default(),
+        }
+    }
+
+    fn clip(&self, clip: PlayerAnim) -> &PlayerClip {
+        match clip {
+            PlayerAnim::Idle => &self.settings.idle,
+            PlayerAnim::Walk => &self.settings.walk,
+            PlayerAnim::Dash => &self.settings.dash,
+        }
+    }
+
+    fn frame(&self, row: usize, clip: PlayerAnim, progress: f32) -> &Texture2D {
+        let clip_def = self.clip(clip);
+        let step = match clip {
+            PlayerAnim::Idle => progress / clip_def.frame_time.max(0.001),
+            PlayerAnim::Walk | PlayerAnim::Dash => progress / clip_def.stride.max(0.001),
+        };
+        let column = clip_def.start + step as u32 % clip_def.frames.max(1);
+        let column = (column as usize).min(self.columns - 1);
+        &self.frames[row.min(self.rows - 1) * self.columns + column]
+    }
+}
 
-use crate::helpers::{clamp_hitbox_to_rect, resolve_collisions_axis, Axis};
-use crate::map::TileMap;
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PlayerAnim {
+    Idle,
+    Walk,
+    Dash,
+}
 
 pub struct Player {
     pos: Vec2,
     vel: Vec2,
+    /// Velocity from hits, added on top of walking and dashing until it dies off.
+    knockback: Vec2,
     hitbox: Rect,
     radius: f32,
-    pub texture: Texture2D,
+    sprite: PlayerSprite,
+    anim: PlayerAnim,
+    /// Pixels travelled, or seconds stood still, since `anim` started.
+    anim_progress: f32,
     last_move_dir: Vec2,
     dash_timer: f32,
+    /// Seconds until the next spent dash charge comes back.
     dash_cooldown: f32,
     dash_dir: Vec2,
+    /// Dashes that can be chained before waiting on the cooldown.
+    dash_charges: u32,
+    /// Charges ready to use.
+    dash_ready: u32,
+    parry_timer: f32,
+    parry_cooldown: f32,
+    /// Set while standing in a liquid tile; swimming is slower and can't dash.
+    swimming: bool,
+    /// Creative mode: no damage, no collisions and no ground effects.
+    creative: bool,
     collision_scratch: Vec<Rect>,
+    /// Non-solid map edge reached since `take_border_crossing`.
+    border_crossing: Option<MapEdge>,
     hp: f32,
     max_hp: f32,
 }
 
 impl Player {
-    pub fn new(pos: Vec2, texture: Texture2D, hitbox: Rect) -> Self {
+    pub fn new(pos: Vec2, sprite: PlayerSprite, hitbox: Rect) -> Self {
         let max_hp = 1000.0;
         Self {
             pos,
             vel: Vec2::ZERO,
+            knockback: Vec2::ZERO,
             hitbox,
             radius: 5.0,
-            texture,
+            sprite,
+            anim: PlayerAnim::Idle,
+            anim_progress: 0.0,
             last_move_dir: Vec2::ZERO,
             dash_timer: 0.0,
             dash_cooldown: 0.0,
             dash_dir: Vec2::ZERO,
+            dash_charges: 1,
+            dash_ready: 1,
+            parry_timer: 0.0,
+            parry_cooldown: 0.0,
+            swimming: false,
+            creative: false,
             collision_scratch: Vec::with_capacity(25),
+            border_crossing: None,
             hp: max_hp,
             max_hp,
         }
@@ -59,15 +247,45 @@ impl Player {
             self.last_move_dir = input;
         }
 
+        let ground = map.tile_properties_at(self.position());
+        self.apply_damage(ground.damage_per_second * dt);
+        self.swimming = ground.liquid && !self.creative;
+        let can_dash = !self.swimming && (self.creative || ground.material.allows_dash());
+
         let accel = 1800.0;
-        let max_speed = 640.0;
+        let friction = if self.creative { 0.0 } else { ground.friction };
+        let mut max_speed = 640.0 * (1.0 - friction);
+        if self.swimming {
+            max_speed *= SWIM_SPEED_SCALE;
+        }
+        if !can_dash {
+            self.dash_timer = 0.0;
+        }
+        if !self.creative {
+            max_speed *= map.slow_factor(self.world_hitbox());
+        }
         let damping = 8.0;
         let dash_speed = 1100.0;
         let dash_duration = 0.07;
         let dash_cooldown = 0.5;
+        let parry_window = 0.18;
+        let parry_cooldown = 0.6;
 
-        if self.dash_cooldown > 0.0 {
-            self.dash_cooldown = (self.dash_cooldown - dt).max(0.0);
+        self.parry_timer = (self.parry_timer - dt).max(0.0);
+        self.parry_cooldown = (self.parry_cooldown - dt).max(0.0);
+        if self.parry_cooldown <= 0.0
+            && (is_key_pressed(KeyCode::F) || is_mouse_button_pressed(MouseButton::Right))
+        {
+            self.parry_timer = parry_window;
+            self.parry_cooldown = parry_cooldown;
+        }
+
+        if self.dash_ready < self.dash_charges {
+            self.dash_cooldown -= dt;
+            if self.dash_cooldown <= 0.0 {
+                self.dash_ready += 1;
+                self.dash_cooldown = if self.dash_ready < self.dash_charges { dash_cooldown } else { 0.0 };
+            }
         }
 
         if self.dash_timer > 0.0 {
@@ -75,7 +293,8 @@ impl Player {
         }
 
         if self.dash_timer <= 0.0
-            && self.dash_cooldown <= 0.0
+            && self.dash_ready > 0
+            && can_dash
             && is_key_pressed(KeyCode::Space)
         {
             let dir = if input.length_squared() > 0.0 {
@@ -86,7 +305,10 @@ impl Player {
             if dir.length_squared() > 0.0 {
                 self.dash_dir = dir.normalize();
                 self.dash_timer = dash_duration;
-                self.dash_cooldown = dash_cooldown;
+                if self.dash_ready == self.dash_charges {
+                    self.dash_cooldown = dash_cooldown;
+                }
+                self.dash_ready -= 1;
             }
         }
 
@@ -107,10 +329,10 @@ impl Player {
         }
 
         let mut pos = self.pos;
-        let mut vel = self.vel;
+        let mut vel = self.vel + self.knockback;
 
         pos.x += vel.x * dt;
-        if !self.is_dashing() {
+        if !self.is_dashing() && !self.creative {
             let probe = hitbox_center_world(pos, self.hitbox);
             if let Some(grid) = map.grid_index(probe) {
                 let radius = collision_radius(map, vel, dt);
@@ -128,7 +350,7 @@ impl Player {
         }
 
         pos.y += vel.y * dt;
-        if !self.is_dashing() {
+        if !self.is_dashing() && !self.creative {
             let probe = hitbox_center_world(pos, self.hitbox);
             if let Some(grid) = map.grid_index(probe) {
                 let radius = collision_radius(map, vel, dt);
@@ -142,29 +364,84 @@ impl Player {
                 );
                 pos = resolved;
                 vel.y = vy;
+                map.fill_one_way_around_grid(grid, radius, &mut self.collision_scratch);
+                let (resolved, vy) =
+                    resolve_one_way(self.hitbox, self.pos.y, pos, vel.y, &mut self.collision_scratch);
+                pos = resolved;
+                vel.y = vy;
             }
         }
 
+        let travelled = self.pos.distance(pos);
         self.pos = pos;
-        self.vel = vel;
+        self.vel = vel - self.knockback;
+        // Walls stop movement along the axis they blocked; bouncy ones throw knockback back.
+        if vel.x == 0.0 {
+            self.vel.x = 0.0;
+            self.knockback.x *= -map.wall_material(self.world_hitbox(), vec2(self.knockback.x, 0.0)).restitution();
+        }
+        if vel.y == 0.0 {
+            self.vel.y = 0.0;
+            self.knockback.y *= -map.wall_material(self.world_hitbox(), vec2(0.0, self.knockback.y)).restitution();
+        }
+        let friction = KNOCKBACK_FRICTION * ground.material.knockback_friction();
+        self.knockback *= (1.0 - friction * dt).clamp(0.0, 1.0);
+        self.update_animation(travelled, dt);
+
+        let (pos, crossed) = map.apply_borders(self.hitbox, self.pos);
+        self.pos = pos;
+        if crossed.is_some() {
+            self.border_crossing = crossed;
+        }
+    }
+
 
-        let border = map.get_border_hitbox();
-        self.pos = clamp_hitbox_to_rect(self.hitbox, self.pos, border);
+    fn update_animation(&mut self, travelled: f32, dt: f32) {
+        let anim = if self.is_dashing() {
+            PlayerAnim::Dash
+        } else if self.vel.length_squared() > WALK_ANIM_SPEED * WALK_ANIM_SPEED {
+            PlayerAnim::Walk
+        } else {
+            PlayerAnim::Idle
+        };
+        if anim != self.anim {
+            self.anim = anim;
+            self.anim_progress = 0.0;
+        }
+        self.anim_progress += match anim {
+            PlayerAnim::Idle => dt,
+            PlayerAnim::Walk | PlayerAnim::Dash => travelled,
+        };
     }
 
+    /// Sheet row for the way the player faces: down, left, right, up.
+    fn facing_row(&self) -> usize {
+        let dir = self.last_move_dir;
+        if dir.x.abs() > dir.y.abs() {
+            if dir.x < 0.0 { 1 } else { 2 }
+        } else if dir.y < 0.0 {
+            3
+        } else {
+            0
+        }
+    }
+
+    /// The frame showing now and its size in world pixels.
+    pub fn sprite_frame(&self) -> (&Texture2D, Vec2) {
+        let texture = self.sprite.frame(self.facing_row(), self.anim, self.anim_progress);
+        (texture, texture.size() * self.sprite.settings.scale)
+    }
 
+    /// Draws the current frame standing on the player's position.
     pub fn draw(&self) {
-        let scale = 0.5;
-        let center_x = self.texture.width() as f32 * scale / 2.0;
-        let center_y = self.texture.height() as f32 * scale / 2.0;
+        let (texture, size) = self.sprite_frame();
         draw_texture_ex(
-            &self.texture,
-            self.pos.x - center_x / 2.0,
-            self.pos.y - center_y,
+            texture,
+            self.pos.x - size.x * 0.5,
+            self.pos.y - size.y,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(Vec2::new(self.texture.width() / 2 as f32 * scale, self.texture.height() / 2 as f32 * scale)),
-                flip_y: false,
+                dest_size: Some(size),
                 ..Default::default()
             },
         );
@@ -174,6 +451,28 @@ impl Player {
         self.pos
     }
 
+    /// Last non-zero movement direction; zero until the player first moves.
+    pub fn facing(&self) -> Vec2 {
+        self.last_move_dir
+    }
+
+    /// Puts the player back into a saved state, dropping any in-flight movement or dash.
+    pub fn restore(&mut self, pos: Vec2, hp: f32, max_hp: f32) {
+        self.pos = pos;
+        self.vel = Vec2::ZERO;
+        self.knockback = Vec2::ZERO;
+        self.dash_timer = 0.0;
+        self.dash_cooldown = 0.0;
+        self.border_crossing = None;
+        self.set_max_hp(max_hp);
+        self.hp = hp.clamp(0.0, self.max_hp);
+    }
+
+    /// The wrapping or transition edge the player went through since the last call.
+    pub fn take_border_crossing(&mut self) -> Option<MapEdge> {
+        self.border_crossing.take()
+    }
+
     pub fn world_hitbox(&self) -> Rect {
         Rect::new(
             self.pos.x + self.hitbox.x,
@@ -184,12 +483,20 @@ impl Player {
     }
 
     pub fn apply_damage(&mut self, amount: f32) {
-        if amount <= 0.0 {
+        if amount <= 0.0 || self.creative {
             return;
         }
         self.hp = (self.hp - amount).max(0.0);
     }
 
+    /// Pushes the player; the push fades out over a fraction of a second.
+    pub fn apply_knockback(&mut self, impulse: Vec2) {
+        if self.creative {
+            return;
+        }
+        self.knockback += impulse;
+    }
+
     pub fn heal(&mut self, amount: f32) {
         if amount <= 0.0 {
             return;
@@ -225,10 +532,35 @@ impl Player {
         self.vel
     }
 
+    /// Sets how many dashes can be chained, with all of them ready.
+    pub fn set_dash_charges(&mut self, charges: u32) {
+        self.dash_charges = charges.max(1);
+        self.dash_ready = self.dash_charges;
+        self.dash_cooldown = 0.0;
+    }
+
+    /// Charges ready and the most there can be.
+    pub fn dash_charges(&self) -> (u32, u32) {
+        (self.dash_ready, self.dash_charges)
+    }
+
     pub fn is_dashing(&self) -> bool {
         self.dash_timer > 0.0
     }
 
+    /// The short window after pressing parry in which enemy projectiles are sent back.
+    pub fn is_parrying(&self) -> bool {
+        self.parry_timer > 0.0
+    }
+
+    pub fn set_creative(&mut self, creative: bool) {
+        self.creative = creative;
+    }
+
+    pub fn is_swimming(&self) -> bool {
+        self.swimming
+    }
+
     pub fn is_moving(&self, deadzone: f32) -> bool {
         self.vel.length() > deadzone
     }
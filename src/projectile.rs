@@ -0,0 +1,439 @@
+use macroquad::prelude::*;
+use crate::asset_pack::{load_string, load_texture};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::cull::ViewCull;
+use crate::entity::{DamageEvent, EntityKind, EntityTarget, PlayerTarget, Target};
+use crate::helpers::{asset_path, data_path, load_wasm_manifest_files};
+use crate::map::{ProjectileSurface, TileMap};
+use crate::particle::{ParticleEmitter, ParticleSystem};
+
+#[derive(Debug)]
+pub enum ProjectileLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    Texture(String),
+}
+
+impl std::fmt::Display for ProjectileLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+            Self::Texture(err) => write!(f, "texture error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ProjectileLoadError {}
+
+impl From<std::io::Error> for ProjectileLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for ProjectileLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone)]
+pub struct ProjectileTemplate {
+    pub texture: Texture2D,
+    pub size: Vec2,
+    pub speed: f32,
+    pub radius: f32,
+    pub lifetime: f32,
+    pub damage: f32,
+    /// Extra distance around the player hitbox that still counts as a close call.
+    pub near_miss_margin: f32,
+    /// Impulse given to entities this projectile hits.
+    pub knockback: f32,
+    pub trail: Option<String>,
+    /// Hazard left where the projectile hits something or runs out.
+    pub impact_hazard: Option<String>,
+}
+
+/// Speed multiplier for projectiles the player parries.
+const PARRY_SPEED_SCALE: f32 = 1.25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectileOwner {
+    Player,
+    Entity { uid: u64, kind: EntityKind },
+}
+
+/// Who projectiles can hit this frame.
+#[derive(Clone, Copy)]
+pub struct ProjectileTargets<'a> {
+    pub player: Option<PlayerTarget>,
+    pub entities: &'a [EntityTarget],
+}
+
+/// Things that happened to projectiles this frame which other systems may want to react to.
+#[derive(Clone, Copy, Debug)]
+pub enum ProjectileEvent {
+    /// A projectile passed close to the player without touching them.
+    NearMiss { pos: Vec2 },
+    /// A projectile overlapped the player while their dash i-frames were active.
+    PerfectDodge { pos: Vec2 },
+    /// The player parried an enemy projectile and sent it back at the shooter.
+    Parried { pos: Vec2 },
+    /// A projectile bounced off a reflecting tile.
+    Reflected { pos: Vec2 },
+}
+
+struct ActiveProjectile {
+    template: usize,
+    pos: Vec2,
+    vel: Vec2,
+    remaining: f32,
+    owner: ProjectileOwner,
+    /// Set once the projectile has reported a near-miss or dodge so it only fires one event.
+    dodged: bool,
+    trail: Option<ParticleEmitter>,
+}
+
+pub struct ProjectileSystem {
+    templates: Vec<ProjectileTemplate>,
+    lookup: HashMap<String, usize>,
+    active: Vec<ActiveProjectile>,
+    /// Impact hazards waiting for the game loop to spawn them.
+    impacts: Vec<(String, Vec2, Option<EntityKind>)>,
+}
+
+impl ProjectileSystem {
+    pub fn empty() -> Self {
+        Self {
+            templates: Vec::new(),
+            lookup: HashMap::new(),
+            active: Vec::new(),
+            impacts: Vec::new(),
+        }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, ProjectileLoadError> {
+        let dir = dir.as_ref();
+        let mut raws = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["virabird_bullet.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| ProjectileLoadError::Io(std::io::Error::other(err.to_string())))?;
+                raws.push(serde_yaml::from_str::<ProjectileFile>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                raws.push(serde_yaml::from_str::<ProjectileFile>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+
+        let mut templates = Vec::with_capacity(raws.len());
+        let mut lookup = HashMap::with_capacity(raws.len());
+        for raw in raws {
+            let texture = load_texture(&asset_path(&raw.sprite))
+                .await
+                .map_err(|err| ProjectileLoadError::Texture(err.to_string()))?;
+            texture.set_filter(FilterMode::Nearest);
+            let size = raw.size.map(Vec2::from).unwrap_or_else(|| texture.size());
+            lookup.insert(raw.id, templates.len());
+            templates.push(ProjectileTemplate {
+                texture,
+                size,
+                speed: raw.speed.unwrap_or(120.0).max(0.0),
+                radius: raw.radius.unwrap_or(2.0).max(0.5),
+                lifetime: raw.lifetime.unwrap_or(3.0).max(0.0),
+                damage: raw.damage.unwrap_or(1.0).max(0.0),
+                near_miss_margin: raw.near_miss_margin.unwrap_or(6.0).max(0.0),
+                knockback: raw.knockback.unwrap_or(0.0).max(0.0),
+                trail: raw.trail,
+                impact_hazard: raw.impact_hazard,
+            });
+        }
+
+        Ok(Self {
+            templates,
+            lookup,
+            active: Vec::new(),
+            impacts: Vec::new(),
+        })
+    }
+
+    /// Fires a projectile from `pos` along `dir`. Returns false for unknown ids or a zero direction.
+    pub fn spawn(
+        &mut self,
+        id: &str,
+        pos: Vec2,
+        dir: Vec2,
+        owner: ProjectileOwner,
+        particles: &ParticleSystem,
+    ) -> bool {
+        let Some(template) = self.lookup.get(id).copied() else {
+            eprintln!("unknown projectile template '{id}'");
+            return false;
+        };
+        if dir.length_squared() <= 0.0001 {
+            return false;
+        }
+        let cfg = &self.templates[template];
+        let trail = cfg
+            .trail
+            .as_deref()
+            .and_then(|trail| particles.emitter(trail, pos));
+        self.active.push(ActiveProjectile {
+            template,
+            pos,
+            vel: dir.normalize() * cfg.speed,
+            remaining: cfg.lifetime,
+            owner,
+            dodged: false,
+            trail,
+        });
+        true
+    }
+
+    /// Moves projectiles, bounces them off reflecting tiles, resolves hits and reports events.
+    ///
+    /// Entity shots threaten the player: a dashing player cannot be hit, and a projectile that
+    /// overlaps them then counts as a perfect dodge and keeps flying, while a parrying player
+    /// sends it back at the shooter as their own shot. Player shots hit live non-friendly entities.
+    /// Spent projectiles with an impact hazard queue it for `take_impact_hazards`.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        targets: ProjectileTargets<'_>,
+        map: &TileMap,
+        particles: &mut ParticleSystem,
+        damage: &mut Vec<DamageEvent>,
+        events: &mut Vec<ProjectileEvent>,
+    ) {
+        let templates = &self.templates;
+        let mut step = |projectile: &mut ActiveProjectile| {
+            projectile.remaining -= dt;
+            if projectile.remaining <= 0.0 {
+                return false;
+            }
+            let cfg = &templates[projectile.template];
+            if !move_through_map(projectile, map, dt, events) {
+                return false;
+            }
+            if let Some(trail) = projectile.trail.as_mut() {
+                particles.update_emitter(trail, projectile.pos, dt);
+            }
+
+            match projectile.owner {
+                ProjectileOwner::Player => hit_entities(projectile, cfg, targets.entities, damage),
+                ProjectileOwner::Entity { uid, .. } => {
+                    let Some(player) = targets.player else {
+                        return true;
+                    };
+                    hit_player(projectile, cfg, player, targets.entities, uid, damage, events)
+                }
+            }
+        };
+        let impacts = &mut self.impacts;
+        self.active.retain_mut(|projectile| {
+            let alive = step(projectile);
+            if !alive && let Some(hazard) = templates[projectile.template].impact_hazard.as_ref() {
+                let owner = match projectile.owner {
+                    ProjectileOwner::Player => None,
+                    ProjectileOwner::Entity { kind, .. } => Some(kind),
+                };
+                impacts.push((hazard.clone(), projectile.pos, owner));
+            }
+            alive
+        });
+    }
+
+    /// Takes the impact hazards queued since the last call, as hazard id, position and the kind of
+    /// entity that fired the projectile (none for the player).
+    pub fn take_impact_hazards(&mut self) -> Vec<(String, Vec2, Option<EntityKind>)> {
+        std::mem::take(&mut self.impacts)
+    }
+
+    pub fn draw_in_view(&self, view: &ViewCull) {
+        for projectile in &self.active {
+            let cfg = &self.templates[projectile.template];
+            let dest = Rect::new(
+                projectile.pos.x - cfg.size.x * 0.5,
+                projectile.pos.y - cfg.size.y * 0.5,
+                cfg.size.x,
+                cfg.size.y,
+            );
+            if !view.rect_visible(dest) {
+                continue;
+            }
+            draw_texture_ex(
+                &cfg.texture,
+                dest.x,
+                dest.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(cfg.size),
+                    rotation: projectile.vel.y.atan2(projectile.vel.x),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+/// Steps a projectile, reflecting off `Reflect` tiles. Returns false if something blocked it.
+fn move_through_map(
+    projectile: &mut ActiveProjectile,
+    map: &TileMap,
+    dt: f32,
+    events: &mut Vec<ProjectileEvent>,
+) -> bool {
+    let from = projectile.pos;
+    let to = from + projectile.vel * dt;
+    let surface_at = |pos: Vec2| match map.grid_index(pos) {
+        Some(grid) => map.projectile_surface(grid.x as usize, grid.y as usize),
+        None => ProjectileSurface::Block,
+    };
+    match surface_at(to) {
+        ProjectileSurface::Open => {
+            projectile.pos = to;
+            true
+        }
+        ProjectileSurface::Block => false,
+        ProjectileSurface::Reflect => {
+            // Flip whichever axis carried the projectile into the reflecting tile.
+            let flip_x = surface_at(vec2(to.x, from.y)) != ProjectileSurface::Open;
+            let flip_y = surface_at(vec2(from.x, to.y)) != ProjectileSurface::Open;
+            if flip_x || !flip_y {
+                projectile.vel.x = -projectile.vel.x;
+            }
+            if flip_y || !flip_x {
+                projectile.vel.y = -projectile.vel.y;
+            }
+            events.push(ProjectileEvent::Reflected { pos: from });
+            true
+        }
+    }
+}
+
+/// Player-owned shots damage the first live entity they touch.
+fn hit_entities(
+    projectile: &ActiveProjectile,
+    cfg: &ProjectileTemplate,
+    entities: &[EntityTarget],
+    damage: &mut Vec<DamageEvent>,
+) -> bool {
+    let radius_sq = cfg.radius * cfg.radius;
+    let hit = entities.iter().find(|target| {
+        target.alive
+            && target.kind != EntityKind::Friend
+            && distance_sq_to_rect(projectile.pos, target.hitbox) <= radius_sq
+    });
+    let Some(target) = hit else {
+        return true;
+    };
+    damage.push(DamageEvent {
+        amount: cfg.damage,
+        target: Target::Entity(*target),
+        knockback: projectile.vel.normalize_or_zero() * cfg.knockback,
+        source: None,
+    });
+    false
+}
+
+/// Resolves an entity shot against the player. Returns false when the projectile is spent.
+fn hit_player(
+    projectile: &mut ActiveProjectile,
+    cfg: &ProjectileTemplate,
+    player: PlayerTarget,
+    entities: &[EntityTarget],
+    shooter: u64,
+    damage: &mut Vec<DamageEvent>,
+    events: &mut Vec<ProjectileEvent>,
+) -> bool {
+    let dist_sq = distance_sq_to_rect(projectile.pos, player.hitbox);
+    if dist_sq <= cfg.radius * cfg.radius {
+        if player.parrying {
+            let speed = projectile.vel.length() * PARRY_SPEED_SCALE;
+            let back = entities
+                .iter()
+                .find(|target| target.id == shooter && target.alive)
+                .map(|target| target.hitbox.center() - projectile.pos)
+                .unwrap_or(-projectile.vel);
+            projectile.vel = back.normalize_or_zero() * speed;
+            projectile.owner = ProjectileOwner::Player;
+            projectile.remaining = cfg.lifetime;
+            events.push(ProjectileEvent::Parried { pos: projectile.pos });
+            return true;
+        }
+        if player.dashing {
+            if !projectile.dodged {
+                projectile.dodged = true;
+                events.push(ProjectileEvent::PerfectDodge { pos: projectile.pos });
+            }
+            return true;
+        }
+        damage.push(DamageEvent {
+            amount: cfg.damage,
+            target: Target::Player(player),
+            knockback: Vec2::ZERO,
+            source: Some(shooter),
+        });
+        return false;
+    }
+
+    let graze = cfg.radius + cfg.near_miss_margin;
+    if !projectile.dodged && dist_sq <= graze * graze {
+        projectile.dodged = true;
+        events.push(ProjectileEvent::NearMiss { pos: projectile.pos });
+    }
+    true
+}
+
+fn distance_sq_to_rect(point: Vec2, rect: Rect) -> f32 {
+    let nearest = vec2(
+        point.x.clamp(rect.x, rect.x + rect.w),
+        point.y.clamp(rect.y, rect.y + rect.h),
+    );
+    point.distance_squared(nearest)
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize)]
+struct ProjectileFile {
+    id: String,
+    sprite: String,
+    #[serde(default)]
+    size: Option<[f32; 2]>,
+    #[serde(default)]
+    speed: Option<f32>,
+    #[serde(default)]
+    radius: Option<f32>,
+    #[serde(default)]
+    lifetime: Option<f32>,
+    #[serde(default)]
+    damage: Option<f32>,
+    #[serde(default)]
+    near_miss_margin: Option<f32>,
+    #[serde(default)]
+    knockback: Option<f32>,
+    #[serde(default)]
+    trail: Option<String>,
+    #[serde(default)]
+    impact_hazard: Option<String>,
+}
@@ -0,0 +1,243 @@
+//! Delivery contracts posted on quest boards.
+//!
+//! A board lists a few contracts, each asking for some number of one item by a given day.
+//! Expired contracts drop off and new ones are rolled from the item database as days pass.
+//! Delivering takes the items from the inventory and pays coins, by each item's `value`, and
+//! reputation. While the board is open, number keys or the Deliver buttons hand in a contract
+//! and Escape closes it.
+//! Contracts posted by a named NPC also raise the player's relationship with them.
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::item::{Inventory, ItemDatabase};
+use crate::relationship::{Relationships, CONTRACT_POINTS};
+use crate::time::WorldTime;
+use crate::ui::{PanelLayout, UiBatch, UiLayer};
+
+const BOARD_SIZE: usize = 3;
+const MAX_COUNT: u32 = 5;
+const MAX_DAYS: u32 = 3;
+/// Extra coins per item for each day less than `MAX_DAYS` the contract gives.
+const RUSH_BONUS: u32 = 2;
+/// The board closes once the player is this far from it.
+const CLOSE_RANGE: f32 = 48.0;
+const STATUS_TIME: f32 = 3.0;
+
+const PANEL: PanelLayout = PanelLayout {
+    width: 360.0,
+    header: 40.0,
+    row_height: 40.0,
+    footer: 0.0,
+};
+const ICON_SIZE: f32 = 24.0;
+const DELIVER_KEYS: [KeyCode; BOARD_SIZE] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Contract {
+    pub item: String,
+    pub count: u32,
+    /// Last day the contract can be delivered on.
+    pub due_day: u32,
+    pub coins: u32,
+    pub reputation: u32,
+    /// Name of the NPC who posted the contract.
+    #[serde(default)]
+    pub client: Option<String>,
+}
+
+/// What a save keeps of the board and the player's earnings.
+#[derive(Default, Serialize, Deserialize)]
+pub struct QuestSaveData {
+    pub contracts: Vec<Contract>,
+    pub rolled_day: u32,
+    pub coins: u32,
+    pub reputation: u32,
+}
+
+#[derive(Default)]
+pub struct ContractBoard {
+    contracts: Vec<Contract>,
+    /// Day the board was last restocked.
+    rolled_day: u32,
+    coins: u32,
+    reputation: u32,
+    /// Area of the board structure while it is open.
+    open_at: Option<Rect>,
+    status: String,
+    status_timer: f32,
+}
+
+impl ContractBoard {
+    pub fn open(&mut self, area: Rect) {
+        self.open_at = Some(area);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open_at.is_some()
+    }
+
+    pub fn coins(&self) -> u32 {
+        self.coins
+    }
+
+    /// Takes `amount` coins if there are enough.
+    pub fn spend_coins(&mut self, amount: u32) -> bool {
+        if self.coins < amount {
+            return false;
+        }
+        self.coins -= amount;
+        true
+    }
+
+    /// Drops expired contracts and restocks the board once per day with contracts from `clients`.
+    pub fn restock_daily(&mut self, day: u32, items: &ItemDatabase, clients: &[String]) {
+        self.contracts.retain(|contract| contract.due_day >= day);
+        if self.rolled_day != day {
+            self.rolled_day = day;
+            self.restock(day, items, clients);
+        }
+    }
+
+    /// Handles input while the board is open.
+    pub fn update(&mut self, inventory: &mut Inventory, relationships: &mut Relationships, player_pos: Vec2, dt: f32) {
+        self.status_timer = (self.status_timer - dt).max(0.0);
+
+        let Some(area) = self.open_at else {
+            return;
+        };
+        if is_key_pressed(KeyCode::Escape) || area.center().distance(player_pos) > CLOSE_RANGE {
+            self.open_at = None;
+            return;
+        }
+        let mouse: Vec2 = mouse_position().into();
+        for index in 0..self.contracts.len() {
+            let clicked = is_mouse_button_pressed(MouseButton::Left) && PANEL.button(index).contains(mouse);
+            if clicked || DELIVER_KEYS.get(index).is_some_and(|&key| is_key_pressed(key)) {
+                self.deliver(index, inventory, relationships);
+                break;
+            }
+        }
+    }
+
+    fn restock(&mut self, day: u32, items: &ItemDatabase, clients: &[String]) {
+        let ids = items.ids();
+        if ids.is_empty() {
+            return;
+        }
+        while self.contracts.len() < BOARD_SIZE {
+            let item = ids[rand::gen_range(0, ids.len())];
+            let count = rand::gen_range(1, MAX_COUNT + 1);
+            let days = rand::gen_range(1, MAX_DAYS + 1);
+            self.contracts.push(Contract {
+                item: item.to_string(),
+                count,
+                due_day: day + days - 1,
+                coins: count * (items.value(item) + (MAX_DAYS - days) * RUSH_BONUS),
+                reputation: 1 + count / 3,
+                client: (!clients.is_empty()).then(|| clients[rand::gen_range(0, clients.len())].clone()),
+            });
+        }
+    }
+
+    fn deliver(&mut self, index: usize, inventory: &mut Inventory, relationships: &mut Relationships) {
+        let Some(contract) = self.contracts.get(index) else {
+            return;
+        };
+        if !inventory.remove(&contract.item, contract.count) {
+            let have = inventory.counts().get(&contract.item).copied().unwrap_or(0);
+            self.set_status(format!("need {} {}, have {have}", contract.count, contract.item));
+            return;
+        }
+        let contract = self.contracts.remove(index);
+        self.coins += contract.coins;
+        self.reputation += contract.reputation;
+        if let Some(client) = &contract.client {
+            relationships.add(client, contract.reputation * CONTRACT_POINTS);
+        }
+        self.set_status(format!("delivered {} {} for {} coins", contract.count, contract.item, contract.coins));
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    pub fn to_save_data(&self) -> QuestSaveData {
+        QuestSaveData {
+            contracts: self.contracts.clone(),
+            rolled_day: self.rolled_day,
+            coins: self.coins,
+            reputation: self.reputation,
+        }
+    }
+
+    pub fn restore(&mut self, data: &QuestSaveData) {
+        *self = Self {
+            contracts: data.contracts.clone(),
+            rolled_day: data.rolled_day,
+            coins: data.coins,
+            reputation: data.reputation,
+            ..Self::default()
+        };
+    }
+
+    /// Queues day, season, time, coins and reputation in the top-right corner.
+    pub fn draw_hud(&self, time: &WorldTime, ui: &mut UiBatch) {
+        let text = format!(
+            "Day {} {} {}   {} coins   {} rep",
+            time.day(),
+            time.season().name(),
+            time.clock(),
+            self.coins,
+            self.reputation
+        );
+        let size = measure_text(&text, None, 20, 1.0);
+        ui.text(UiLayer::Hud, text, vec2(screen_width() - size.width - 20.0, 30.0), 20.0, WHITE);
+    }
+
+    /// Queues the open board and the last status message.
+    pub fn draw_ui(&self, day: u32, items: &ItemDatabase, inventory: &Inventory, ui: &mut UiBatch) {
+        if self.is_open() {
+            let frame = PANEL.frame(self.contracts.len().max(1));
+            ui.panel(frame, "Contracts");
+            if self.contracts.is_empty() {
+                let pos = PANEL.row(0).point() + vec2(10.0, 20.0);
+                ui.text(UiLayer::Panel, "nothing posted today", pos, 18.0, LIGHTGRAY);
+            }
+
+            for (index, contract) in self.contracts.iter().enumerate() {
+                let row = PANEL.row(index);
+                if let Some(def) = items.get(&contract.item) {
+                    ui.texture(UiLayer::Panel, &def.texture, PANEL.icon(index, ICON_SIZE), WHITE);
+                }
+                let have = inventory.counts().get(&contract.item).copied().unwrap_or(0);
+                let days_left = contract.due_day - day.min(contract.due_day);
+                let due = match days_left {
+                    0 => "due today".to_string(),
+                    1 => "due tomorrow".to_string(),
+                    n => format!("due in {n} days"),
+                };
+                let text_x = row.x + 20.0 + ICON_SIZE;
+                ui.text(
+                    UiLayer::Panel,
+                    format!("{}. {}/{} {}", index + 1, have.min(contract.count), contract.count, contract.item),
+                    vec2(text_x, row.y + 16.0),
+                    18.0,
+                    WHITE,
+                );
+                let reward = match &contract.client {
+                    Some(client) => format!("{due}, {} coins, {} rep, for {client}", contract.coins, contract.reputation),
+                    None => format!("{due}, {} coins, {} rep", contract.coins, contract.reputation),
+                };
+                ui.text(UiLayer::Panel, reward, vec2(text_x, row.y + 32.0), 16.0, LIGHTGRAY);
+                ui.button(PANEL.button(index), "Deliver", have >= contract.count);
+            }
+        }
+
+        if self.status_timer > 0.0 {
+            let size = measure_text(&self.status, None, 20, 1.0);
+            let pos = vec2((screen_width() - size.width) * 0.5, screen_height() * 0.8);
+            ui.text(UiLayer::Toast, self.status.as_str(), pos, 20.0, WHITE);
+        }
+    }
+}
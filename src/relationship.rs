@@ -0,0 +1,300 @@
+//! Relationships with named NPCs.
+//!
+//! Giving an NPC an item it likes, or delivering a contract it posted, earns relationship
+//! points. Each level unlocks more of the NPC's dialogue and a bigger discount in its shop.
+//! Pressing E next to an NPC opens its panel: G gives a gift, number keys or the Buy buttons
+//! buy from the shop, and Escape closes it.
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::entity::{EntityDatabase, NpcInfo, ShopOffer};
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::item::{Inventory, ItemDatabase};
+use crate::quest::ContractBoard;
+use crate::ui::{PanelLayout, UiBatch, UiLayer};
+
+/// Points needed for each level and its label. Level 0 is where everyone starts.
+const LEVELS: [(u32, &str); 4] = [(0, "stranger"), (10, "acquaintance"), (30, "friend"), (60, "close friend")];
+/// Fraction taken off shop prices per level.
+const DISCOUNT_PER_LEVEL: f32 = 0.1;
+/// Relationship points per reputation point of a delivered contract.
+pub const CONTRACT_POINTS: u32 = 2;
+/// How close the player has to be to start talking, and how far they can walk off before the
+/// panel closes.
+const TALK_RANGE: f32 = 40.0;
+const CLOSE_RANGE: f32 = 64.0;
+const STATUS_TIME: f32 = 3.0;
+
+/// The name and dialogue block sits in the header, above the shop rows.
+const PANEL: PanelLayout = PanelLayout {
+    width: 380.0,
+    header: 96.0,
+    row_height: 34.0,
+    footer: 30.0,
+};
+const ICON_SIZE: f32 = 20.0;
+const BUY_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// Relationship points per NPC name. Saved as-is.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Relationships {
+    points: HashMap<String, u32>,
+    /// Day each NPC last took a gift; NPCs take one gift a day.
+    #[serde(default)]
+    gifted_day: HashMap<String, u32>,
+}
+
+impl Relationships {
+    pub fn points(&self, npc: &str) -> u32 {
+        self.points.get(npc).copied().unwrap_or(0)
+    }
+
+    pub fn level(&self, npc: &str) -> usize {
+        let points = self.points(npc);
+        LEVELS.iter().rposition(|&(min, _)| points >= min).unwrap_or(0)
+    }
+
+    pub fn add(&mut self, npc: &str, points: u32) {
+        if points == 0 {
+            return;
+        }
+        *self.points.entry(npc.to_string()).or_insert(0) += points;
+    }
+
+    /// Shop price after the discount for the current level; never below one coin.
+    pub fn price(&self, npc: &str, base: u32) -> u32 {
+        let discount = self.level(npc) as f32 * DISCOUNT_PER_LEVEL;
+        ((base as f32 * (1.0 - discount)).round() as u32).max(1)
+    }
+
+    fn gifted_on(&self, npc: &str, day: u32) -> bool {
+        self.gifted_day.get(npc) == Some(&day)
+    }
+}
+
+pub fn level_name(level: usize) -> &'static str {
+    LEVELS[level.min(LEVELS.len() - 1)].1
+}
+
+/// Names of every NPC in the database, for contracts to be posted by.
+pub fn npc_names(db: &EntityDatabase) -> Vec<String> {
+    let mut names: Vec<String> = db
+        .entities
+        .iter()
+        .filter(|def| def.npc.is_some())
+        .map(|def| def.name.clone())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// The talk/gift/shop panel of the NPC the player is talking to.
+#[derive(Default)]
+pub struct NpcPanel {
+    /// The NPC entity while the panel is open.
+    open: Option<EntityHandle>,
+    /// Sold by every NPC on top of their own shop, from today's calendar events.
+    event_stock: Vec<ShopOffer>,
+    status: String,
+    status_timer: f32,
+}
+
+impl NpcPanel {
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    pub fn close(&mut self) {
+        self.open = None;
+    }
+
+    pub fn set_event_stock(&mut self, stock: Vec<ShopOffer>) {
+        self.event_stock = stock;
+    }
+
+    fn offers<'a>(&'a self, info: &'a NpcInfo) -> impl Iterator<Item = &'a ShopOffer> {
+        info.shop.iter().chain(self.event_stock.iter())
+    }
+
+    /// Opens the panel for a particular NPC, like the vendor of a stall the player clicked.
+    pub fn open(&mut self, handle: EntityHandle) {
+        self.open = Some(handle);
+    }
+
+    /// Opens the panel for the nearest living NPC in talking range. Returns false if there is
+    /// none.
+    pub fn talk(&mut self, entities: &EntityPool, db: &EntityDatabase, player_pos: Vec2) -> bool {
+        let nearest = entities
+            .iter_with_handles()
+            .filter(|(_, ent)| !ent.instance.is_corpse() && db.entities[ent.instance.def].npc.is_some())
+            .map(|(handle, ent)| (handle, ent.hitbox(db).center().distance(player_pos)))
+            .filter(|&(_, distance)| distance <= TALK_RANGE)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        self.open = nearest.map(|(handle, _)| handle);
+        self.open.is_some()
+    }
+
+    /// Closes the panel when the NPC is gone, out of range, or Escape is pressed.
+    pub fn update(&mut self, entities: &EntityPool, db: &EntityDatabase, player_pos: Vec2, dt: f32) {
+        self.status_timer = (self.status_timer - dt).max(0.0);
+        let Some(handle) = self.open else {
+            return;
+        };
+        let in_range = open_npc(handle, entities, db).is_some_and(|(_, _, pos)| pos.distance(player_pos) <= CLOSE_RANGE);
+        if !in_range || is_key_pressed(KeyCode::Escape) {
+            self.open = None;
+        }
+    }
+
+    /// Gifts and purchases while the panel is open. Coins come from the contract board.
+    pub fn handle_input(
+        &mut self,
+        day: u32,
+        entities: &EntityPool,
+        db: &EntityDatabase,
+        relationships: &mut Relationships,
+        board: &mut ContractBoard,
+        inventory: &mut Inventory,
+    ) {
+        let Some((name, info, _)) = self.open.and_then(|handle| open_npc(handle, entities, db)) else {
+            return;
+        };
+        if is_key_pressed(KeyCode::G) {
+            self.give_gift(name, info, day, relationships, inventory);
+        }
+        let mouse: Vec2 = mouse_position().into();
+        let bought = self
+            .offers(info)
+            .enumerate()
+            .find(|&(index, _)| {
+                let clicked = is_mouse_button_pressed(MouseButton::Left) && PANEL.button(index).contains(mouse);
+                clicked || BUY_KEYS.get(index).is_some_and(|&key| is_key_pressed(key))
+            })
+            .map(|(_, offer)| offer.clone());
+        if let Some(offer) = bought {
+            let price = relationships.price(name, offer.price);
+            if board.spend_coins(price) {
+                inventory.add(&offer.item, 1);
+                self.set_status(format!("bought {} for {price} coins", offer.item));
+            } else {
+                self.set_status(format!("{} costs {price} coins, have {}", offer.item, board.coins()));
+            }
+        }
+    }
+
+    /// Gives the liked item the player has that is worth the most points.
+    fn give_gift(
+        &mut self,
+        name: &str,
+        info: &NpcInfo,
+        day: u32,
+        relationships: &mut Relationships,
+        inventory: &mut Inventory,
+    ) {
+        if relationships.gifted_on(name, day) {
+            self.set_status(format!("{name} already got a gift today"));
+            return;
+        }
+        let gift = info
+            .gifts
+            .iter()
+            .filter(|(item, _)| inventory.has(item))
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)));
+        let Some((item, &points)) = gift else {
+            self.set_status(format!("you have nothing {name} wants"));
+            return;
+        };
+        inventory.remove(item, 1);
+        let level = relationships.level(name);
+        relationships.add(name, points);
+        relationships.gifted_day.insert(name.to_string(), day);
+        let new_level = relationships.level(name);
+        if new_level > level {
+            self.set_status(format!("{name} loved the {item} and is now your {}", level_name(new_level)));
+        } else {
+            self.set_status(format!("{name} liked the {item} (+{points})"));
+        }
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    /// Queues the open panel and the last status message.
+    pub fn draw_ui(
+        &self,
+        entities: &EntityPool,
+        db: &EntityDatabase,
+        items: &ItemDatabase,
+        relationships: &Relationships,
+        ui: &mut UiBatch,
+    ) {
+        if let Some((name, info, _)) = self.open.and_then(|handle| open_npc(handle, entities, db)) {
+            let frame = PANEL.frame(self.offers(info).count());
+            let origin = frame.point();
+            ui.panel(frame, name);
+
+            let level = relationships.level(name);
+            let standing = match LEVELS.get(level + 1) {
+                Some(&(next, _)) => format!("{}, {}/{next}", level_name(level), relationships.points(name)),
+                None => level_name(level).to_string(),
+            };
+            let size = measure_text(&standing, None, 18, 1.0);
+            let standing_pos = vec2(frame.right() - size.width - 10.0, origin.y + 24.0);
+            ui.text(UiLayer::Panel, standing, standing_pos, 18.0, LIGHTGRAY);
+            if let Some(line) = info.dialogue.iter().rev().find(|line| line.level <= level) {
+                ui.text(UiLayer::Panel, format!("\"{}\"", line.text), origin + vec2(10.0, 56.0), 18.0, WHITE);
+            }
+            if self.offers(info).next().is_some() {
+                ui.text(UiLayer::Panel, "Shop", origin + vec2(10.0, PANEL.header - 10.0), 20.0, WHITE);
+            }
+
+            for (index, offer) in self.offers(info).enumerate() {
+                let row = PANEL.row(index);
+                if let Some(def) = items.get(&offer.item) {
+                    ui.texture(UiLayer::Panel, &def.texture, PANEL.icon(index, ICON_SIZE), WHITE);
+                }
+                let price = relationships.price(name, offer.price);
+                let label = if price < offer.price {
+                    format!("{}. {} - {price} coins (was {})", index + 1, offer.item, offer.price)
+                } else {
+                    format!("{}. {} - {price} coins", index + 1, offer.item)
+                };
+                ui.text(UiLayer::Panel, label, vec2(row.x + 20.0 + ICON_SIZE, row.y + 22.0), 18.0, WHITE);
+                ui.button(PANEL.button(index), "Buy", true);
+            }
+            ui.panel_footer(frame, "G: give a gift   Esc: close");
+        }
+
+        if self.status_timer > 0.0 {
+            let size = measure_text(&self.status, None, 20, 1.0);
+            let pos = vec2((screen_width() - size.width) * 0.5, screen_height() * 0.85);
+            ui.text(UiLayer::Toast, self.status.as_str(), pos, 20.0, WHITE);
+        }
+    }
+}
+
+/// Name, NPC info and position of the living entity behind `handle`.
+fn open_npc<'a>(
+    handle: EntityHandle,
+    entities: &EntityPool,
+    db: &'a EntityDatabase,
+) -> Option<(&'a str, &'a NpcInfo, Vec2)> {
+    let ent = entities.get(handle).filter(|ent| !ent.instance.is_corpse())?;
+    let def = &db.entities[ent.instance.def];
+    let info = def.npc.as_ref()?;
+    Some((&def.name, info, ent.hitbox(db).center()))
+}
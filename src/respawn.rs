@@ -0,0 +1,92 @@
+//! Where the player gets back up after being knocked out.
+//!
+//! Each map keeps its own anchor: the generated world under `WORLD_MAP`, and authored levels
+//! under their path. Resting at a bed or camp moves the anchor of the current map there. A map
+//! without one sends the player back to where they entered it. Anchors are kept in saves, so a
+//! bed found long ago still counts after loading.
+
+use macroquad::prelude::*;
+use std::collections::HashMap;
+use crate::ui::{UiBatch, UiLayer};
+
+/// Key of the generated world's anchor.
+pub const WORLD_MAP: &str = "world";
+/// Seconds a knocked-out player stays down.
+const RESPAWN_DELAY: f32 = 3.0;
+
+pub struct RespawnAnchors {
+    map: String,
+    /// Where the player entered the current map.
+    entry: Vec2,
+    anchors: HashMap<String, Vec2>,
+    /// Seconds until the player gets up, while knocked out.
+    down: Option<f32>,
+}
+
+impl RespawnAnchors {
+    pub fn new(map: &str, entry: Vec2) -> Self {
+        Self {
+            map: map.to_string(),
+            entry,
+            anchors: HashMap::new(),
+            down: None,
+        }
+    }
+
+    /// Switches to the map the player just arrived in at `entry`.
+    pub fn enter(&mut self, map: &str, entry: Vec2) {
+        self.map = map.to_string();
+        self.entry = entry;
+    }
+
+    /// Makes `pos` the current map's anchor.
+    pub fn set(&mut self, pos: Vec2) {
+        self.anchors.insert(self.map.clone(), pos);
+    }
+
+    /// Where the player respawns on the current map.
+    pub fn point(&self) -> Vec2 {
+        self.anchors.get(&self.map).copied().unwrap_or(self.entry)
+    }
+
+    /// Call every frame. True once the player has been down long enough to get up.
+    pub fn update(&mut self, dead: bool, dt: f32) -> bool {
+        if !dead {
+            self.down = None;
+            return false;
+        }
+        let timer = self.down.get_or_insert(RESPAWN_DELAY);
+        *timer -= dt;
+        if *timer > 0.0 {
+            return false;
+        }
+        self.down = None;
+        true
+    }
+
+    pub fn to_save_data(&self) -> HashMap<String, [f32; 2]> {
+        self.anchors.iter().map(|(map, pos)| (map.clone(), pos.to_array())).collect()
+    }
+
+    pub fn restore(&mut self, saved: &HashMap<String, [f32; 2]>) {
+        self.anchors = saved.iter().map(|(map, &pos)| (map.clone(), Vec2::from(pos))).collect();
+        self.down = None;
+    }
+
+    /// Counts down to getting up while the player is knocked out.
+    pub fn draw_ui(&self, ui: &mut UiBatch) {
+        let Some(timer) = self.down else {
+            return;
+        };
+        let text = format!("Knocked out... back up in {}", timer.ceil().max(1.0) as u32);
+        let font_size = 28.0;
+        let size = measure_text(&text, None, font_size as u16, 1.0);
+        ui.text(
+            UiLayer::Toast,
+            text.as_str(),
+            vec2((screen_width() - size.width) * 0.5, screen_height() * 0.4),
+            font_size,
+            WHITE,
+        );
+    }
+}
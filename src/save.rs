@@ -0,0 +1,432 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use crate::entity::{Entity, EntityDatabase, MovementRegistry};
+use crate::entity_pool::EntityPool;
+use crate::item::Inventory;
+use crate::map::{LegacyMapSaveData, MapSaveData, TileMap};
+use crate::player::Player;
+use crate::quest::{ContractBoard, QuestSaveData};
+use crate::farming::{Farm, PlantedCrop};
+use crate::relationship::Relationships;
+use crate::respawn::RespawnAnchors;
+use crate::simulation::Simulation;
+use crate::statistics::Statistics;
+use crate::time::WorldTime;
+use crate::upgrade::Upgrades;
+use crate::world::WorldSettings;
+
+/// Width of save thumbnails in pixels; the height follows the screen's aspect.
+const THUMBNAIL_WIDTH: usize = 96;
+
+/// Bumped whenever the save layout changes incompatibly, together with a new entry in
+/// `MIGRATIONS` that upgrades files from the previous version.
+pub const SAVE_VERSION: u32 = 2;
+
+/// Rewrites a save's json in place from one version to the next.
+type Migration = fn(&mut serde_json::Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` save to version `n + 2`. The length ties the
+/// table to `SAVE_VERSION`, so a bump without a migration doesn't compile.
+const MIGRATIONS: [Migration; SAVE_VERSION as usize - 1] = [migrate_chunked_map];
+
+/// Version 2 streams the map in chunks instead of storing whole-map layers.
+fn migrate_chunked_map(save: &mut serde_json::Value) -> Result<(), String> {
+    let map = save.get_mut("map").ok_or("missing map")?;
+    let legacy: LegacyMapSaveData = serde_json::from_value(map.take()).map_err(|err| err.to_string())?;
+    *map = serde_json::to_value(legacy.into_chunked()?).map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+/// Runs the migrations a save needs to reach `SAVE_VERSION`, returning the version it had.
+fn migrate(save: &mut serde_json::Value) -> Result<u32, SaveError> {
+    let found = save
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or(SaveError::Version {
+            found: 0,
+            expected: SAVE_VERSION,
+        })?;
+    if found == 0 || found > SAVE_VERSION {
+        return Err(SaveError::Version {
+            found,
+            expected: SAVE_VERSION,
+        });
+    }
+    for version in found..SAVE_VERSION {
+        MIGRATIONS[version as usize - 1](save).map_err(|reason| SaveError::Migration { from: version, reason })?;
+        save["version"] = serde_json::Value::from(version + 1);
+    }
+    Ok(found)
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Version { found: u32, expected: u32 },
+    /// Upgrading from version `from` failed.
+    Migration { from: u32, reason: String },
+    Unsupported,
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Json(err) => write!(f, "json error: {err}"),
+            Self::Version { found, expected } => {
+                write!(f, "save version {found} is not supported (expected {expected})")
+            }
+            Self::Migration { from, reason } => write!(f, "could not upgrade save from version {from}: {reason}"),
+            Self::Unsupported => write!(f, "saving is not supported on this platform"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerSaveData {
+    pub pos: [f32; 2],
+    pub hp: f32,
+    pub max_hp: f32,
+    #[serde(default)]
+    pub inventory: HashMap<String, u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EntitySaveData {
+    pub id: String,
+    pub pos: [f32; 2],
+    pub home: [f32; 2],
+    pub hp: f32,
+    #[serde(default)]
+    pub tier: u32,
+    /// Food of entities with hunger.
+    #[serde(default)]
+    pub food: Option<f32>,
+    #[serde(default)]
+    pub tamed: bool,
+    #[serde(default)]
+    pub pet: bool,
+}
+
+impl EntitySaveData {
+    pub fn from_entity(ent: &Entity, db: &EntityDatabase) -> Self {
+        Self {
+            id: db.entities[ent.instance.def].id.clone(),
+            pos: ent.instance.pos.to_array(),
+            home: ent.instance.home.to_array(),
+            hp: ent.instance.hp,
+            tier: ent.instance.tier,
+            food: ent.instance.hunger.map(|hunger| hunger.food),
+            tamed: ent.instance.tamed,
+            pet: ent.instance.pet,
+        }
+    }
+
+    /// Spawns the entity again from its definition; None when the id is unknown.
+    pub fn respawn(&self, db: &EntityDatabase, registry: &MovementRegistry, world: &WorldSettings) -> Option<Entity> {
+        let mut ent = Entity::spawn(db, &self.id, Vec2::from(self.pos), registry)?;
+        world.scale_to_tier(&mut ent.instance, self.tier);
+        ent.instance.home = Vec2::from(self.home);
+        ent.instance.hp = self.hp.clamp(0.0, ent.instance.max_hp);
+        ent.instance.tamed = self.tamed;
+        if self.pet {
+            ent.instance.make_pet();
+        }
+        if let (Some(hunger), Some(food)) = (ent.instance.hunger.as_mut(), self.food) {
+            hunger.food = food.clamp(0.0, hunger.info.max);
+        }
+        Some(ent)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub map: MapSaveData,
+    pub player: PlayerSaveData,
+    pub entities: Vec<EntitySaveData>,
+    #[serde(default)]
+    pub quests: QuestSaveData,
+    /// Seconds of play, for the world time.
+    #[serde(default)]
+    pub elapsed: f32,
+    #[serde(default)]
+    pub relationships: Relationships,
+    /// Cutscenes already seen, so introductions don't replay.
+    #[serde(default)]
+    pub cutscenes: Vec<String>,
+    /// Crops in the ground; tilled soil is part of the map.
+    #[serde(default)]
+    pub farm: Vec<PlantedCrop>,
+    /// Bare soil with fertilizer in it.
+    #[serde(default)]
+    pub fertilized: Vec<(usize, usize)>,
+    #[serde(default)]
+    pub meta: SaveMeta,
+    /// Respawn anchor of each map, by the map's key.
+    #[serde(default)]
+    pub respawn: HashMap<String, [f32; 2]>,
+    #[serde(default)]
+    pub statistics: Statistics,
+    /// Level bought of each upgrade, by id.
+    #[serde(default)]
+    pub upgrades: HashMap<String, u32>,
+}
+
+/// What the load menu shows about a save before it is restored.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SaveMeta {
+    pub day: u32,
+    pub clock: String,
+    /// Seed of the generated world; `None` for authored levels.
+    #[serde(default)]
+    pub seed: Option<u32>,
+    #[serde(default)]
+    pub thumbnail: Option<SaveThumbnail>,
+}
+
+/// A small picture of the scene at the moment of saving.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveThumbnail {
+    pub width: u16,
+    pub height: u16,
+    /// RGBA pixels, rows top to bottom, hex encoded.
+    pub rgba: String,
+}
+
+impl SaveThumbnail {
+    /// Shrinks a frame read back from the GPU, whose rows run bottom to top, by averaging
+    /// the block of pixels behind each thumbnail pixel.
+    pub fn from_frame(frame: &Image) -> Option<Self> {
+        let (src_w, src_h) = (frame.width as usize, frame.height as usize);
+        if src_w == 0 || src_h == 0 || frame.bytes.len() < src_w * src_h * 4 {
+            return None;
+        }
+        let width = THUMBNAIL_WIDTH.min(src_w);
+        let height = (src_h * width / src_w).max(1);
+        let mut rgba = String::with_capacity(width * height * 8);
+        for y in 0..height {
+            let (top, bottom) = (y * src_h / height, ((y + 1) * src_h / height).max(y * src_h / height + 1));
+            for x in 0..width {
+                let (left, right) = (x * src_w / width, ((x + 1) * src_w / width).max(x * src_w / width + 1));
+                let mut sum = [0usize; 4];
+                for sy in top..bottom {
+                    let row = (src_h - 1 - sy) * src_w;
+                    for sx in left..right {
+                        let pixel = &frame.bytes[(row + sx) * 4..(row + sx) * 4 + 4];
+                        for (total, &channel) in sum.iter_mut().zip(pixel) {
+                            *total += channel as usize;
+                        }
+                    }
+                }
+                let count = (bottom - top) * (right - left);
+                for total in sum {
+                    rgba.push_str(&format!("{:02x}", total / count));
+                }
+            }
+        }
+        Some(Self {
+            width: width as u16,
+            height: height as u16,
+            rgba,
+        })
+    }
+
+    /// Decodes the pixels into a texture, or `None` if the data is damaged.
+    pub fn to_texture(&self) -> Option<Texture2D> {
+        let bytes = (0..self.rgba.len())
+            .step_by(2)
+            .map(|i| self.rgba.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .collect::<Option<Vec<u8>>>()?;
+        if bytes.len() != self.width as usize * self.height as usize * 4 {
+            return None;
+        }
+        let texture = Texture2D::from_rgba8(self.width, self.height, &bytes);
+        texture.set_filter(FilterMode::Nearest);
+        Some(texture)
+    }
+}
+
+impl SaveFile {
+    pub fn capture(
+        map: &TileMap,
+        player: &Player,
+        inventory: &Inventory,
+        entities: &[Entity],
+        db: &EntityDatabase,
+        contract_board: &ContractBoard,
+        time: &WorldTime,
+    ) -> Self {
+        let entities = entities
+            .iter()
+            .filter(|ent| !ent.instance.is_corpse())
+            .map(|ent| EntitySaveData::from_entity(ent, db))
+            .collect();
+        Self {
+            version: SAVE_VERSION,
+            map: map.to_save_data(),
+            player: PlayerSaveData {
+                pos: player.position().to_array(),
+                hp: player.hp(),
+                max_hp: player.max_hp(),
+                inventory: inventory.counts().clone(),
+            },
+            entities,
+            quests: contract_board.to_save_data(),
+            elapsed: time.elapsed(),
+            relationships: Relationships::default(),
+            cutscenes: Vec::new(),
+            farm: Vec::new(),
+            fertilized: Vec::new(),
+            meta: SaveMeta {
+                day: time.day(),
+                clock: time.clock(),
+                seed: None,
+                thumbnail: None,
+            },
+            respawn: HashMap::new(),
+            statistics: Statistics::default(),
+            upgrades: HashMap::new(),
+        }
+    }
+
+    pub fn with_relationships(mut self, relationships: &Relationships) -> Self {
+        self.relationships = relationships.clone();
+        self
+    }
+
+    pub fn with_cutscenes(mut self, played: Vec<String>) -> Self {
+        self.cutscenes = played;
+        self
+    }
+
+    pub fn with_farm(mut self, farm: &Farm) -> Self {
+        self.farm = farm.to_save_data();
+        self.fertilized = farm.fertilized_tiles();
+        self
+    }
+
+    pub fn with_respawn(mut self, respawn: &RespawnAnchors) -> Self {
+        self.respawn = respawn.to_save_data();
+        self
+    }
+
+    pub fn with_statistics(mut self, statistics: &Statistics) -> Self {
+        self.statistics = statistics.clone();
+        self
+    }
+
+    pub fn with_upgrades(mut self, upgrades: &Upgrades) -> Self {
+        self.upgrades = upgrades.to_save_data();
+        self
+    }
+
+    /// Adds the entities the simulation put away, so they come back after loading.
+    pub fn with_dormant(mut self, simulation: &Simulation) -> Self {
+        self.entities.extend(simulation.dormant().iter().cloned());
+        self
+    }
+
+    pub fn with_seed(mut self, seed: Option<u32>) -> Self {
+        self.meta.seed = seed;
+        self
+    }
+
+    pub fn with_thumbnail(mut self, thumbnail: Option<SaveThumbnail>) -> Self {
+        self.meta.thumbnail = thumbnail;
+        self
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        if cfg!(target_arch = "wasm32") {
+            return Err(SaveError::Unsupported);
+        }
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        if cfg!(target_arch = "wasm32") {
+            return Err(SaveError::Unsupported);
+        }
+        let mut raw: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        let found = migrate(&mut raw)?;
+        if found != SAVE_VERSION {
+            eprintln!("upgraded save from version {found} to {SAVE_VERSION}");
+        }
+        Ok(serde_json::from_value(raw)?)
+    }
+
+    pub fn restore_player(&self, player: &mut Player, inventory: &mut Inventory) {
+        player.restore(Vec2::from(self.player.pos), self.player.hp, self.player.max_hp);
+        *inventory = Inventory::default();
+        for (id, &count) in &self.player.inventory {
+            inventory.add(id, count);
+        }
+    }
+
+    pub fn restore_quests(&self, contract_board: &mut ContractBoard, time: &mut WorldTime) {
+        contract_board.restore(&self.quests);
+        time.set_elapsed(self.elapsed);
+    }
+
+    pub fn restore_relationships(&self, relationships: &mut Relationships) {
+        *relationships = self.relationships.clone();
+    }
+
+    pub fn restore_farm(&self, farm: &mut Farm) {
+        farm.restore(&self.farm, &self.fertilized);
+    }
+
+    pub fn restore_respawn(&self, respawn: &mut RespawnAnchors) {
+        respawn.restore(&self.respawn);
+    }
+
+    pub fn restore_statistics(&self, statistics: &mut Statistics) {
+        *statistics = self.statistics.clone();
+    }
+
+    pub fn restore_upgrades(&self, upgrades: &mut Upgrades) {
+        upgrades.restore(&self.upgrades);
+    }
+
+    /// Respawns saved entities from their definitions; unknown ids are skipped with a warning.
+    pub fn restore_entities(
+        &self,
+        db: &EntityDatabase,
+        registry: &MovementRegistry,
+        world: &WorldSettings,
+    ) -> EntityPool {
+        let mut out = EntityPool::default();
+        for saved in &self.entities {
+            let Some(ent) = saved.respawn(db, registry, world) else {
+                eprintln!("saved entity '{}' has no definition; skipping", saved.id);
+                continue;
+            };
+            out.insert(ent);
+        }
+        out
+    }
+}
@@ -0,0 +1,122 @@
+//! Distance-based simulation detail, to keep large worlds cheap.
+//!
+//! With a `simulation` block in world.yaml, entities more than `sleep_distance` past the edge of
+//! the view fall asleep: their behaviour trees, movement and collision stop, and only their hunger
+//! keeps ticking. Entities more than `despawn_distance` from the player are taken out of the world
+//! and kept as save records, then spawned again once the player comes back within
+//! `wake_distance` of them. Pets and NPCs stay in the world however far away they are.
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+use crate::entity::{EntityDatabase, MovementRegistry};
+use crate::entity_pool::EntityPool;
+use crate::save::EntitySaveData;
+use crate::world::WorldSettings;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SimulationSettings {
+    /// How far outside the view entities keep running, in world pixels.
+    #[serde(default = "default_sleep_distance")]
+    pub sleep_distance: f32,
+    /// Distance from the player past which entities are put away.
+    #[serde(default = "default_despawn_distance")]
+    pub despawn_distance: f32,
+    /// Distance from the player within which put-away entities come back; keep it under
+    /// `despawn_distance` so they don't flicker in and out at the edge.
+    #[serde(default = "default_wake_distance")]
+    pub wake_distance: f32,
+}
+
+fn default_sleep_distance() -> f32 {
+    160.0
+}
+
+fn default_despawn_distance() -> f32 {
+    1200.0
+}
+
+fn default_wake_distance() -> f32 {
+    1000.0
+}
+
+#[derive(Default)]
+pub struct Simulation {
+    settings: Option<SimulationSettings>,
+    /// Entities put away for being too far from the player.
+    dormant: Vec<EntitySaveData>,
+}
+
+impl Simulation {
+    /// Without settings every entity runs every frame and none is put away.
+    pub fn new(settings: Option<SimulationSettings>) -> Self {
+        Self {
+            settings,
+            dormant: Vec::new(),
+        }
+    }
+
+    /// Forgets the put-away entities, e.g. after loading a save or changing level.
+    pub fn clear(&mut self) {
+        self.dormant.clear();
+    }
+
+    pub fn dormant(&self) -> &[EntitySaveData] {
+        &self.dormant
+    }
+
+    /// Whether an entity at `pos` should only get the sleeping tick, given the camera's view in
+    /// world space.
+    pub fn is_asleep(&self, pos: Vec2, view: Rect) -> bool {
+        let Some(settings) = self.settings.as_ref() else {
+            return false;
+        };
+        let dx = (view.x - pos.x).max(pos.x - view.right()).max(0.0);
+        let dy = (view.y - pos.y).max(pos.y - view.bottom()).max(0.0);
+        dx * dx + dy * dy > settings.sleep_distance * settings.sleep_distance
+    }
+
+    /// Puts away entities that are too far from the player and brings back the ones it came
+    /// near again. Call once a frame, before the entities update.
+    pub fn update(
+        &mut self,
+        entities: &mut EntityPool,
+        db: &EntityDatabase,
+        registry: &MovementRegistry,
+        world: &WorldSettings,
+        player_pos: Vec2,
+    ) {
+        let Some(settings) = self.settings.as_ref() else {
+            return;
+        };
+        let despawn_sq = settings.despawn_distance * settings.despawn_distance;
+        let far: Vec<_> = entities
+            .iter_with_handles()
+            .filter(|(_, ent)| {
+                !ent.instance.is_corpse()
+                    && !ent.instance.pet
+                    && db.entities[ent.instance.def].npc.is_none()
+                    && ent.instance.pos.distance_squared(player_pos) > despawn_sq
+            })
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in far {
+            if let Some(ent) = entities.remove(handle) {
+                self.dormant.push(EntitySaveData::from_entity(&ent, db));
+            }
+        }
+
+        let wake_sq = settings.wake_distance.min(settings.despawn_distance).powi(2);
+        let (waking, dormant): (Vec<_>, Vec<_>) = std::mem::take(&mut self.dormant)
+            .into_iter()
+            .partition(|saved| Vec2::from(saved.pos).distance_squared(player_pos) <= wake_sq);
+        self.dormant = dormant;
+        for saved in waking {
+            match saved.respawn(db, registry, world) {
+                Some(ent) => {
+                    entities.insert(ent);
+                }
+                None => eprintln!("dormant entity '{}' has no definition; dropping it", saved.id),
+            }
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use macroquad::audio::{load_sound, play_sound, stop_sound, PlaySoundParams, Sound};
+use macroquad::audio::{play_sound, stop_sound, PlaySoundParams, Sound};
 use macroquad::prelude::Vec2;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::Path;
+use crate::asset_pack::load_sound;
 use crate::helpers::asset_path;
+use crate::mods;
 
 #[derive(Debug)]
 pub enum SoundLoadError {
@@ -91,6 +93,18 @@ const WASM_BUILTIN_SOUNDS: &[BuiltinSoundDef] = &[
         min_distance: 60.0,
         variance: 0.0,
     },
+    BuiltinSoundDef {
+        id: "footstep_sand",
+        path: "src/assets/sounds/gras.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.5,
+        looped: false,
+        spatial: false,
+        pitch: 0.8,
+        max_distance: 600.0,
+        min_distance: 60.0,
+        variance: 0.0,
+    },
     BuiltinSoundDef {
         id: "hurt",
         path: "src/assets/sounds/hurt.wav",
@@ -115,6 +129,114 @@ const WASM_BUILTIN_SOUNDS: &[BuiltinSoundDef] = &[
         min_distance: 60.0,
         variance: 0.0,
     },
+    BuiltinSoundDef {
+        id: "pickup",
+        path: "src/assets/sounds/coinpickup.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.5,
+        looped: false,
+        spatial: false,
+        pitch: 1.0,
+        max_distance: 600.0,
+        min_distance: 60.0,
+        variance: 0.0,
+    },
+    BuiltinSoundDef {
+        id: "near_miss",
+        path: "src/assets/sounds/moveSelect.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.4,
+        looped: false,
+        spatial: false,
+        pitch: 1.0,
+        max_distance: 600.0,
+        min_distance: 60.0,
+        variance: 0.0,
+    },
+    BuiltinSoundDef {
+        id: "perfect_dodge",
+        path: "src/assets/sounds/select.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.5,
+        looped: false,
+        spatial: false,
+        pitch: 1.0,
+        max_distance: 600.0,
+        min_distance: 60.0,
+        variance: 0.0,
+    },
+    BuiltinSoundDef {
+        id: "parry",
+        path: "src/assets/sounds/select.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.6,
+        looped: false,
+        spatial: false,
+        pitch: 1.4,
+        max_distance: 600.0,
+        min_distance: 60.0,
+        variance: 0.0,
+    },
+    BuiltinSoundDef {
+        id: "ping",
+        path: "src/assets/sounds/select.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.6,
+        looped: false,
+        spatial: false,
+        pitch: 1.5,
+        max_distance: 600.0,
+        min_distance: 60.0,
+        variance: 0.0,
+    },
+    BuiltinSoundDef {
+        id: "virat_chitter",
+        path: "src/assets/sounds/goofysound.wav",
+        channel: SoundChannel::Ambient,
+        volume: 0.35,
+        looped: false,
+        spatial: true,
+        pitch: 1.5,
+        max_distance: 240.0,
+        min_distance: 40.0,
+        variance: 0.2,
+    },
+    BuiltinSoundDef {
+        id: "bird_call",
+        path: "src/assets/sounds/select.wav",
+        channel: SoundChannel::Ambient,
+        volume: 0.3,
+        looped: false,
+        spatial: true,
+        pitch: 1.8,
+        max_distance: 320.0,
+        min_distance: 60.0,
+        variance: 0.3,
+    },
+    BuiltinSoundDef {
+        id: "structure_break",
+        path: "src/assets/sounds/hurt.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.6,
+        looped: false,
+        spatial: true,
+        pitch: 0.6,
+        max_distance: 480.0,
+        min_distance: 80.0,
+        variance: 0.1,
+    },
+    BuiltinSoundDef {
+        id: "death_squeak",
+        path: "src/assets/sounds/goofysound.wav",
+        channel: SoundChannel::Sfx,
+        volume: 0.5,
+        looped: false,
+        spatial: true,
+        pitch: 1.6,
+        max_distance: 420.0,
+        min_distance: 60.0,
+        variance: 0.15,
+    },
 ];
 
 pub struct SoundSystem {
@@ -205,6 +327,16 @@ impl SoundSystem {
         })
     }
 
+    /// Adds `other`'s sounds, replacing those with the same id. `merged` sees each id and
+    /// whether it replaced one.
+    pub fn merge(&mut self, other: Self, mut merged: impl FnMut(&str, bool)) {
+        for sound in other.sounds {
+            let id = sound.entry.id.clone();
+            let replaced = mods::upsert(&mut self.sounds, &mut self.lookup, id.clone(), sound);
+            merged(&id, replaced);
+        }
+    }
+
     pub fn set_channel_volume(&mut self, channel: SoundChannel, volume: f32) {
         self.channel_volume.insert(channel, volume.clamp(0.0, 1.0));
     }
@@ -0,0 +1,42 @@
+//! Uniform bucket grid for "what is near this point" queries.
+//!
+//! Built once per frame from entity positions so behaviours that look at their neighbours, like
+//! `flock`, don't each scan every entity on the map.
+
+use macroquad::prelude::*;
+use std::collections::HashMap;
+
+/// Side of a grid cell in world pixels; queries larger than this visit more cells.
+pub const CELL_SIZE: f32 = 64.0;
+
+#[derive(Default)]
+pub struct SpatialGrid {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    /// Buckets each position by cell, keyed by its index in `positions`.
+    pub fn build(positions: impl IntoIterator<Item = Vec2>) -> Self {
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (index, pos) in positions.into_iter().enumerate() {
+            cells.entry(cell_of(pos)).or_default().push(index);
+        }
+        Self { cells }
+    }
+
+    /// Indices in every cell touching the square around `pos`. Callers still check the distance;
+    /// this only narrows down the candidates.
+    pub fn candidates(&self, pos: Vec2, radius: f32) -> impl Iterator<Item = usize> + '_ {
+        let (min_x, min_y) = cell_of(pos - Vec2::splat(radius));
+        let (max_x, max_y) = cell_of(pos + Vec2::splat(radius));
+        (min_y..=max_y)
+            .flat_map(move |y| (min_x..=max_x).map(move |x| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+fn cell_of(pos: Vec2) -> (i32, i32) {
+    ((pos.x / CELL_SIZE).floor() as i32, (pos.y / CELL_SIZE).floor() as i32)
+}
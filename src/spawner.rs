@@ -0,0 +1,353 @@
+//! Ambient spawning from spawn tables in `src/spawner/*.yaml`.
+//!
+//! With a `spawner` block in world.yaml, the spawner tops up the wildlife around the player every
+//! `interval` seconds. It picks a table entry by weight among those still under their
+//! `max_alive`, then looks for open ground between `min_distance` and `max_distance` from the
+//! player, outside the view, that matches the entry's biome, region and time of day. Entities it
+//! spawned are despawned once they wander past `despawn_distance`, freeing room for new ones.
+//! Structure, calendar and editor spawns are never despawned.
+//!
+//! The spawner also places the waves of structure encounters, which a structure's
+//! `start_encounter` trigger sends in at most once a day.
+
+use crate::asset_pack::load_string;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::helpers::{data_path, load_wasm_manifest_files, random_f32};
+use crate::map::{EntitySpawn, StructureEncounter, TileMap};
+use crate::time::{SpawnTime, WorldTime};
+
+/// Positions tried per spawn before giving up until the next interval.
+const PLACEMENT_ATTEMPTS: u32 = 8;
+
+#[derive(Debug)]
+pub enum SpawnTableLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for SpawnTableLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnTableLoadError {}
+
+impl From<std::io::Error> for SpawnTableLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for SpawnTableLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpawnerSettings {
+    /// Seconds between spawns.
+    #[serde(default = "default_interval")]
+    pub interval: f32,
+    /// Ring around the player new entities appear in, in world pixels.
+    #[serde(default = "default_min_distance")]
+    pub min_distance: f32,
+    #[serde(default = "default_max_distance")]
+    pub max_distance: f32,
+    /// Spawned entities further than this from the player are removed.
+    #[serde(default = "default_despawn_distance")]
+    pub despawn_distance: f32,
+    /// Cap on spawner entities alive at once across every table.
+    #[serde(default = "default_max_alive")]
+    pub max_alive: u32,
+}
+
+fn default_interval() -> f32 {
+    2.0
+}
+
+fn default_min_distance() -> f32 {
+    220.0
+}
+
+fn default_max_distance() -> f32 {
+    320.0
+}
+
+fn default_despawn_distance() -> f32 {
+    480.0
+}
+
+fn default_max_alive() -> u32 {
+    16
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SpawnEntry {
+    pub entity: String,
+    /// Relative chance against the other entries that can spawn.
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    /// Most of this entry alive at once.
+    #[serde(default = "default_entry_max_alive")]
+    pub max_alive: u32,
+    /// Biome tags the entity appears in; empty means any. Maps without a generator have no
+    /// biomes, so entries that list some never spawn there.
+    #[serde(default)]
+    pub biomes: Vec<String>,
+    /// Map region tags the entity must appear inside; empty means anywhere.
+    #[serde(default)]
+    pub regions: Vec<String>,
+    #[serde(default)]
+    pub time: SpawnTime,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+fn default_entry_max_alive() -> u32 {
+    4
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SpawnTable {
+    pub id: String,
+    #[serde(default)]
+    pub entries: Vec<SpawnEntry>,
+}
+
+/// An entity the spawner wants placed; hand its handle back through `Spawner::track`.
+pub struct AmbientSpawn {
+    pub entity: String,
+    pub pos: Vec2,
+    entry: usize,
+}
+
+#[derive(Default)]
+pub struct Spawner {
+    settings: Option<SpawnerSettings>,
+    /// Entries of every table, in table id order.
+    entries: Vec<SpawnEntry>,
+    /// Entities this spawner placed, with the entry each came from.
+    spawned: Vec<(EntityHandle, usize)>,
+    timer: f32,
+    /// Day each structure encounter last started, by structure id and trigger position.
+    encounters: HashMap<(String, i32, i32), u32>,
+}
+
+impl Spawner {
+    /// Loads every table in `dir`; without settings the spawner never spawns anything.
+    pub async fn load_from(
+        dir: impl AsRef<Path>,
+        settings: Option<SpawnerSettings>,
+    ) -> Result<Self, SpawnTableLoadError> {
+        let dir = dir.as_ref();
+        let mut tables = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let files = load_wasm_manifest_files(&dir, &["wildlife.yaml"]).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| SpawnTableLoadError::Io(std::io::Error::other(err.to_string())))?;
+                tables.push(serde_yaml::from_str::<SpawnTable>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                tables.push(serde_yaml::from_str::<SpawnTable>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+        tables.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(Self {
+            settings,
+            entries: tables.into_iter().flat_map(|table| table.entries).collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Forgets which entities it spawned, e.g. after loading a save or changing level; those
+    /// entities stay in the world for good.
+    pub fn clear(&mut self) {
+        self.spawned.clear();
+        self.timer = 0.0;
+        self.encounters.clear();
+    }
+
+    /// Places the wave of the encounter whose trigger pins cover `area`, unless that structure
+    /// already sent one on `day`. The spawns wait on their time of day like structure spawns.
+    pub fn start_encounter(
+        &mut self,
+        structure_id: &str,
+        area: Rect,
+        encounter: &StructureEncounter,
+        day: u32,
+        map: &TileMap,
+    ) -> Vec<EntitySpawn> {
+        let key = (structure_id.to_string(), area.x as i32, area.y as i32);
+        if self.encounters.get(&key) == Some(&day) {
+            return Vec::new();
+        }
+        self.encounters.insert(key, day);
+
+        let center = area.center();
+        let radius = encounter.radius.max(0.0);
+        let mut spawns = Vec::new();
+        for spawn in &encounter.wave {
+            for _ in 0..spawn.count {
+                let pos = (0..PLACEMENT_ATTEMPTS)
+                    .map(|_| {
+                        let angle = rand::gen_range(0.0, std::f32::consts::TAU);
+                        center + Vec2::from_angle(angle) * rand::gen_range(radius * 0.5, radius)
+                    })
+                    .find(|&pos| open_ground(map, pos))
+                    .unwrap_or(center);
+                spawns.push(EntitySpawn {
+                    entity: spawn.entity.clone(),
+                    pos,
+                    time: spawn.time,
+                });
+            }
+        }
+        spawns
+    }
+
+    /// Records the entity placed for `spawn`.
+    pub fn track(&mut self, spawn: &AmbientSpawn, handle: EntityHandle) {
+        self.spawned.push((handle, spawn.entry));
+    }
+
+    /// Removes spawned entities that wandered too far from the player and forgets ones that
+    /// died, became pets or were removed some other way.
+    pub fn despawn_far(&mut self, entities: &mut EntityPool, player_pos: Vec2) {
+        let Some(settings) = self.settings.as_ref() else {
+            return;
+        };
+        let max_distance_sq = settings.despawn_distance * settings.despawn_distance;
+        self.spawned.retain(|&(handle, _)| {
+            let Some(ent) = entities.get(handle) else {
+                return false;
+            };
+            // Pets stay with the player and no longer count against the caps.
+            if ent.instance.pet {
+                return false;
+            }
+            if ent.instance.pos.distance_squared(player_pos) > max_distance_sq {
+                entities.remove(handle);
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Call every frame; returns what to spawn this frame, if anything. `view` is the camera's
+    /// view in world space, kept clear so nothing pops in on screen.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        player_pos: Vec2,
+        view: Rect,
+        map: &TileMap,
+        time: &WorldTime,
+    ) -> Option<AmbientSpawn> {
+        let settings = self.settings.as_ref()?;
+        self.timer -= dt;
+        if self.timer > 0.0 {
+            return None;
+        }
+        self.timer = settings.interval.max(0.1);
+        if self.spawned.len() >= settings.max_alive as usize {
+            return None;
+        }
+
+        let open: Vec<usize> = (0..self.entries.len())
+            .filter(|&index| {
+                let entry = &self.entries[index];
+                let alive = self.spawned.iter().filter(|&&(_, from)| from == index).count();
+                entry.weight > 0.0 && alive < entry.max_alive as usize && time.allows(entry.time)
+            })
+            .collect();
+        let total: f32 = open.iter().map(|&index| self.entries[index].weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut roll = random_f32() * total;
+        let index = *open
+            .iter()
+            .find(|&&index| {
+                roll -= self.entries[index].weight;
+                roll <= 0.0
+            })
+            .unwrap_or(open.last()?);
+        let entry = &self.entries[index];
+
+        let min_distance = settings.min_distance.max(0.0);
+        let max_distance = settings.max_distance.max(min_distance);
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let angle = rand::gen_range(0.0, std::f32::consts::TAU);
+            let distance = rand::gen_range(min_distance, max_distance);
+            let pos = player_pos + Vec2::from_angle(angle) * distance;
+            if !view.contains(pos) && accepts(entry, map, pos) {
+                return Some(AmbientSpawn {
+                    entity: entry.entity.clone(),
+                    pos,
+                    entry: index,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Whether `pos` is open, dry, loaded ground matching the entry's biome and region filters.
+fn accepts(entry: &SpawnEntry, map: &TileMap, pos: Vec2) -> bool {
+    if !open_ground(map, pos) {
+        return false;
+    }
+    if !entry.biomes.is_empty()
+        && !map.biome_at(pos).is_some_and(|biome| entry.biomes.iter().any(|tag| tag == biome.tag()))
+    {
+        return false;
+    }
+    entry.regions.is_empty()
+        || map
+            .regions_containing(pos)
+            .any(|region| entry.regions.contains(&region.tag))
+}
+
+/// Whether `pos` is on a loaded tile that isn't solid, liquid or harmful.
+fn open_ground(map: &TileMap, pos: Vec2) -> bool {
+    if pos.x < 0.0 || pos.y < 0.0 {
+        return false;
+    }
+    let x = (pos.x / map.tile_size()) as usize;
+    let y = (pos.y / map.tile_size()) as usize;
+    if !map.is_tile_loaded(x, y) || map.is_solid(x, y) {
+        return false;
+    }
+    let ground = map.tile_properties_at(pos);
+    !ground.liquid && ground.damage_per_second <= 0.0
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,85 @@
+//! Vendors bound to the structures they tend, like the keeper of a market stall.
+//!
+//! A structure with a `vendor` asks the map for one every time it loads. The registry links each
+//! structure, by its top-left tile, to the entity standing at it, so chunks that unload and load
+//! again don't bring a second keeper. A vendor restored from a save is adopted again by its home,
+//! which is where the structure put it. When the structure is removed its vendor goes with it;
+//! a vendor that died is replaced the next time the structure loads.
+
+use macroquad::prelude::*;
+use std::collections::HashMap;
+use crate::entity::EntityDatabase;
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::map::VendorSpawn;
+
+/// World pixels a restored vendor's home may be off from the spot its structure asks for.
+const ADOPT_DISTANCE: f32 = 1.0;
+
+struct StallLink {
+    vendor: EntityHandle,
+    footprint: Rect,
+}
+
+#[derive(Default)]
+pub struct StallRegistry {
+    links: HashMap<(usize, usize), StallLink>,
+}
+
+impl StallRegistry {
+    /// Forgets every link, e.g. after loading a save or changing level.
+    pub fn clear(&mut self) {
+        self.links.clear();
+    }
+
+    /// Whether the structure behind `spawn` already has a living vendor. An unbound entity of the
+    /// right kind waiting at the spot, like one restored from a save, is bound to it here.
+    pub fn has_vendor(&mut self, spawn: &VendorSpawn, entities: &EntityPool, db: &EntityDatabase) -> bool {
+        let alive = |handle| entities.get(handle).is_some_and(|ent| !ent.instance.is_corpse());
+        if self.links.get(&spawn.stall).is_some_and(|link| alive(link.vendor)) {
+            return true;
+        }
+        let adopted = entities
+            .iter_with_handles()
+            .find(|&(handle, ent)| {
+                !ent.instance.is_corpse()
+                    && db.entities[ent.instance.def].id == spawn.entity
+                    && ent.instance.home.distance(spawn.pos) <= ADOPT_DISTANCE
+                    && !self.links.values().any(|link| link.vendor == handle)
+            })
+            .map(|(handle, _)| handle);
+        match adopted {
+            Some(handle) => {
+                self.link(spawn, handle);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Binds the entity spawned for `spawn` to its structure.
+    pub fn link(&mut self, spawn: &VendorSpawn, vendor: EntityHandle) {
+        self.links.insert(
+            spawn.stall,
+            StallLink {
+                vendor,
+                footprint: spawn.footprint,
+            },
+        );
+    }
+
+    /// Removes the vendor of the structure whose top-left tile is `stall`.
+    pub fn remove_stall(&mut self, stall: (usize, usize), entities: &mut EntityPool) {
+        if let Some(link) = self.links.remove(&stall) {
+            entities.remove(link.vendor);
+        }
+    }
+
+    /// The living vendor of the structure overlapping `area`, e.g. an interactor's rectangle.
+    pub fn vendor_at(&self, area: Rect, entities: &EntityPool) -> Option<EntityHandle> {
+        self.links
+            .values()
+            .filter(|link| link.footprint.overlaps(&area))
+            .map(|link| link.vendor)
+            .find(|&handle| entities.get(handle).is_some_and(|ent| !ent.instance.is_corpse()))
+    }
+}
@@ -0,0 +1,36 @@
+//! Running tallies of the player's game, kept in saves.
+//!
+//! For every kind of creature, how many the player has defeated and which items it has been seen
+//! dropping. Tooltips use the drops to tell known loot from loot still to be found.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    /// Creatures defeated, by entity id.
+    #[serde(default)]
+    kills: HashMap<String, u32>,
+    /// Items seen dropping, by the id of the entity that dropped them.
+    #[serde(default)]
+    drops: HashMap<String, BTreeSet<String>>,
+}
+
+impl Statistics {
+    pub fn record_kill(&mut self, entity: &str) {
+        *self.kills.entry(entity.to_string()).or_default() += 1;
+    }
+
+    pub fn record_drop(&mut self, entity: &str, item: &str) {
+        self.drops.entry(entity.to_string()).or_default().insert(item.to_string());
+    }
+
+    pub fn kills(&self, entity: &str) -> u32 {
+        self.kills.get(entity).copied().unwrap_or(0)
+    }
+
+    /// Whether `entity` has been seen dropping `item`.
+    pub fn drop_seen(&self, entity: &str, item: &str) -> bool {
+        self.drops.get(entity).is_some_and(|items| items.contains(item))
+    }
+}
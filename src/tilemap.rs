@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use macroquad::prelude::*;
+use crate::asset_pack::{load_string, load_texture};
 use crate::helpers::asset_path;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -0,0 +1,243 @@
+//! World time: the day counter and the day/night cycle.
+//!
+//! Days start at dawn. The time of day is a fraction of the day (0 at dawn), and the scene is
+//! multiplied by an ambient color that follows it, from warm at dawn and dusk to dark blue at
+//! night. Behaviours and structure spawns can ask whether it is
+//! night. Every `season_days` days the season turns, from spring through winter and round again.
+
+use macroquad::prelude::*;
+use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation};
+use serde::Deserialize;
+
+/// Multiplies whatever is drawn under it by the draw color.
+const TINT_VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec4 color0;
+varying lowp vec4 color;
+uniform mat4 Model;
+uniform mat4 Projection;
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+}
+"#;
+
+const TINT_FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+void main() {
+    gl_FragColor = color;
+}
+"#;
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct TimeSettings {
+    /// Seconds of play per in-game day.
+    pub day_length: f32,
+    /// Fractions of the day (0 = dawn) when night starts and ends; `night_start` comes first.
+    pub night_start: f32,
+    pub night_end: f32,
+    /// Ambient colors at noon and at midnight; dawn and dusk blend through `twilight`.
+    pub day_color: [u8; 3],
+    pub twilight_color: [u8; 3],
+    pub night_color: [u8; 3],
+    /// In-game days per season.
+    pub season_days: f32,
+}
+
+impl Default for TimeSettings {
+    fn default() -> Self {
+        Self {
+            day_length: 240.0,
+            night_start: 0.6,
+            night_end: 0.95,
+            day_color: [255, 255, 255],
+            twilight_color: [255, 190, 150],
+            night_color: [90, 100, 160],
+            season_days: 7.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    const ALL: [Season; 4] = [Season::Spring, Season::Summer, Season::Autumn, Season::Winter];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Spring => "Spring",
+            Self::Summer => "Summer",
+            Self::Autumn => "Autumn",
+            Self::Winter => "Winter",
+        }
+    }
+}
+
+/// When a structure's spawns may appear.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpawnTime {
+    #[default]
+    Any,
+    Day,
+    Night,
+}
+
+pub struct WorldTime {
+    settings: TimeSettings,
+    elapsed: f32,
+    tint: Option<Material>,
+}
+
+impl WorldTime {
+    pub fn new(settings: TimeSettings) -> Self {
+        let tint = load_material(
+            ShaderSource::Glsl {
+                vertex: TINT_VERTEX,
+                fragment: TINT_FRAGMENT,
+            },
+            MaterialParams {
+                pipeline_params: PipelineParams {
+                    color_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::Value(BlendValue::DestinationColor),
+                        BlendFactor::Zero,
+                    )),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .inspect_err(|err| eprintln!("day/night tint unavailable: {err}"))
+        .ok();
+        Self {
+            settings,
+            elapsed: 0.0,
+            tint,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+
+    /// Day 1 is the first day.
+    pub fn day(&self) -> u32 {
+        1 + (self.elapsed / self.day_length()) as u32
+    }
+
+    /// Fraction of the current day, 0 at dawn.
+    pub fn time_of_day(&self) -> f32 {
+        (self.elapsed / self.day_length()).fract()
+    }
+
+    pub fn season(&self) -> Season {
+        let index = (self.days_elapsed() / self.settings.season_days.max(1.0)) as usize;
+        Season::ALL[index % Season::ALL.len()]
+    }
+
+    pub fn is_night(&self) -> bool {
+        let t = self.time_of_day();
+        t >= self.settings.night_start && t < self.settings.night_end
+    }
+
+    pub fn allows(&self, time: SpawnTime) -> bool {
+        match time {
+            SpawnTime::Any => true,
+            SpawnTime::Day => !self.is_night(),
+            SpawnTime::Night => self.is_night(),
+        }
+    }
+
+    /// 24-hour clock with dawn at 6:00, for the HUD.
+    pub fn clock(&self) -> String {
+        let minutes = ((self.time_of_day() * 24.0 + 6.0) * 60.0) as u32 % (24 * 60);
+        format!("{:02}:{:02}", minutes / 60, minutes % 60)
+    }
+
+    /// In-game days since the start, with the fraction of the current day.
+    pub fn days_elapsed(&self) -> f32 {
+        self.elapsed / self.day_length()
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn set_elapsed(&mut self, elapsed: f32) {
+        self.elapsed = elapsed.max(0.0);
+    }
+
+    /// Jumps ahead to the next dawn. Everything that follows the clock, like crop growth and
+    /// calendar events, catches up on its own.
+    pub fn skip_to_morning(&mut self) {
+        self.elapsed = (self.days_elapsed().floor() + 1.0) * self.day_length();
+    }
+
+    /// Color the scene is multiplied by right now.
+    pub fn ambient(&self) -> Color {
+        let settings = &self.settings;
+        let day = rgb(settings.day_color);
+        let twilight = rgb(settings.twilight_color);
+        let night = rgb(settings.night_color);
+        // Twilight takes this much of the day on each side of night.
+        let fade = 0.05;
+        let t = self.time_of_day();
+        let (start, end) = (settings.night_start, settings.night_end);
+        if t < start - fade {
+            // Morning light warms up into the day.
+            blend(twilight, day, ((t + 1.0 - end) / fade).clamp(0.0, 1.0))
+        } else if t < start {
+            blend(day, twilight, (t - (start - fade)) / fade)
+        } else if t < start + fade {
+            blend(twilight, night, (t - start) / fade)
+        } else if t < end - fade {
+            night
+        } else if t < end {
+            blend(night, twilight, (t - (end - fade)) / fade)
+        } else {
+            blend(twilight, day, (t - end) / fade)
+        }
+    }
+
+    /// Multiplies the area by the ambient color. Call with the world camera set, after the
+    /// world has been drawn.
+    pub fn draw_tint(&self, area: Rect) {
+        let Some(tint) = self.tint.as_ref() else {
+            return;
+        };
+        let color = self.ambient();
+        if color == WHITE {
+            return;
+        }
+        gl_use_material(tint);
+        draw_rectangle(area.x, area.y, area.w, area.h, color);
+        gl_use_default_material();
+    }
+
+    fn day_length(&self) -> f32 {
+        self.settings.day_length.max(1.0)
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::from_rgba(r, g, b, 255)
+}
+
+fn blend(from: Color, to: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    Color::new(
+        from.r + (to.r - from.r) * t,
+        from.g + (to.g - from.g) * t,
+        from.b + (to.b - from.b) * t,
+        1.0,
+    )
+}
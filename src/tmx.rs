@@ -0,0 +1,456 @@
+//! Minimal reader for Tiled `.tmx` maps and `.tsx` tilesets.
+//!
+//! Only what the map loader needs is supported: orthogonal, non-infinite maps with CSV encoded
+//! tile layers, object groups with rectangle objects, and custom properties.
+
+use std::collections::HashMap;
+
+/// Tiled stores flip/rotation flags in the top bits of each gid.
+const GID_FLAG_MASK: u32 = 0xE000_0000;
+
+#[derive(Debug)]
+pub struct TmxError(pub String);
+
+impl std::fmt::Display for TmxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tmx error: {}", self.0)
+    }
+}
+
+impl std::error::Error for TmxError {}
+
+fn err<T>(msg: impl Into<String>) -> Result<T, TmxError> {
+    Err(TmxError(msg.into()))
+}
+
+pub struct TmxMap {
+    pub width: usize,
+    pub height: usize,
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub tilesets: Vec<TmxTilesetRef>,
+    pub layers: Vec<TmxLayer>,
+    pub object_groups: Vec<TmxObjectGroup>,
+    pub properties: HashMap<String, String>,
+}
+
+/// A `<tileset>` entry in a map; either embedded or pointing at an external `.tsx`.
+pub struct TmxTilesetRef {
+    pub first_gid: u32,
+    pub source: Option<String>,
+    pub tileset: Option<TmxTileset>,
+}
+
+pub struct TmxTileset {
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub columns: usize,
+    pub tile_count: usize,
+    pub image: String,
+}
+
+pub struct TmxLayer {
+    pub name: String,
+    pub properties: HashMap<String, String>,
+    /// Row-major gids with flip flags stripped; 0 means no tile.
+    pub gids: Vec<u32>,
+}
+
+pub struct TmxObjectGroup {
+    pub name: String,
+    pub objects: Vec<TmxObject>,
+}
+
+pub struct TmxObject {
+    pub name: String,
+    /// `class` (Tiled 1.9+) or the older `type` attribute.
+    pub class: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub properties: HashMap<String, String>,
+}
+
+pub fn parse_tmx(src: &str) -> Result<TmxMap, TmxError> {
+    let root = parse_xml(src)?;
+    if root.name != "map" {
+        return err(format!("expected <map> root, found <{}>", root.name));
+    }
+    if root.attr("infinite") == Some("1") {
+        return err("infinite maps are not supported");
+    }
+    if let Some(orientation) = root.attr("orientation")
+        && orientation != "orthogonal"
+    {
+        return err(format!("{orientation} maps are not supported"));
+    }
+
+    let width = root.attr_parse::<usize>("width")?;
+    let height = root.attr_parse::<usize>("height")?;
+    let mut map = TmxMap {
+        width,
+        height,
+        tile_width: root.attr_parse("tilewidth")?,
+        tile_height: root.attr_parse("tileheight")?,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        object_groups: Vec::new(),
+        properties: root.properties(),
+    };
+
+    for child in &root.children {
+        match child.name.as_str() {
+            "tileset" => {
+                let source = child.attr("source").map(str::to_string);
+                let tileset = if source.is_some() {
+                    None
+                } else {
+                    Some(tileset_from_element(child)?)
+                };
+                map.tilesets.push(TmxTilesetRef {
+                    first_gid: child.attr_parse("firstgid")?,
+                    source,
+                    tileset,
+                });
+            }
+            "layer" => map.layers.push(layer_from_element(child, width * height)?),
+            "objectgroup" => map.object_groups.push(object_group_from_element(child)?),
+            _ => {}
+        }
+    }
+    Ok(map)
+}
+
+pub fn parse_tsx(src: &str) -> Result<TmxTileset, TmxError> {
+    let root = parse_xml(src)?;
+    if root.name != "tileset" {
+        return err(format!("expected <tileset> root, found <{}>", root.name));
+    }
+    tileset_from_element(&root)
+}
+
+fn tileset_from_element(el: &XmlElement) -> Result<TmxTileset, TmxError> {
+    let Some(image) = el.child("image") else {
+        return err("tilesets made of individual images are not supported");
+    };
+    Ok(TmxTileset {
+        tile_width: el.attr_parse("tilewidth")?,
+        tile_height: el.attr_parse("tileheight")?,
+        columns: el.attr_parse("columns")?,
+        tile_count: el.attr_parse("tilecount")?,
+        image: image
+            .attr("source")
+            .ok_or_else(|| TmxError("tileset image without source".to_string()))?
+            .to_string(),
+    })
+}
+
+fn layer_from_element(el: &XmlElement, len: usize) -> Result<TmxLayer, TmxError> {
+    let name = el.attr("name").unwrap_or_default().to_string();
+    let Some(data) = el.child("data") else {
+        return err(format!("layer '{name}' has no data"));
+    };
+    match data.attr("encoding") {
+        Some("csv") => {}
+        other => {
+            return err(format!(
+                "layer '{name}' uses {} encoding; only csv is supported",
+                other.unwrap_or("xml")
+            ));
+        }
+    }
+    let mut gids = Vec::with_capacity(len);
+    for cell in data.text.split(',') {
+        let cell = cell.trim();
+        if cell.is_empty() {
+            continue;
+        }
+        let gid: u32 = cell
+            .parse()
+            .map_err(|_| TmxError(format!("layer '{name}' has invalid gid '{cell}'")))?;
+        gids.push(gid & !GID_FLAG_MASK);
+    }
+    if gids.len() != len {
+        return err(format!("layer '{name}' has {} tiles, expected {len}", gids.len()));
+    }
+    Ok(TmxLayer {
+        name,
+        properties: el.properties(),
+        gids,
+    })
+}
+
+fn object_group_from_element(el: &XmlElement) -> Result<TmxObjectGroup, TmxError> {
+    let mut objects = Vec::new();
+    for obj in el.children.iter().filter(|child| child.name == "object") {
+        objects.push(TmxObject {
+            name: obj.attr("name").unwrap_or_default().to_string(),
+            class: obj
+                .attr("class")
+                .or_else(|| obj.attr("type"))
+                .unwrap_or_default()
+                .to_string(),
+            x: obj.attr_parse("x")?,
+            y: obj.attr_parse("y")?,
+            width: obj.attr_or("width", 0.0)?,
+            height: obj.attr_or("height", 0.0)?,
+            properties: obj.properties(),
+        });
+    }
+    Ok(TmxObjectGroup {
+        name: el.attr("name").unwrap_or_default().to_string(),
+        objects,
+    })
+}
+
+struct XmlElement {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn attr_parse<T: std::str::FromStr>(&self, key: &str) -> Result<T, TmxError> {
+        let Some(raw) = self.attr(key) else {
+            return err(format!("<{}> is missing '{key}'", self.name));
+        };
+        raw.parse()
+            .map_err(|_| TmxError(format!("<{}> has invalid '{key}' value '{raw}'", self.name)))
+    }
+
+    fn attr_or<T: std::str::FromStr>(&self, key: &str, default: T) -> Result<T, TmxError> {
+        if self.attr(key).is_none() {
+            return Ok(default);
+        }
+        self.attr_parse(key)
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn properties(&self) -> HashMap<String, String> {
+        let Some(props) = self.child("properties") else {
+            return HashMap::new();
+        };
+        props
+            .children
+            .iter()
+            .filter(|prop| prop.name == "property")
+            .filter_map(|prop| {
+                let name = prop.attr("name")?.to_string();
+                // Multi-line string properties keep their value as element text.
+                let value = prop.attr("value").map(str::to_string).unwrap_or_else(|| prop.text.clone());
+                Some((name, value))
+            })
+            .collect()
+    }
+}
+
+/// Parses just enough XML for Tiled files: elements, attributes, text, comments and
+/// declarations. Namespaces, CDATA and DTDs are not handled.
+fn parse_xml(src: &str) -> Result<XmlElement, TmxError> {
+    let mut stack: Vec<XmlElement> = Vec::new();
+    let mut rest = src;
+
+    while let Some(start) = rest.find('<') {
+        if let Some(parent) = stack.last_mut() {
+            parent.text.push_str(&unescape(&rest[..start]));
+        }
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after.find("-->").ok_or_else(|| TmxError("unterminated comment".to_string()))?;
+            rest = &after[end + 3..];
+            continue;
+        }
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest.find('>').ok_or_else(|| TmxError("unterminated declaration".to_string()))?;
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        let end = tag_end(rest).ok_or_else(|| TmxError("unterminated tag".to_string()))?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let Some(el) = stack.pop() else {
+                return err(format!("unexpected </{}>", name.trim()));
+            };
+            if el.name != name.trim() {
+                return err(format!("<{}> closed by </{}>", el.name, name.trim()));
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(el),
+                None => return Ok(el),
+            }
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let el = parse_start_tag(tag)?;
+        if self_closing {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(el),
+                None => return Ok(el),
+            }
+        } else {
+            stack.push(el);
+        }
+    }
+    err("document has no root element or is truncated")
+}
+
+/// Index of the `>` closing the tag at the start of `src`, skipping any inside quoted attribute
+/// values.
+fn tag_end(src: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in src.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            (Some(open), _) if c == open => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_start_tag(tag: &str) -> Result<XmlElement, TmxError> {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = tag[..name_end].to_string();
+    let mut attrs = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| TmxError(format!("malformed attribute in <{name}>")))?;
+        let key = rest[..eq].trim().to_string();
+        let value_src = rest[eq + 1..].trim_start();
+        let quote = value_src
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| TmxError(format!("unquoted attribute '{key}' in <{name}>")))?;
+        let close = value_src[1..]
+            .find(quote)
+            .ok_or_else(|| TmxError(format!("unterminated attribute '{key}' in <{name}>")))?;
+        attrs.push((key, unescape(&value_src[1..close + 1])));
+        rest = value_src[close + 2..].trim_start();
+    }
+    Ok(XmlElement {
+        name,
+        attrs,
+        children: Vec::new(),
+        text: String::new(),
+    })
+}
+
+fn unescape(raw: &str) -> String {
+    if !raw.contains('&') {
+        return raw.to_string();
+    }
+    raw.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <!-- a <comment> with a > in it -->
+ <properties>
+  <property name="title" value="a &gt; b &amp; &quot;c&quot;"/>
+  <property name="notes">first line
+second line</property>
+ </properties>
+ <tileset firstgid="1" source="tiles.tsx"/>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,0,
+2147483652,5,6
+</data>
+ </layer>
+ <objectgroup id="2" name="spawns">
+  <object id="1" name="arrow -> door" type="warp" x="8" y='24.5'/>
+ </objectgroup>
+</map>"#;
+
+    #[test]
+    fn parses_tags_attributes_and_text() {
+        let root = parse_xml(MAP).expect("map parses");
+        assert_eq!(root.name, "map");
+        assert_eq!(root.attr("tilewidth"), Some("16"));
+        let props = root.properties();
+        assert_eq!(props["title"], "a > b & \"c\"");
+        assert_eq!(props["notes"], "first line\nsecond line");
+        let object = &root.child("objectgroup").expect("object group").children[0];
+        assert_eq!(object.attr("name"), Some("arrow -> door"));
+        assert_eq!(object.attr("y"), Some("24.5"));
+    }
+
+    #[test]
+    fn skips_gt_inside_quoted_attributes() {
+        let root = parse_xml(r#"<a b="x > y" c='1>2' d="it's"><e/></a>"#).expect("parses");
+        assert_eq!(root.attr("b"), Some("x > y"));
+        assert_eq!(root.attr("c"), Some("1>2"));
+        assert_eq!(root.attr("d"), Some("it's"));
+        assert_eq!(root.children.len(), 1);
+        assert_eq!(root.children[0].name, "e");
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for src in [
+            "<a><b></a>",
+            "<a b=\"1\">",
+            "<a b=1/>",
+            "<a b=\"1/>",
+            "<!-- never closed",
+            "",
+        ] {
+            assert!(parse_xml(src).is_err(), "accepted {src:?}");
+        }
+    }
+
+    #[test]
+    fn decodes_csv_layers() {
+        let map = parse_tmx(MAP).expect("map parses");
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.tilesets[0].source.as_deref(), Some("tiles.tsx"));
+        let layer = &map.layers[0];
+        assert_eq!(layer.name, "ground");
+        // The flipped 4 loses its flag bits.
+        assert_eq!(layer.gids, [1, 2, 0, 4, 5, 6]);
+        let object = &map.object_groups[0].objects[0];
+        assert_eq!(object.class, "warp");
+        assert_eq!((object.x, object.y, object.width), (8.0, 24.5, 0.0));
+    }
+
+    #[test]
+    fn rejects_bad_csv_layers() {
+        let short = MAP.replace("5,6", "5");
+        assert!(parse_tmx(&short).is_err());
+        let invalid = MAP.replace("5,6", "5,x");
+        assert!(parse_tmx(&invalid).is_err());
+        let base64 = MAP.replace("encoding=\"csv\"", "encoding=\"base64\"");
+        assert!(parse_tmx(&base64).is_err());
+    }
+}
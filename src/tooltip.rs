@@ -0,0 +1,110 @@
+//! Tooltip card for the creature under the cursor.
+//!
+//! With a monocle in the inventory, or while the entity debug overlay is on, hovering an entity
+//! shows a card with its name and kind, a health bar, its notable traits, how many the player has
+//! defeated, and what it drops. Drops the player hasn't seen from that kind yet show as "???".
+
+use macroquad::prelude::*;
+use crate::entity::{Entity, EntityDatabase, EntityKind};
+use crate::entity_pool::{EntityHandle, EntityPool};
+use crate::label::display_name;
+use crate::statistics::Statistics;
+use crate::ui::{UiBatch, UiLayer};
+
+/// Item that turns on tooltips while carried.
+pub const MONOCLE_ITEM: &str = "monocle";
+const CARD_WIDTH: f32 = 210.0;
+const PADDING: f32 = 8.0;
+const LINE_HEIGHT: f32 = 18.0;
+const FONT_SIZE: f32 = 16.0;
+const TITLE_SIZE: f32 = 22.0;
+const BAR_HEIGHT: f32 = 8.0;
+/// Screen pixels between the cursor and the card.
+const CURSOR_GAP: f32 = 16.0;
+const CARD_COLOR: Color = Color::new(0.08, 0.08, 0.12, 0.9);
+const BAR_BACK: Color = Color::new(0.3, 0.1, 0.1, 1.0);
+const BAR_FILL: Color = Color::new(0.85, 0.2, 0.2, 1.0);
+const DETAIL_COLOR: Color = Color::new(0.8, 0.8, 0.85, 1.0);
+
+/// The entity whose hitbox holds `point`, nearest to it by center when several overlap.
+pub fn entity_under<'a>(entities: &'a EntityPool, db: &EntityDatabase, point: Vec2) -> Option<(EntityHandle, &'a Entity)> {
+    entities
+        .iter_with_handles()
+        .filter(|(_, ent)| ent.hitbox(db).contains(point))
+        .min_by(|a, b| {
+            let a = a.1.hitbox(db).center().distance_squared(point);
+            let b = b.1.hitbox(db).center().distance_squared(point);
+            a.total_cmp(&b)
+        })
+}
+
+/// Queues the card for `ent` beside the cursor at `mouse`, kept on screen.
+pub fn draw_entity_tooltip(ent: &Entity, db: &EntityDatabase, stats: &Statistics, mouse: Vec2, ui: &mut UiBatch) {
+    let def = &db.entities[ent.instance.def];
+    let kind = match def.kind {
+        EntityKind::Enemy => "Enemy",
+        EntityKind::Friend => "Friendly",
+        EntityKind::Misc => "Neutral",
+    };
+    let mut lines = vec![kind.to_string()];
+    let traits: Vec<String> = def
+        .traits
+        .iter()
+        .filter_map(|&index| db.traits.get(index))
+        .map(|def| def.id.as_str())
+        // Targeting and collision switches say nothing about the creature itself.
+        .filter(|id| !id.starts_with("target_") && !id.starts_with("no_"))
+        .map(display_name)
+        .collect();
+    if !traits.is_empty() {
+        lines.push(format!("Traits: {}", traits.join(", ")));
+    }
+    let kills = stats.kills(&def.id);
+    if kills > 0 {
+        lines.push(format!("Defeated: {kills}"));
+    }
+    if !def.death.drops.is_empty() {
+        let drops: Vec<String> = def
+            .death
+            .drops
+            .iter()
+            .map(|drop| {
+                if stats.drop_seen(&def.id, &drop.item) {
+                    display_name(&drop.item)
+                } else {
+                    "???".to_string()
+                }
+            })
+            .collect();
+        lines.push(format!("Drops: {}", drops.join(", ")));
+    }
+
+    let height = PADDING * 2.0 + TITLE_SIZE + BAR_HEIGHT + PADDING + (lines.len() + 1) as f32 * LINE_HEIGHT;
+    let mut origin = mouse + Vec2::splat(CURSOR_GAP);
+    if origin.x + CARD_WIDTH > screen_width() {
+        origin.x = mouse.x - CURSOR_GAP - CARD_WIDTH;
+    }
+    if origin.y + height > screen_height() {
+        origin.y = mouse.y - CURSOR_GAP - height;
+    }
+    let origin = origin.max(Vec2::ZERO);
+    ui.rect(UiLayer::Toast, Rect::new(origin.x, origin.y, CARD_WIDTH, height), CARD_COLOR);
+
+    let x = origin.x + PADDING;
+    let mut y = origin.y + PADDING + TITLE_SIZE * 0.8;
+    ui.text(UiLayer::Toast, def.name.as_str(), vec2(x, y), TITLE_SIZE, WHITE);
+    y += PADDING;
+
+    let bar_width = CARD_WIDTH - PADDING * 2.0;
+    let health = (ent.instance.hp / ent.instance.max_hp.max(1.0)).clamp(0.0, 1.0);
+    ui.rect(UiLayer::Toast, Rect::new(x, y, bar_width, BAR_HEIGHT), BAR_BACK);
+    ui.rect(UiLayer::Toast, Rect::new(x, y, bar_width * health, BAR_HEIGHT), BAR_FILL);
+    y += BAR_HEIGHT + PADDING;
+
+    let hp = format!("{:.0}/{:.0} hp", ent.instance.hp.max(0.0), ent.instance.max_hp);
+    for line in std::iter::once(hp).chain(lines) {
+        y += LINE_HEIGHT * 0.8;
+        ui.text(UiLayer::Toast, line, vec2(x, y), FONT_SIZE, DETAIL_COLOR);
+        y += LINE_HEIGHT * 0.2;
+    }
+}
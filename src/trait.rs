@@ -2,11 +2,16 @@ use crate::entity::{
     BehaviorRuntime,
     EntityContext,
     EntityInstance,
+    EntityRequest,
     MovementParams,
+    OrphanRule,
+    PatrolMode,
     StatBlock,
+    SummonRequest,
     TraitDef,
     Target,
 };
+use crate::blackboard::BlackboardValue;
 use macroquad::prelude::*;
 
 pub fn append_builtin_traits(traits: &mut Vec<TraitDef>) {
@@ -22,17 +27,15 @@ pub fn append_builtin_traits(traits: &mut Vec<TraitDef>) {
         });
     };
 
-    push_trait("target_player", &["target_player"]);
-    push_trait("target_nearest_entity", &["target_nearest_entity"]);
-    push_trait("target_nearest_enemy", &["target_nearest_enemy"]);
-    push_trait("target_nearest_friend", &["target_nearest_friend"]);
-    push_trait("target_nearest_misc", &["target_nearest_misc"]);
     push_trait("no_map_collision", &["no_map_collision"]);
     push_trait("no_entity_collision", &["no_entity_collision"]);
     push_trait("no_enemy_collision", &["no_enemy_collision"]);
     push_trait("no_friend_collision", &["no_friend_collision"]);
     push_trait("no_misc_collision", &["no_misc_collision"]);
     push_trait("no_player_collision", &["no_player_collision"]);
+    push_trait("cant_swim", &["cant_swim"]);
+    push_trait("capturable", &["capturable"]);
+    push_trait("requires_los", &["requires_los"]);
 }
 
 pub fn movement_idle(
@@ -75,6 +78,37 @@ pub fn movement_wander(
     }
 }
 
+/// Wanders like `wander` but stays within `radius` of home, heading back whenever it strays out.
+pub fn movement_wander_home(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let speed = params.get("speed").copied().unwrap_or(entity.speed);
+    let interval = params.get("interval").copied().unwrap_or(1.0);
+    let radius = params.get("radius").copied().unwrap_or(48.0);
+
+    let to_home = entity.home - entity.pos;
+    let outside = to_home.length() > radius;
+    behavior.timer -= dt;
+    if behavior.timer <= 0.0
+        || behavior.dir.length_squared() == 0.0
+        || (outside && behavior.dir.dot(to_home) <= 0.0)
+    {
+        behavior.timer = interval.max(0.1);
+        let angle = macroquad::rand::gen_range(0.0, std::f32::consts::TAU);
+        let random_dir = vec2(angle.cos(), angle.sin());
+        behavior.dir = if outside {
+            (to_home.normalize() + random_dir * 0.5).normalize_or(random_dir)
+        } else {
+            random_dir
+        };
+    }
+    entity.vel = behavior.dir * speed;
+}
+
 pub fn movement_seek(
     entity: &mut EntityInstance,
     _behavior: &mut BehaviorRuntime,
@@ -223,6 +257,597 @@ pub fn movement_virabird_ai(
         // Match old JS dash behavior: move position directly while dashing.
         entity.pos += behavior.dir * dash_speed * dt;
     }
+}
+
+pub fn movement_drop_hazard(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let interval = params.get("interval").copied().unwrap_or(3.0).max(0.1);
+    let Some(id) = behavior.labels.get("hazard") else {
+        return;
+    };
+
+    behavior.cooldown -= dt;
+    if behavior.cooldown > 0.0 {
+        return;
+    }
+    behavior.cooldown = interval;
+    entity.requests.push(EntityRequest::SpawnHazard {
+        id: id.clone(),
+        pos: entity.pos,
+    });
+}
+
+/// Drops the `item` label at the entity's home every `interval` seconds.
+pub fn movement_produce_item(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let interval = params.get("interval").copied().unwrap_or(30.0).max(0.1);
+    let Some(id) = behavior.labels.get("item") else {
+        return;
+    };
+
+    behavior.timer += dt;
+    if behavior.timer < interval {
+        return;
+    }
+    behavior.timer = 0.0;
+    entity.requests.push(EntityRequest::DropItem {
+        id: id.clone(),
+        pos: entity.home,
+    });
+}
+
+/// Walks to the nearest trough with feed and takes a bite from it every `interval` seconds.
+pub fn movement_seek_feed(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let speed = params.get("speed").copied().unwrap_or(entity.speed);
+    let range = params.get("range").copied().unwrap_or(160.0);
+    let reach = params.get("reach").copied().unwrap_or(10.0);
+    let interval = params.get("interval").copied().unwrap_or(1.5).max(0.1);
+    let Some(bite) = entity.hunger.map(|hunger| hunger.info.bite) else {
+        return;
+    };
+
+    behavior.cooldown = (behavior.cooldown - dt).max(0.0);
+    let center = entity.pos + entity.center_offset;
+    let Some(trough) = ctx.map.nearest_feed(center, range) else {
+        return;
+    };
+    let to_trough = trough.center() - center;
+    if to_trough.length() > reach {
+        entity.vel = to_trough.normalize_or_zero() * speed;
+        return;
+    }
+    if behavior.cooldown <= 0.0 {
+        behavior.cooldown = interval;
+        entity.requests.push(EntityRequest::Eat {
+            pos: trough.center(),
+            amount: bite,
+        });
+    }
+}
+
+/// Walks to the nearest structure tagged with the `tag` label within `range`, works on it for
+/// `work_time` seconds once within `reach`, then removes it from the map. Rests `interval`
+/// seconds before looking for the next one.
+pub fn movement_harvest_structure(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let Some(tag) = behavior.labels.get("tag") else {
+        return;
+    };
+    let range = params.get("range").copied().unwrap_or(160.0);
+    let reach = params.get("reach").copied().unwrap_or(10.0);
+    let work_time = params.get("work_time").copied().unwrap_or(2.0).max(0.0);
+    let interval = params.get("interval").copied().unwrap_or(1.0).max(0.0);
+
+    if behavior.cooldown > 0.0 {
+        behavior.cooldown = (behavior.cooldown - dt).max(0.0);
+        return;
+    }
+    let center = entity.pos + entity.center_offset;
+    let Some((footprint, tile)) = ctx.map.nearest_structure(center, tag, range) else {
+        behavior.progress = 0.0;
+        return;
+    };
+    // Work from the middle of the bottom edge, where trunks and doors are.
+    let spot = vec2(footprint.center().x, footprint.bottom());
+    if center.distance(spot) > reach {
+        behavior.progress = 0.0;
+        follow_path(entity, behavior, spot, dt, params, ctx);
+        return;
+    }
+    behavior.path.clear();
+    entity.vel = Vec2::ZERO;
+    entity.working = true;
+    behavior.progress += dt;
+    if behavior.progress >= work_time {
+        behavior.progress = 0.0;
+        behavior.cooldown = interval;
+        entity.requests.push(EntityRequest::Harvest { tile });
+    }
+}
+
+/// Flies to the nearest crop within `range` that hasn't been pollinated today, hovers over it for
+/// `work_time` seconds once within `reach`, then pollinates it. Rests `interval` seconds before
+/// looking for the next one.
+pub fn movement_pollinate(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let speed = params.get("speed").copied().unwrap_or(entity.speed);
+    let range = params.get("range").copied().unwrap_or(120.0);
+    let reach = params.get("reach").copied().unwrap_or(4.0);
+    let work_time = params.get("work_time").copied().unwrap_or(1.5).max(0.0);
+    let interval = params.get("interval").copied().unwrap_or(2.0).max(0.0);
+
+    if behavior.cooldown > 0.0 {
+        behavior.cooldown = (behavior.cooldown - dt).max(0.0);
+        return;
+    }
+    let center = entity.pos + entity.center_offset;
+    let Some((tile, spot)) = ctx.nearest_crop(center, range) else {
+        behavior.progress = 0.0;
+        return;
+    };
+    let to_spot = spot - center;
+    if to_spot.length() > reach {
+        behavior.progress = 0.0;
+        entity.vel = to_spot.normalize_or_zero() * speed;
+        return;
+    }
+    entity.vel = Vec2::ZERO;
+    behavior.progress += dt;
+    if behavior.progress >= work_time {
+        behavior.progress = 0.0;
+        behavior.cooldown = interval;
+        entity.requests.push(EntityRequest::Pollinate { tile });
+    }
+}
+
+/// Keeps a pet within `distance` of the player, teleporting it next to them once it falls more
+/// than `teleport` pixels behind. While it has a target within `assist_range` of the player it
+/// goes after that instead.
+pub fn movement_follow_owner(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let Some(player) = ctx.player else {
+        behavior.path.clear();
+        return;
+    };
+    let distance = params.get("distance").copied().unwrap_or(20.0);
+    let teleport = params.get("teleport").copied().unwrap_or(240.0);
+    let assist_range = params.get("assist_range").copied().unwrap_or(160.0);
+    let center = entity.pos + entity.center_offset;
+
+    if center.distance(player.pos) > teleport {
+        let angle = macroquad::rand::gen_range(0.0, std::f32::consts::TAU);
+        entity.pos = player.pos + Vec2::from_angle(angle) * distance - entity.center_offset;
+        behavior.path.clear();
+        return;
+    }
+    if let Some(target) = entity.current_target.as_ref().map(Target::position)
+        && target.distance(player.pos) <= assist_range
+    {
+        follow_path(entity, behavior, target, dt, params, ctx);
+        return;
+    }
+    if center.distance(player.pos) > distance {
+        follow_path(entity, behavior, player.pos, dt, params, ctx);
+    } else {
+        behavior.path.clear();
+    }
+}
+
+pub fn movement_shoot_projectile(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let interval = params.get("interval").copied().unwrap_or(2.0).max(0.1);
+    let range = params.get("range").copied().unwrap_or(160.0);
+    let Some(id) = behavior.labels.get("projectile") else {
+        return;
+    };
+
+    if behavior.cooldown > 0.0 {
+        behavior.cooldown = (behavior.cooldown - dt).max(0.0);
+        return;
+    }
+    let Some(target) = entity.current_target.as_ref().map(Target::position) else {
+        return;
+    };
+    let dir = target - entity.pos;
+    if dir.length_squared() <= 0.0001 || dir.length() > range {
+        return;
+    }
+    behavior.cooldown = interval;
+    entity.requests.push(EntityRequest::SpawnProjectile {
+        id: id.clone(),
+        pos: entity.pos,
+        dir: dir.normalize(),
+    });
+}
+
+/// Casts the ability named by the `ability` label whenever it is ready. Run it with `multiple`
+/// next to a movement; the caster stands still only while the cast winds up.
+pub fn movement_cast_ability(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    _params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    if let Some(id) = behavior.labels.get("ability") {
+        entity.abilities.queue(id);
+    }
+}
+
+/// Every `interval` seconds, spawns `count` children of the `entity` label within `radius` pixels,
+/// up to `max` alive at once (0 for no limit). The `on_parent_death` label (`despawn`, `enrage`
+/// or `stay`) sets what the children do when this entity dies.
+pub fn movement_summon(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let Some(id) = behavior.labels.get("entity") else {
+        return;
+    };
+    if behavior.cooldown > 0.0 {
+        behavior.cooldown = (behavior.cooldown - dt).max(0.0);
+        return;
+    }
+    behavior.cooldown = params.get("interval").copied().unwrap_or(5.0).max(0.1);
+    let count = params.get("count").copied().unwrap_or(1.0).max(0.0) as u32;
+    let radius = params.get("radius").copied().unwrap_or(8.0).max(0.0);
+    let max = params.get("max").copied().unwrap_or(0.0).max(0.0) as u32;
+    let on_parent_death = behavior
+        .labels
+        .get("on_parent_death")
+        .and_then(|name| OrphanRule::from_name(name))
+        .unwrap_or_default();
+    for _ in 0..count {
+        let angle = macroquad::rand::gen_range(0.0, std::f32::consts::TAU);
+        let offset = Vec2::from_angle(angle) * macroquad::rand::gen_range(0.0, radius);
+        entity.requests.push(EntityRequest::Summon(SummonRequest {
+            id: id.clone(),
+            pos: entity.pos + offset,
+            max,
+            on_parent_death,
+        }));
+    }
+}
+
+pub fn movement_return_home(
+    entity: &mut EntityInstance,
+    _behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let speed = params.get("speed").copied().unwrap_or(entity.speed);
+    let dir = entity.home - entity.pos;
+    if dir.length_squared() > 0.0001 {
+        entity.vel = dir.normalize() * speed;
+    }
+}
+
+/// Walks an A* route to the current target so walls and structure colliders are avoided.
+/// Replans every `repath` seconds; until a route is found it seeks the target directly.
+pub fn movement_path_to_target(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let Some(target) = entity.current_target.as_ref().map(Target::position) else {
+        behavior.path.clear();
+        return;
+    };
+    follow_path(entity, behavior, target, dt, params, ctx);
+}
+
+/// Walks an A* route to `target`, replanning every `repath` seconds.
+fn follow_path(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    target: Vec2,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let speed = params.get("speed").copied().unwrap_or(entity.speed);
+    let repath = params.get("repath").copied().unwrap_or(0.5);
+    let arrive = params.get("arrive_radius").copied().unwrap_or(3.0);
+    let body = entity.pos + entity.center_offset;
+    behavior.timer -= dt;
+    if behavior.timer <= 0.0 {
+        behavior.timer = repath.max(0.05);
+        behavior.path = crate::pathfinding::find_path(ctx.map, body, target);
+    }
+    while let Some(&next) = behavior.path.first()
+        && body.distance(next) <= arrive
+    {
+        behavior.path.remove(0);
+    }
+
+    let goal = behavior.path.first().copied().unwrap_or(target);
+    let dir = goal - body;
+    if dir.length_squared() > 0.0001 {
+        entity.vel = dir.normalize() * speed;
+    }
+}
+
+/// Stores `value` (0 when left out) in the number variable named by the `var` label.
+pub fn movement_set_var(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let Some(var) = behavior.labels.get("var") else {
+        return;
+    };
+    let value = params.get("value").copied().unwrap_or(0.0);
+    entity.blackboard.set(var, BlackboardValue::Number(value));
+}
+
+/// Sets the flag named by the `var` label; a `value` of 0 clears it instead of setting it.
+pub fn movement_set_flag(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let Some(var) = behavior.labels.get("var") else {
+        return;
+    };
+    let value = params.get("value").is_none_or(|&value| value != 0.0);
+    entity.blackboard.set(var, BlackboardValue::Flag(value));
+}
+
+pub fn movement_clear_var(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    _params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    if let Some(var) = behavior.labels.get("var") {
+        entity.blackboard.remove(var);
+    }
+}
+
+/// Stores where the current target is in the position variable named by the `var` label.
+pub fn movement_remember_target(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    _params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let Some(var) = behavior.labels.get("var") else {
+        return;
+    };
+    if let Some(target) = entity.current_target.as_ref().map(Target::position) {
+        entity.blackboard.set(var, BlackboardValue::Position(target));
+    }
+}
 
-    // Projectile shooting is not implemented in this runtime yet.
+/// Walks to the position in the `var` variable, like `path_to_target`, and forgets it once
+/// within `arrive_radius` of it.
+pub fn movement_investigate(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let Some(var) = behavior.labels.get("var") else {
+        return;
+    };
+    let Some(spot) = entity.blackboard.position(var) else {
+        behavior.path.clear();
+        return;
+    };
+    let arrive = params.get("arrive_radius").copied().unwrap_or(3.0);
+    if (entity.pos + entity.center_offset).distance(spot) <= arrive {
+        entity.blackboard.remove(var);
+        behavior.path.clear();
+        entity.vel = Vec2::ZERO;
+        return;
+    }
+    follow_path(entity, behavior, spot, dt, params, ctx);
+}
+
+/// Walks the def's `patrol` route, standing `wait` seconds at each waypoint. A `mode` label
+/// (`loop` or `ping_pong`) overrides the route's own; entities without a route stand still.
+pub fn movement_patrol(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let Some(patrol) = entity.patrol.as_ref() else {
+        entity.vel = Vec2::ZERO;
+        return;
+    };
+    let mode = behavior
+        .labels
+        .get("mode")
+        .and_then(|name| PatrolMode::from_name(name))
+        .unwrap_or(patrol.route.mode);
+    let wait = params.get("wait").copied().unwrap_or(patrol.route.wait);
+    let arrive = params.get("arrive_radius").copied().unwrap_or(3.0);
+    let waypoint = entity.home + entity.center_offset + patrol.route.waypoints[patrol.next];
+
+    if behavior.cooldown > 0.0 {
+        behavior.cooldown -= dt;
+        entity.vel = Vec2::ZERO;
+        return;
+    }
+    if (entity.pos + entity.center_offset).distance(waypoint) <= arrive {
+        if let Some(patrol) = entity.patrol.as_mut() {
+            patrol.advance(mode);
+        }
+        behavior.cooldown = wait;
+        // Plan towards the new waypoint on the next step instead of finishing the old route.
+        behavior.timer = 0.0;
+        behavior.path.clear();
+        entity.vel = Vec2::ZERO;
+        return;
+    }
+    follow_path(entity, behavior, waypoint, dt, params, ctx);
+}
+
+/// Steers with the other living entities of the same def within `radius`: away from any closer
+/// than `separation`, along their average heading and towards their middle. Adds to whatever the
+/// rest of the tree set, so it is meant to run as a `multiple` action beside the main movement.
+pub fn movement_flock(
+    entity: &mut EntityInstance,
+    _behavior: &mut BehaviorRuntime,
+    _dt: f32,
+    params: &MovementParams,
+    ctx: &EntityContext,
+) {
+    let radius = params.get("radius").copied().unwrap_or(64.0);
+    let separation = params.get("separation").copied().unwrap_or(20.0);
+    let separation_force = params.get("separation_force").copied().unwrap_or(1200.0);
+    let alignment_force = params.get("alignment_force").copied().unwrap_or(300.0);
+    let cohesion_force = params.get("cohesion_force").copied().unwrap_or(400.0);
+
+    let mut count = 0usize;
+    let mut away = Vec2::ZERO;
+    let mut heading = Vec2::ZERO;
+    let mut middle = Vec2::ZERO;
+    for other in ctx.neighbours(entity.pos, radius) {
+        if other.id == entity.uid || other.def != entity.def || !other.alive {
+            continue;
+        }
+        count += 1;
+        heading += other.vel;
+        middle += other.pos;
+        let offset = entity.pos - other.pos;
+        let dist = offset.length();
+        if dist < separation && dist > 0.0001 {
+            // Closer neighbours push harder.
+            away += offset / dist * (1.0 - dist / separation);
+        }
+    }
+    if count == 0 {
+        return;
+    }
+    middle /= count as f32;
+    entity.vel += away.normalize_or_zero() * separation_force
+        + heading.normalize_or_zero() * alignment_force
+        + (middle - entity.pos).normalize_or_zero() * cohesion_force;
+}
+
+/// Stands still and turns to face the other way every `interval` seconds, give or take.
+pub fn movement_look_around(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let interval = params.get("interval").copied().unwrap_or(1.2).max(0.1);
+    entity.vel = Vec2::ZERO;
+    if behavior.dir == Vec2::ZERO {
+        behavior.dir = Vec2::X;
+        behavior.timer = interval;
+    }
+    behavior.timer -= dt;
+    if behavior.timer <= 0.0 {
+        behavior.timer = interval * macroquad::rand::gen_range(0.7, 1.3);
+        behavior.dir.x = -behavior.dir.x;
+    }
+    entity.pose.flip_x = behavior.dir.x < 0.0;
+}
+
+/// Stands still and dips `depth` pixels for `duration` seconds every `interval` seconds, like a
+/// bird pecking at the ground.
+pub fn movement_peck(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let interval = params.get("interval").copied().unwrap_or(1.0).max(0.1);
+    let duration = params.get("duration").copied().unwrap_or(0.2).clamp(0.05, interval);
+    let depth = params.get("depth").copied().unwrap_or(1.5);
+    entity.vel = Vec2::ZERO;
+    behavior.timer += dt;
+    if behavior.timer >= interval {
+        behavior.timer = 0.0;
+    }
+    if behavior.timer < duration {
+        entity.pose.lift = -depth * (behavior.timer / duration * std::f32::consts::PI).sin();
+    }
+}
+
+/// Hops `height` pixels in a random direction every `interval` seconds, moving at `speed` for the
+/// `duration` of each hop and standing still in between.
+pub fn movement_hop(
+    entity: &mut EntityInstance,
+    behavior: &mut BehaviorRuntime,
+    dt: f32,
+    params: &MovementParams,
+    _ctx: &EntityContext,
+) {
+    let speed = params.get("speed").copied().unwrap_or(entity.speed);
+    let interval = params.get("interval").copied().unwrap_or(1.5);
+    let duration = params.get("duration").copied().unwrap_or(0.3).max(0.05);
+    let height = params.get("height").copied().unwrap_or(3.0);
+
+    behavior.timer -= dt;
+    if behavior.timer <= 0.0 {
+        behavior.timer = interval.max(duration);
+        behavior.cooldown = duration;
+        let angle = macroquad::rand::gen_range(0.0, std::f32::consts::TAU);
+        behavior.dir = vec2(angle.cos(), angle.sin());
+    }
+    if behavior.cooldown <= 0.0 {
+        entity.vel = Vec2::ZERO;
+        return;
+    }
+    behavior.cooldown = (behavior.cooldown - dt).max(0.0);
+    let t = 1.0 - behavior.cooldown / duration;
+    entity.pose.lift = (t * std::f32::consts::PI).sin() * height;
+    entity.vel = behavior.dir * speed;
 }
@@ -0,0 +1,192 @@
+//! Batched screen-space UI.
+//!
+//! HUD pieces queue their fills, textures and text into a `UiBatch` during the frame,
+//! and `flush` draws them all at once ordered by layer. Within a layer, draws are grouped by
+//! kind. Consecutive draws then share a texture (the white texture, a sprite or the font atlas),
+//! and macroquad merges them into a few draw calls instead of one per switch. Each group keeps
+//! the order it was queued in. Outlines land on top of the fills and textures in their layer, and
+//! text on top of everything else in it.
+
+use macroquad::prelude::*;
+
+const PANEL_COLOR: Color = Color::new(0.0, 0.0, 0.0, 0.75);
+const PANEL_BUTTON_SIZE: Vec2 = vec2(80.0, 24.0);
+/// Gap between a panel's edge and what's inside it.
+const PANEL_PADDING: f32 = 10.0;
+const DISABLED_BUTTON_COLOR: Color = Color::new(0.2, 0.2, 0.2, 1.0);
+
+/// Draw order of batched UI; later layers go on top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UiLayer {
+    /// Text and outlines pinned over places in the world, under the HUD.
+    World,
+    /// Always-on readouts such as hearts and the clock.
+    Hud,
+    /// Short-lived messages over the HUD.
+    Toast,
+    /// Open menus such as the contract board and shops.
+    Panel,
+    Debug,
+}
+
+enum UiCommand {
+    Rect { rect: Rect, color: Color },
+    Texture { texture: Texture2D, rect: Rect, color: Color },
+    RectLines { rect: Rect, thickness: f32, color: Color },
+    Text { text: String, pos: Vec2, size: f32, color: Color },
+}
+
+impl UiCommand {
+    /// Group inside a layer.
+    fn kind(&self) -> u8 {
+        match self {
+            Self::Rect { .. } => 0,
+            Self::Texture { .. } => 1,
+            Self::RectLines { .. } => 2,
+            Self::Text { .. } => 3,
+        }
+    }
+}
+
+/// Where the parts of a list panel go: a header, one row per entry with a button on its right,
+/// then a footer. Panels sit centered a quarter of the way down the screen.
+#[derive(Clone, Copy)]
+pub struct PanelLayout {
+    pub width: f32,
+    pub header: f32,
+    pub row_height: f32,
+    pub footer: f32,
+}
+
+impl PanelLayout {
+    pub fn origin(&self) -> Vec2 {
+        vec2((screen_width() - self.width) * 0.5, screen_height() * 0.25)
+    }
+
+    /// The whole panel with `rows` rows.
+    pub fn frame(&self, rows: usize) -> Rect {
+        let origin = self.origin();
+        let height = self.header + self.row_height * rows as f32 + self.footer;
+        Rect::new(origin.x, origin.y, self.width, height)
+    }
+
+    pub fn row(&self, index: usize) -> Rect {
+        let origin = self.origin();
+        Rect::new(origin.x, origin.y + self.header + self.row_height * index as f32, self.width, self.row_height)
+    }
+
+    /// A `size` square at the left of row `index`, centered on it.
+    pub fn icon(&self, index: usize, size: f32) -> Rect {
+        let row = self.row(index);
+        Rect::new(row.x + PANEL_PADDING, row.y + (row.h - size) * 0.5, size, size)
+    }
+
+    /// The button at the right of row `index`, for drawing and for hit-testing clicks.
+    pub fn button(&self, index: usize) -> Rect {
+        let row = self.row(index);
+        Rect::new(
+            row.right() - PANEL_BUTTON_SIZE.x - PANEL_PADDING,
+            row.y + (row.h - PANEL_BUTTON_SIZE.y) * 0.5,
+            PANEL_BUTTON_SIZE.x,
+            PANEL_BUTTON_SIZE.y,
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct UiBatch {
+    commands: Vec<(UiLayer, UiCommand)>,
+}
+
+impl UiBatch {
+    pub fn rect(&mut self, layer: UiLayer, rect: Rect, color: Color) {
+        self.commands.push((layer, UiCommand::Rect { rect, color }));
+    }
+
+    /// Queues the outline of `rect`, like `draw_rectangle_lines`.
+    pub fn rect_lines(&mut self, layer: UiLayer, rect: Rect, thickness: f32, color: Color) {
+        self.commands.push((layer, UiCommand::RectLines { rect, thickness, color }));
+    }
+
+    /// Queues a texture stretched over `rect`.
+    pub fn texture(&mut self, layer: UiLayer, texture: &Texture2D, rect: Rect, color: Color) {
+        self.commands.push((
+            layer,
+            UiCommand::Texture {
+                texture: texture.clone(),
+                rect,
+                color,
+            },
+        ));
+    }
+
+    /// Queues text with its baseline at `pos`, like `draw_text`.
+    pub fn text(&mut self, layer: UiLayer, text: impl Into<String>, pos: Vec2, size: f32, color: Color) {
+        self.commands.push((
+            layer,
+            UiCommand::Text {
+                text: text.into(),
+                pos,
+                size,
+                color,
+            },
+        ));
+    }
+
+    /// Queues a panel's dimmed background and outline, and `title` in its top-left corner.
+    pub fn panel(&mut self, frame: Rect, title: &str) {
+        self.rect(UiLayer::Panel, frame, PANEL_COLOR);
+        self.rect_lines(UiLayer::Panel, frame, 1.0, WHITE);
+        self.text(UiLayer::Panel, title, frame.point() + vec2(PANEL_PADDING, 24.0), 24.0, WHITE);
+    }
+
+    /// Queues a line of hint text along the bottom of a panel.
+    pub fn panel_footer(&mut self, frame: Rect, text: &str) {
+        let pos = vec2(frame.x + PANEL_PADDING, frame.bottom() - 10.0);
+        self.text(UiLayer::Panel, text, pos, 16.0, LIGHTGRAY);
+    }
+
+    /// Queues a panel button with `label` centered on it, lit while the mouse is over it. Disabled
+    /// buttons are greyed out.
+    pub fn button(&mut self, rect: Rect, label: &str, enabled: bool) {
+        let mouse: Vec2 = mouse_position().into();
+        let fill = match (enabled, rect.contains(mouse)) {
+            (true, true) => GRAY,
+            (true, false) => DARKGRAY,
+            (false, _) => DISABLED_BUTTON_COLOR,
+        };
+        let ink = if enabled { WHITE } else { GRAY };
+        self.rect(UiLayer::Panel, rect, fill);
+        self.rect_lines(UiLayer::Panel, rect, 1.0, ink);
+        let width = measure_text(label, None, 18, 1.0).width;
+        let pos = vec2(rect.x + (rect.w - width) * 0.5, rect.y + rect.h * 0.5 + 5.0);
+        self.text(UiLayer::Panel, label, pos, 18.0, ink);
+    }
+
+    /// Draws everything queued since the last flush in screen space and empties the batch.
+    pub fn flush(&mut self) {
+        // Stable, so each group keeps its queue order.
+        self.commands.sort_by_key(|(layer, command)| (*layer, command.kind()));
+        for (_, command) in self.commands.drain(..) {
+            match command {
+                UiCommand::Rect { rect, color } => draw_rectangle(rect.x, rect.y, rect.w, rect.h, color),
+                UiCommand::Texture { texture, rect, color } => draw_texture_ex(
+                    &texture,
+                    rect.x,
+                    rect.y,
+                    color,
+                    DrawTextureParams {
+                        dest_size: Some(rect.size()),
+                        ..Default::default()
+                    },
+                ),
+                UiCommand::RectLines { rect, thickness, color } => {
+                    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, thickness, color);
+                }
+                UiCommand::Text { text, pos, size, color } => {
+                    draw_text(&text, pos.x, pos.y, size, color);
+                }
+            }
+        }
+    }
+}
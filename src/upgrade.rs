@@ -0,0 +1,341 @@
+//! Upgrades bought at stations.
+//!
+//! Each `src/upgrade/*.yaml` file is one upgrade: the station that sells it, what one level
+//! does, and the coins and materials every level costs. A structure whose `on_interact` runs
+//! `open_upgrades` is a station for the upgrades naming its id. Its panel lists them with the
+//! next level's cost; number keys or the Buy buttons pay for it out of the contract board's
+//! coins and the inventory. Levels bought are kept in saves.
+
+use macroquad::prelude::*;
+use crate::asset_pack::load_string;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use crate::capture::CaptureNet;
+use crate::helpers::{data_path, load_wasm_manifest_files};
+use crate::item::Inventory;
+use crate::player::Player;
+use crate::quest::ContractBoard;
+use crate::ui::{PanelLayout, UiBatch, UiLayer};
+
+/// How far the player can walk from the station before the panel closes.
+const CLOSE_RANGE: f32 = 64.0;
+const STATUS_TIME: f32 = 3.0;
+
+const PANEL: PanelLayout = PanelLayout {
+    width: 400.0,
+    header: 40.0,
+    row_height: 40.0,
+    footer: 30.0,
+};
+const BUY_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+#[derive(Debug)]
+pub enum UpgradeLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for UpgradeLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for UpgradeLoadError {}
+
+impl From<std::io::Error> for UpgradeLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for UpgradeLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+/// What a level of an upgrade improves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeEffect {
+    /// Dashes that can be chained.
+    DashCharges,
+    /// Pixels added to the capture net's reach.
+    NetRange,
+    /// Damage added to capture net hits.
+    NetDamage,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UpgradeCost {
+    #[serde(default)]
+    pub coins: u32,
+    /// Items used up, by id.
+    #[serde(default)]
+    pub materials: BTreeMap<String, u32>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UpgradeDef {
+    pub id: String,
+    pub name: String,
+    /// Id of the structure that sells it.
+    pub station: String,
+    pub effect: UpgradeEffect,
+    /// Effect of each level.
+    #[serde(default = "default_amount")]
+    pub amount: f32,
+    /// Cost of each level in order; the upgrade maxes out after the last.
+    pub levels: Vec<UpgradeCost>,
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+pub struct UpgradeDatabase {
+    defs: Vec<UpgradeDef>,
+}
+
+impl UpgradeDatabase {
+    pub fn empty() -> Self {
+        Self { defs: Vec::new() }
+    }
+
+    pub async fn load_from(dir: impl AsRef<Path>) -> Result<Self, UpgradeLoadError> {
+        let dir = dir.as_ref();
+        let mut defs = Vec::new();
+
+        if cfg!(target_arch = "wasm32") {
+            let dir = data_path(&dir.to_string_lossy());
+            let fallback = ["dash_charges.yaml", "net_range.yaml", "net_weights.yaml"];
+            let files = load_wasm_manifest_files(&dir, &fallback).await;
+            for file in files {
+                let path = format!("{}/{}", dir, file);
+                let raw_str = load_string(&path)
+                    .await
+                    .map_err(|err| UpgradeLoadError::Io(std::io::Error::other(err.to_string())))?;
+                defs.push(serde_yaml::from_str::<UpgradeDef>(&raw_str)?);
+            }
+        } else if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if !is_yaml(&path) {
+                    continue;
+                }
+                defs.push(serde_yaml::from_str::<UpgradeDef>(&std::fs::read_to_string(&path)?)?);
+            }
+        }
+        defs.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(Self { defs })
+    }
+
+    /// Upgrades sold at the station structure `station`, in id order.
+    pub fn at_station<'a>(&'a self, station: &'a str) -> impl Iterator<Item = &'a UpgradeDef> {
+        self.defs.iter().filter(move |def| def.station == station)
+    }
+}
+
+/// Level bought of each upgrade, by id.
+#[derive(Clone, Default)]
+pub struct Upgrades {
+    levels: HashMap<String, u32>,
+}
+
+impl Upgrades {
+    pub fn level(&self, id: &str) -> u32 {
+        self.levels.get(id).copied().unwrap_or(0)
+    }
+
+    /// Sum of `effect` over every upgrade bought.
+    pub fn bonus(&self, db: &UpgradeDatabase, effect: UpgradeEffect) -> f32 {
+        db.defs
+            .iter()
+            .filter(|def| def.effect == effect)
+            .map(|def| def.amount * self.level(&def.id).min(def.levels.len() as u32) as f32)
+            .sum()
+    }
+
+    /// Hands the bought effects to the player and their tools; call after buying or loading.
+    pub fn apply(&self, db: &UpgradeDatabase, player: &mut Player, net: &mut CaptureNet) {
+        player.set_dash_charges(1 + self.bonus(db, UpgradeEffect::DashCharges).round().max(0.0) as u32);
+        net.set_upgrades(self.bonus(db, UpgradeEffect::NetRange), self.bonus(db, UpgradeEffect::NetDamage));
+    }
+
+    pub fn to_save_data(&self) -> HashMap<String, u32> {
+        self.levels.clone()
+    }
+
+    pub fn restore(&mut self, saved: &HashMap<String, u32>) {
+        self.levels = saved.clone();
+    }
+}
+
+/// The open station's upgrade list.
+#[derive(Default)]
+pub struct UpgradePanel {
+    /// Station structure id and its area, while open.
+    open_at: Option<(String, Rect)>,
+    status: String,
+    status_timer: f32,
+}
+
+impl UpgradePanel {
+    pub fn open(&mut self, station: &str, area: Rect) {
+        self.open_at = Some((station.to_string(), area));
+    }
+
+    pub fn close(&mut self) {
+        self.open_at = None;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open_at.is_some()
+    }
+
+    /// Closes when the player walks off or presses Escape, and buys the picked upgrade's next
+    /// level. Returns true when something was bought, so the caller can apply it.
+    pub fn update(
+        &mut self,
+        db: &UpgradeDatabase,
+        upgrades: &mut Upgrades,
+        board: &mut ContractBoard,
+        inventory: &mut Inventory,
+        player_pos: Vec2,
+        dt: f32,
+    ) -> bool {
+        self.status_timer = (self.status_timer - dt).max(0.0);
+        let Some((station, area)) = self.open_at.clone() else {
+            return false;
+        };
+        if is_key_pressed(KeyCode::Escape) || area.center().distance(player_pos) > CLOSE_RANGE {
+            self.open_at = None;
+            return false;
+        }
+        let mouse: Vec2 = mouse_position().into();
+        let picked = db.at_station(&station).enumerate().find(|&(index, _)| {
+            let clicked = is_mouse_button_pressed(MouseButton::Left) && PANEL.button(index).contains(mouse);
+            clicked || BUY_KEYS.get(index).is_some_and(|&key| is_key_pressed(key))
+        });
+        let Some((_, def)) = picked else {
+            return false;
+        };
+        let level = upgrades.level(&def.id);
+        let Some(cost) = def.levels.get(level as usize) else {
+            self.set_status(format!("{} is fully upgraded", def.name));
+            return false;
+        };
+        if let Some(missing) = missing(cost, board, inventory) {
+            self.set_status(format!("{} needs {missing}", def.name));
+            return false;
+        }
+        board.spend_coins(cost.coins);
+        for (item, &count) in &cost.materials {
+            inventory.remove(item, count);
+        }
+        upgrades.levels.insert(def.id.clone(), level + 1);
+        self.set_status(format!("{} upgraded to level {}", def.name, level + 1));
+        true
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = STATUS_TIME;
+    }
+
+    /// Queues the open panel and the last status message.
+    pub fn draw_ui(
+        &self,
+        db: &UpgradeDatabase,
+        upgrades: &Upgrades,
+        board: &ContractBoard,
+        inventory: &Inventory,
+        ui: &mut UiBatch,
+    ) {
+        if let Some((station, _)) = self.open_at.as_ref() {
+            let rows = db.at_station(station).count();
+            let frame = PANEL.frame(rows.max(1));
+            ui.panel(frame, "Upgrades");
+            if rows == 0 {
+                let pos = PANEL.row(0).point() + vec2(10.0, 20.0);
+                ui.text(UiLayer::Panel, "nothing to upgrade here", pos, 18.0, LIGHTGRAY);
+            }
+
+            for (index, def) in db.at_station(station).enumerate() {
+                let row = PANEL.row(index);
+                let level = upgrades.level(&def.id);
+                let max = def.levels.len() as u32;
+                ui.text(
+                    UiLayer::Panel,
+                    format!("{}. {} ({}/{max})", index + 1, def.name, level.min(max)),
+                    vec2(row.x + 10.0, row.y + 16.0),
+                    18.0,
+                    WHITE,
+                );
+                let cost = def.levels.get(level as usize);
+                let price = cost.map_or_else(|| "maxed out".to_string(), cost_text);
+                ui.text(UiLayer::Panel, price, vec2(row.x + 10.0, row.y + 32.0), 16.0, LIGHTGRAY);
+
+                let ready = cost.is_some_and(|cost| missing(cost, board, inventory).is_none());
+                ui.button(PANEL.button(index), "Buy", ready);
+            }
+            ui.panel_footer(frame, "Esc: close");
+        }
+
+        if self.status_timer > 0.0 {
+            let size = measure_text(&self.status, None, 20, 1.0);
+            let pos = vec2((screen_width() - size.width) * 0.5, screen_height() * 0.85);
+            ui.text(UiLayer::Toast, self.status.as_str(), pos, 20.0, WHITE);
+        }
+    }
+}
+
+/// Queues a pip per dash charge above the hearts, filled when ready; nothing with one charge.
+pub fn draw_dash_charges(player: &Player, ui: &mut UiBatch) {
+    let (ready, charges) = player.dash_charges();
+    if charges <= 1 {
+        return;
+    }
+    let pips: String = (0..charges).map(|index| if index < ready { '*' } else { '.' }).collect();
+    ui.text(UiLayer::Hud, format!("dash {pips}"), vec2(20.0, screen_height() - 40.0), 18.0, WHITE);
+}
+
+/// The first thing the player lacks for `cost`, as text, or `None` when they can pay.
+fn missing(cost: &UpgradeCost, board: &ContractBoard, inventory: &Inventory) -> Option<String> {
+    if board.coins() < cost.coins {
+        return Some(format!("{} coins, have {}", cost.coins, board.coins()));
+    }
+    cost.materials.iter().find_map(|(item, &count)| {
+        let have = inventory.counts().get(item).copied().unwrap_or(0);
+        (have < count).then(|| format!("{count} {item}, have {have}"))
+    })
+}
+
+fn cost_text(cost: &UpgradeCost) -> String {
+    let mut parts = vec![format!("{} coins", cost.coins)];
+    parts.extend(cost.materials.iter().map(|(item, count)| format!("{count} {item}")));
+    parts.join(", ")
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
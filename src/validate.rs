@@ -0,0 +1,368 @@
+//! Static checks over the game's content files.
+//!
+//! `validate_content` reads every definition under one or more content roots (`src`, then any
+//! mods) and reports what the loaders would only trip over at runtime: files that don't parse,
+//! unknown traits, behaviors, particles, sounds, items, entities, cutscenes, factions and biomes,
+//! unregistered interact functions, and sprite or sound files that don't exist. Later roots may reference
+//! definitions from earlier ones, the way mods build on the built-in content.
+
+use serde_yaml::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::entity::TraitDef;
+use crate::faction::PLAYER_FACTION;
+use crate::interact::InteractRegistry;
+use crate::r#trait::append_builtin_traits;
+use crate::worldgen::Biome;
+
+/// Definition directories under a content root, by the kind of id their files define.
+const CONTENT_DIRS: &[(&str, &str)] = &[
+    ("trait", "entity/trait"),
+    ("behavior", "entity/behaviour"),
+    ("ability", "entity/ability"),
+    ("entity", "entity/enemy"),
+    ("entity", "entity/friend"),
+    ("entity", "entity/misc"),
+    ("structure", "structure"),
+    ("particle", "particle"),
+    ("sound", "sound"),
+    ("hazard", "hazard"),
+    ("projectile", "projectile"),
+    ("item", "item"),
+    ("cutscene", "cutscene"),
+    ("crop", "crop"),
+    ("spawn table", "spawner"),
+    ("upgrade", "upgrade"),
+    ("critter", "ambient"),
+];
+
+/// One problem in a content file.
+pub struct Issue {
+    pub file: PathBuf,
+    /// 1-based line, when the offending text could be found.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.file.display(), self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+struct ContentFile {
+    kind: &'static str,
+    path: PathBuf,
+    raw: String,
+    value: Value,
+}
+
+impl ContentFile {
+    fn id(&self) -> Option<&str> {
+        self.value.get("id").and_then(Value::as_str)
+    }
+}
+
+#[derive(Default)]
+struct Validator {
+    files: Vec<ContentFile>,
+    /// Defined ids by kind.
+    ids: HashMap<&'static str, HashSet<String>>,
+    issues: Vec<Issue>,
+}
+
+/// Checks the content under `roots`, in load order, and returns every problem found.
+pub fn validate_content(roots: &[PathBuf]) -> Vec<Issue> {
+    let mut validator = Validator::default();
+    let mut builtin_traits: Vec<TraitDef> = Vec::new();
+    append_builtin_traits(&mut builtin_traits);
+    for def in builtin_traits {
+        validator.define("trait", def.id);
+    }
+    for faction in [PLAYER_FACTION, "enemy", "friend", "misc"] {
+        validator.define("faction", faction.to_string());
+    }
+    for root in roots {
+        validator.read_root(root);
+    }
+
+    let interact = InteractRegistry::new();
+    let files = std::mem::take(&mut validator.files);
+    for file in &files {
+        match file.kind {
+            "entity" => validator.check_entity(file),
+            "structure" => validator.check_structure(file, &interact),
+            "particle" => validator.check_path(file, "texture", str_at(&file.value, &["texture"])),
+            "sound" => validator.check_path(file, "path", str_at(&file.value, &["path"])),
+            "projectile" => {
+                validator.check_path(file, "sprite", str_at(&file.value, &["sprite"]));
+                validator.check_ref(file, "impact_hazard", "hazard", str_at(&file.value, &["impact_hazard"]));
+            }
+            "item" => validator.check_path(file, "sprite", str_at(&file.value, &["sprite"])),
+            "crop" => {
+                validator.check_ref(file, "seed", "item", str_at(&file.value, &["seed"]));
+                validator.check_ref(file, "harvest", "item", str_at(&file.value, &["harvest"]));
+                for item in mapping_values(file.value.get("quality_harvest")) {
+                    validator.check_ref(file, "quality_harvest", "item", item.as_str());
+                }
+            }
+            "hazard" => validator.check_ref(file, "particle", "particle", str_at(&file.value, &["particle"])),
+            "spawn table" => validator.check_spawn_table(file),
+            "ability" => {
+                let effect = |key: &str| str_at(&file.value, &["effect", key]);
+                validator.check_ref(file, "projectile", "projectile", effect("projectile"));
+                validator.check_ref(file, "entity", "entity", effect("entity"));
+                validator.check_ref(file, "particle", "particle", effect("particle"));
+            }
+            "factions" => {
+                for (attacker, targets) in mapping_entries(file.value.get("hostile")) {
+                    validator.check_ref(file, "hostile", "faction", attacker.as_str());
+                    for target in targets.as_sequence().into_iter().flatten() {
+                        validator.check_ref(file, "hostile", "faction", target.as_str());
+                    }
+                }
+            }
+            "upgrade" => {
+                validator.check_ref(file, "station", "structure", str_at(&file.value, &["station"]));
+                for level in seq_at(&file.value, "levels") {
+                    let materials = level.get("materials").and_then(Value::as_mapping).into_iter().flatten();
+                    for material in materials.filter_map(|(id, _)| id.as_str()) {
+                        validator.check_ref(file, "materials", "item", Some(material));
+                    }
+                }
+            }
+            "world" => {
+                let upgrades = file.value.get("tiers").and_then(|tiers| tiers.get("upgrades"));
+                for (from, to) in mapping_entries(upgrades) {
+                    validator.check_ref(file, "upgrades", "item", from.as_str());
+                    validator.check_ref(file, "upgrades", "item", to.as_str());
+                }
+                for terrain in mapping_values(file.value.get("terrain_particles")) {
+                    validator.check_ref(file, "walk", "particle", str_at(terrain, &["walk"]));
+                    validator.check_ref(file, "dash", "particle", str_at(terrain, &["dash"]));
+                }
+            }
+            _ => {}
+        }
+    }
+    validator.issues
+}
+
+impl Validator {
+    fn define(&mut self, kind: &'static str, id: String) -> bool {
+        self.ids.entry(kind).or_default().insert(id)
+    }
+
+    fn issue(&mut self, file: &ContentFile, line: Option<usize>, message: String) {
+        self.issues.push(Issue {
+            file: file.path.clone(),
+            line,
+            message,
+        });
+    }
+
+    fn read_root(&mut self, root: &Path) {
+        let mut root_ids: HashSet<(&'static str, String)> = HashSet::new();
+        for &(kind, dir) in CONTENT_DIRS {
+            let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+                continue;
+            };
+            let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+            paths.sort();
+            for path in paths {
+                let Some(file) = self.read_file(kind, path) else {
+                    continue;
+                };
+                match file.id().map(str::to_string) {
+                    Some(id) => {
+                        if !root_ids.insert((kind, id.clone())) {
+                            let line = line_of(&file.raw, "id", &id);
+                            self.issue(&file, line, format!("duplicate {kind} id '{id}'"));
+                        }
+                        self.define(kind, id);
+                    }
+                    None => self.issue(&file, None, "missing 'id'".to_string()),
+                }
+                if let Some(faction) = str_at(&file.value, &["faction"]).filter(|_| kind == "entity") {
+                    self.define("faction", faction.to_string());
+                }
+                self.files.push(file);
+            }
+        }
+        let factions = root.join("entity/factions.yaml");
+        if factions.is_file()
+            && let Some(file) = self.read_file("factions", factions)
+        {
+            self.files.push(file);
+        }
+        let world = root.join("world.yaml");
+        if world.is_file()
+            && let Some(file) = self.read_file("world", world)
+        {
+            self.files.push(file);
+        }
+    }
+
+    /// Parses one definition file, recording a parse error instead when it's malformed.
+    fn read_file(&mut self, kind: &'static str, path: PathBuf) -> Option<ContentFile> {
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        let is_json = ext.eq_ignore_ascii_case("json");
+        let is_yaml = ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml");
+        // The wasm manifests aren't definitions.
+        if !(is_json || is_yaml) || path.file_name().is_some_and(|name| name == "index.json") {
+            return None;
+        }
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) => {
+                self.issues.push(Issue {
+                    file: path,
+                    line: None,
+                    message: format!("unreadable: {err}"),
+                });
+                return None;
+            }
+        };
+        let parsed = if is_json {
+            serde_json::from_str::<Value>(&raw).map_err(|err| (Some(err.line()), err.to_string()))
+        } else {
+            serde_yaml::from_str::<Value>(&raw)
+                .map_err(|err| (err.location().map(|location| location.line()), err.to_string()))
+        };
+        match parsed {
+            Ok(value) => Some(ContentFile { kind, path, raw, value }),
+            Err((line, message)) => {
+                self.issues.push(Issue {
+                    file: path,
+                    line,
+                    message: format!("parse error: {message}"),
+                });
+                None
+            }
+        }
+    }
+
+    /// Reports `id` under `key` when no `kind` definition has it.
+    fn check_ref(&mut self, file: &ContentFile, key: &str, kind: &str, id: Option<&str>) {
+        let Some(id) = id else {
+            return;
+        };
+        if !self.ids.get(kind).is_some_and(|ids| ids.contains(id)) {
+            let line = line_of(&file.raw, key, id);
+            self.issue(file, line, format!("unknown {kind} '{id}' in '{key}'"));
+        }
+    }
+
+    /// Reports a sprite or sound path under `key` that doesn't exist.
+    fn check_path(&mut self, file: &ContentFile, key: &str, path: Option<&str>) {
+        let Some(path) = path else {
+            return;
+        };
+        if !Path::new(path).is_file() {
+            let line = line_of(&file.raw, key, path);
+            self.issue(file, line, format!("missing file '{path}' in '{key}'"));
+        }
+    }
+
+    fn check_entity(&mut self, file: &ContentFile) {
+        let value = &file.value;
+        for id in strs_at(value, "traits") {
+            self.check_ref(file, "traits", "trait", Some(id));
+        }
+        self.check_ref(file, "behavior_id", "behavior", str_at(value, &["behavior_id"]));
+        self.check_path(file, "sprite", str_at(value, &["visuals", "sprite"]));
+        let clips = value.get("visuals").and_then(|visuals| visuals.get("clips"));
+        for sprite in mapping_values(clips).filter_map(Value::as_str) {
+            self.check_path(file, "clips", Some(sprite));
+        }
+        let visuals = value.get("visuals");
+        if visuals.is_some_and(|visuals| visuals.get("animations").is_some() && visuals.get("sheet").is_none()) {
+            self.issue(file, None, "'animations' without a 'sheet' to take frames from".to_string());
+        }
+        self.check_ref(file, "particle", "particle", str_at(value, &["death", "particle"]));
+        self.check_ref(file, "sound", "sound", str_at(value, &["death", "sound"]));
+        if let Some(death) = value.get("death") {
+            for item in strs_at(death, "loot") {
+                self.check_ref(file, "loot", "item", Some(item));
+            }
+            for drop in seq_at(death, "drops") {
+                self.check_ref(file, "item", "item", str_at(drop, &["item"]));
+            }
+        }
+        for vocal in seq_at(value, "vocals") {
+            self.check_ref(file, "sound", "sound", str_at(vocal, &["sound"]));
+        }
+        self.check_ref(file, "intro_cutscene", "cutscene", str_at(value, &["intro_cutscene"]));
+        self.check_ref(file, "tame", "item", str_at(value, &["tame", "item"]));
+    }
+
+    fn check_spawn_table(&mut self, file: &ContentFile) {
+        for entry in seq_at(&file.value, "entries") {
+            self.check_ref(file, "entity", "entity", str_at(entry, &["entity"]));
+            for biome in strs_at(entry, "biomes") {
+                if !Biome::ALL.iter().any(|known| known.tag() == biome) {
+                    let line = line_of(&file.raw, "biomes", biome);
+                    self.issue(file, line, format!("unknown biome '{biome}' in 'biomes'"));
+                }
+            }
+        }
+    }
+
+    fn check_structure(&mut self, file: &ContentFile, interact: &InteractRegistry) {
+        let value = &file.value;
+        for key in ["on_interact", "on_trigger"] {
+            for name in strs_at(value, key) {
+                if !interact.contains(name) {
+                    let line = line_of(&file.raw, key, name);
+                    self.issue(file, line, format!("unknown interact function '{name}' in '{key}'"));
+                }
+            }
+        }
+        for spawn in seq_at(value, "spawns") {
+            self.check_ref(file, "entity", "entity", str_at(spawn, &["entity"]));
+        }
+        if let Some(encounter) = value.get("encounter") {
+            for spawn in seq_at(encounter, "wave") {
+                self.check_ref(file, "entity", "entity", str_at(spawn, &["entity"]));
+            }
+        }
+        self.check_ref(file, "vendor", "entity", str_at(value, &["vendor", "entity"]));
+        self.check_path(file, "icon", str_at(value, &["highlight", "icon"]));
+    }
+}
+
+/// String at a nested key path.
+fn str_at<'a>(value: &'a Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().try_fold(value, |value, key| value.get(key))?.as_str()
+}
+
+fn seq_at<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    value.get(key).and_then(Value::as_sequence).into_iter().flatten()
+}
+
+fn strs_at<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = &'a str> {
+    seq_at(value, key).filter_map(Value::as_str)
+}
+
+fn mapping_entries(value: Option<&Value>) -> impl Iterator<Item = (&Value, &Value)> {
+    value.and_then(Value::as_mapping).into_iter().flatten()
+}
+
+fn mapping_values(value: Option<&Value>) -> impl Iterator<Item = &Value> {
+    value.and_then(Value::as_mapping).into_iter().flat_map(|mapping| mapping.values())
+}
+
+/// Line of the first `needle` at or after the first line mentioning `key`.
+fn line_of(raw: &str, key: &str, needle: &str) -> Option<usize> {
+    let start = raw.lines().position(|line| line.contains(key)).unwrap_or(0);
+    raw.lines()
+        .enumerate()
+        .skip(start)
+        .find(|(_, line)| line.contains(needle))
+        .map(|(index, _)| index + 1)
+}
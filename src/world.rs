@@ -0,0 +1,234 @@
+use macroquad::prelude::*;
+use crate::asset_pack::load_string;
+use serde::Deserialize;
+use std::collections::HashMap;
+use crate::ambient::AmbientSettings;
+use crate::simulation::SimulationSettings;
+use crate::debug::DebugProfile;
+use crate::director::DirectorSettings;
+use crate::entity::EntityInstance;
+use crate::farming::FarmingSettings;
+use crate::helpers::data_path;
+use crate::map::{AmbientOcclusion, MapBorders};
+use crate::player::PlayerSpriteSettings;
+use crate::spawner::SpawnerSettings;
+use crate::time::TimeSettings;
+use crate::worldgen::WorldGenSettings;
+
+const DEFAULT_WALK_PARTICLES: &str = "dust_trail";
+const DEFAULT_DASH_PARTICLES: &str = "dash_afterimage";
+
+#[derive(Debug)]
+pub enum WorldLoadError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for WorldLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Yaml(err) => write!(f, "yaml error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for WorldLoadError {}
+
+impl From<std::io::Error> for WorldLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for WorldLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+/// Distance-based difficulty: every `tier_distance` pixels away from spawn adds a tier.
+#[derive(Clone, Deserialize)]
+pub struct TierScaling {
+    #[serde(default = "default_tier_distance")]
+    pub tier_distance: f32,
+    #[serde(default)]
+    pub max_tier: u32,
+    /// Fractional stat increase per tier, e.g. `hp: 0.25` is +25% hp per tier.
+    #[serde(default)]
+    pub stats_per_tier: HashMap<String, f32>,
+    /// Extra loot rolled per tier; fractions are rolled as a chance.
+    #[serde(default)]
+    pub loot_per_tier: f32,
+    /// Chance per tier that a drop is swapped for its entry in `upgrades`.
+    #[serde(default)]
+    pub upgrade_per_tier: f32,
+    /// The next-tier item for each item that has one.
+    #[serde(default)]
+    pub upgrades: HashMap<String, String>,
+}
+
+/// Fog of war over tiles the player hasn't been near yet.
+#[derive(Clone, Deserialize)]
+pub struct FogSettings {
+    /// Pixels around the player that are revealed.
+    #[serde(default = "default_reveal_radius")]
+    pub reveal_radius: f32,
+    /// RGBA drawn over unexplored tiles; lower alpha dims instead of hiding.
+    #[serde(default = "default_fog_color")]
+    pub color: [u8; 4],
+}
+
+/// Walk and dash particle templates for one terrain tag; unset ones use the defaults.
+#[derive(Clone, Default, Deserialize)]
+pub struct TerrainParticles {
+    #[serde(default)]
+    pub walk: Option<String>,
+    #[serde(default)]
+    pub dash: Option<String>,
+}
+
+impl FogSettings {
+    pub fn color(&self) -> Color {
+        let [r, g, b, a] = self.color;
+        Color::from_rgba(r, g, b, a)
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct WorldSettings {
+    #[serde(default)]
+    pub tiers: Option<TierScaling>,
+    #[serde(default)]
+    pub worldgen: WorldGenSettings,
+    #[serde(default)]
+    pub time: TimeSettings,
+    /// Allows creative mode (F3) for building without dying or running out of items.
+    #[serde(default)]
+    pub creative: bool,
+    #[serde(default)]
+    pub fog: Option<FogSettings>,
+    /// Particles by the `terrain` tag of the tile under a walking or dashing actor.
+    #[serde(default)]
+    pub terrain_particles: HashMap<String, TerrainParticles>,
+    #[serde(default)]
+    pub farming: FarmingSettings,
+    /// Map edges of generated worlds; authored levels set theirs in Tiled.
+    #[serde(default)]
+    pub borders: MapBorders,
+    #[serde(default)]
+    pub player_sprite: PlayerSpriteSettings,
+    /// Dynamic difficulty; off when missing.
+    #[serde(default)]
+    pub director: Option<DirectorSettings>,
+    /// Ambient spawning from the tables in `src/spawner`; off when missing.
+    #[serde(default)]
+    pub spawner: Option<SpawnerSettings>,
+    /// Butterflies, frogs and other critters from `src/ambient`; off when missing.
+    #[serde(default)]
+    pub ambient: Option<AmbientSettings>,
+    /// Sleeping and putting away entities far from the player; off when missing.
+    #[serde(default)]
+    pub simulation: Option<SimulationSettings>,
+    /// Shade around walls and other tall tiles; off when missing.
+    #[serde(default)]
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    /// Developer tools and their keys; all off when missing.
+    #[serde(default)]
+    pub debug: Option<DebugProfile>,
+}
+
+impl WorldSettings {
+    pub async fn load(path: &str) -> Result<Self, WorldLoadError> {
+        let raw_str = if cfg!(target_arch = "wasm32") {
+            load_string(&data_path(path))
+                .await
+                .map_err(|err| WorldLoadError::Io(std::io::Error::other(err.to_string())))?
+        } else {
+            std::fs::read_to_string(path)?
+        };
+        Ok(serde_yaml::from_str(&raw_str)?)
+    }
+
+    /// Particle template for walking on a terrain tag.
+    pub fn walk_particles(&self, terrain: Option<&str>) -> &str {
+        terrain
+            .and_then(|terrain| self.terrain_particles.get(terrain))
+            .and_then(|particles| particles.walk.as_deref())
+            .unwrap_or(DEFAULT_WALK_PARTICLES)
+    }
+
+    /// Particle template for dashing over a terrain tag.
+    pub fn dash_particles(&self, terrain: Option<&str>) -> &str {
+        terrain
+            .and_then(|terrain| self.terrain_particles.get(terrain))
+            .and_then(|particles| particles.dash.as_deref())
+            .unwrap_or(DEFAULT_DASH_PARTICLES)
+    }
+
+    /// Tier for a position, measured from `origin` (the player's spawn point).
+    pub fn tier_at(&self, origin: Vec2, pos: Vec2) -> u32 {
+        let Some(tiers) = self.tiers.as_ref() else {
+            return 0;
+        };
+        let tier = (origin.distance(pos) / tiers.tier_distance.max(1.0)).floor() as u32;
+        if tiers.max_tier > 0 {
+            tier.min(tiers.max_tier)
+        } else {
+            tier
+        }
+    }
+
+    /// Scales a freshly spawned entity for the tier of its spawn position.
+    pub fn apply_tier(&self, entity: &mut EntityInstance, origin: Vec2) {
+        let tier = self.tier_at(origin, entity.pos);
+        self.scale_to_tier(entity, tier);
+    }
+
+    pub fn scale_to_tier(&self, entity: &mut EntityInstance, tier: u32) {
+        let Some(tiers) = self.tiers.as_ref() else {
+            return;
+        };
+        if tier == 0 {
+            return;
+        }
+        entity.apply_tier(tier, &tiers.stats_per_tier);
+    }
+
+    /// How many copies of each loot entry an entity of `tier` drops.
+    pub fn loot_count(&self, tier: u32) -> u32 {
+        let Some(tiers) = self.tiers.as_ref() else {
+            return 1;
+        };
+        let bonus = tiers.loot_per_tier.max(0.0) * tier as f32;
+        let extra = bonus.floor();
+        let chance = bonus - extra;
+        let roll = if crate::helpers::random_f32() < chance { 1 } else { 0 };
+        1 + extra as u32 + roll
+    }
+
+    /// The item an entity of `tier` drops in place of `item`: its upgrade, with a chance that
+    /// grows by `upgrade_per_tier` each tier, or `item` itself.
+    pub fn loot_item<'a>(&'a self, item: &'a str, tier: u32) -> &'a str {
+        let Some(tiers) = self.tiers.as_ref() else {
+            return item;
+        };
+        let Some(upgrade) = tiers.upgrades.get(item) else {
+            return item;
+        };
+        let chance = tiers.upgrade_per_tier.max(0.0) * tier as f32;
+        if crate::helpers::random_f32() < chance { upgrade } else { item }
+    }
+}
+
+fn default_tier_distance() -> f32 {
+    400.0
+}
+
+fn default_reveal_radius() -> f32 {
+    112.0
+}
+
+fn default_fog_color() -> [u8; 4] {
+    [0, 0, 0, 255]
+}
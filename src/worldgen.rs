@@ -0,0 +1,430 @@
+//! Seeded biome generation for the open world map.
+//!
+//! Two value-noise fields, elevation and moisture, decide each tile's biome: low elevation is
+//! water, and the rest splits into desert, plains or forest by moisture.
+//!
+//! `WorldGenerator` puts a whole new world together from a seed: the map, the structures rolled
+//! onto it and the merchant waiting by the spawn; wildlife comes from the spawner instead. Nothing in it draws from
+//! `macroquad::rand`, so the same seed always gives the same world.
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+use crate::autotile::AutotileRules;
+use crate::map::{EntitySpawn, StructureDef, TileMap};
+use crate::time::SpawnTime;
+
+/// Tiles across the new-game preview, centered on the spawn.
+const PREVIEW_SPAN: f32 = 1024.0;
+/// The merchant waits near the spawn so the player meets them early.
+const MERCHANT_OFFSET: Vec2 = vec2(40.0, -24.0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Desert,
+    Water,
+}
+
+impl Biome {
+    pub const ALL: [Self; 4] = [Self::Plains, Self::Forest, Self::Desert, Self::Water];
+
+    /// Tag used by structure definitions to weight placement per biome.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::Plains => "plains",
+            Self::Forest => "forest",
+            Self::Desert => "desert",
+            Self::Water => "water",
+        }
+    }
+
+    pub fn is_water(self) -> bool {
+        self == Self::Water
+    }
+
+    /// Flat color of the biome in the new-game preview.
+    pub fn preview_color(self) -> Color {
+        match self {
+            Self::Plains => Color::from_rgba(118, 170, 84, 255),
+            Self::Forest => Color::from_rgba(46, 104, 58, 255),
+            Self::Desert => Color::from_rgba(214, 190, 120, 255),
+            Self::Water => Color::from_rgba(58, 108, 178, 255),
+        }
+    }
+}
+
+/// Seed for text a player typed or was sent: a plain number is used as is, anything else is
+/// hashed, so "mossy hills" is as good a seed as 1337.
+pub fn seed_from_text(text: &str) -> u32 {
+    let text = text.trim();
+    if let Ok(seed) = text.parse::<u32>() {
+        return seed;
+    }
+    // FNV-1a.
+    text.bytes().fold(0x811c_9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// Background tile id for each biome.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct BiomeTiles {
+    pub plains: u8,
+    pub forest: u8,
+    pub desert: u8,
+    pub water: u8,
+}
+
+impl Default for BiomeTiles {
+    fn default() -> Self {
+        Self {
+            plains: 24,
+            forest: 21,
+            desert: 118,
+            water: 204,
+        }
+    }
+}
+
+impl BiomeTiles {
+    fn get(&self, biome: Biome) -> u8 {
+        match biome {
+            Biome::Plains => self.plains,
+            Biome::Forest => self.forest,
+            Biome::Desert => self.desert,
+            Biome::Water => self.water,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct WorldGenSettings {
+    pub seed: u32,
+    /// Width and height of the world in tiles. Chunks are generated as the player reaches them,
+    /// so this only bounds how far they can go.
+    pub size: usize,
+    /// Rough size of a biome in tiles.
+    pub scale: f32,
+    /// Elevation (0..1) below which tiles become water.
+    pub water_level: f32,
+    /// Moisture (0..1) below which land becomes desert.
+    pub dry_level: f32,
+    /// Moisture (0..1) above which land becomes forest.
+    pub wet_level: f32,
+    /// Radius in tiles around the player spawn that is always plains.
+    pub spawn_clearing: f32,
+    pub tiles: BiomeTiles,
+}
+
+impl WorldGenSettings {
+    /// Middle of the world in world pixels, where new games start.
+    pub fn spawn(&self, tile_size: f32) -> Vec2 {
+        Vec2::splat(self.size.max(1) as f32 * tile_size * 0.5)
+    }
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        Self {
+            seed: 1337,
+            size: 16384,
+            scale: 96.0,
+            water_level: 0.32,
+            dry_level: 0.35,
+            wet_level: 0.62,
+            spawn_clearing: 12.0,
+            tiles: BiomeTiles::default(),
+        }
+    }
+}
+
+/// Biome generator for a streamed map. Every query is a pure function of the settings, so
+/// chunks can be generated in any order and regenerated identically after being dropped.
+#[derive(Clone)]
+pub struct WorldGen {
+    settings: WorldGenSettings,
+    /// Spawn position in tiles; the area around it is always plains.
+    spawn_tile: Vec2,
+    tile_count: usize,
+}
+
+impl WorldGen {
+    /// `spawn` is in world pixels; background tiles past `tile_count` fall back to tile 0.
+    pub fn new(settings: WorldGenSettings, spawn: Vec2, tile_size: f32, tile_count: usize) -> Self {
+        Self {
+            settings,
+            spawn_tile: spawn / tile_size,
+            tile_count,
+        }
+    }
+
+    pub fn biome(&self, x: usize, y: usize) -> Biome {
+        let settings = &self.settings;
+        let p = vec2(x as f32, y as f32);
+        if p.distance_squared(self.spawn_tile) <= settings.spawn_clearing.max(0.0).powi(2) {
+            return Biome::Plains;
+        }
+        let scale = settings.scale.max(1.0);
+        let elevation = fbm(p / scale, settings.seed);
+        let moisture = fbm(p / scale, settings.seed ^ 0x5bd1_e995);
+        if elevation < settings.water_level {
+            Biome::Water
+        } else if moisture < settings.dry_level {
+            Biome::Desert
+        } else if moisture > settings.wet_level {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /// Background tile for a biome. Biomes whose tag matches an autotile terrain are re-picked
+    /// by the map's autotile rules so their edges blend.
+    pub fn background(&self, biome: Biome) -> u8 {
+        let id = self.settings.tiles.get(biome);
+        if (id as usize) < self.tile_count { id } else { 0 }
+    }
+}
+
+/// Builds new worlds from a seed; see the module docs.
+pub struct WorldGenerator {
+    settings: WorldGenSettings,
+    structures: Vec<StructureDef>,
+    autotile: AutotileRules,
+    tile_size: f32,
+    tile_count: usize,
+}
+
+impl WorldGenerator {
+    /// `settings.seed` is ignored; every method takes the seed to use.
+    pub fn new(
+        settings: WorldGenSettings,
+        structures: Vec<StructureDef>,
+        autotile: AutotileRules,
+        tile_size: f32,
+        tile_count: usize,
+    ) -> Self {
+        Self {
+            settings,
+            structures,
+            autotile,
+            tile_size,
+            tile_count,
+        }
+    }
+
+    pub fn spawn(&self) -> Vec2 {
+        self.settings.spawn(self.tile_size)
+    }
+
+    pub fn worldgen(&self, seed: u32) -> WorldGen {
+        let settings = WorldGenSettings {
+            seed,
+            ..self.settings.clone()
+        };
+        WorldGen::new(settings, self.spawn(), self.tile_size, self.tile_count)
+    }
+
+    /// An empty map that generates chunks and rolls structures from `seed` as it streams them.
+    pub fn map(&self, seed: u32) -> TileMap {
+        let size = self.settings.size.max(1);
+        let mut map = TileMap::new(size, size, self.tile_size, Vec2::splat(self.tile_size), 0.0);
+        map.set_autotile_rules(self.autotile.clone());
+        map.set_worldgen(self.worldgen(seed));
+        map.set_structure_defs(self.structures.clone(), seed);
+        map
+    }
+
+    /// A `size` by `size` picture of the biomes within `PREVIEW_SPAN` tiles of the spawn, one
+    /// sample per pixel, for picking a seed before generating anything.
+    pub fn preview(&self, seed: u32, size: u16) -> Image {
+        let worldgen = self.worldgen(seed);
+        let mut image = Image::gen_image_color(size, size, BLACK);
+        let spawn = self.spawn() / self.tile_size;
+        let step = PREVIEW_SPAN / size.max(1) as f32;
+        let origin = spawn - Vec2::splat(PREVIEW_SPAN * 0.5);
+        let max_tile = self.settings.size.saturating_sub(1);
+        for py in 0..size as u32 {
+            for px in 0..size as u32 {
+                let tile = origin + vec2(px as f32 + 0.5, py as f32 + 0.5) * step;
+                let x = (tile.x.max(0.0) as usize).min(max_tile);
+                let y = (tile.y.max(0.0) as usize).min(max_tile);
+                image.set_pixel(px, py, worldgen.biome(x, y).preview_color());
+            }
+        }
+        image
+    }
+
+    /// The residents a new world starts with around `center`.
+    pub fn population(&self, center: Vec2) -> Vec<EntitySpawn> {
+        vec![EntitySpawn {
+            entity: "merchant".to_string(),
+            pos: center + MERCHANT_OFFSET,
+            time: SpawnTime::Any,
+        }]
+    }
+
+    /// A new world with the chunks around the spawn already generated, and every entity to
+    /// spawn in it: the residents and what the generated structures asked for.
+    pub fn generate(&self, seed: u32) -> (TileMap, Vec<EntitySpawn>) {
+        let mut map = self.map(seed);
+        map.generate_around(self.spawn());
+        let mut spawns = self.population(self.spawn());
+        spawns.extend(map.take_structure_spawns());
+        (map, spawns)
+    }
+}
+
+/// Three octaves of value noise, normalized to roughly 0..1.
+fn fbm(p: Vec2, seed: u32) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut norm = 0.0;
+    let mut freq = 1.0;
+    for octave in 0..3u32 {
+        total += value_noise(p * freq, seed.wrapping_add(octave.wrapping_mul(0x9E37_79B9))) * amplitude;
+        norm += amplitude;
+        amplitude *= 0.5;
+        freq *= 2.0;
+    }
+    total / norm
+}
+
+fn value_noise(p: Vec2, seed: u32) -> f32 {
+    let cell = p.floor();
+    let frac = p - cell;
+    let (x0, y0) = (cell.x as i32, cell.y as i32);
+    let smooth = frac * frac * (Vec2::splat(3.0) - 2.0 * frac);
+    let a = lattice(x0, y0, seed);
+    let b = lattice(x0 + 1, y0, seed);
+    let c = lattice(x0, y0 + 1, seed);
+    let d = lattice(x0 + 1, y0 + 1, seed);
+    let top = a + (b - a) * smooth.x;
+    let bottom = c + (d - c) * smooth.x;
+    top + (bottom - top) * smooth.y
+}
+
+fn lattice(x: i32, y: i32, seed: u32) -> f32 {
+    let mut v = (x as u32).wrapping_mul(0x9E37_79B1) ^ (y as u32).wrapping_mul(0x85EB_CA6B) ^ seed;
+    v ^= v >> 16;
+    v = v.wrapping_mul(0x7FEB_352D);
+    v ^= v >> 15;
+    v as f32 / u32::MAX as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{load_structures_from_dir, LayerKind};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Tiles either side of the spawn to hash; wider than the chunks `generate` loads.
+    const HASH_SPAN: usize = 128;
+    /// Seed, tile hash, spawn count and spawn hash of a few known worlds.
+    const GOLDEN: [(u32, u64, usize, u64); 2] = [
+        (1337, 0xdeae_e4d0_eb7d_c5f8, 19, 0x0767_9f3b_8e8a_3096),
+        (424242, 0x0c06_437f_a868_100e, 10, 0xc6c1_3a74_1533_1b58),
+    ];
+
+    /// Drives a loader to completion; the native file loaders never actually wait.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /// The shipped structures and terrain rules with default settings, so the goldens only move
+    /// when generation or that content changes.
+    fn generator() -> WorldGenerator {
+        let mut structures = block_on(load_structures_from_dir("src/structure")).expect("structures load");
+        // Directory order differs between filesystems, and placement rolls in definition order.
+        structures.sort_by(|a, b| a.id.cmp(&b.id));
+        let autotile = block_on(AutotileRules::load("src/terrain.json")).expect("autotile rules load");
+        WorldGenerator::new(WorldGenSettings::default(), structures, autotile, 16.0, 256)
+    }
+
+    fn fnv1a(hash: &mut u64, bytes: &[u8]) {
+        for &byte in bytes {
+            *hash = (*hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// FNV-1a over the layers and biome of every generated tile within `HASH_SPAN` tiles of the
+    /// spawn, row by row.
+    fn tile_hash(map: &TileMap, spawn: Vec2) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325;
+        let spawn = (spawn / map.tile_size()).as_uvec2();
+        let (cx, cy) = (spawn.x as usize, spawn.y as usize);
+        for y in cy.saturating_sub(HASH_SPAN)..(cy + HASH_SPAN).min(map.height()) {
+            for x in cx.saturating_sub(HASH_SPAN)..(cx + HASH_SPAN).min(map.width()) {
+                if !map.is_tile_loaded(x, y) {
+                    continue;
+                }
+                fnv1a(&mut hash, &(x as u32).to_le_bytes());
+                fnv1a(&mut hash, &(y as u32).to_le_bytes());
+                for layer in [LayerKind::Background, LayerKind::Foreground, LayerKind::Overlay] {
+                    fnv1a(&mut hash, &[map.tile_at(layer, x, y)]);
+                }
+                let center = (vec2(x as f32, y as f32) + 0.5) * map.tile_size();
+                fnv1a(&mut hash, map.biome_at(center).map_or("none", Biome::tag).as_bytes());
+            }
+        }
+        hash
+    }
+
+    /// FNV-1a over each spawn's entity, whole-pixel position and time of day, in order.
+    fn spawn_hash(spawns: &[EntitySpawn]) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325;
+        for spawn in spawns {
+            let line = format!("{} {:.0},{:.0} {:?}\n", spawn.entity, spawn.pos.x, spawn.pos.y, spawn.time);
+            fnv1a(&mut hash, line.as_bytes());
+        }
+        hash
+    }
+
+    #[test]
+    fn generate_matches_golden() {
+        let generator = generator();
+        for (seed, tiles, spawn_count, spawns) in GOLDEN {
+            let (map, generated) = generator.generate(seed);
+            assert_eq!(tile_hash(&map, generator.spawn()), tiles, "tiles for seed {seed}");
+            assert_eq!(generated.len(), spawn_count, "spawn count for seed {seed}");
+            assert_eq!(generated[0].entity, "merchant", "residents come first for seed {seed}");
+            assert_eq!(spawn_hash(&generated), spawns, "spawns for seed {seed}");
+        }
+    }
+
+    #[test]
+    fn generate_is_deterministic() {
+        let generator = generator();
+        let (first_map, first_spawns) = generator.generate(7);
+        let (second_map, second_spawns) = generator.generate(7);
+        assert_eq!(tile_hash(&first_map, generator.spawn()), tile_hash(&second_map, generator.spawn()));
+        assert_eq!(spawn_hash(&first_spawns), spawn_hash(&second_spawns));
+    }
+
+    #[test]
+    fn streaming_order_does_not_change_the_world() {
+        let generator = generator();
+        let spawn = generator.spawn();
+        // Far enough apart that each area overlaps the other's edge chunks.
+        let east = spawn + vec2(96.0 * 16.0, 32.0 * 16.0);
+        let stream = |centers: [Vec2; 2]| {
+            let mut map = generator.map(1337);
+            for center in centers {
+                map.generate_around(center);
+            }
+            let mut spawns = map.take_structure_spawns();
+            spawns.sort_by_key(|spawn| format!("{} {:.0},{:.0}", spawn.entity, spawn.pos.x, spawn.pos.y));
+            (tile_hash(&map, spawn), tile_hash(&map, east), spawn_hash(&spawns))
+        };
+        assert_eq!(stream([spawn, east]), stream([east, spawn]));
+    }
+}
@@ -0,0 +1,44 @@
+//! The Y-sorted draw pass.
+//!
+//! Between the foreground and overlay layers, `sort_by_bottom` tiles, entities and the player are
+//! drawn back to front by their baseline, the world y where they touch the ground. Whatever stands
+//! lower on screen is drawn later, so the player passes in front of a tree's trunk when below it
+//! and behind it when above.
+
+use macroquad::prelude::*;
+use crate::map::{SortedTile, TileMap};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Sorted {
+    Tile(SortedTile),
+    /// Index into the entity pool.
+    Entity(usize),
+    Player,
+}
+
+#[derive(Default)]
+pub struct YSortPass {
+    items: Vec<(f32, Sorted)>,
+    tiles: Vec<SortedTile>,
+}
+
+impl YSortPass {
+    /// Starts a frame's pass with the map's sorted tiles in `area`.
+    pub fn begin(&mut self, map: &TileMap, area: Rect) {
+        self.items.clear();
+        map.collect_sorted_tiles(area, &mut self.tiles);
+        self.items
+            .extend(self.tiles.drain(..).map(|tile| (tile.baseline, Sorted::Tile(tile))));
+    }
+
+    pub fn push(&mut self, baseline: f32, item: Sorted) {
+        self.items.push((baseline, item));
+    }
+
+    /// Everything pushed since `begin`, back to front. Ties keep push order, so characters pushed
+    /// after the tiles stay in front of a tile with the same baseline.
+    pub fn sorted(&mut self) -> impl Iterator<Item = Sorted> + '_ {
+        self.items.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.items.iter().map(|&(_, item)| item)
+    }
+}